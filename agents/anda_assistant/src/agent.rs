@@ -1,8 +1,8 @@
 use anda_cognitive_nexus::{CognitiveNexus, ConceptPK};
 use anda_core::{
     Agent, AgentContext, AgentOutput, BoxError, CompletionRequest, Document, Documents,
-    Message, Principal, Resource, StateFeatures, Tool, ToolSet, Usage, evaluate_tokens,
-    update_resources,
+    Message, Principal, Resource, StateFeatures, Tool, ToolSet, ToolStrategy, Usage,
+    evaluate_tokens, update_resources,
 };
 use anda_db::{database::AndaDB, index::BTree};
 use anda_engine::{
@@ -26,6 +26,7 @@ pub struct Assistant {
     memory: Arc<MemoryManagement>,
     tools: Vec<String>,
     system_instructions: String,
+    tool_strategy: ToolStrategy,
 }
 
 impl Assistant {
@@ -62,6 +63,7 @@ impl Assistant {
         Ok(Self {
             max_input_tokens: 65535,
             system_instructions: SYSTEM_INSTRUCTIONS.to_string(),
+            tool_strategy: ToolStrategy::Auto,
             memory,
             tools: vec![
                 memory_name,
@@ -78,11 +80,20 @@ impl Assistant {
         self
     }
 
+    /// Sets the system instructions template, rendered per request by
+    /// [`AgentCtx::render_instructions`], e.g. `{user_name}` and `{datetime}`.
     pub fn with_system_instructions(mut self, instructions: &str) -> Self {
         self.system_instructions = instructions.to_string();
         self
     }
 
+    /// Sets the default tool strategy, overridden per request by
+    /// [`anda_core::RequestMeta::tool_strategy`].
+    pub fn with_tool_strategy(mut self, strategy: ToolStrategy) -> Self {
+        self.tool_strategy = strategy;
+        self
+    }
+
     pub fn tools(&self) -> Result<ToolSet<BaseCtx>, BoxError> {
         let mut tools = ToolSet::new();
         tools.add(self.memory.clone())?;
@@ -208,7 +219,8 @@ impl Agent<AgentCtx> for Assistant {
         let primer = self.memory.describe_primer().await?;
         let instructions = format!(
             "{}\n---\n# Your Identity & Knowledge Domain Map\n{}\n",
-            SYSTEM_INSTRUCTIONS, primer
+            ctx.render_instructions(&self.system_instructions),
+            primer
         );
 
         let (mut conversations, mut cursor) = self
@@ -303,20 +315,22 @@ impl Agent<AgentCtx> for Assistant {
         };
 
         let assistant = self.clone();
-        let mut runner = ctx.completion_iter(
-            CompletionRequest {
-                instructions,
-                prompt,
-                chat_history,
-                documents: Documents::new("resources".to_string(), resource_docs),
-                tools: ctx.tool_definitions(Some(
-                    &self.tools.iter().map(|v| v.as_str()).collect::<Vec<_>>(),
-                )),
-                tool_choice_required: false,
-                ..Default::default()
-            },
-            resources,
-        );
+        let mut runner = ctx
+            .completion_iter(
+                CompletionRequest {
+                    instructions,
+                    prompt,
+                    chat_history,
+                    documents: Documents::new("resources".to_string(), resource_docs),
+                    tools: ctx.tool_definitions(Some(
+                        &self.tools.iter().map(|v| v.as_str()).collect::<Vec<_>>(),
+                    )),
+                    tool_choice_required: false,
+                    ..Default::default()
+                },
+                resources,
+            )
+            .with_tool_strategy(ctx.meta().tool_strategy.unwrap_or(self.tool_strategy));
 
         tokio::spawn(async move {
             let mut rt = async || {
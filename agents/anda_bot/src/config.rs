@@ -1,4 +1,5 @@
 use anda_core::BoxError;
+use anda_engine::model::{ModelConfig, ProviderConfig};
 use config::{Config, File, FileFormat};
 use serde::{Deserialize, Serialize};
 
@@ -30,6 +31,55 @@ pub struct Llm {
     pub openai_completion_model: String,
 }
 
+impl Llm {
+    /// Returns the model configuration, OpenAI is used if its API key is set,
+    /// otherwise DeepSeek for completion and Cohere for embedding.
+    pub fn model_config(&self) -> ModelConfig {
+        let provider = |provider: &str, api_key: &str, endpoint: &str, model: &str| {
+            (!api_key.is_empty()).then(|| ProviderConfig {
+                provider: provider.to_string(),
+                api_key: api_key.to_string(),
+                endpoint: endpoint.to_string(),
+                model: model.to_string(),
+            })
+        };
+
+        if self.openai_api_key.is_empty() {
+            ModelConfig {
+                completion: provider(
+                    "deepseek",
+                    &self.deepseek_api_key,
+                    &self.deepseek_endpoint,
+                    &self.deepseek_model,
+                ),
+                embedding: provider(
+                    "cohere",
+                    &self.cohere_api_key,
+                    "",
+                    &self.cohere_embedding_model,
+                ),
+                ..Default::default()
+            }
+        } else {
+            ModelConfig {
+                completion: provider(
+                    "openai",
+                    &self.openai_api_key,
+                    &self.openai_endpoint,
+                    &self.openai_completion_model,
+                ),
+                embedding: provider(
+                    "openai",
+                    &self.openai_api_key,
+                    &self.openai_endpoint,
+                    &self.openai_embedding_model,
+                ),
+                ..Default::default()
+            }
+        }
+    }
+}
+
 /// Configuration for the Google search should be encrypted and stored in the ICP COSE canister.
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Google {
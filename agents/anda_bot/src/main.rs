@@ -5,8 +5,9 @@ use anda_engine::{
     context::{TEEClient, TEEClientBuilder, Web3SDK},
     engine::{AgentInfo, EngineBuilder},
     extension::google::GoogleSearchTool,
+    logger::{env_log_filter, init_filtered_logger},
     management::SYSTEM_PATH,
-    model::{Model, cohere, deepseek, openai},
+    model::Model,
     store::{LocalFileSystem, Store},
 };
 use anda_engine_server::shutdown_signal;
@@ -29,7 +30,7 @@ use ic_object_store::{
 use ic_tee_agent::setting::decrypt_payload;
 use std::collections::{BTreeMap, BTreeSet};
 use std::{net::SocketAddr, sync::Arc, time::Duration};
-use structured_logger::{Builder, async_json::new_writer, get_env_level, unix_ms};
+use structured_logger::{Builder, async_json::new_writer, unix_ms};
 use tokio::net::TcpStream;
 use tokio_util::sync::CancellationToken;
 
@@ -103,6 +104,14 @@ pub enum Commands {
         #[arg(long, env = "ROOT_SECRET")]
         root_secret: String,
 
+        /// Previous 48 bytes root secret in hex, still accepted for signature verification after a rotation
+        #[arg(long, env = "PREVIOUS_ROOT_SECRET")]
+        previous_root_secret: Option<String>,
+
+        /// Grace period in seconds during which the previous root secret is accepted
+        #[arg(long, env = "ROOT_SECRET_GRACE_SECS", default_value_t = 86400)]
+        root_secret_grace_secs: u64,
+
         /// Path to the configuration file
         #[clap(long, env = "CONFIG_FILE_PATH", default_value = "./Config.toml")]
         config: String,
@@ -134,9 +143,11 @@ fn main() -> Result<(), BoxError> {
             } else {
                 new_writer(tokio::io::stdout())
             };
-            Builder::with_level(&get_env_level().to_string())
+            // Log at the most verbose level, the filter from env can be changed at runtime
+            let logger = Builder::with_level("trace")
                 .with_target_writer("*", writer)
-                .init();
+                .build();
+            init_filtered_logger(logger, env_log_filter())?;
 
             log::info!("bootstrap {}@{}", APP_NAME, APP_VERSION);
             match bootstrap(cli).await {
@@ -173,6 +184,8 @@ async fn bootstrap(cli: Cli) -> Result<(), BoxError> {
         Some(Commands::StartLocal {
             id_secret,
             root_secret,
+            previous_root_secret,
+            root_secret_grace_secs,
             config,
             store_path,
             manager,
@@ -182,12 +195,23 @@ async fn bootstrap(cli: Cli) -> Result<(), BoxError> {
             let root_secret = hex::decode(root_secret)?;
             let root_secret: [u8; 48] =
                 root_secret.try_into().map_err(|_| "invalid root_secret")?;
+            let previous_root_secret = match previous_root_secret {
+                Some(secret) => {
+                    let secret = hex::decode(secret)?;
+                    let secret: [u8; 48] = secret
+                        .try_into()
+                        .map_err(|_| "invalid previous_root_secret")?;
+                    Some((secret, Duration::from_secs(root_secret_grace_secs)))
+                }
+                None => None,
+            };
 
             bootstrap_local(
                 cli.port,
                 cli.ic_host,
                 &id_secret,
                 root_secret,
+                previous_root_secret,
                 cfg,
                 store_path,
                 manager,
@@ -363,6 +387,7 @@ async fn bootstrap_local(
     ic_host: String,
     id_secret: &str,
     root_secret: [u8; 48],
+    previous_root_secret: Option<([u8; 48], Duration)>,
     cfg: config::Conf,
     store_path: String,
     _manager: String,
@@ -374,12 +399,14 @@ async fn bootstrap_local(
     let default_agent = engine_name.to_ascii_lowercase();
 
     let identity = load_identity(id_secret)?;
-    let web3 = Web3Client::builder()
+    let mut web3 = Web3Client::builder()
         .with_ic_host(&ic_host)
         .with_identity(Arc::new(identity))
-        .with_root_secret(root_secret)
-        .build()
-        .await?;
+        .with_root_secret(root_secret);
+    if let Some((previous_root_secret, grace)) = previous_root_secret {
+        web3 = web3.with_previous_root_secret(previous_root_secret, grace);
+    }
+    let web3 = web3.build().await?;
     let my_principal = web3.get_principal();
     log::info!(
         "start local service, principal: {:?}",
@@ -509,42 +536,7 @@ async fn connect_object_store(
 }
 
 fn connect_model(cfg: &config::Llm) -> Result<Model, BoxError> {
-    if cfg.openai_api_key.is_empty() {
-        Ok(Model::new(
-            Arc::new(
-                deepseek::Client::new(
-                    &cfg.deepseek_api_key,
-                    if cfg.deepseek_endpoint.is_empty() {
-                        None
-                    } else {
-                        Some(cfg.deepseek_endpoint.clone())
-                    },
-                )
-                .completion_model(if cfg.deepseek_model.is_empty() {
-                    deepseek::DEEKSEEK_V3
-                } else {
-                    &cfg.deepseek_model
-                }),
-            ),
-            Arc::new(
-                cohere::Client::new(&cfg.cohere_api_key, None)
-                    .embedding_model(&cfg.cohere_embedding_model),
-            ),
-        ))
-    } else {
-        let cli = openai::Client::new(
-            &cfg.openai_api_key,
-            if cfg.openai_endpoint.is_empty() {
-                None
-            } else {
-                Some(cfg.openai_endpoint.clone())
-            },
-        );
-        Ok(Model::new(
-            Arc::new(cli.completion_model(&cfg.openai_completion_model)),
-            Arc::new(cli.embedding_model(&cfg.openai_embedding_model)),
-        ))
-    }
+    Model::from_config(&cfg.model_config())
 }
 
 async fn start_server(
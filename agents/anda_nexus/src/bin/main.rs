@@ -4,13 +4,18 @@ use anda_db::{
     storage::StorageConfig,
 };
 use anda_engine::{
-    context::{Web3ClientFeatures, Web3SDK},
+    context::{HttpPolicy, Web3ClientFeatures, Web3SDK},
     engine::{AgentInfo, EchoEngineInfo, EngineBuilder},
+    logger::{env_log_filter, init_filtered_logger},
     management::{BaseManagement, SYSTEM_PATH, Visibility},
+    model::Model,
     store::{InMemory, LocalFileSystem, ObjectStore, Store},
 };
-use anda_engine_server::{ServerBuilder, shutdown_signal};
-use anda_nexus::{Conf, NexusNode};
+use anda_engine_server::{DatabaseStatus, ServerBuilder, shutdown_signal};
+use anda_nexus::{
+    Conf, DEFAULT_MAX_PARTICIPANTS, DEFAULT_PAGE_LIMIT, FlushPolicy, MAX_PAGE_LIMIT,
+    MAX_PARTICIPANTS, NexusNode, NexusOptions, SummarizeThreadTool,
+};
 use anda_object_store::MetaStoreBuilder;
 use anda_web3_client::client::{Client as Web3Client, load_identity};
 use clap::Parser;
@@ -21,7 +26,7 @@ use std::{
     collections::{BTreeMap, BTreeSet},
     sync::Arc,
 };
-use structured_logger::{Builder, async_json::new_writer, get_env_level};
+use structured_logger::{Builder, async_json::new_writer};
 use tokio_util::sync::CancellationToken;
 
 const APP_NAME: &str = env!("CARGO_PKG_NAME");
@@ -55,22 +60,22 @@ async fn main() -> Result<(), BoxError> {
     let cli = Cli::parse();
 
     // Initialize structured logging with JSON format
-    Builder::with_level(&get_env_level().to_string())
+    // Log at the most verbose level, the filter from env can be changed at runtime
+    let logger = Builder::with_level("trace")
         .with_target_writer("*", new_writer(tokio::io::stdout()))
-        .init();
+        .build();
+    init_filtered_logger(logger, env_log_filter())?;
 
     // Create global cancellation token for graceful shutdown
     let global_cancel_token = CancellationToken::new();
 
     let cfg = Conf::from_file(&cli.config)?;
-    log::debug!("{:?}", cfg);
+    cfg.validate()
+        .map_err(|err| format!("invalid config {:?}: {err}", cli.config))?;
 
-    // Parse and validate cryptographic secrets
+    // Parse cryptographic secrets
     let identity = load_identity(&cfg.id_secret)?;
-    let root_secret = hex::decode(&cfg.root_secret)?;
-    let root_secret: [u8; 48] = root_secret
-        .try_into()
-        .map_err(|_| format!("invalid root_secret: {:?}", cfg.root_secret))?;
+    let root_secret = cfg.root_secret()?;
 
     // Initialize Web3 client for ICP network interaction
     let web3 = Web3Client::builder()
@@ -109,11 +114,63 @@ async fn main() -> Result<(), BoxError> {
         lock: Some(ByteBufB64(lock.into())),
     };
 
-    let db = AndaDB::connect(object_store.clone(), db_config).await?;
+    let db = Arc::new(AndaDB::connect(object_store.clone(), db_config).await?);
 
-    let nexus = NexusNode::connect(Arc::new(db)).await?;
+    let default_opts = NexusOptions::default();
+    let opts = NexusOptions {
+        eager_load: !cfg.lazy_load_threads.unwrap_or(false),
+        load_concurrency: cfg
+            .thread_load_concurrency
+            .unwrap_or(default_opts.load_concurrency),
+    };
+    let mut nexus = NexusNode::connect_with(db.clone(), opts)
+        .await?
+        .with_webhooks(
+            Arc::new(Web3SDK::from_web3(web3.clone())),
+            HttpPolicy::new(),
+        );
+    if let Some(max) = cfg.max_threads_per_user {
+        nexus = nexus.with_max_threads_per_user(max);
+    }
+    if cfg.default_page_limit.is_some() || cfg.max_page_limit.is_some() {
+        nexus = nexus.with_page_limits(
+            cfg.default_page_limit.unwrap_or(DEFAULT_PAGE_LIMIT),
+            cfg.max_page_limit.unwrap_or(MAX_PAGE_LIMIT),
+        );
+    }
+    if cfg.default_max_participants.is_some() || cfg.max_participants.is_some() {
+        nexus = nexus.with_participant_limits(
+            cfg.default_max_participants
+                .unwrap_or(DEFAULT_MAX_PARTICIPANTS),
+            cfg.max_participants.unwrap_or(MAX_PARTICIPANTS),
+        );
+    }
+    if cfg.message_flush_max_pending.is_some() || cfg.message_flush_max_delay_ms.is_some() {
+        let default_policy = FlushPolicy::default();
+        nexus = nexus.with_flush_policy(FlushPolicy {
+            max_pending: cfg
+                .message_flush_max_pending
+                .unwrap_or(default_policy.max_pending),
+            max_delay_ms: cfg
+                .message_flush_max_delay_ms
+                .unwrap_or(default_policy.max_delay_ms),
+        });
+    }
     let nexus = Arc::new(nexus);
-    let tools = NexusNode::tools(nexus)?;
+    let flush_ticker = if cfg.message_flush_max_pending.unwrap_or(1) > 1 {
+        Some(
+            nexus
+                .clone()
+                .spawn_flush_ticker(global_cancel_token.clone()),
+        )
+    } else {
+        None
+    };
+    let mut tools = NexusNode::tools(nexus.clone())?;
+    if let Some(model) = &cfg.model {
+        let model = Model::from_config(model).map_err(|err| format!("invalid model: {err}"))?;
+        tools.add(SummarizeThreadTool::new(nexus.clone(), model))?;
+    }
     let tools_name = tools.names();
     let info = AgentInfo {
         handle: "icp_ledger_agent".to_string(),
@@ -151,9 +208,20 @@ async fn main() -> Result<(), BoxError> {
         .with_app_version(APP_VERSION.to_string())
         .with_addr(format!("127.0.0.1:{}", cli.port))
         .with_engines(engines, None)
-        .serve(shutdown_signal(global_cancel_token))
+        .with_database_status(move || {
+            vec![DatabaseStatus {
+                name: db.name().to_string(),
+                collections: db.metadata().collections.len() as u64,
+            }]
+        })
+        .serve(shutdown_signal(global_cancel_token.clone()))
         .await?;
 
+    global_cancel_token.cancel();
+    if let Some(ticker) = flush_ticker {
+        let _ = ticker.await;
+    }
+    nexus.flush_messages().await?;
     Ok(())
 }
 
@@ -1,14 +1,38 @@
 use anda_core::BoxError;
+use anda_engine::model::ModelConfig;
 use config::{Config, File, FileFormat};
+use object_store::aws::AmazonS3ConfigKey;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
+use crate::{DEFAULT_MAX_PARTICIPANTS, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT, MAX_PARTICIPANTS};
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Conf {
     pub id_secret: String,
     pub root_secret: String,
     pub object_store: String,
     pub object_store_config: Option<BTreeMap<String, String>>,
+    /// The maximum number of threads a user can participate in, unlimited if not set.
+    pub max_threads_per_user: Option<usize>,
+    /// Loads thread states on first access instead of at startup if true.
+    pub lazy_load_threads: Option<bool>,
+    /// The maximum number of thread states loaded concurrently at startup, default to 16.
+    pub thread_load_concurrency: Option<usize>,
+    /// The default number of items returned by paginated calls, default to 100.
+    pub default_page_limit: Option<usize>,
+    /// The maximum number of items returned by paginated calls, default to 1000.
+    pub max_page_limit: Option<usize>,
+    /// The default maximum number of participants of a new thread, default to 100.
+    pub default_max_participants: Option<u64>,
+    /// The upper limit of the maximum number of participants of a thread, default to 1000.
+    pub max_participants: Option<u64>,
+    /// The maximum number of unflushed messages per thread, default to 1 (flush on every message).
+    pub message_flush_max_pending: Option<usize>,
+    /// The maximum age in milliseconds of the oldest unflushed message, default to 0.
+    pub message_flush_max_delay_ms: Option<u64>,
+    /// The model of the thread summarizing tool, the tool is not registered if not set.
+    pub model: Option<ModelConfig>,
 }
 
 impl Conf {
@@ -22,4 +46,114 @@ impl Conf {
         let cfg: Self = toml::from_str(content)?;
         Ok(cfg)
     }
+
+    /// Validates the secrets, the object store config and the limits,
+    /// returns an error naming the first invalid field.
+    pub fn validate(&self) -> Result<(), BoxError> {
+        self.validate_id_secret()?;
+        self.root_secret()?;
+        self.validate_object_store()?;
+
+        let to_u64 = |v: Option<usize>| v.map(|v| v as u64);
+        for (field, value) in [
+            ("max_threads_per_user", to_u64(self.max_threads_per_user)),
+            (
+                "thread_load_concurrency",
+                to_u64(self.thread_load_concurrency),
+            ),
+            ("default_page_limit", to_u64(self.default_page_limit)),
+            ("max_page_limit", to_u64(self.max_page_limit)),
+            ("default_max_participants", self.default_max_participants),
+            ("max_participants", self.max_participants),
+            (
+                "message_flush_max_pending",
+                to_u64(self.message_flush_max_pending),
+            ),
+        ] {
+            if value == Some(0) {
+                return Err(format!("{field}: must be greater than 0").into());
+            }
+        }
+
+        let default_page_limit = self.default_page_limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+        let max_page_limit = self.max_page_limit.unwrap_or(MAX_PAGE_LIMIT);
+        if default_page_limit > max_page_limit {
+            return Err(format!(
+                "default_page_limit: {default_page_limit} exceeds max_page_limit {max_page_limit}"
+            )
+            .into());
+        }
+        let default_max_participants = self
+            .default_max_participants
+            .unwrap_or(DEFAULT_MAX_PARTICIPANTS);
+        let max_participants = self.max_participants.unwrap_or(MAX_PARTICIPANTS);
+        if default_max_participants > max_participants {
+            return Err(format!(
+                "default_max_participants: {default_max_participants} exceeds max_participants {max_participants}"
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the 48-byte hex-encoded root secret.
+    pub fn root_secret(&self) -> Result<[u8; 48], BoxError> {
+        if self.root_secret.is_empty() {
+            return Err("root_secret: is required".into());
+        }
+        let secret = hex::decode(&self.root_secret)
+            .map_err(|err| format!("root_secret: invalid hex: {err}"))?;
+        secret
+            .try_into()
+            .map_err(|v: Vec<u8>| format!("root_secret: expected 48 bytes, got {}", v.len()).into())
+    }
+
+    /// `id_secret` is "Anonymous", a PEM file path or a 32-byte hex-encoded secret.
+    fn validate_id_secret(&self) -> Result<(), BoxError> {
+        if self.id_secret.is_empty() {
+            return Err("id_secret: is required".into());
+        }
+        if self.id_secret == "Anonymous" || std::path::Path::new(&self.id_secret).is_file() {
+            return Ok(());
+        }
+        let hex_secret = self.id_secret.strip_prefix("0x").unwrap_or(&self.id_secret);
+        let secret = hex::decode(hex_secret).map_err(|err| {
+            format!("id_secret: neither a PEM file nor a hex-encoded secret: {err}")
+        })?;
+        if secret.len() != 32 {
+            return Err(format!("id_secret: expected 32 bytes, got {}", secret.len()).into());
+        }
+        Ok(())
+    }
+
+    fn validate_object_store(&self) -> Result<(), BoxError> {
+        let cfg = self.object_store_config.as_ref();
+        match self.object_store.as_str() {
+            "s3" => {
+                let cfg = cfg
+                    .filter(|cfg| !cfg.is_empty())
+                    .ok_or("object_store_config: is required for the s3 object store")?;
+                for key in cfg.keys() {
+                    key.to_ascii_lowercase()
+                        .parse::<AmazonS3ConfigKey>()
+                        .map_err(|_| {
+                            format!("object_store_config: unknown s3 config key {key:?}")
+                        })?;
+                }
+            }
+            ty => {
+                if cfg.is_some_and(|cfg| !cfg.is_empty()) {
+                    let ty = match ty {
+                        "" | "memory" | "in_memory" => "in-memory",
+                        _ => "local file system",
+                    };
+                    return Err(
+                        format!("object_store_config: not used by the {ty} object store").into(),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
 }
@@ -1,37 +1,339 @@
 use anda_core::{
-    BoxError, FunctionDefinition, Json, Resource, ResourceRef, StateFeatures, Tool, ToolOutput,
-    ToolSet, Xid, gen_schema_for, update_resources,
+    BoxError, CONTENT_TYPE_JSON, CompletionRequest, FunctionDefinition, Json, Resource,
+    ResourceRef, StateFeatures, Tool, ToolOutput, ToolSet, Xid, gen_schema_for, update_resources,
 };
 use anda_db::{
-    collection::{Collection, CollectionConfig},
+    collection::{Collection, CollectionConfig, CollectionMetadata},
     database::AndaDB,
     error::DBError,
-    index::BTree,
-    query::{Filter, Query, RangeQuery},
+    index::{BM25, BTree, DefaultIndexHooks, Hnsw, IndexHooks, from_virtual_field_name},
+    query::{Filter, Query, RangeQuery, Search},
 };
-use anda_db_schema::Fv;
+use anda_db_schema::{ByteArrayB64, ByteBufB64, Document, Fv, Vector};
 use anda_db_tfs::jieba_tokenizer;
-use anda_engine::{ANONYMOUS, context::BaseCtx, unix_ms};
+use anda_engine::{
+    context::{BaseCtx, HttpPolicy, Web3SDK},
+    model::Model,
+    unix_ms,
+};
 
 use anda_kip::Response;
 use candid::Principal;
 use futures::stream::{self, StreamExt};
-use parking_lot::RwLock;
-use schemars::{JsonSchema, Schema, SchemaGenerator};
+use ic_auth_verifier::sha3_256;
+use parking_lot::{Mutex, RwLock};
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::{
+    borrow::Cow,
     collections::{BTreeMap, BTreeSet},
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
 };
+use tokio::sync::Mutex as AsyncMutex;
+use tokio_util::sync::CancellationToken;
+use url::Url;
 
 use crate::types::*;
 
+/// Maximum size of a resource uploaded in chunks, 128 MB.
+pub const MAX_RESOURCE_UPLOAD_SIZE: usize = 128 * 1024 * 1024;
+
+/// Unfinished uploads are discarded after 1 hour.
+const RESOURCE_UPLOAD_TTL_MS: u64 = 3600 * 1000;
+
+/// Default maximum number of unfinished uploads per user.
+pub const DEFAULT_MAX_UPLOADS_PER_USER: usize = 4;
+
+/// Default maximum total size of the data buffered by unfinished uploads, 512 MB.
+pub const DEFAULT_MAX_UPLOAD_BYTES: usize = 512 * 1024 * 1024;
+
+/// Maximum number of webhook subscriptions per thread.
+pub const MAX_THREAD_SUBSCRIPTIONS: usize = 100;
+
+/// Thread names treated as untitled by [`MessageTool::with_auto_title`].
+pub const DEFAULT_UNTITLED_NAMES: &[&str] = &["New chat", "New thread", "Untitled"];
+
+/// Maximum length in bytes of a thread name.
+const MAX_THREAD_NAME_LEN: usize = 128;
+
+/// Maximum length in bytes of a message idempotency key.
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 128;
+
+/// Maximum number of attempts to deliver a webhook notification.
+const WEBHOOK_MAX_ATTEMPTS: u32 = 5;
+
+/// Default number of items returned by a paginated call.
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+
+/// Maximum number of items returned by a paginated call.
+pub const MAX_PAGE_LIMIT: usize = 1000;
+
+/// Default number of recent messages summarized by [`SummarizeThreadTool`].
+pub const DEFAULT_SUMMARIZE_MESSAGES: usize = 50;
+
+/// Maximum number of recent messages summarized by [`SummarizeThreadTool`].
+pub const MAX_SUMMARIZE_MESSAGES: usize = 200;
+
+/// Default maximum number of participants of a new thread.
+pub const DEFAULT_MAX_PARTICIPANTS: u64 = 100;
+
+/// Upper limit of the maximum number of participants a thread manager can set.
+pub const MAX_PARTICIPANTS: u64 = 1000;
+
+/// Policy of flushing thread message collections after messages are added.
+///
+/// A collection is flushed once `max_pending` messages are unflushed, or once the oldest unflushed
+/// message is older than `max_delay_ms`. The age is checked when a message is added and by
+/// [`NexusNode::flush_expired`], which runs periodically in [`NexusNode::spawn_flush_ticker`].
+/// Pending messages are also flushed before reading messages of the thread and by
+/// [`NexusNode::flush_messages`].
+#[derive(Debug, Clone, Copy)]
+pub struct FlushPolicy {
+    /// Maximum number of unflushed messages per thread, 1 flushes on every message.
+    pub max_pending: usize,
+    /// Maximum age in milliseconds of the oldest unflushed message.
+    pub max_delay_ms: u64,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        Self {
+            max_pending: 1,
+            max_delay_ms: 0,
+        }
+    }
+}
+
+/// Options for connecting a [`NexusNode`].
+#[derive(Debug, Clone)]
+pub struct NexusOptions {
+    /// Loads all thread states when connecting if true, otherwise thread states are
+    /// loaded on first access and [`NexusNode::public_threads`] only lists loaded threads.
+    pub eager_load: bool,
+    /// Maximum number of thread states loaded concurrently when connecting.
+    pub load_concurrency: usize,
+}
+
+impl Default for NexusOptions {
+    fn default() -> Self {
+        Self {
+            eager_load: true,
+            load_concurrency: 16,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct NexusNode {
     db: Arc<AndaDB>,
-    threads: Arc<Collection>,
+    // replaced when the indexes of the threads collection are rebuilt
+    threads: RwLock<Arc<Collection>>,
     thread_states: RwLock<BTreeMap<u64, Arc<RwLock<ThreadState>>>>,
+    uploads: RwLock<ResourceUploads>,
+    max_uploads_per_user: usize,
+    max_upload_bytes: usize,
+    subscriptions_collection: Arc<Collection>,
+    // thread id -> user -> subscription
+    subscriptions: RwLock<BTreeMap<u64, BTreeMap<Principal, Subscription>>>,
+    webhooks: Option<Webhooks>,
+    max_threads_per_user: Option<usize>,
+    eager_load: bool,
+    default_page_limit: usize,
+    max_page_limit: usize,
+    default_max_participants: u64,
+    max_participants: u64,
+    flush_policy: FlushPolicy,
+    // thread id -> (number of unflushed messages, timestamp of the oldest one)
+    pending_flushes: RwLock<BTreeMap<u64, (usize, u64)>>,
+    // (thread id, user, idempotency key) -> lock held across the lookup and the insert
+    idempotency_locks: Mutex<BTreeMap<IdempotencyLockKey, Arc<AsyncMutex<()>>>>,
+    // thread id -> lock held across the read-modify-write of the thread's participants
+    participants_locks: ThreadLocks,
+}
+
+/// (thread id, user, idempotency key)
+type IdempotencyLockKey = (u64, Principal, String);
+
+type ThreadLocks = Mutex<BTreeMap<u64, Arc<AsyncMutex<()>>>>;
+
+/// Holds the participants lock of a thread, the lock is removed from the map when
+/// the last guard of the thread is dropped.
+struct ThreadLockGuard<'a> {
+    locks: &'a ThreadLocks,
+    thread_id: u64,
+    guard: Option<tokio::sync::OwnedMutexGuard<()>>,
+}
+
+impl<'a> ThreadLockGuard<'a> {
+    async fn lock(locks: &'a ThreadLocks, thread_id: u64) -> Self {
+        let lock = locks.lock().entry(thread_id).or_default().clone();
+        Self {
+            locks,
+            thread_id,
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+}
+
+impl Drop for ThreadLockGuard<'_> {
+    fn drop(&mut self) {
+        self.guard.take();
+        let mut locks = self.locks.lock();
+        if locks
+            .get(&self.thread_id)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&self.thread_id);
+        }
+    }
+}
+
+/// Index hooks of the collections created by [`NexusNode::rebuild_indexes`]. They skip
+/// indexing the placeholder document that [`copy_documents`] adds to take the ID of a removed
+/// document, so that it can not collide with the unique keys of the copied documents.
+#[derive(Default)]
+struct PlaceholderHooks {
+    placeholder_id: AtomicU64,
+}
+
+impl PlaceholderHooks {
+    fn is_placeholder(&self, doc: &Document) -> bool {
+        doc.id() == self.placeholder_id.load(Ordering::Acquire)
+    }
+}
+
+impl IndexHooks for PlaceholderHooks {
+    fn btree_index_value<'a>(&self, index: &BTree, doc: &'a Document) -> Option<Cow<'a, Fv>> {
+        if self.is_placeholder(doc) {
+            return None;
+        }
+        DefaultIndexHooks.btree_index_value(index, doc)
+    }
+
+    fn bm25_index_value<'a>(&self, index: &BM25, doc: &'a Document) -> Option<Cow<'a, str>> {
+        if self.is_placeholder(doc) {
+            return None;
+        }
+        DefaultIndexHooks.bm25_index_value(index, doc)
+    }
+
+    fn hnsw_index_value<'a>(&self, index: &Hnsw, doc: &'a Document) -> Option<Cow<'a, Vector>> {
+        if self.is_placeholder(doc) {
+            return None;
+        }
+        DefaultIndexHooks.hnsw_index_value(index, doc)
+    }
+}
+
+/// Copies the documents of a collection to an empty one, keeping their IDs.
+/// Returns the number of copied documents.
+async fn copy_documents(
+    source: &Collection,
+    target: &Collection,
+    hooks: &PlaceholderHooks,
+) -> Result<usize, BoxError> {
+    // IDs are assigned sequentially, so the IDs of removed documents are taken by a
+    // placeholder document that is not indexed and is removed right away
+    let placeholder = match source.ids().first() {
+        Some(first) => source.get(*first).await?,
+        None => return Ok(0),
+    };
+    let mut count = 0;
+    for id in 1..=source.max_document_id() {
+        let doc = match source.get(id).await {
+            Ok(doc) => Some(doc),
+            Err(DBError::NotFound { .. }) => None,
+            Err(err) => return Err(err.into()),
+        };
+        let is_placeholder = doc.is_none();
+        if is_placeholder {
+            hooks.placeholder_id.store(id, Ordering::Release);
+        }
+
+        let rt = target.add(doc.unwrap_or_else(|| placeholder.clone())).await;
+        let rt = match rt {
+            Ok(new_id) if new_id != id => Err(format!(
+                "Document {} of collection {} was copied as {}",
+                id,
+                source.name(),
+                new_id
+            )
+            .into()),
+            Ok(new_id) if is_placeholder => target
+                .remove(new_id)
+                .await
+                .map(|_| ())
+                .map_err(BoxError::from),
+            Ok(_) => {
+                count += 1;
+                Ok(())
+            }
+            Err(err) => Err(err.into()),
+        };
+        if is_placeholder {
+            hooks.placeholder_id.store(0, Ordering::Release);
+        }
+        rt?;
+    }
+    target.flush(unix_ms()).await?;
+    Ok(count)
+}
+
+/// Signs and sends webhook notifications.
+#[derive(Clone)]
+struct Webhooks {
+    signer: Arc<Web3SDK>,
+    policy: Arc<HttpPolicy>,
+}
+
+impl std::fmt::Debug for Webhooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Webhooks")
+            .field("signer", &self.signer.get_principal().to_text())
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+/// A resource being uploaded in chunks.
+#[derive(Debug)]
+struct ResourceUpload {
+    user: Principal,
+    thread_id: u64,
+    resource: Resource,
+    data: Vec<u8>,
+    updated_at: u64,
+}
+
+/// Unfinished uploads with the total size of their buffered data.
+#[derive(Debug, Default)]
+struct ResourceUploads {
+    uploads: BTreeMap<Xid, ResourceUpload>,
+    total_bytes: usize,
+}
+
+impl ResourceUploads {
+    fn remove(&mut self, upload_id: &Xid) -> Option<ResourceUpload> {
+        let upload = self.uploads.remove(upload_id)?;
+        self.total_bytes -= upload.data.len();
+        Some(upload)
+    }
+
+    fn remove_expired(&mut self, now_ms: u64) {
+        let total_bytes = &mut self.total_bytes;
+        self.uploads.retain(|_, u| {
+            let keep = u.updated_at + RESOURCE_UPLOAD_TTL_MS > now_ms;
+            if !keep {
+                *total_bytes -= u.data.len();
+            }
+            keep
+        });
+    }
 }
 
 impl NexusNode {
@@ -43,15 +345,27 @@ impl NexusNode {
         format!("{}_resources", id)
     }
 
+    fn threads(&self) -> Arc<Collection> {
+        self.threads.read().clone()
+    }
+
     pub fn tools(nexus: Arc<NexusNode>) -> Result<ToolSet<BaseCtx>, BoxError> {
         let mut tools = ToolSet::new();
         tools.add(ThreadTool::new(nexus.clone()))?;
         tools.add(MessageTool::new(nexus.clone()))?;
         tools.add(GetResourceTool::new(nexus.clone()))?;
+        tools.add(SearchResourcesTool::new(nexus.clone()))?;
+        tools.add(GetResourceByHashTool::new(nexus.clone()))?;
+        tools.add(ListMyThreadsTool::new(nexus.clone()))?;
+        tools.add(ResourceUploadTool::new(nexus.clone()))?;
         Ok(tools)
     }
 
     pub async fn connect(db: Arc<AndaDB>) -> Result<Self, BoxError> {
+        Self::connect_with(db, NexusOptions::default()).await
+    }
+
+    pub async fn connect_with(db: Arc<AndaDB>, opts: NexusOptions) -> Result<Self, BoxError> {
         let schema = Thread::schema()?;
         let threads = db
             .open_or_create_collection(
@@ -75,7 +389,11 @@ impl NexusNode {
             )
             .await?;
 
-        let thread_ids = threads.ids();
+        let thread_ids = if opts.eager_load {
+            threads.ids()
+        } else {
+            Vec::new()
+        };
 
         let rt = stream::iter(thread_ids.into_iter())
             .map(|id| {
@@ -91,10 +409,39 @@ impl NexusNode {
                     Some(Ok((thread._id, thread.to_state())))
                 }
             })
-            .buffer_unordered(16)
+            .buffer_unordered(opts.load_concurrency.max(1))
             .collect::<Vec<Option<Result<_, DBError>>>>()
             .await;
 
+        let schema = Subscription::schema()?;
+        let subscriptions_collection = db
+            .open_or_create_collection(
+                schema,
+                CollectionConfig {
+                    name: "subscriptions".to_string(),
+                    description: "thread webhook subscriptions collection".to_string(),
+                },
+                async |collection| {
+                    collection.create_btree_index_nx(&["thread_id"]).await?;
+
+                    Ok::<(), DBError>(())
+                },
+            )
+            .await?;
+        let mut subscriptions: BTreeMap<u64, BTreeMap<Principal, Subscription>> = BTreeMap::new();
+        for id in subscriptions_collection.ids() {
+            match subscriptions_collection.get_as::<Subscription>(id).await {
+                Ok(sub) => {
+                    subscriptions
+                        .entry(sub.thread_id)
+                        .or_default()
+                        .insert(sub.user, sub);
+                }
+                Err(DBError::NotFound { .. }) => {}
+                Err(err) => log::error!("Failed to load subscription {}: {}", id, err),
+            }
+        }
+
         let mut thread_states: BTreeMap<u64, Arc<RwLock<ThreadState>>> = BTreeMap::new();
         for r in rt.into_iter().flatten() {
             match r {
@@ -110,11 +457,393 @@ impl NexusNode {
 
         Ok(Self {
             db,
-            threads,
+            threads: RwLock::new(threads),
             thread_states: RwLock::new(thread_states),
+            uploads: RwLock::new(ResourceUploads::default()),
+            max_uploads_per_user: DEFAULT_MAX_UPLOADS_PER_USER,
+            max_upload_bytes: DEFAULT_MAX_UPLOAD_BYTES,
+            subscriptions_collection,
+            subscriptions: RwLock::new(subscriptions),
+            webhooks: None,
+            max_threads_per_user: None,
+            eager_load: opts.eager_load,
+            default_page_limit: DEFAULT_PAGE_LIMIT,
+            max_page_limit: MAX_PAGE_LIMIT,
+            default_max_participants: DEFAULT_MAX_PARTICIPANTS,
+            max_participants: MAX_PARTICIPANTS,
+            flush_policy: FlushPolicy::default(),
+            pending_flushes: RwLock::new(BTreeMap::new()),
+            idempotency_locks: Mutex::new(BTreeMap::new()),
+            participants_locks: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Sets the maximum number of threads a user can participate in when creating a new thread.
+    pub fn with_max_threads_per_user(mut self, max: usize) -> Self {
+        self.max_threads_per_user = Some(max);
+        self
+    }
+
+    /// Sets the default and maximum number of items returned by paginated calls,
+    /// default to [`DEFAULT_PAGE_LIMIT`] and [`MAX_PAGE_LIMIT`].
+    pub fn with_page_limits(mut self, default_limit: usize, max_limit: usize) -> Self {
+        self.max_page_limit = max_limit.max(1);
+        self.default_page_limit = default_limit.clamp(1, self.max_page_limit);
+        self
+    }
+
+    /// Sets the default maximum number of participants of a new thread and the upper limit
+    /// a thread manager can set, default to [`DEFAULT_MAX_PARTICIPANTS`] and [`MAX_PARTICIPANTS`].
+    pub fn with_participant_limits(mut self, default_max: u64, max: u64) -> Self {
+        self.max_participants = max.max(1);
+        self.default_max_participants = default_max.clamp(1, self.max_participants);
+        self
+    }
+
+    /// Enables webhook subscriptions, notifications are signed by the signer's identity.
+    /// Webhook URLs are checked against the policy when subscribing and when delivering,
+    /// loopback, private and other non-public networks are always denied.
+    pub fn with_webhooks(mut self, signer: Arc<Web3SDK>, policy: HttpPolicy) -> Self {
+        self.webhooks = Some(Webhooks {
+            signer,
+            policy: Arc::new(policy.with_deny_private_networks()),
+        });
+        self
+    }
+
+    /// Sets the maximum number of unfinished uploads per user and the maximum total size of
+    /// the data they buffer in memory, default to [`DEFAULT_MAX_UPLOADS_PER_USER`] and
+    /// [`DEFAULT_MAX_UPLOAD_BYTES`].
+    pub fn with_upload_limits(mut self, max_uploads_per_user: usize, max_bytes: usize) -> Self {
+        self.max_uploads_per_user = max_uploads_per_user.max(1);
+        self.max_upload_bytes = max_bytes;
+        self
+    }
+
+    /// Sets the policy of flushing thread message collections, default to flushing on every message.
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush_policy = FlushPolicy {
+            max_pending: policy.max_pending.max(1),
+            max_delay_ms: policy.max_delay_ms,
+        };
+        self
+    }
+
+    /// Flushes the unflushed messages of all threads, e.g. before shutting down.
+    pub async fn flush_messages(&self) -> Result<(), BoxError> {
+        let thread_ids: Vec<u64> = self.pending_flushes.read().keys().cloned().collect();
+        for thread_id in thread_ids {
+            let collection = self.get_message_collection(thread_id).await?;
+            self.flush_pending(thread_id, &collection).await?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the threads whose oldest unflushed message is at least `max_delay_ms` old.
+    /// Returns the number of flushed threads, a thread that fails to flush is kept pending.
+    pub async fn flush_expired(&self, now_ms: u64) -> usize {
+        let thread_ids: Vec<u64> = self
+            .pending_flushes
+            .read()
+            .iter()
+            .filter(|(_, (_, oldest))| {
+                now_ms.saturating_sub(*oldest) >= self.flush_policy.max_delay_ms
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        let mut flushed = 0;
+        for thread_id in thread_ids {
+            let rt = match self.get_message_collection(thread_id).await {
+                Ok(collection) => self.flush_collection(thread_id, &collection, now_ms).await,
+                Err(err) => Err(err),
+            };
+            match rt {
+                Ok(_) => flushed += 1,
+                Err(err) => {
+                    log::warn!("Failed to flush messages of thread {}: {}", thread_id, err);
+                }
+            }
+        }
+        flushed
+    }
+
+    /// Spawns a task that calls [`NexusNode::flush_expired`] every half `max_delay_ms`,
+    /// at least every 100 ms, until the token is cancelled. Without it a thread that stops
+    /// receiving messages keeps its unflushed messages until it is read or flushed.
+    pub fn spawn_flush_ticker(
+        self: Arc<Self>,
+        cancel_token: CancellationToken,
+    ) -> tokio::task::JoinHandle<()> {
+        let period = Duration::from_millis((self.flush_policy.max_delay_ms / 2).max(100));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = cancel_token.cancelled() => return,
+                    _ = interval.tick() => {
+                        self.flush_expired(unix_ms()).await;
+                    }
+                }
+            }
         })
     }
 
+    /// Rebuilds the BTree and BM25 indexes of a collection from scratch over its existing
+    /// documents, e.g. after an upgrade that changed tokenization or if an index is corrupted.
+    /// Returns the number of reindexed documents.
+    ///
+    /// `collection_name` is "threads", "{thread_id}_messages" or "{thread_id}_resources".
+    /// AndaDB can not drop the indexes of an open collection, so the documents are copied to
+    /// a backup collection "{collection_name}_backup", then the collection is recreated with
+    /// empty indexes and the documents are copied back with their IDs. The collection is
+    /// read-only during the rebuild, so writes to it fail. If copying the documents back fails,
+    /// the collection is recreated from the backup once more, and the backup is kept only if that
+    /// fails too.
+    pub async fn rebuild_indexes(&self, collection_name: &str) -> Result<usize, BoxError> {
+        let is_threads = collection_name == "threads";
+        let is_thread_collection = collection_name.split_once('_').is_some_and(|(id, kind)| {
+            id.parse::<u64>().is_ok() && (kind == "messages" || kind == "resources")
+        });
+        if !is_threads && !is_thread_collection {
+            return Err(format!("Collection {} can not be rebuilt", collection_name).into());
+        }
+        let backup_name = format!("{}_backup", collection_name);
+        if self.db.metadata().collections.contains(&backup_name) {
+            return Err(format!(
+                "Backup collection {} exists, restore or delete it before rebuilding",
+                backup_name
+            )
+            .into());
+        }
+
+        let source = if is_threads {
+            self.threads()
+        } else {
+            self.db
+                .open_collection(collection_name.to_string(), async |collection| {
+                    collection.set_tokenizer(jieba_tokenizer());
+
+                    Ok::<(), DBError>(())
+                })
+                .await?
+        };
+        if source.is_empty() {
+            return Ok(0);
+        }
+
+        source.set_read_only(true);
+        let metadata = source.metadata();
+        let hooks = Arc::new(PlaceholderHooks::default());
+        let backup = match self
+            .create_collection_like(&metadata, &backup_name, hooks.clone())
+            .await
+        {
+            Ok(backup) => backup,
+            Err(err) => {
+                source.set_read_only(false);
+                return Err(err);
+            }
+        };
+        if let Err(err) = copy_documents(&source, &backup, &hooks).await {
+            source.set_read_only(false);
+            self.db.delete_collection(&backup_name).await?;
+            return Err(err);
+        }
+
+        self.db.delete_collection(collection_name).await?;
+        let count = match self
+            .restore_collection(&metadata, &backup, collection_name, is_threads)
+            .await
+        {
+            Ok(count) => count,
+            Err(err) => {
+                log::error!(
+                    "Failed to rebuild collection {}, restoring it from {}: {}",
+                    collection_name,
+                    backup_name,
+                    err
+                );
+                // the partial collection is replaced, the backup is kept if that fails too
+                self.restore_collection(&metadata, &backup, collection_name, is_threads)
+                    .await
+                    .map_err(|err| {
+                        format!(
+                            "Failed to restore collection {} from {}: {}",
+                            collection_name, backup_name, err
+                        )
+                    })?;
+                self.db.delete_collection(&backup_name).await?;
+                return Err(err);
+            }
+        };
+        self.db.delete_collection(&backup_name).await?;
+        Ok(count)
+    }
+
+    /// Recreates a collection with empty indexes from its backup, replacing a partially copied
+    /// one, and swaps the cached "threads" collection to it.
+    async fn restore_collection(
+        &self,
+        metadata: &CollectionMetadata,
+        backup: &Collection,
+        collection_name: &str,
+        is_threads: bool,
+    ) -> Result<usize, BoxError> {
+        if self.db.metadata().collections.contains(collection_name) {
+            self.db.delete_collection(collection_name).await?;
+        }
+        let hooks = Arc::new(PlaceholderHooks::default());
+        let target = self
+            .create_collection_like(metadata, collection_name, hooks.clone())
+            .await?;
+        if is_threads {
+            *self.threads.write() = target.clone();
+        }
+        copy_documents(backup, &target, &hooks).await
+    }
+
+    /// Creates an empty collection with the schema, tokenizer and indexes of another one.
+    async fn create_collection_like(
+        &self,
+        metadata: &CollectionMetadata,
+        name: &str,
+        hooks: Arc<PlaceholderHooks>,
+    ) -> Result<Arc<Collection>, BoxError> {
+        let btree_indexes: Vec<Vec<String>> = metadata
+            .btree_indexes
+            .keys()
+            .map(|name| from_virtual_field_name(name))
+            .collect();
+        let bm25_indexes: Vec<Vec<String>> = metadata
+            .bm25_indexes
+            .keys()
+            .map(|name| from_virtual_field_name(name))
+            .collect();
+        let collection = self
+            .db
+            .create_collection(
+                metadata.schema.clone(),
+                CollectionConfig {
+                    name: name.to_string(),
+                    description: metadata.config.description.clone(),
+                },
+                async |collection| {
+                    collection.set_tokenizer(jieba_tokenizer());
+                    collection.set_index_hooks(hooks);
+                    for fields in &btree_indexes {
+                        let fields: Vec<&str> = fields.iter().map(|f| f.as_str()).collect();
+                        collection.create_btree_index(&fields).await?;
+                    }
+                    for fields in &bm25_indexes {
+                        let fields: Vec<&str> = fields.iter().map(|f| f.as_str()).collect();
+                        collection.create_bm25_index(&fields).await?;
+                    }
+
+                    Ok::<(), DBError>(())
+                },
+            )
+            .await?;
+        Ok(collection)
+    }
+
+    /// Returns the storage bytes a thread consumes, e.g. for quota enforcement and billing.
+    ///
+    /// It is the sum of the CBOR-encoded sizes of the documents in the thread's message and
+    /// resource collections, including the resource blobs stored in them. Index overhead and
+    /// unfinished uploads are not counted.
+    ///
+    /// It reads and encodes every document of the thread, so the cost grows with the size of
+    /// the thread. Callers enforcing quotas should cache the result instead of computing it on
+    /// every request.
+    pub async fn thread_storage_bytes(&self, thread_id: u64) -> Result<u64, BoxError> {
+        self.check_thread_state(thread_id).await?;
+        let collections = [
+            self.get_message_collection(thread_id).await?,
+            self.get_resource_collection(thread_id).await?,
+        ];
+
+        let mut total = 0u64;
+        let mut buf: Vec<u8> = Vec::new();
+        for collection in collections {
+            for id in collection.ids() {
+                let doc = match collection.get(id).await {
+                    Ok(doc) => doc,
+                    Err(DBError::NotFound { .. }) => continue,
+                    Err(err) => return Err(err.into()),
+                };
+                buf.clear();
+                ciborium::into_writer(&doc, &mut buf)
+                    .map_err(|err| format!("failed to encode document {id}: {err}"))?;
+                total += buf.len() as u64;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Records a message added to the thread and flushes the collection if the policy is met.
+    async fn flush_added(
+        &self,
+        thread_id: u64,
+        collection: &Collection,
+        timestamp: u64,
+    ) -> Result<(), BoxError> {
+        let flush = {
+            let mut pending = self.pending_flushes.write();
+            let entry = pending.entry(thread_id).or_insert((0, timestamp));
+            entry.0 += 1;
+            entry.0 >= self.flush_policy.max_pending
+                || timestamp.saturating_sub(entry.1) >= self.flush_policy.max_delay_ms
+        };
+        if flush {
+            self.flush_collection(thread_id, collection, timestamp)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the collection if the thread has unflushed messages.
+    async fn flush_pending(&self, thread_id: u64, collection: &Collection) -> Result<(), BoxError> {
+        if self.pending_flushes.read().contains_key(&thread_id) {
+            self.flush_collection(thread_id, collection, unix_ms())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the collection and clears the pending messages of the thread only if the flush
+    /// succeeds. Messages added during the flush are kept pending.
+    async fn flush_collection(
+        &self,
+        thread_id: u64,
+        collection: &Collection,
+        timestamp: u64,
+    ) -> Result<(), BoxError> {
+        let count = self
+            .pending_flushes
+            .read()
+            .get(&thread_id)
+            .map(|(count, _)| *count)
+            .unwrap_or(0);
+        collection.flush(timestamp).await?;
+        let mut pending = self.pending_flushes.write();
+        if let Some(entry) = pending.get_mut(&thread_id) {
+            if entry.0 <= count {
+                pending.remove(&thread_id);
+            } else {
+                entry.0 -= count;
+                entry.1 = timestamp;
+            }
+        }
+        Ok(())
+    }
+
+    fn page_limit(&self, limit: Option<usize>) -> usize {
+        limit
+            .unwrap_or(self.default_page_limit)
+            .min(self.max_page_limit)
+    }
+
     async fn get_message_collection(&self, thread_id: u64) -> Result<Arc<Collection>, BoxError> {
         let collection = self
             .db
@@ -172,7 +901,18 @@ impl NexusNode {
         owner: Principal,
         name: String,
         description: Option<String>,
+        max_participants: Option<u64>,
     ) -> Result<Thread, BoxError> {
+        if let Some(max) = self.max_threads_per_user
+            && self.my_thread_ids(&owner).await.len() >= max
+        {
+            return Err(format!(
+                "User {} has reached the maximum number of threads: {}",
+                owner, max
+            )
+            .into());
+        }
+
         let updated_at = unix_ms();
         let mut thread = Thread {
             _id: 0,
@@ -184,9 +924,12 @@ impl NexusNode {
             created_at: updated_at,
             updated_at,
             description,
+            max_participants: max_participants
+                .unwrap_or(self.default_max_participants)
+                .clamp(1, self.max_participants),
             ..Default::default()
         };
-        let id = self.threads.add_from(&thread).await.unwrap();
+        let id = self.threads().add_from(&thread).await.unwrap();
         thread._id = id;
 
         let schema = Message::schema()?;
@@ -201,6 +944,10 @@ impl NexusNode {
                 async |collection| {
                     collection.set_tokenizer(jieba_tokenizer());
                     collection.create_btree_index_nx(&["user"]).await?;
+                    collection
+                        .create_btree_index_nx(&["idempotency_key"])
+                        .await?;
+                    collection.create_btree_index_nx(&["reply_to"]).await?;
                     collection.create_bm25_index_nx(&["content"]).await?;
 
                     Ok::<(), DBError>(())
@@ -239,6 +986,9 @@ impl NexusNode {
 
     pub async fn fetch_my_threads_state(&self, user: &Principal) -> Vec<ThreadState> {
         let ids: Vec<u64> = self.my_thread_ids(user).await;
+        for id in ids.iter() {
+            self.load_thread_state(*id).await;
+        }
         let mut rt = Vec::with_capacity(ids.len());
         let states = self.thread_states.read();
         for id in ids {
@@ -252,7 +1002,39 @@ impl NexusNode {
         rt
     }
 
-    pub fn public_threads_state(&self, ids: BTreeSet<u64>) -> Vec<ThreadState> {
+    /// Returns the latest activity across the user's active threads as
+    /// `(thread_id, latest_message_by, latest_message_at)`, most recent first.
+    pub async fn my_activity_feed(
+        &self,
+        user: &Principal,
+        limit: Option<usize>,
+    ) -> Vec<(u64, Option<Principal>, u64)> {
+        let limit = self.page_limit(limit);
+        let ids: Vec<u64> = self.my_thread_ids(user).await;
+        for id in ids.iter() {
+            self.load_thread_state(*id).await;
+        }
+        let mut feed = Vec::with_capacity(ids.len());
+        {
+            let states = self.thread_states.read();
+            for id in ids {
+                if let Some(s) = states.get(&id) {
+                    let s = s.read();
+                    if s.status == ThreadStatus::Active {
+                        feed.push((id, s.latest_message_by, s.latest_message_at));
+                    }
+                }
+            }
+        }
+        feed.sort_by(|a, b| b.2.cmp(&a.2).then(b.0.cmp(&a.0))); // sort by latest_message_at desc
+        feed.truncate(limit);
+        feed
+    }
+
+    pub async fn public_threads_state(&self, ids: BTreeSet<u64>) -> Vec<ThreadState> {
+        for id in ids.iter() {
+            self.load_thread_state(*id).await;
+        }
         let mut rt = Vec::with_capacity(ids.len());
         let states = self.thread_states.read();
         for id in ids {
@@ -266,10 +1048,36 @@ impl NexusNode {
         rt
     }
 
+    /// Returns the states of the given active threads that the user can read,
+    /// i.e. the user is a participant or the thread is public, by thread ID.
+    pub async fn fetch_threads_state(
+        &self,
+        user: &Principal,
+        ids: BTreeSet<u64>,
+    ) -> BTreeMap<u64, ThreadState> {
+        let my_ids: BTreeSet<u64> = self.my_thread_ids(user).await.into_iter().collect();
+        for id in ids.iter() {
+            self.load_thread_state(*id).await;
+        }
+        let mut rt = BTreeMap::new();
+        let states = self.thread_states.read();
+        for id in ids {
+            if let Some(s) = states.get(&id) {
+                let s = s.read();
+                if s.status == ThreadStatus::Active
+                    && (s.visibility == ThreadVisibility::Public || my_ids.contains(&id))
+                {
+                    rt.insert(id, s.clone());
+                }
+            }
+        }
+        rt
+    }
+
     pub async fn get_thread(&self, user: &Principal, _id: u64) -> Result<Thread, BoxError> {
-        self.check_thread_state(_id)?;
+        self.check_thread_state(_id).await?;
 
-        let thread: Thread = self.threads.get_as(_id).await?;
+        let thread: Thread = self.threads().get_as(_id).await?;
         if thread.has_permission(user, ThreadPermission::Read) {
             Ok(thread)
         } else {
@@ -281,15 +1089,29 @@ impl NexusNode {
         }
     }
 
+    /// Lists the threads of the user by ID, optionally only the threads
+    /// updated at or after `updated_since` (in milliseconds) for incremental sync.
     pub async fn list_my_threads(
         &self,
         user: &Principal,
         cursor: Option<String>,
         limit: Option<usize>,
+        updated_since: Option<u64>,
     ) -> Result<(Vec<ThreadInfo>, Option<String>), BoxError> {
-        let limit = limit.unwrap_or(100).min(1000);
+        let limit = self.page_limit(limit);
         let cursor = (BTree::from_cursor::<u64>(&cursor)?).unwrap_or_default();
         let mut ids: Vec<u64> = self.my_thread_ids(user).await;
+        if let Some(since) = updated_since {
+            for id in ids.iter() {
+                self.load_thread_state(*id).await;
+            }
+            let states = self.thread_states.read();
+            ids.retain(|id| {
+                states
+                    .get(id)
+                    .is_some_and(|state| state.read().updated_at >= since)
+            });
+        }
         if cursor > 0 {
             ids.sort();
             ids.retain(|&id| id > cursor);
@@ -305,7 +1127,7 @@ impl NexusNode {
 
         let mut threads = Vec::with_capacity(ids.len());
         for id in ids {
-            if let Ok(thread) = self.threads.get_as(id).await {
+            if let Ok(thread) = self.threads().get_as(id).await {
                 threads.push(thread);
             }
         }
@@ -313,7 +1135,7 @@ impl NexusNode {
     }
 
     pub async fn public_threads(&self, limit: Option<usize>) -> Result<Vec<ThreadInfo>, BoxError> {
-        let limit = limit.unwrap_or(100).min(1000);
+        let limit = self.page_limit(limit);
 
         let mut candidates = {
             let states = self.thread_states.read();
@@ -333,7 +1155,7 @@ impl NexusNode {
         candidates.sort_by(|a, b| b.1.cmp(&a.1)); // sort by updated_at desc
         let mut threads = Vec::with_capacity(limit);
         for (id, _) in candidates.into_iter().take(limit) {
-            if let Ok(thread) = self.threads.get_as(id).await {
+            if let Ok(thread) = self.threads().get_as(id).await {
                 threads.push(thread);
             }
         }
@@ -348,9 +1170,9 @@ impl NexusNode {
         mut input: UpdateThreadInfo,
     ) -> Result<Thread, BoxError> {
         input.validate_and_normalize()?;
-        self.check_thread_state(_id)?;
+        self.check_thread_state(_id).await?;
 
-        let thread: Thread = self.threads.get_as(_id).await?;
+        let thread: Thread = self.threads().get_as(_id).await?;
         if !thread.has_permission(user, ThreadPermission::Manage) {
             return Err(format!(
                 "User {} does not have permission to manage thread {}",
@@ -382,18 +1204,105 @@ impl NexusNode {
         if let Some(visibility) = &input.visibility {
             changes.insert("visibility".to_string(), Fv::Text(visibility.to_string()));
         }
-
-        let doc = self.threads.update(_id, changes).await?;
-        if let Some(state) = self.thread_states.write().get_mut(&_id) {
+        let max_participants = input
+            .max_participants
+            .map(|max| max.min(self.max_participants));
+        if let Some(max) = max_participants {
+            if max < thread.participants.len() as u64 {
+                return Err(format!(
+                    "Max participants {} is less than the current participants {}",
+                    max,
+                    thread.participants.len()
+                )
+                .into());
+            }
+            changes.insert("max_participants".to_string(), Fv::U64(max));
+        }
+
+        let doc = self.threads().update(_id, changes).await?;
+        if let Some(state) = self.thread_states.write().get_mut(&_id) {
             let mut s = state.write();
             s.updated_at = updated_at;
             if let Some(visibility) = input.visibility {
                 s.visibility = visibility;
             }
+            if let Some(max) = max_participants {
+                s.max_participants = max;
+            }
         }
         Ok(doc.try_into()?)
     }
 
+    /// Generates a title for an untitled thread (see [`Thread::is_untitled`]) with the model
+    /// from its first message and sets it as the thread name.
+    /// Returns None if the thread is not untitled or has been named in the meantime.
+    pub async fn auto_title_thread(
+        &self,
+        model: &Model,
+        thread_id: u64,
+        first_message: &str,
+        untitled_names: &[String],
+    ) -> Result<Option<Thread>, BoxError> {
+        self.check_thread_state(thread_id).await?;
+        let thread: Thread = self.threads().get_as(thread_id).await?;
+        if !thread.is_untitled(untitled_names) || first_message.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let output = model
+            .completion(CompletionRequest {
+                instructions: "You name chat threads. Reply with a short title of at most 8 words for the conversation that starts with the user's message, in the language of the message, without quotes or punctuation at the end.".to_string(),
+                prompt: first_message.to_string(),
+                max_output_tokens: Some(32),
+                ..Default::default()
+            })
+            .await?;
+        if let Some(failed_reason) = output.failed_reason {
+            return Err(failed_reason.into());
+        }
+
+        let mut title = output
+            .content
+            .lines()
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or_default()
+            .trim()
+            .trim_matches(|c| matches!(c, '"' | '\'' | '“' | '”' | '#' | '*'))
+            .trim()
+            .to_string();
+        if title.len() > MAX_THREAD_NAME_LEN {
+            let mut end = MAX_THREAD_NAME_LEN;
+            while !title.is_char_boundary(end) {
+                end -= 1;
+            }
+            title.truncate(end);
+        }
+        if title.is_empty() {
+            return Err("Model returned an empty thread title".into());
+        }
+
+        // the thread may have been renamed while the title was generated
+        let thread: Thread = self.threads().get_as(thread_id).await?;
+        if !thread.is_untitled(untitled_names) {
+            return Ok(None);
+        }
+        let updated_at = unix_ms();
+        let doc = self
+            .threads()
+            .update(
+                thread_id,
+                BTreeMap::from([
+                    ("name".to_string(), Fv::Text(title)),
+                    ("updated_at".to_string(), Fv::U64(updated_at)),
+                ]),
+            )
+            .await?;
+        if let Some(state) = self.thread_states.write().get_mut(&thread_id) {
+            state.write().updated_at = updated_at;
+        }
+        Ok(Some(doc.try_into()?))
+    }
+
     pub async fn update_thread_controllers(
         &self,
         user: &Principal,
@@ -406,9 +1315,10 @@ impl NexusNode {
         if controllers.len() > 5 {
             return Err("Controllers cannot be more than 5".to_string().into());
         }
-        self.check_thread_state(_id)?;
+        self.check_thread_state(_id).await?;
 
-        let mut thread: Thread = self.threads.get_as(_id).await?;
+        let _guard = ThreadLockGuard::lock(&self.participants_locks, _id).await;
+        let mut thread: Thread = self.threads().get_as(_id).await?;
         if !thread.has_permission(user, ThreadPermission::Control) {
             return Err(format!(
                 "User {} does not have permission to control thread {}",
@@ -429,7 +1339,7 @@ impl NexusNode {
         let updated_at = unix_ms();
         let participants = thread.participants.len() as u64;
         let doc = self
-            .threads
+            .threads()
             .update(
                 _id,
                 BTreeMap::from([
@@ -468,9 +1378,10 @@ impl NexusNode {
         if managers.len() > 5 {
             return Err("Managers cannot be more than 5".to_string().into());
         }
-        self.check_thread_state(_id)?;
+        self.check_thread_state(_id).await?;
 
-        let mut thread: Thread = self.threads.get_as(_id).await?;
+        let _guard = ThreadLockGuard::lock(&self.participants_locks, _id).await;
+        let mut thread: Thread = self.threads().get_as(_id).await?;
         if !thread.has_permission(user, ThreadPermission::Control) {
             return Err(format!(
                 "User {} does not have permission to control thread {}",
@@ -491,7 +1402,7 @@ impl NexusNode {
         let updated_at = unix_ms();
         let participants = thread.participants.len() as u64;
         let doc = self
-            .threads
+            .threads()
             .update(
                 _id,
                 BTreeMap::from([
@@ -528,6 +1439,8 @@ impl NexusNode {
             return Err("Participants cannot be empty".to_string().into());
         }
 
+        self.load_thread_state(_id).await;
+        let _guard = ThreadLockGuard::lock(&self.participants_locks, _id).await;
         let (num_participants, max_participants) = {
             match self.thread_states.read().get(&_id) {
                 Some(state) => {
@@ -545,7 +1458,7 @@ impl NexusNode {
             return Err(format!("Exceed max participants limit: {}", max_participants).into());
         }
 
-        let mut thread: Thread = self.threads.get_as(_id).await?;
+        let mut thread: Thread = self.threads().get_as(_id).await?;
         if !thread.has_permission(user, ThreadPermission::Manage) {
             return Err(format!(
                 "User {} does not have permission to manage thread {}",
@@ -560,7 +1473,7 @@ impl NexusNode {
         let updated_at = unix_ms();
         let participants = thread.participants.len() as u64;
         let doc = self
-            .threads
+            .threads()
             .update(
                 _id,
                 BTreeMap::from([
@@ -596,8 +1509,9 @@ impl NexusNode {
             return Err("Participants cannot be empty".to_string().into());
         }
 
-        self.check_thread_state(_id)?;
-        let mut thread: Thread = self.threads.get_as(_id).await?;
+        self.check_thread_state(_id).await?;
+        let _guard = ThreadLockGuard::lock(&self.participants_locks, _id).await;
+        let mut thread: Thread = self.threads().get_as(_id).await?;
         if !thread.has_permission(user, ThreadPermission::Manage) {
             return Err(format!(
                 "User {} does not have permission to manage thread {}",
@@ -623,7 +1537,7 @@ impl NexusNode {
         let updated_at = unix_ms();
         let participants = thread.participants.len() as u64;
         let doc = self
-            .threads
+            .threads()
             .update(
                 _id,
                 BTreeMap::from([
@@ -650,6 +1564,7 @@ impl NexusNode {
     }
 
     pub async fn quit_thread(&self, user: &Principal, _id: u64) -> Result<(), BoxError> {
+        self.load_thread_state(_id).await;
         {
             match self.thread_states.read().get(&_id) {
                 Some(state) => {
@@ -661,7 +1576,8 @@ impl NexusNode {
             }
         }
 
-        let mut thread: Thread = self.threads.get_as(_id).await?;
+        let _guard = ThreadLockGuard::lock(&self.participants_locks, _id).await;
+        let mut thread: Thread = self.threads().get_as(_id).await?;
         if !thread.participants.contains_key(user) {
             return Err(format!("User {} is not a participant of thread {}", user, _id).into());
         }
@@ -714,7 +1630,7 @@ impl NexusNode {
                     .collect(),
             ),
         );
-        self.threads.update(_id, changes).await?;
+        self.threads().update(_id, changes).await?;
         if let Some(state) = self.thread_states.write().get_mut(&_id) {
             let mut s = state.write();
             s.participants = participants;
@@ -724,6 +1640,7 @@ impl NexusNode {
     }
 
     pub async fn delete_thread(&self, user: &Principal, _id: u64) -> Result<(), BoxError> {
+        self.load_thread_state(_id).await;
         {
             match self.thread_states.read().get(&_id) {
                 Some(state) => {
@@ -735,7 +1652,7 @@ impl NexusNode {
             }
         }
 
-        let thread: Thread = self.threads.get_as(_id).await?;
+        let thread: Thread = self.threads().get_as(_id).await?;
         if !thread.has_permission(user, ThreadPermission::Control) {
             return Err(format!(
                 "User {} does not have permission to control thread {}",
@@ -748,7 +1665,7 @@ impl NexusNode {
             self.thread_states.write().remove(&_id);
         }
 
-        self.threads.remove(_id).await?;
+        self.threads().remove(_id).await?;
         self.db
             .delete_collection(Self::thread_resource_collection_name(_id).as_str())
             .await?;
@@ -765,7 +1682,7 @@ impl NexusNode {
         status: ThreadStatus,
     ) -> Result<(), BoxError> {
         let updated_at = unix_ms();
-        self.threads
+        self.threads()
             .update(
                 _id,
                 BTreeMap::from([
@@ -788,7 +1705,7 @@ impl NexusNode {
         max_participants: u64,
     ) -> Result<(), BoxError> {
         let updated_at = unix_ms();
-        self.threads
+        self.threads()
             .update(
                 _id,
                 BTreeMap::from([
@@ -805,7 +1722,28 @@ impl NexusNode {
         Ok(())
     }
 
-    fn check_thread_state(&self, thread_id: u64) -> Result<ThreadVisibility, BoxError> {
+    /// Loads the thread state on first access if thread states are not loaded eagerly.
+    async fn load_thread_state(&self, thread_id: u64) {
+        if self.eager_load || self.thread_states.read().contains_key(&thread_id) {
+            return;
+        }
+
+        match self.threads().get_as::<Thread>(thread_id).await {
+            Ok(thread) => {
+                self.thread_states
+                    .write()
+                    .entry(thread_id)
+                    .or_insert_with(|| Arc::new(RwLock::new(thread.to_state())));
+            }
+            Err(DBError::NotFound { .. }) => {}
+            Err(err) => {
+                log::error!("Failed to load thread {}: {}", thread_id, err);
+            }
+        }
+    }
+
+    async fn check_thread_state(&self, thread_id: u64) -> Result<ThreadVisibility, BoxError> {
+        self.load_thread_state(thread_id).await;
         match self.thread_states.read().get(&thread_id) {
             Some(state) => {
                 let s = state.read();
@@ -819,57 +1757,193 @@ impl NexusNode {
     }
 
     async fn my_thread_ids(&self, user: &Principal) -> Vec<u64> {
-        self.threads
-            .search_ids(Query {
-                filter: Some(Filter::Field((
+        // search_ids returns the first 10 matches by default, so query all of them
+        self.threads()
+            .query_ids(
+                Filter::Field((
                     "participants".to_string(),
                     RangeQuery::Eq(Fv::Bytes(user.as_slice().to_vec())),
-                ))),
-                ..Default::default()
-            })
+                )),
+                None,
+            )
             .await
             .unwrap_or_default()
     }
 }
 
 impl NexusNode {
+    /// Adds a message to a thread.
+    ///
+    /// The `role` is one of "user", "assistant" or "system". Any participant can post
+    /// "user" and "assistant" messages, only managers can post "system" messages.
+    ///
+    /// If `idempotency_key` is set and the user already added a message with the same key
+    /// to the thread, that message is returned instead of adding a new one. Concurrent adds
+    /// with the same key are serialized, so only one message is added.
+    #[allow(clippy::too_many_arguments)]
     pub async fn add_message(
         &self,
         user: &Principal,
         thread_id: u64,
         reply_to: u64,
+        role: &str,
         message: String,
         resources: Vec<Resource>,
+        idempotency_key: Option<String>,
     ) -> Result<Message, BoxError> {
-        self.check_thread_state(thread_id)?;
+        let (message, _) = self
+            .add_or_get_message(
+                user,
+                thread_id,
+                reply_to,
+                role,
+                message,
+                resources,
+                idempotency_key,
+            )
+            .await?;
+        Ok(message)
+    }
+
+    /// Adds a message to a thread like [`Self::add_message`],
+    /// returns the message and whether it was newly added.
+    #[allow(clippy::too_many_arguments)]
+    async fn add_or_get_message(
+        &self,
+        user: &Principal,
+        thread_id: u64,
+        reply_to: u64,
+        role: &str,
+        message: String,
+        resources: Vec<Resource>,
+        idempotency_key: Option<String>,
+    ) -> Result<(Message, bool), BoxError> {
+        self.check_thread_state(thread_id).await?;
         let ids = self.my_thread_ids(user).await;
         if !ids.contains(&thread_id) {
             return Err(
                 format!("User {} is not a participant of thread {}", user, thread_id).into(),
             );
         }
+        match role {
+            "user" | "assistant" => {}
+            "system" => {
+                let thread: Thread = self.threads().get_as(thread_id).await?;
+                if !thread.has_permission(user, ThreadPermission::Manage) {
+                    return Err(format!(
+                        "User {} does not have permission to post system messages in thread {}",
+                        user, thread_id
+                    )
+                    .into());
+                }
+            }
+            _ => return Err(format!("Invalid message role: {:?}", role).into()),
+        }
 
         let collection = self.get_message_collection(thread_id).await?;
+        let key = match idempotency_key {
+            Some(key) => {
+                Self::check_idempotency_key(&key)?;
+                // collections created before idempotency keys were introduced can not store them
+                if collection.schema().get_field("idempotency_key").is_some() {
+                    Some(key)
+                } else {
+                    log::warn!(
+                        "Collection {} does not support idempotency keys, adding the message without deduplication",
+                        collection.name()
+                    );
+                    None
+                }
+            }
+            None => None,
+        };
+        let Some(key) = key else {
+            let message = self
+                .insert_message(
+                    user,
+                    thread_id,
+                    &collection,
+                    reply_to,
+                    role,
+                    message,
+                    resources,
+                    None,
+                )
+                .await?;
+            return Ok((message, true));
+        };
+
+        let lock_key = (thread_id, *user, key.clone());
+        let lock = self
+            .idempotency_locks
+            .lock()
+            .entry(lock_key.clone())
+            .or_default()
+            .clone();
+        let rt = {
+            let _guard = lock.lock().await;
+            match Self::find_idempotent_message(&collection, user, &key).await {
+                Ok(Some(existing)) => Ok((existing, false)),
+                Ok(None) => self
+                    .insert_message(
+                        user,
+                        thread_id,
+                        &collection,
+                        reply_to,
+                        role,
+                        message,
+                        resources,
+                        Some(key),
+                    )
+                    .await
+                    .map(|message| (message, true)),
+                Err(err) => Err(err),
+            }
+        };
+        drop(lock);
+        let mut locks = self.idempotency_locks.lock();
+        if locks
+            .get(&lock_key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&lock_key);
+        }
+        rt
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn insert_message(
+        &self,
+        user: &Principal,
+        thread_id: u64,
+        collection: &Collection,
+        reply_to: u64,
+        role: &str,
+        message: String,
+        resources: Vec<Resource>,
+        idempotency_key: Option<String>,
+    ) -> Result<Message, BoxError> {
+        if reply_to > 0 && !collection.contains(reply_to) {
+            return Err(format!("Reply to message {} not found", reply_to).into());
+        }
+
         let timestamp = unix_ms();
         let resources = update_resources(user, resources);
         let resources = self.try_add_resources(thread_id, &resources).await?;
         let content = vec![message.into()];
         let mut message = Message {
             _id: 0,
-            role: "user".to_string(),
+            role: role.to_string(),
             user: Some(*user),
             content,
             resources,
             timestamp,
             reply_to,
+            idempotency_key,
         };
 
-        if reply_to > 0 && !collection.contains(reply_to) {
-            return Err(format!("Reply to message {} not found", reply_to).into());
-        }
-
         let _id = collection.add_from(&message).await?;
-        collection.flush(timestamp).await?;
+        self.flush_added(thread_id, collection, timestamp).await?;
         message._id = _id;
 
         if let Some(state) = self.thread_states.write().get_mut(&thread_id) {
@@ -879,16 +1953,327 @@ impl NexusNode {
             s.latest_message_at = timestamp;
         }
 
+        self.notify_subscribers(thread_id, &message).await;
         Ok(message)
     }
 
+    /// Returns the message the user added with the idempotency key, if any.
+    pub async fn find_message_by_idempotency_key(
+        &self,
+        user: &Principal,
+        thread_id: u64,
+        key: &str,
+    ) -> Result<Option<Message>, BoxError> {
+        self.check_thread_state(thread_id).await?;
+        let ids = self.my_thread_ids(user).await;
+        if !ids.contains(&thread_id) {
+            return Err(
+                format!("User {} is not a participant of thread {}", user, thread_id).into(),
+            );
+        }
+
+        Self::check_idempotency_key(key)?;
+        let collection = self.get_message_collection(thread_id).await?;
+        // collections created before idempotency keys were introduced can not store them
+        if collection.schema().get_field("idempotency_key").is_none() {
+            return Ok(None);
+        }
+        Self::find_idempotent_message(&collection, user, key).await
+    }
+
+    fn check_idempotency_key(key: &str) -> Result<(), BoxError> {
+        if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+            return Err(format!(
+                "Idempotency key must be 1 to {} bytes",
+                MAX_IDEMPOTENCY_KEY_LEN
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    async fn find_idempotent_message(
+        collection: &Collection,
+        user: &Principal,
+        key: &str,
+    ) -> Result<Option<Message>, BoxError> {
+        let mut rt: Vec<Message> = collection
+            .search_as(Query {
+                filter: Some(Filter::And(vec![
+                    Box::new(Filter::Field((
+                        "idempotency_key".to_string(),
+                        RangeQuery::Eq(Fv::Text(key.to_string())),
+                    ))),
+                    Box::new(Filter::Field((
+                        "user".to_string(),
+                        RangeQuery::Eq(Fv::Bytes(user.as_slice().to_vec())),
+                    ))),
+                ])),
+                limit: Some(1),
+                ..Default::default()
+            })
+            .await?;
+        Ok(rt.pop())
+    }
+
+    /// Subscribes the user to new messages in a thread. A signed [`MessageNotification`]
+    /// is POSTed to `url` for each message added to the thread.
+    ///
+    /// Webhooks must be enabled with [`Self::with_webhooks`]. Subscriptions are persisted,
+    /// a subscription is dropped once the user is no longer a participant of the thread.
+    pub async fn subscribe_thread(
+        &self,
+        user: &Principal,
+        thread_id: u64,
+        url: String,
+    ) -> Result<(), BoxError> {
+        let webhooks = self
+            .webhooks
+            .as_ref()
+            .ok_or("Webhook subscriptions are not enabled")?;
+        self.check_thread_state(thread_id).await?;
+        let ids = self.my_thread_ids(user).await;
+        if !ids.contains(&thread_id) {
+            return Err(
+                format!("User {} is not a participant of thread {}", user, thread_id).into(),
+            );
+        }
+
+        let u = Url::parse(&url).map_err(|e| format!("Webhook URL is invalid: {}", e))?;
+        if u.scheme() != "https" {
+            return Err("Webhook URL must start with https://".into());
+        }
+        webhooks
+            .policy
+            .check(&url)
+            .await
+            .map_err(|e| format!("Webhook URL is not allowed: {}", e))?;
+
+        let existing = {
+            let subscriptions = self.subscriptions.read();
+            let subs = subscriptions.get(&thread_id);
+            let existing = subs.and_then(|subs| subs.get(user)).map(|sub| sub._id);
+            if existing.is_none() && subs.is_some_and(|subs| subs.len() >= MAX_THREAD_SUBSCRIPTIONS)
+            {
+                return Err(format!(
+                    "Thread {} has reached the maximum number of subscriptions {}",
+                    thread_id, MAX_THREAD_SUBSCRIPTIONS
+                )
+                .into());
+            }
+            existing
+        };
+
+        let mut sub = Subscription {
+            _id: 0,
+            thread_id,
+            user: *user,
+            url,
+            created_at: unix_ms(),
+        };
+        match existing {
+            Some(id) => {
+                self.subscriptions_collection
+                    .update(
+                        id,
+                        BTreeMap::from([("url".to_string(), Fv::Text(sub.url.clone()))]),
+                    )
+                    .await?;
+                sub._id = id;
+            }
+            None => {
+                sub._id = self.subscriptions_collection.add_from(&sub).await?;
+            }
+        }
+        self.subscriptions_collection.flush(unix_ms()).await?;
+        self.subscriptions
+            .write()
+            .entry(thread_id)
+            .or_default()
+            .insert(*user, sub);
+        Ok(())
+    }
+
+    /// Unsubscribes the user from a thread, returns false if the user was not subscribed.
+    pub async fn unsubscribe_thread(
+        &self,
+        user: &Principal,
+        thread_id: u64,
+    ) -> Result<bool, BoxError> {
+        let sub = {
+            let mut subscriptions = self.subscriptions.write();
+            match subscriptions.get_mut(&thread_id) {
+                Some(subs) => {
+                    let sub = subs.remove(user);
+                    if subs.is_empty() {
+                        subscriptions.remove(&thread_id);
+                    }
+                    sub
+                }
+                None => None,
+            }
+        };
+        match sub {
+            Some(sub) => {
+                self.subscriptions_collection.remove(sub._id).await?;
+                self.subscriptions_collection.flush(unix_ms()).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Marks the messages of a thread up to `message_id` as read by the user,
+    /// returns the user's last-read message ID, which never moves backwards.
+    pub async fn mark_read(
+        &self,
+        user: &Principal,
+        thread_id: u64,
+        message_id: u64,
+    ) -> Result<u64, BoxError> {
+        self.check_thread_state(thread_id).await?;
+        let latest_message_id = self
+            .thread_states
+            .read()
+            .get(&thread_id)
+            .map(|state| state.read().latest_message_id)
+            .unwrap_or_default();
+        if message_id > latest_message_id {
+            return Err(format!("Message {} not found in thread {}", message_id, thread_id).into());
+        }
+
+        let _guard = ThreadLockGuard::lock(&self.participants_locks, thread_id).await;
+        let mut thread: Thread = self.threads().get_as(thread_id).await?;
+        let last_read = match thread.participants.get_mut(user) {
+            Some(last_read) => last_read,
+            None => {
+                return Err(
+                    format!("User {} is not a participant of thread {}", user, thread_id).into(),
+                );
+            }
+        };
+        if message_id <= *last_read {
+            return Ok(*last_read);
+        }
+        *last_read = message_id;
+
+        // marking as read is per user, so it does not change the thread's updated_at
+        self.threads()
+            .update(
+                thread_id,
+                BTreeMap::from([(
+                    "participants".to_string(),
+                    Fv::Map(
+                        thread
+                            .participants
+                            .into_iter()
+                            .map(|(k, v)| (k.as_ref().into(), v.into()))
+                            .collect(),
+                    ),
+                )]),
+            )
+            .await?;
+        Ok(message_id)
+    }
+
+    /// Returns the number of existing messages in a thread after the user's last-read message,
+    /// excluding the user's own messages.
+    pub async fn unread_count(&self, user: &Principal, thread_id: u64) -> Result<u64, BoxError> {
+        self.check_thread_state(thread_id).await?;
+        let thread: Thread = self.threads().get_as(thread_id).await?;
+        let last_read =
+            thread.participants.get(user).copied().ok_or_else(|| {
+                format!("User {} is not a participant of thread {}", user, thread_id)
+            })?;
+        let latest_message_id = self
+            .thread_states
+            .read()
+            .get(&thread_id)
+            .map(|state| state.read().latest_message_id)
+            .unwrap_or_default();
+        if latest_message_id <= last_read {
+            return Ok(0);
+        }
+
+        // message IDs are not contiguous after deletions, so count the messages that exist
+        let collection = self.get_message_collection(thread_id).await?;
+        let unread_ids: BTreeSet<u64> = collection
+            .query_ids(
+                Filter::Field(("_id".to_string(), RangeQuery::Gt(Fv::U64(last_read)))),
+                None,
+            )
+            .await?
+            .into_iter()
+            .filter(|id| *id <= latest_message_id)
+            .collect();
+        let own_unread = collection
+            .query_ids(
+                Filter::Field((
+                    "user".to_string(),
+                    RangeQuery::Eq(Fv::Bytes(user.as_slice().to_vec())),
+                )),
+                None,
+            )
+            .await?
+            .into_iter()
+            .filter(|id| unread_ids.contains(id))
+            .count();
+        Ok((unread_ids.len() - own_unread) as u64)
+    }
+
+    /// Notifies the thread's subscribers of a new message in the background,
+    /// retrying with exponential backoff if the delivery fails.
+    async fn notify_subscribers(&self, thread_id: u64, message: &Message) {
+        let Some(webhooks) = &self.webhooks else {
+            return;
+        };
+        let subscriptions: Vec<Subscription> = match self.subscriptions.read().get(&thread_id) {
+            Some(subs) => subs.values().cloned().collect(),
+            None => return,
+        };
+        let participants = match self.threads().get_as::<Thread>(thread_id).await {
+            Ok(thread) => thread.participants,
+            Err(err) => {
+                log::error!("Failed to load thread {}: {}", thread_id, err);
+                return;
+            }
+        };
+
+        let body = match serde_json::to_vec(&MessageNotification {
+            thread_id,
+            message: message.clone(),
+        }) {
+            Ok(body) => body,
+            Err(err) => {
+                log::error!("Failed to encode message notification: {}", err);
+                return;
+            }
+        };
+        let digest = sha3_256(&body);
+
+        for sub in subscriptions {
+            if !participants.contains_key(&sub.user) {
+                if let Err(err) = self.unsubscribe_thread(&sub.user, thread_id).await {
+                    log::warn!("Failed to drop subscription {}: {}", sub._id, err);
+                }
+                continue;
+            }
+
+            let webhooks = webhooks.clone();
+            let body = body.clone();
+            tokio::spawn(async move {
+                deliver_webhook(&webhooks, &sub.url, digest, body).await;
+            });
+        }
+    }
+
     pub async fn get_message(
         &self,
         user: &Principal,
         thread_id: u64,
         message_id: u64,
     ) -> Result<Message, BoxError> {
-        let v = self.check_thread_state(thread_id)?;
+        let v = self.check_thread_state(thread_id).await?;
         let ids = self.my_thread_ids(user).await;
         if !ids.contains(&thread_id) && v != ThreadVisibility::Public {
             return Err(
@@ -897,11 +2282,76 @@ impl NexusNode {
         }
 
         let collection = self.get_message_collection(thread_id).await?;
+        self.flush_pending(thread_id, &collection).await?;
         let message: Message = collection.get_as(message_id).await?;
 
         Ok(message)
     }
 
+    /// Returns the root message and all of its descendants following `reply_to`,
+    /// in ascending order of message ID.
+    ///
+    /// The replies are looked up in the `reply_to` index. Threads created before the index
+    /// was added fall back to scanning the messages after the root.
+    pub async fn get_message_thread(
+        &self,
+        user: &Principal,
+        thread_id: u64,
+        root_message_id: u64,
+    ) -> Result<Vec<Message>, BoxError> {
+        let v = self.check_thread_state(thread_id).await?;
+        let ids = self.my_thread_ids(user).await;
+        if !ids.contains(&thread_id) && v != ThreadVisibility::Public {
+            return Err(
+                format!("User {} is not a participant of thread {}", user, thread_id).into(),
+            );
+        }
+
+        let collection = self.get_message_collection(thread_id).await?;
+        self.flush_pending(thread_id, &collection).await?;
+        let root: Message = collection.get_as(root_message_id).await?;
+        let mut messages = vec![root];
+        if collection.metadata().btree_indexes.contains_key("reply_to") {
+            let mut i = 0;
+            while i < messages.len() {
+                let reply_ids = collection
+                    .query_ids(
+                        Filter::Field((
+                            "reply_to".to_string(),
+                            RangeQuery::Eq(Fv::U64(messages[i]._id)),
+                        )),
+                        None,
+                    )
+                    .await?;
+                for id in reply_ids {
+                    if let Ok(message) = collection.get_as::<Message>(id).await {
+                        messages.push(message);
+                    }
+                }
+                i += 1;
+            }
+            messages.sort_by_key(|m| m._id);
+            return Ok(messages);
+        }
+
+        // a reply is always added after the message it replies to,
+        // so a single pass over the later messages collects the whole subtree
+        let mut tree_ids = BTreeSet::from([root_message_id]);
+        for id in collection.ids() {
+            if id <= root_message_id {
+                continue;
+            }
+            if let Ok(message) = collection.get_as::<Message>(id).await
+                && tree_ids.contains(&message.reply_to)
+            {
+                tree_ids.insert(message._id);
+                messages.push(message);
+            }
+        }
+
+        Ok(messages)
+    }
+
     pub async fn list_messages(
         &self,
         user: &Principal,
@@ -909,7 +2359,7 @@ impl NexusNode {
         cursor: Option<String>,
         limit: Option<usize>,
     ) -> Result<(Vec<Message>, Option<String>), BoxError> {
-        let v = self.check_thread_state(thread_id)?;
+        let v = self.check_thread_state(thread_id).await?;
         let ids = self.my_thread_ids(user).await;
         if !ids.contains(&thread_id) && v != ThreadVisibility::Public {
             return Err(
@@ -917,10 +2367,11 @@ impl NexusNode {
             );
         }
 
-        let limit = limit.unwrap_or(100).min(1000);
+        let limit = self.page_limit(limit);
         let cursor = (BTree::from_cursor::<u64>(&cursor)?).unwrap_or_default();
 
         let collection = self.get_message_collection(thread_id).await?;
+        self.flush_pending(thread_id, &collection).await?;
         let mut message_ids = collection.ids();
         if cursor > 0 {
             message_ids.retain(|id| *id < cursor);
@@ -945,13 +2396,69 @@ impl NexusNode {
         Ok((messages, cursor))
     }
 
+    /// Searches messages by full-text query on content across all active threads the user
+    /// participates in. Returns up to `limit` `(thread_id, message)` hits, newest first.
+    pub async fn search_my_messages(
+        &self,
+        user: &Principal,
+        query: String,
+        limit: Option<usize>,
+    ) -> Result<Vec<(u64, Message)>, BoxError> {
+        if query.trim().is_empty() {
+            return Err("query is required".into());
+        }
+
+        let limit = self.page_limit(limit);
+        let mut thread_ids = Vec::new();
+        for id in self.my_thread_ids(user).await {
+            if self.check_thread_state(id).await.is_ok() {
+                thread_ids.push(id);
+            }
+        }
+
+        let rt = stream::iter(thread_ids)
+            .map(|thread_id| {
+                let query = query.clone();
+                async move {
+                    let collection = self.get_message_collection(thread_id).await?;
+                    self.flush_pending(thread_id, &collection).await?;
+                    let messages: Vec<Message> = collection
+                        .search_as(Query {
+                            search: Some(Search {
+                                text: Some(query),
+                                logical_search: true,
+                                ..Default::default()
+                            }),
+                            limit: Some(limit),
+                            ..Default::default()
+                        })
+                        .await?;
+                    Ok::<_, BoxError>(messages.into_iter().map(move |m| (thread_id, m)))
+                }
+            })
+            .buffer_unordered(16)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut hits: Vec<(u64, Message)> = Vec::new();
+        for r in rt {
+            match r {
+                Ok(messages) => hits.extend(messages),
+                Err(err) => log::warn!("Failed to search thread messages: {}", err),
+            }
+        }
+        hits.sort_by_key(|(_, m)| std::cmp::Reverse(m.timestamp));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
     pub async fn delete_message(
         &self,
         user: &Principal,
         thread_id: u64,
         message_id: u64,
     ) -> Result<(), BoxError> {
-        self.check_thread_state(thread_id)?;
+        self.check_thread_state(thread_id).await?;
         let ids = self.my_thread_ids(user).await;
         if !ids.contains(&thread_id) {
             return Err(
@@ -975,7 +2482,8 @@ impl NexusNode {
         }
 
         collection.remove(message_id).await?;
-        collection.flush(timestamp).await?;
+        self.flush_collection(thread_id, &collection, timestamp)
+            .await?;
         let latest_message_id = collection.latest_document_id().unwrap_or_default();
         let (latest_message_by, latest_message_id, latest_message_at) =
             if let Ok(message) = collection.get_as::<Message>(latest_message_id).await {
@@ -1001,7 +2509,7 @@ impl NexusNode {
         thread_id: u64,
         id: u64,
     ) -> Result<Resource, BoxError> {
-        let v = self.check_thread_state(thread_id)?;
+        let v = self.check_thread_state(thread_id).await?;
         let ids = self.my_thread_ids(user).await;
         if !ids.contains(&thread_id) && v != ThreadVisibility::Public {
             return Err(
@@ -1014,17 +2522,232 @@ impl NexusNode {
         Ok(resource)
     }
 
-    async fn try_add_resources(
+    /// Gets a resource in a thread by the SHA3-256 hash of its content,
+    /// so clients can check whether a file is already uploaded before sending it.
+    /// The blob of the returned resource is omitted.
+    pub async fn resource_by_hash(
         &self,
+        user: &Principal,
         thread_id: u64,
-        resources: &[Resource],
-    ) -> Result<Vec<Resource>, BoxError> {
-        let collection = self.get_resource_collection(thread_id).await?;
-        let mut rs: Vec<Resource> = Vec::with_capacity(resources.len());
+        hash: &[u8; 32],
+    ) -> Result<Option<Resource>, BoxError> {
+        let v = self.check_thread_state(thread_id).await?;
+        let ids = self.my_thread_ids(user).await;
+        if !ids.contains(&thread_id) && v != ThreadVisibility::Public {
+            return Err(
+                format!("User {} is not a participant of thread {}", user, thread_id).into(),
+            );
+        }
+
+        let collection = self.get_resource_collection(thread_id).await?;
+        let mut rt: Vec<Resource> = collection
+            .search_as(Query {
+                filter: Some(Filter::Field((
+                    "hash".to_string(),
+                    RangeQuery::Eq(Fv::Bytes(hash.to_vec())),
+                ))),
+                limit: Some(1),
+                ..Default::default()
+            })
+            .await?;
+        Ok(rt.pop().map(|r| Resource { blob: None, ..r }))
+    }
+
+    /// Searches resources in a thread by full-text query on name, description and metadata,
+    /// filtered by tags (all must match) and MIME type.
+    /// At least one of `query`, `tags` or `mime_type` is required.
+    /// The blobs of the returned resources are omitted, use [`Self::get_resource`] to get them.
+    pub async fn search_resources(
+        &self,
+        user: &Principal,
+        thread_id: u64,
+        query: Option<String>,
+        tags: Vec<String>,
+        mime_type: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Resource>, BoxError> {
+        let v = self.check_thread_state(thread_id).await?;
+        let ids = self.my_thread_ids(user).await;
+        if !ids.contains(&thread_id) && v != ThreadVisibility::Public {
+            return Err(
+                format!("User {} is not a participant of thread {}", user, thread_id).into(),
+            );
+        }
+
+        let mut filters: Vec<Box<Filter>> = tags
+            .into_iter()
+            .map(|tag| {
+                Box::new(Filter::Field((
+                    "tags".to_string(),
+                    RangeQuery::Eq(Fv::Text(tag)),
+                )))
+            })
+            .collect();
+        if let Some(mime_type) = mime_type {
+            filters.push(Box::new(Filter::Field((
+                "mime_type".to_string(),
+                RangeQuery::Eq(Fv::Text(mime_type)),
+            ))));
+        }
+        let filter = match filters.len() {
+            0 => None,
+            1 => filters.pop().map(|f| *f),
+            _ => Some(Filter::And(filters)),
+        };
+        let search = query.filter(|q| !q.trim().is_empty()).map(|text| Search {
+            text: Some(text),
+            logical_search: true,
+            ..Default::default()
+        });
+        if search.is_none() && filter.is_none() {
+            return Err("query, tags or mime_type is required".into());
+        }
+
+        let collection = self.get_resource_collection(thread_id).await?;
+        let mut rt: Vec<Resource> = collection
+            .search_as(Query {
+                search,
+                filter,
+                limit: Some(self.page_limit(limit)),
+            })
+            .await?;
+        for r in rt.iter_mut() {
+            r.blob = None;
+        }
+        Ok(rt)
+    }
+
+    /// Begins a chunked upload of a resource to a thread.
+    /// The blob of the resource is ignored, its content should be sent with [`Self::append_chunk`].
+    /// Returns the upload ID.
+    ///
+    /// The data is buffered in memory until the upload is finished, so the number of
+    /// unfinished uploads per user and their total size are limited, see [`Self::with_upload_limits`].
+    pub async fn begin_resource_upload(
+        &self,
+        user: &Principal,
+        thread_id: u64,
+        resource: Resource,
+    ) -> Result<Xid, BoxError> {
+        self.check_thread_state(thread_id).await?;
+        let ids = self.my_thread_ids(user).await;
+        if !ids.contains(&thread_id) {
+            return Err(
+                format!("User {} is not a participant of thread {}", user, thread_id).into(),
+            );
+        }
+
+        let now_ms = unix_ms();
+        let upload_id = Xid::new();
+        let mut uploads = self.uploads.write();
+        uploads.remove_expired(now_ms);
+        if uploads.uploads.values().filter(|u| &u.user == user).count() >= self.max_uploads_per_user
+        {
+            return Err(format!(
+                "User {} has reached the maximum number of unfinished uploads: {}",
+                user, self.max_uploads_per_user
+            )
+            .into());
+        }
+        uploads.uploads.insert(
+            upload_id.clone(),
+            ResourceUpload {
+                user: *user,
+                thread_id,
+                resource: Resource {
+                    _id: 0,
+                    blob: None,
+                    size: None,
+                    hash: None,
+                    ..resource
+                },
+                data: Vec::new(),
+                updated_at: now_ms,
+            },
+        );
+
+        Ok(upload_id)
+    }
+
+    /// Appends a chunk of data to a resource upload.
+    /// Returns the total size of the uploaded data.
+    pub fn append_chunk(
+        &self,
+        user: &Principal,
+        upload_id: &Xid,
+        chunk: &[u8],
+    ) -> Result<usize, BoxError> {
+        let mut uploads = self.uploads.write();
+        let total_bytes = uploads.total_bytes;
+        let upload = match uploads.uploads.get_mut(upload_id) {
+            Some(upload) if &upload.user == user => upload,
+            _ => return Err(format!("Resource upload {} not found", upload_id).into()),
+        };
+
+        if upload.data.len() + chunk.len() > MAX_RESOURCE_UPLOAD_SIZE {
+            uploads.remove(upload_id);
+            return Err(format!(
+                "Resource upload {} exceeds the maximum size of {} bytes",
+                upload_id, MAX_RESOURCE_UPLOAD_SIZE
+            )
+            .into());
+        }
+        if total_bytes + chunk.len() > self.max_upload_bytes {
+            return Err("Too many resources are being uploaded, try again later".into());
+        }
+
+        upload.data.extend_from_slice(chunk);
+        upload.updated_at = unix_ms();
+        let size = upload.data.len();
+        uploads.total_bytes += chunk.len();
+        Ok(size)
+    }
+
+    /// Finishes a resource upload and adds the resource to the thread.
+    /// Returns the resource ID, which can be referenced in [`Self::add_message`]
+    /// by a resource with the same `_id`.
+    pub async fn finish_resource_upload(
+        &self,
+        user: &Principal,
+        upload_id: &Xid,
+    ) -> Result<u64, BoxError> {
+        let upload = {
+            let mut uploads = self.uploads.write();
+            match uploads.uploads.get(upload_id) {
+                Some(upload) if &upload.user == user => uploads.remove(upload_id).unwrap(),
+                _ => return Err(format!("Resource upload {} not found", upload_id).into()),
+            }
+        };
+
+        self.check_thread_state(upload.thread_id).await?;
+        let size = upload.data.len();
+        let resource = Resource {
+            blob: Some(upload.data.into()),
+            size: Some(size),
+            ..upload.resource
+        };
+        let resources = update_resources(user, vec![resource]);
+        let resources = self.try_add_resources(upload.thread_id, &resources).await?;
+        Ok(resources[0]._id)
+    }
+
+    async fn try_add_resources(
+        &self,
+        thread_id: u64,
+        resources: &[Resource],
+    ) -> Result<Vec<Resource>, BoxError> {
+        let collection = self.get_resource_collection(thread_id).await?;
+        let mut rs: Vec<Resource> = Vec::with_capacity(resources.len());
         let mut count = 0;
         for r in resources.iter() {
             let rf: ResourceRef = r.into();
+            let mut hash = r.hash.clone();
             let id = if r._id > 0 {
+                if hash.is_none()
+                    && let Ok(existing) = collection.get_as::<Resource>(r._id).await
+                {
+                    hash = existing.hash;
+                }
                 r._id // TODO: check if the resource exists and has permission
             } else {
                 match collection.add_from(&rf).await {
@@ -1040,6 +2763,7 @@ impl NexusNode {
             let r2 = Resource {
                 _id: id,
                 blob: None,
+                hash,
                 ..r.clone()
             };
             rs.push(r2)
@@ -1064,6 +2788,9 @@ pub enum ThreadToolArgs {
         /// The description of the thread to create
         #[serde(skip_serializing_if = "Option::is_none")]
         description: Option<String>,
+        /// The maximum number of participants, capped by the engine limit
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_participants: Option<u64>,
     },
     /// Update a thread basic info
     Update {
@@ -1125,12 +2852,20 @@ pub enum ThreadToolArgs {
         cursor: Option<String>,
         /// The limit for pagination, default to 100
         limit: Option<usize>,
+        /// Only list threads updated at or after this timestamp in milliseconds
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        updated_since: Option<u64>,
     },
     /// List public threads
     ListPublic {
         /// The limit for pagination, default to 100
         limit: Option<usize>,
     },
+    /// List the latest activity across my threads, most recent first
+    ActivityFeed {
+        /// The maximum number of threads, default to 100
+        limit: Option<usize>,
+    },
     /// Fetch all my threads state
     FetchMyThreadsState {},
     /// Fetch specified public threads state
@@ -1138,6 +2873,35 @@ pub enum ThreadToolArgs {
         /// The thread IDs to fetch
         thread_ids: Vec<u64>,
     },
+    /// Fetch specified threads state that I participate in or are public, by thread ID
+    FetchThreadsState {
+        /// The thread IDs to fetch
+        thread_ids: Vec<u64>,
+    },
+    /// Subscribe to new messages in a thread via a signed webhook
+    Subscribe {
+        /// The ID of the thread to subscribe to
+        thread_id: u64,
+        /// The HTTPS URL to POST notifications to
+        url: String,
+    },
+    /// Unsubscribe from a thread
+    Unsubscribe {
+        /// The ID of the thread to unsubscribe from
+        thread_id: u64,
+    },
+    /// Mark messages in a thread as read
+    MarkRead {
+        /// The ID of the thread
+        thread_id: u64,
+        /// The ID of the last read message
+        message_id: u64,
+    },
+    /// Get the number of unread messages in a thread
+    UnreadCount {
+        /// The ID of the thread
+        thread_id: u64,
+    },
 }
 
 /// A tool for conversation API
@@ -1145,6 +2909,7 @@ pub enum ThreadToolArgs {
 pub struct ThreadTool {
     nexus: Arc<NexusNode>,
     schema: Json,
+    examples: Vec<Json>,
 }
 
 impl ThreadTool {
@@ -1153,7 +2918,18 @@ impl ThreadTool {
     /// Creates a new SearchConversationsTool instance
     pub fn new(nexus: Arc<NexusNode>) -> Self {
         let schema = gen_schema_for::<ThreadToolArgs>();
-        Self { nexus, schema }
+        let examples = vec![
+            json!({"type": "create", "name": "Weekly sync", "description": "Team updates"}),
+            json!({"type": "get", "thread_id": 1}),
+            json!({"type": "listMy", "cursor": null, "limit": 20}),
+            json!({"type": "addParticipants", "thread_id": 1, "user_ids": ["2vxsx-fae"]}),
+            json!({"type": "markRead", "thread_id": 1, "message_id": 42}),
+        ];
+        Self {
+            nexus,
+            schema,
+            examples,
+        }
     }
 }
 
@@ -1175,6 +2951,7 @@ impl Tool<BaseCtx> for ThreadTool {
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: self.examples.clone(),
         }
     }
 
@@ -1185,13 +2962,16 @@ impl Tool<BaseCtx> for ThreadTool {
         _resources: Vec<Resource>,
     ) -> Result<ToolOutput<Self::Output>, BoxError> {
         let caller = ctx.caller().to_owned();
-        if caller == ANONYMOUS {
-            return Err("unauthenticated".into());
-        }
-
         let resp = match args {
-            ThreadToolArgs::Create { name, description } => {
-                let thread = self.nexus.create_thread(caller, name, description).await?;
+            ThreadToolArgs::Create {
+                name,
+                description,
+                max_participants,
+            } => {
+                let thread = self
+                    .nexus
+                    .create_thread(caller, name, description, max_participants)
+                    .await?;
                 Response::Ok {
                     result: json!(thread),
                     next_cursor: None,
@@ -1289,9 +3069,15 @@ impl Tool<BaseCtx> for ThreadTool {
                     ignore: None,
                 }
             }
-            ThreadToolArgs::ListMy { cursor, limit } => {
-                let (threads, next_cursor) =
-                    self.nexus.list_my_threads(&caller, cursor, limit).await?;
+            ThreadToolArgs::ListMy {
+                cursor,
+                limit,
+                updated_since,
+            } => {
+                let (threads, next_cursor) = self
+                    .nexus
+                    .list_my_threads(&caller, cursor, limit, updated_since)
+                    .await?;
                 Response::Ok {
                     result: json!(threads),
                     next_cursor,
@@ -1306,6 +3092,26 @@ impl Tool<BaseCtx> for ThreadTool {
                     ignore: None,
                 }
             }
+            ThreadToolArgs::ActivityFeed { limit } => {
+                let feed: Vec<Json> = self
+                    .nexus
+                    .my_activity_feed(&caller, limit)
+                    .await
+                    .into_iter()
+                    .map(|(thread_id, by, at)| {
+                        json!({
+                            "thread_id": thread_id,
+                            "latest_message_by": by,
+                            "latest_message_at": at,
+                        })
+                    })
+                    .collect();
+                Response::Ok {
+                    result: json!(feed),
+                    next_cursor: None,
+                    ignore: None,
+                }
+            }
             ThreadToolArgs::FetchMyThreadsState {} => {
                 let states = self.nexus.fetch_my_threads_state(&caller).await;
                 Response::Ok {
@@ -1316,13 +3122,57 @@ impl Tool<BaseCtx> for ThreadTool {
             }
             ThreadToolArgs::FetchPublicThreadsState { thread_ids } => {
                 let ids: BTreeSet<u64> = thread_ids.into_iter().collect();
-                let states = self.nexus.public_threads_state(ids);
+                let states = self.nexus.public_threads_state(ids).await;
+                Response::Ok {
+                    result: json!(states),
+                    next_cursor: None,
+                    ignore: None,
+                }
+            }
+            ThreadToolArgs::FetchThreadsState { thread_ids } => {
+                let ids: BTreeSet<u64> = thread_ids.into_iter().collect();
+                let states = self.nexus.fetch_threads_state(&caller, ids).await;
                 Response::Ok {
                     result: json!(states),
                     next_cursor: None,
                     ignore: None,
                 }
             }
+            ThreadToolArgs::Subscribe { thread_id, url } => {
+                self.nexus.subscribe_thread(&caller, thread_id, url).await?;
+                Response::Ok {
+                    result: json!({ "subscribed": thread_id }),
+                    next_cursor: None,
+                    ignore: None,
+                }
+            }
+            ThreadToolArgs::Unsubscribe { thread_id } => {
+                let unsubscribed = self.nexus.unsubscribe_thread(&caller, thread_id).await?;
+                Response::Ok {
+                    result: json!({ "unsubscribed": unsubscribed }),
+                    next_cursor: None,
+                    ignore: None,
+                }
+            }
+            ThreadToolArgs::MarkRead {
+                thread_id,
+                message_id,
+            } => {
+                let last_read = self.nexus.mark_read(&caller, thread_id, message_id).await?;
+                Response::Ok {
+                    result: json!({ "last_read": last_read }),
+                    next_cursor: None,
+                    ignore: None,
+                }
+            }
+            ThreadToolArgs::UnreadCount { thread_id } => {
+                let unread = self.nexus.unread_count(&caller, thread_id).await?;
+                Response::Ok {
+                    result: json!({ "unread": unread }),
+                    next_cursor: None,
+                    ignore: None,
+                }
+            }
         };
 
         Ok(ToolOutput::new(resp))
@@ -1342,6 +3192,10 @@ pub enum MessageToolArgs {
         message: String,
         /// Reply to message ID
         reply_to: Option<u64>,
+        /// Message role: "user" (default), "assistant" or "system" (managers only)
+        role: Option<String>,
+        /// Client-supplied idempotency key, retrying with the same key returns the existing message
+        idempotency_key: Option<String>,
     },
     /// Get a message in a thread
     Get {
@@ -1350,6 +3204,13 @@ pub enum MessageToolArgs {
         /// Message ID
         message_id: u64,
     },
+    /// Get a message and all of its replies, following reply_to
+    GetReplies {
+        /// Thread ID
+        thread_id: u64,
+        /// Message ID of the root message
+        message_id: u64,
+    },
     /// List messages in a thread (倒序分页：cursor 为上一页最早消息的 _id)
     List {
         thread_id: u64,
@@ -1359,13 +3220,36 @@ pub enum MessageToolArgs {
     },
     /// Delete the latest message (只能删除最新一条且必须本人)
     Delete { thread_id: u64, message_id: u64 },
+    /// Search messages across all my threads, newest first
+    Search {
+        /// Full-text query on message content
+        query: String,
+        /// default 100, max 1000
+        limit: Option<usize>,
+    },
 }
 
 /// A tool for thread messages API
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct MessageTool {
     nexus: Arc<NexusNode>,
     schema: Json,
+    examples: Vec<Json>,
+    title_model: Option<Model>,
+    untitled_names: Vec<String>,
+}
+
+impl std::fmt::Debug for MessageTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the title model is not Debug
+        f.debug_struct("MessageTool")
+            .field("nexus", &self.nexus)
+            .field("schema", &self.schema)
+            .field("examples", &self.examples)
+            .field("auto_title", &self.title_model.is_some())
+            .field("untitled_names", &self.untitled_names)
+            .finish()
+    }
 }
 
 impl MessageTool {
@@ -1373,7 +3257,34 @@ impl MessageTool {
 
     pub fn new(nexus: Arc<NexusNode>) -> Self {
         let schema = gen_schema_for::<MessageToolArgs>();
-        Self { nexus, schema }
+        let examples = vec![
+            json!({"type": "add", "thread_id": 1, "message": "Hello!", "reply_to": null, "role": null}),
+            json!({"type": "list", "thread_id": 1, "cursor": null, "limit": 20}),
+        ];
+        Self {
+            nexus,
+            schema,
+            examples,
+            title_model: None,
+            untitled_names: DEFAULT_UNTITLED_NAMES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+
+    /// Generates a thread title with the model when the first message is added to an
+    /// untitled thread, see [`NexusNode::auto_title_thread`]. The title is generated in
+    /// the background and does not delay the add. Disabled by default.
+    pub fn with_auto_title(mut self, model: Model) -> Self {
+        self.title_model = Some(model);
+        self
+    }
+
+    /// Sets the thread names treated as untitled, default to [`DEFAULT_UNTITLED_NAMES`].
+    pub fn with_untitled_names(mut self, names: Vec<String>) -> Self {
+        self.untitled_names = names;
+        self
     }
 }
 
@@ -1395,6 +3306,7 @@ impl Tool<BaseCtx> for MessageTool {
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: self.examples.clone(),
         }
     }
 
@@ -1405,26 +3317,43 @@ impl Tool<BaseCtx> for MessageTool {
         resources: Vec<Resource>,
     ) -> Result<ToolOutput<Self::Output>, BoxError> {
         let caller = ctx.caller().to_owned();
-        if caller == ANONYMOUS {
-            return Err("unauthenticated".into());
-        }
-
         let resp = match args {
             MessageToolArgs::Add {
                 thread_id,
                 message,
                 reply_to,
+                role,
+                idempotency_key,
             } => {
-                let msg = self
+                let text = self.title_model.as_ref().map(|_| message.clone());
+                let (msg, created) = self
                     .nexus
-                    .add_message(
+                    .add_or_get_message(
                         &caller,
                         thread_id,
                         reply_to.unwrap_or_default(),
+                        role.as_deref().unwrap_or("user"),
                         message,
                         resources,
+                        idempotency_key,
                     )
                     .await?;
+                // message IDs start at 1 in each thread
+                if created
+                    && msg._id == 1
+                    && let (Some(model), Some(text)) = (self.title_model.clone(), text)
+                {
+                    let nexus = self.nexus.clone();
+                    let untitled_names = self.untitled_names.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = nexus
+                            .auto_title_thread(&model, thread_id, &text, &untitled_names)
+                            .await
+                        {
+                            log::warn!("Failed to generate title of thread {}: {}", thread_id, err);
+                        }
+                    });
+                }
                 Response::Ok {
                     result: json!(msg),
                     next_cursor: None,
@@ -1445,6 +3374,20 @@ impl Tool<BaseCtx> for MessageTool {
                     ignore: None,
                 }
             }
+            MessageToolArgs::GetReplies {
+                thread_id,
+                message_id,
+            } => {
+                let messages = self
+                    .nexus
+                    .get_message_thread(&caller, thread_id, message_id)
+                    .await?;
+                Response::Ok {
+                    result: json!(messages),
+                    next_cursor: None,
+                    ignore: None,
+                }
+            }
             MessageToolArgs::List {
                 thread_id,
                 cursor,
@@ -1473,6 +3416,20 @@ impl Tool<BaseCtx> for MessageTool {
                     ignore: None,
                 }
             }
+            MessageToolArgs::Search { query, limit } => {
+                let hits = self.nexus.search_my_messages(&caller, query, limit).await?;
+                let hits: Vec<Json> = hits
+                    .into_iter()
+                    .map(|(thread_id, message)| {
+                        json!({ "thread_id": thread_id, "message": message })
+                    })
+                    .collect();
+                Response::Ok {
+                    result: json!(hits),
+                    next_cursor: None,
+                    ignore: None,
+                }
+            }
         };
 
         Ok(ToolOutput::new(resp))
@@ -1523,6 +3480,7 @@ impl Tool<BaseCtx> for GetResourceTool {
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: Vec::new(),
         }
     }
 
@@ -1533,20 +3491,1834 @@ impl Tool<BaseCtx> for GetResourceTool {
         _resources: Vec<Resource>,
     ) -> Result<ToolOutput<Self::Output>, BoxError> {
         let caller = ctx.caller().to_owned();
-        if caller == ANONYMOUS {
-            return Err("unauthenticated".into());
+        let res = self
+            .nexus
+            .get_resource(&caller, args.thread_id, args.resource_id)
+            .await?;
+
+        Ok(ToolOutput::new(res))
+    }
+}
+
+/// Get a resource in a thread by content hash
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetResourceByHashToolArgs {
+    /// Thread ID
+    thread_id: u64,
+    /// SHA3-256 hash of the resource content, base64-url encoded
+    hash: String,
+}
+
+/// A tool for getting thread resources by content hash
+#[derive(Debug, Clone)]
+pub struct GetResourceByHashTool {
+    nexus: Arc<NexusNode>,
+    schema: Json,
+}
+
+impl GetResourceByHashTool {
+    pub const NAME: &'static str = "get_resource_by_hash_api";
+
+    pub fn new(nexus: Arc<NexusNode>) -> Self {
+        let schema = gen_schema_for::<GetResourceByHashToolArgs>();
+        Self { nexus, schema }
+    }
+}
+
+impl Tool<BaseCtx> for GetResourceByHashTool {
+    type Args = GetResourceByHashToolArgs;
+    type Output = Option<Resource>;
+
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        "Get Resource By Hash API".to_string()
+    }
+
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: self.name(),
+            description: self.description(),
+            parameters: self.schema.clone(),
+            strict: Some(true),
+            examples: Vec::new(),
         }
+    }
 
+    async fn call(
+        &self,
+        ctx: BaseCtx,
+        args: Self::Args,
+        _resources: Vec<Resource>,
+    ) -> Result<ToolOutput<Self::Output>, BoxError> {
+        let hash: ByteArrayB64<32> = args
+            .hash
+            .parse()
+            .map_err(|err| format!("invalid hash {:?}: {:?}", args.hash, err))?;
+        let caller = ctx.caller().to_owned();
         let res = self
             .nexus
-            .get_resource(&caller, args.thread_id, args.resource_id)
+            .resource_by_hash(&caller, args.thread_id, &hash)
             .await?;
 
         Ok(ToolOutput::new(res))
     }
 }
 
-fn principals_set_schema(generator: &mut SchemaGenerator) -> Schema {
-    
-    Vec::<String>::json_schema(generator)
+/// Search resources in a thread
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResourcesToolArgs {
+    /// Thread ID
+    thread_id: u64,
+    /// Full-text query on the name, description and metadata of resources
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<String>,
+    /// Tags that the resources must all have, e.g. "pdf"
+    #[serde(default)]
+    tags: Vec<String>,
+    /// MIME type of the resources, e.g. "application/pdf"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime_type: Option<String>,
+    /// Maximum number of resources to return
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<usize>,
+}
+
+/// A tool for searching thread resources
+#[derive(Debug, Clone)]
+pub struct SearchResourcesTool {
+    nexus: Arc<NexusNode>,
+    schema: Json,
+}
+
+impl SearchResourcesTool {
+    pub const NAME: &'static str = "search_resources_api";
+
+    pub fn new(nexus: Arc<NexusNode>) -> Self {
+        let schema = gen_schema_for::<SearchResourcesToolArgs>();
+        Self { nexus, schema }
+    }
+}
+
+impl Tool<BaseCtx> for SearchResourcesTool {
+    type Args = SearchResourcesToolArgs;
+    type Output = Vec<Resource>;
+
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        "Search Resources API".to_string()
+    }
+
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: self.name(),
+            description: self.description(),
+            parameters: self.schema.clone(),
+            strict: Some(true),
+            examples: Vec::new(),
+        }
+    }
+
+    async fn call(
+        &self,
+        ctx: BaseCtx,
+        args: Self::Args,
+        _resources: Vec<Resource>,
+    ) -> Result<ToolOutput<Self::Output>, BoxError> {
+        let caller = ctx.caller().to_owned();
+        let res = self
+            .nexus
+            .search_resources(
+                &caller,
+                args.thread_id,
+                args.query,
+                args.tags,
+                args.mime_type,
+                args.limit,
+            )
+            .await?;
+
+        Ok(ToolOutput::new(res))
+    }
+}
+
+/// List the threads the caller participates in
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ListMyThreadsToolArgs {
+    /// The cursor for pagination, from the previous call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+    /// Maximum number of threads to return, default to 100
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<usize>,
+    /// Only list threads updated at or after this timestamp in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated_since: Option<u64>,
+}
+
+/// A tool for an agent to list the threads of the caller it is assisting
+#[derive(Debug, Clone)]
+pub struct ListMyThreadsTool {
+    nexus: Arc<NexusNode>,
+    schema: Json,
+}
+
+impl ListMyThreadsTool {
+    pub const NAME: &'static str = "list_my_threads_api";
+
+    pub fn new(nexus: Arc<NexusNode>) -> Self {
+        let schema = gen_schema_for::<ListMyThreadsToolArgs>();
+        Self { nexus, schema }
+    }
+}
+
+impl Tool<BaseCtx> for ListMyThreadsTool {
+    type Args = ListMyThreadsToolArgs;
+    type Output = Response;
+
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        "List the threads the current user participates in".to_string()
+    }
+
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: self.name(),
+            description: self.description(),
+            parameters: self.schema.clone(),
+            strict: Some(true),
+            examples: Vec::new(),
+        }
+    }
+
+    async fn call(
+        &self,
+        ctx: BaseCtx,
+        args: Self::Args,
+        _resources: Vec<Resource>,
+    ) -> Result<ToolOutput<Self::Output>, BoxError> {
+        let caller = ctx.caller().to_owned();
+        let (threads, next_cursor) = self
+            .nexus
+            .list_my_threads(&caller, args.cursor, args.limit, args.updated_since)
+            .await?;
+
+        Ok(ToolOutput::new(Response::Ok {
+            result: json!(threads),
+            next_cursor,
+            ignore: None,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ResourceUploadToolArgs {
+    /// Begin a chunked upload of a resource to a thread
+    Begin {
+        /// Thread ID
+        thread_id: u64,
+        /// The name of the resource
+        name: String,
+        /// Tags of the resource, e.g. "image", "png"
+        #[serde(default)]
+        tags: Vec<String>,
+        /// MIME type of the resource, e.g. "image/png"
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+        /// The description of the resource
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+    },
+    /// Append a chunk of data to an upload
+    Append {
+        /// The upload ID returned by begin
+        upload_id: String,
+        /// The chunk of data, base64-url encoded
+        chunk: String,
+    },
+    /// Finish an upload and add the resource to the thread
+    Finish {
+        /// The upload ID returned by begin
+        upload_id: String,
+    },
+}
+
+/// A tool for uploading large resources to a thread in chunks
+#[derive(Debug, Clone)]
+pub struct ResourceUploadTool {
+    nexus: Arc<NexusNode>,
+    schema: Json,
+}
+
+impl ResourceUploadTool {
+    pub const NAME: &'static str = "resource_upload_api";
+
+    pub fn new(nexus: Arc<NexusNode>) -> Self {
+        let schema = gen_schema_for::<ResourceUploadToolArgs>();
+        Self { nexus, schema }
+    }
+}
+
+impl Tool<BaseCtx> for ResourceUploadTool {
+    type Args = ResourceUploadToolArgs;
+    type Output = Response;
+
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        "Resource Upload API".to_string()
+    }
+
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: self.name(),
+            description: self.description(),
+            parameters: self.schema.clone(),
+            strict: Some(true),
+            examples: Vec::new(),
+        }
+    }
+
+    async fn call(
+        &self,
+        ctx: BaseCtx,
+        args: Self::Args,
+        _resources: Vec<Resource>,
+    ) -> Result<ToolOutput<Self::Output>, BoxError> {
+        let caller = ctx.caller().to_owned();
+        let result = match args {
+            ResourceUploadToolArgs::Begin {
+                thread_id,
+                name,
+                tags,
+                mime_type,
+                description,
+            } => {
+                let upload_id = self
+                    .nexus
+                    .begin_resource_upload(
+                        &caller,
+                        thread_id,
+                        Resource {
+                            name,
+                            tags,
+                            mime_type,
+                            description,
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                json!({ "upload_id": upload_id })
+            }
+            ResourceUploadToolArgs::Append { upload_id, chunk } => {
+                let upload_id = parse_upload_id(&upload_id)?;
+                let chunk: ByteBufB64 = chunk
+                    .parse()
+                    .map_err(|err| format!("invalid chunk: {:?}", err))?;
+                let size = self.nexus.append_chunk(&caller, &upload_id, &chunk)?;
+                json!({ "size": size })
+            }
+            ResourceUploadToolArgs::Finish { upload_id } => {
+                let upload_id = parse_upload_id(&upload_id)?;
+                let resource_id = self
+                    .nexus
+                    .finish_resource_upload(&caller, &upload_id)
+                    .await?;
+                json!({ "resource_id": resource_id })
+            }
+        };
+
+        Ok(ToolOutput::new(Response::Ok {
+            result,
+            next_cursor: None,
+            ignore: None,
+        }))
+    }
+}
+
+fn parse_upload_id(upload_id: &str) -> Result<Xid, BoxError> {
+    upload_id
+        .parse()
+        .map_err(|err| format!("invalid upload ID {:?}: {:?}", upload_id, err).into())
+}
+
+/// Summarize the recent messages of a thread
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SummarizeThreadToolArgs {
+    /// The thread ID
+    thread_id: u64,
+    /// Number of recent messages to summarize, default to 50
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<usize>,
+}
+
+/// A tool for an agent to summarize the recent messages of a thread with the model.
+/// The caller must be able to read the thread, the token usage is reported in the tool output.
+#[derive(Clone)]
+pub struct SummarizeThreadTool {
+    nexus: Arc<NexusNode>,
+    model: Model,
+    max_messages: usize,
+    schema: Json,
+}
+
+impl SummarizeThreadTool {
+    pub const NAME: &'static str = "summarize_thread_api";
+
+    pub fn new(nexus: Arc<NexusNode>, model: Model) -> Self {
+        let schema = gen_schema_for::<SummarizeThreadToolArgs>();
+        Self {
+            nexus,
+            model,
+            max_messages: MAX_SUMMARIZE_MESSAGES,
+            schema,
+        }
+    }
+
+    /// Sets the maximum number of recent messages summarized in one call,
+    /// default to [`MAX_SUMMARIZE_MESSAGES`].
+    pub fn with_max_messages(mut self, max: usize) -> Self {
+        self.max_messages = max.max(1);
+        self
+    }
+}
+
+impl Tool<BaseCtx> for SummarizeThreadTool {
+    type Args = SummarizeThreadToolArgs;
+    type Output = Response;
+
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        "Summarize the recent messages of a thread as a short TL;DR".to_string()
+    }
+
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: self.name(),
+            description: self.description(),
+            parameters: self.schema.clone(),
+            strict: Some(true),
+            examples: Vec::new(),
+        }
+    }
+
+    async fn call(
+        &self,
+        ctx: BaseCtx,
+        args: Self::Args,
+        _resources: Vec<Resource>,
+    ) -> Result<ToolOutput<Self::Output>, BoxError> {
+        let caller = ctx.caller().to_owned();
+        let limit = args
+            .limit
+            .unwrap_or(DEFAULT_SUMMARIZE_MESSAGES)
+            .clamp(1, self.max_messages);
+        let (messages, _) = self
+            .nexus
+            .list_messages(&caller, args.thread_id, None, Some(limit))
+            .await?;
+
+        let transcript: Vec<String> = messages
+            .iter()
+            .filter_map(|m| {
+                let text = anda_core::Message {
+                    content: m.content.clone(),
+                    ..Default::default()
+                }
+                .text()?;
+                let sender = m.user.map(|u| u.to_text()).unwrap_or(m.role.clone());
+                Some(format!("[{}] {} ({}): {}", m._id, sender, m.role, text))
+            })
+            .collect();
+        if transcript.is_empty() {
+            return Ok(ToolOutput::new(Response::Ok {
+                result: json!({"summary": "", "messages": 0}),
+                next_cursor: None,
+                ignore: None,
+            }));
+        }
+
+        let output = self
+            .model
+            .completion(CompletionRequest {
+                instructions: "You summarize chat threads. Write a concise TL;DR of the conversation, keeping key decisions, open questions and action items. Reply in the language of the conversation.".to_string(),
+                prompt: transcript.join("\n"),
+                ..Default::default()
+            })
+            .await?;
+        if !output.is_truncated()
+            && let Some(failed_reason) = output.failed_reason
+        {
+            return Err(failed_reason.into());
+        }
+
+        let mut res = ToolOutput::new(Response::Ok {
+            result: json!({"summary": output.content, "messages": transcript.len()}),
+            next_cursor: None,
+            ignore: None,
+        });
+        res.usage = output.usage;
+        Ok(res)
+    }
+}
+
+async fn deliver_webhook(webhooks: &Webhooks, url: &str, digest: [u8; 32], body: Vec<u8>) {
+    let mut delay = Duration::from_secs(1);
+    for attempt in 1..=WEBHOOK_MAX_ATTEMPTS {
+        match send_webhook(webhooks, url, digest, body.clone()).await {
+            Ok(status) if status.is_success() => return,
+            Ok(status) => {
+                log::warn!(
+                    "Webhook {} returned status {}, attempt {}",
+                    url,
+                    status,
+                    attempt
+                );
+            }
+            Err(err) => {
+                log::warn!("Webhook {} failed: {}, attempt {}", url, err, attempt);
+            }
+        }
+
+        if attempt < WEBHOOK_MAX_ATTEMPTS {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+    log::error!(
+        "Failed to deliver webhook to {} after {} attempts",
+        url,
+        WEBHOOK_MAX_ATTEMPTS
+    );
+}
+
+async fn send_webhook(
+    webhooks: &Webhooks,
+    url: &str,
+    digest: [u8; 32],
+    body: Vec<u8>,
+) -> Result<http::StatusCode, BoxError> {
+    let mut headers = http::HeaderMap::new();
+    headers.insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static(CONTENT_TYPE_JSON),
+    );
+    // signed on every attempt, as the envelope expires
+    let se = webhooks.signer.sign_envelope(digest).await?;
+    se.to_authorization(&mut headers)?;
+    let res = webhooks
+        .policy
+        .send(url, http::Method::POST, Some(headers), Some(body))
+        .await?;
+    Ok(res.status())
+}
+
+fn principals_set_schema(_generator: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": "array",
+        "items": {
+            "type": "string",
+            "pattern": "^[a-z2-7]{1,5}(-[a-z2-7]{1,5})*$",
+            "description": "An ICP principal in textual form, e.g. \"2vxsx-fae\" or \"rrkah-fqaaa-aaaaa-aaaaq-cai\""
+        },
+        "uniqueItems": true
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anda_core::ToolInput;
+    use anda_db::database::DBConfig;
+    use anda_engine::engine::{AgentInfo, EchoEngineInfo, Engine, EngineBuilder};
+    use object_store::{
+        GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+        PutMultipartOptions, PutOptions, PutPayload, PutResult, memory::InMemory, path::Path,
+    };
+
+    async fn nexus_node() -> NexusNode {
+        NexusNode::connect(test_db().await).await.unwrap()
+    }
+
+    async fn test_db() -> Arc<AndaDB> {
+        let db = AndaDB::connect(Arc::new(InMemory::new()), DBConfig::default())
+            .await
+            .unwrap();
+        Arc::new(db)
+    }
+
+    #[tokio::test]
+    async fn test_add_message_idempotency_key() {
+        let nexus = Arc::new(nexus_node().await);
+        let user = Principal::from_slice(&[1; 29]);
+        let thread = nexus
+            .create_thread(user, "Test".to_string(), None, None)
+            .await
+            .unwrap();
+
+        let add = |key: &str| {
+            let nexus = nexus.clone();
+            let key = key.to_string();
+            async move {
+                nexus
+                    .add_message(
+                        &user,
+                        thread._id,
+                        0,
+                        "user",
+                        "Hello".to_string(),
+                        vec![],
+                        Some(key),
+                    )
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let (m1, m2) = tokio::join!(add("req-1"), add("req-1"));
+        assert_eq!(m1._id, m2._id);
+        assert_eq!(m1.idempotency_key.as_deref(), Some("req-1"));
+        let m3 = add("req-1").await;
+        assert_eq!(m3._id, m1._id);
+        let m4 = add("req-2").await;
+        assert_ne!(m4._id, m1._id);
+        assert!(nexus.idempotency_locks.lock().is_empty());
+
+        let found = nexus
+            .find_message_by_idempotency_key(&user, thread._id, "req-2")
+            .await
+            .unwrap();
+        assert_eq!(found.map(|m| m._id), Some(m4._id));
+
+        let (messages, _) = nexus
+            .list_messages(&user, thread._id, None, None)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 2);
+
+        assert!(
+            nexus
+                .add_message(
+                    &user,
+                    thread._id,
+                    0,
+                    "user",
+                    "Hello".to_string(),
+                    vec![],
+                    Some("".to_string()),
+                )
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resource_upload() {
+        let nexus = nexus_node().await.with_upload_limits(2, 10);
+        let user = Principal::from_slice(&[1; 29]);
+        let other = Principal::from_slice(&[2; 29]);
+        let thread = nexus
+            .create_thread(user, "Test".to_string(), None, None)
+            .await
+            .unwrap();
+        let resource = Resource {
+            name: "hello.txt".to_string(),
+            tags: vec!["text".to_string()],
+            ..Default::default()
+        };
+
+        assert!(
+            nexus
+                .begin_resource_upload(&other, thread._id, resource.clone())
+                .await
+                .is_err()
+        );
+        let id1 = nexus
+            .begin_resource_upload(&user, thread._id, resource.clone())
+            .await
+            .unwrap();
+        let id2 = nexus
+            .begin_resource_upload(&user, thread._id, resource.clone())
+            .await
+            .unwrap();
+        assert!(
+            nexus
+                .begin_resource_upload(&user, thread._id, resource.clone())
+                .await
+                .is_err()
+        );
+
+        assert!(nexus.append_chunk(&other, &id1, b"hello").is_err());
+        assert_eq!(nexus.append_chunk(&user, &id1, b"hello").unwrap(), 5);
+        assert_eq!(nexus.append_chunk(&user, &id2, b"world").unwrap(), 5);
+        // the total buffered size is capped across uploads
+        assert!(nexus.append_chunk(&user, &id1, b"!").is_err());
+        assert_eq!(nexus.uploads.read().total_bytes, 10);
+
+        let resource_id = nexus.finish_resource_upload(&user, &id1).await.unwrap();
+        assert_eq!(nexus.uploads.read().total_bytes, 5);
+        assert!(nexus.finish_resource_upload(&user, &id1).await.is_err());
+        let res = nexus
+            .get_resource(&user, thread._id, resource_id)
+            .await
+            .unwrap();
+        assert_eq!(res.name, "hello.txt");
+        assert_eq!(res.size, Some(5));
+        assert_eq!(res.blob.unwrap().as_ref(), b"hello");
+
+        assert_eq!(nexus.append_chunk(&user, &id2, b"!").unwrap(), 6);
+        assert!(
+            nexus
+                .begin_resource_upload(&user, thread._id, resource)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_thread() {
+        let db = test_db().await;
+        let user = Principal::from_slice(&[1; 29]);
+        let other = Principal::from_slice(&[2; 29]);
+        let url = "https://1.1.1.1/hook".to_string();
+
+        let nexus = NexusNode::connect(db.clone()).await.unwrap();
+        let thread = nexus
+            .create_thread(user, "Test".to_string(), None, None)
+            .await
+            .unwrap();
+        assert!(
+            nexus
+                .subscribe_thread(&user, thread._id, url.clone())
+                .await
+                .is_err()
+        );
+
+        let nexus = NexusNode::connect(db.clone())
+            .await
+            .unwrap()
+            .with_webhooks(Arc::new(Web3SDK::not_implemented()), HttpPolicy::new());
+        for bad in [
+            "http://1.1.1.1/hook",
+            "https://127.0.0.1/hook",
+            "https://10.0.0.1/hook",
+            "https://[::1]/hook",
+            "https://169.254.169.254/latest/meta-data",
+        ] {
+            assert!(
+                nexus
+                    .subscribe_thread(&user, thread._id, bad.to_string())
+                    .await
+                    .is_err(),
+                "{bad}"
+            );
+        }
+        assert!(
+            nexus
+                .subscribe_thread(&other, thread._id, url.clone())
+                .await
+                .is_err()
+        );
+        nexus
+            .subscribe_thread(&user, thread._id, url.clone())
+            .await
+            .unwrap();
+        nexus
+            .add_thread_participants(&user, thread._id, BTreeSet::from([other]))
+            .await
+            .unwrap();
+        nexus
+            .subscribe_thread(&other, thread._id, url.clone())
+            .await
+            .unwrap();
+
+        // subscriptions are loaded when connecting
+        let nexus = NexusNode::connect(db.clone())
+            .await
+            .unwrap()
+            .with_webhooks(Arc::new(Web3SDK::not_implemented()), HttpPolicy::new());
+        assert_eq!(nexus.subscriptions.read()[&thread._id].len(), 2);
+
+        // adding a message drops the subscriptions of users who left the thread
+        nexus.quit_thread(&other, thread._id).await.unwrap();
+        nexus
+            .add_message(
+                &user,
+                thread._id,
+                0,
+                "user",
+                "Hello".to_string(),
+                vec![],
+                None,
+            )
+            .await
+            .unwrap();
+        let subs = nexus.subscriptions.read()[&thread._id].clone();
+        assert_eq!(subs.keys().collect::<Vec<_>>(), vec![&user]);
+
+        assert!(nexus.unsubscribe_thread(&user, thread._id).await.unwrap());
+        assert!(!nexus.unsubscribe_thread(&user, thread._id).await.unwrap());
+        let nexus = NexusNode::connect(db).await.unwrap();
+        assert!(nexus.subscriptions.read().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_indexes() {
+        let nexus = nexus_node().await;
+        let user = Principal::from_slice(&[1; 29]);
+        let thread = nexus
+            .create_thread(user, "Test".to_string(), None, None)
+            .await
+            .unwrap();
+        for (content, key) in [("apple", "k1"), ("banana", "k2"), ("cherry", "k3")] {
+            nexus
+                .add_message(
+                    &user,
+                    thread._id,
+                    0,
+                    "user",
+                    content.to_string(),
+                    vec![],
+                    Some(key.to_string()),
+                )
+                .await
+                .unwrap();
+        }
+        nexus.delete_message(&user, thread._id, 3).await.unwrap();
+
+        // corrupt the indexes of the message collection
+        let name = NexusNode::thread_message_collection_name(thread._id);
+        let collection = nexus.get_message_collection(thread._id).await.unwrap();
+        let now_ms = unix_ms();
+        let keys = collection.get_btree_index(&["idempotency_key"]).unwrap();
+        assert!(keys.remove(1, &Fv::Text("k1".to_string()), now_ms));
+        keys.insert(2, &Fv::Text("ghost".to_string()), now_ms)
+            .unwrap();
+        let contents = collection.get_bm25_index(&["content"]).unwrap();
+        assert!(contents.remove(1, "apple", now_ms));
+        assert!(
+            nexus
+                .find_message_by_idempotency_key(&user, thread._id, "k1")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            nexus
+                .search_my_messages(&user, "apple".to_string(), None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        assert!(nexus.rebuild_indexes("messages").await.is_err());
+        assert_eq!(nexus.rebuild_indexes(&name).await.unwrap(), 2);
+        let msg = nexus
+            .find_message_by_idempotency_key(&user, thread._id, "k1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(msg._id, 1);
+        assert!(
+            nexus
+                .find_message_by_idempotency_key(&user, thread._id, "ghost")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let rt = nexus
+            .search_my_messages(&user, "apple".to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(rt.len(), 1);
+        assert_eq!(rt[0].1._id, 1);
+        assert!(nexus.get_message(&user, thread._id, 3).await.is_err());
+        assert_eq!(
+            nexus.get_message(&user, thread._id, 2).await.unwrap()._id,
+            2
+        );
+        let msg = nexus
+            .add_message(
+                &user,
+                thread._id,
+                0,
+                "user",
+                "durian".to_string(),
+                vec![],
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(msg._id, 4);
+        assert!(
+            !nexus
+                .db
+                .metadata()
+                .collections
+                .contains(&format!("{}_backup", name))
+        );
+
+        assert_eq!(nexus.rebuild_indexes("threads").await.unwrap(), 1);
+        let thread2 = nexus
+            .create_thread(user, "Test 2".to_string(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(thread2._id, thread._id + 1);
+        assert_eq!(
+            nexus.get_thread(&user, thread._id).await.unwrap().name,
+            "Test"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_rebuild_threads_with_removed_thread() {
+        let nexus = nexus_node().await;
+        let user = Principal::from_slice(&[1; 29]);
+        let mut threads = Vec::new();
+        for name in ["A", "B", "C"] {
+            threads.push(
+                nexus
+                    .create_thread(user, name.to_string(), None, None)
+                    .await
+                    .unwrap(),
+            );
+        }
+        nexus.delete_thread(&user, threads[1]._id).await.unwrap();
+
+        assert_eq!(nexus.rebuild_indexes("threads").await.unwrap(), 2);
+        assert!(!nexus.db.metadata().collections.contains("threads_backup"));
+        for thread in [&threads[0], &threads[2]] {
+            let ids = nexus
+                .threads()
+                .query_ids(
+                    Filter::Field((
+                        "id".to_string(),
+                        RangeQuery::Eq(Fv::Bytes(thread.id.0.to_vec())),
+                    )),
+                    None,
+                )
+                .await
+                .unwrap();
+            assert_eq!(ids, vec![thread._id]);
+        }
+        assert!(nexus.get_thread(&user, threads[1]._id).await.is_err());
+        let (list, _) = nexus
+            .list_my_threads(&user, None, None, None)
+            .await
+            .unwrap();
+        let mut ids: Vec<u64> = list.iter().map(|t| t._id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![threads[0]._id, threads[2]._id]);
+
+        let thread = nexus
+            .create_thread(user, "D".to_string(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(thread._id, threads[2]._id + 1);
+        assert_eq!(nexus.get_thread(&user, thread._id).await.unwrap().name, "D");
+        assert_eq!(
+            nexus.get_thread(&user, threads[2]._id).await.unwrap().name,
+            "C"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_mark_read() {
+        let nexus = Arc::new(nexus_node().await);
+        let owner = Principal::from_slice(&[1; 29]);
+        let other = Principal::from_slice(&[2; 29]);
+        let thread = nexus
+            .create_thread(owner, "Test".to_string(), None, None)
+            .await
+            .unwrap();
+        nexus
+            .add_thread_participants(&owner, thread._id, BTreeSet::from([other]))
+            .await
+            .unwrap();
+        let add = async |user: &Principal| {
+            nexus
+                .add_message(
+                    user,
+                    thread._id,
+                    0,
+                    "user",
+                    "Hello".to_string(),
+                    vec![],
+                    None,
+                )
+                .await
+                .unwrap()
+                ._id
+        };
+
+        add(&owner).await;
+        add(&owner).await;
+        let id = add(&other).await;
+        // own messages are not unread
+        assert_eq!(nexus.unread_count(&other, thread._id).await.unwrap(), 2);
+        assert_eq!(nexus.unread_count(&owner, thread._id).await.unwrap(), 1);
+
+        assert_eq!(nexus.mark_read(&other, thread._id, id).await.unwrap(), id);
+        assert_eq!(nexus.mark_read(&other, thread._id, 1).await.unwrap(), id);
+        assert_eq!(nexus.unread_count(&other, thread._id).await.unwrap(), 0);
+        assert!(nexus.mark_read(&other, thread._id, id + 1).await.is_err());
+        add(&owner).await;
+        assert_eq!(nexus.unread_count(&other, thread._id).await.unwrap(), 1);
+
+        // concurrent updates of the participants do not overwrite each other
+        let users: Vec<Principal> = (3..13u8).map(|i| Principal::from_slice(&[i; 29])).collect();
+        let adds = users.iter().map(|user| {
+            let nexus = nexus.clone();
+            let user = *user;
+            tokio::spawn(async move {
+                nexus
+                    .add_thread_participants(&owner, thread._id, BTreeSet::from([user]))
+                    .await
+                    .unwrap();
+            })
+        });
+        let reads = [owner, other].map(|user| {
+            let nexus = nexus.clone();
+            tokio::spawn(async move {
+                nexus.mark_read(&user, thread._id, id + 1).await.unwrap();
+            })
+        });
+        for task in adds.collect::<Vec<_>>().into_iter().chain(reads) {
+            task.await.unwrap();
+        }
+
+        let thread: Thread = nexus.threads().get_as(thread._id).await.unwrap();
+        assert_eq!(thread.participants.len(), 12);
+        assert_eq!(thread.participants.get(&owner), Some(&(id + 1)));
+        assert_eq!(thread.participants.get(&other), Some(&(id + 1)));
+        assert!(users.iter().all(|u| thread.participants.get(u) == Some(&0)));
+        assert!(nexus.participants_locks.lock().is_empty());
+    }
+
+    /// Wraps an in-memory store and fails writes while `fail` is set.
+    #[derive(Debug, Default)]
+    struct FailingStore {
+        inner: InMemory,
+        fail: std::sync::atomic::AtomicBool,
+    }
+
+    impl std::fmt::Display for FailingStore {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "FailingStore")
+        }
+    }
+
+    impl FailingStore {
+        fn check(&self) -> object_store::Result<()> {
+            if self.fail.load(std::sync::atomic::Ordering::SeqCst) {
+                return Err(object_store::Error::NotImplemented);
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ObjectStore for FailingStore {
+        async fn put_opts(
+            &self,
+            location: &Path,
+            payload: PutPayload,
+            opts: PutOptions,
+        ) -> object_store::Result<PutResult> {
+            self.check()?;
+            self.inner.put_opts(location, payload, opts).await
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOptions,
+        ) -> object_store::Result<Box<dyn MultipartUpload>> {
+            self.check()?;
+            self.inner.put_multipart_opts(location, opts).await
+        }
+
+        async fn get_opts(
+            &self,
+            location: &Path,
+            options: GetOptions,
+        ) -> object_store::Result<GetResult> {
+            self.inner.get_opts(location, options).await
+        }
+
+        async fn delete(&self, location: &Path) -> object_store::Result<()> {
+            self.check()?;
+            self.inner.delete(location).await
+        }
+
+        fn list(
+            &self,
+            prefix: Option<&Path>,
+        ) -> futures::stream::BoxStream<'static, object_store::Result<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        async fn list_with_delimiter(
+            &self,
+            prefix: Option<&Path>,
+        ) -> object_store::Result<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+            self.check()?;
+            self.inner.copy(from, to).await
+        }
+
+        async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+            self.check()?;
+            self.inner.copy_if_not_exists(from, to).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush_policy() {
+        let store = Arc::new(FailingStore::default());
+        let db = AndaDB::connect(store.clone(), DBConfig::default())
+            .await
+            .unwrap();
+        let nexus = NexusNode::connect(Arc::new(db))
+            .await
+            .unwrap()
+            .with_flush_policy(FlushPolicy {
+                max_pending: 3,
+                max_delay_ms: 60_000,
+            });
+        let user = Principal::from_slice(&[1; 29]);
+        let thread = nexus
+            .create_thread(user, "Test".to_string(), None, None)
+            .await
+            .unwrap();
+        let add = async |content: &str| {
+            nexus
+                .add_message(
+                    &user,
+                    thread._id,
+                    0,
+                    "user",
+                    content.to_string(),
+                    vec![],
+                    None,
+                )
+                .await
+                .unwrap();
+        };
+        let pending = || nexus.pending_flushes.read().get(&thread._id).cloned();
+
+        add("1").await;
+        add("2").await;
+        let (count, oldest) = pending().unwrap();
+        assert_eq!(count, 2);
+        // not expired yet
+        assert_eq!(nexus.flush_expired(oldest + 59_999).await, 0);
+        assert_eq!(pending().unwrap().0, 2);
+
+        // the pending messages are kept if the flush fails
+        store.fail.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(nexus.flush_expired(oldest + 60_000).await, 0);
+        assert_eq!(pending(), Some((2, oldest)));
+
+        store.fail.store(false, std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(nexus.flush_expired(oldest + 60_000).await, 1);
+        assert_eq!(pending(), None);
+
+        // flushes once max_pending messages are unflushed
+        add("3").await;
+        add("4").await;
+        assert_eq!(pending().unwrap().0, 2);
+        add("5").await;
+        assert_eq!(pending(), None);
+    }
+
+    #[tokio::test]
+    async fn test_flush_ticker() {
+        let nexus = NexusNode::connect(test_db().await)
+            .await
+            .unwrap()
+            .with_flush_policy(FlushPolicy {
+                max_pending: 10,
+                max_delay_ms: 200,
+            });
+        let nexus = Arc::new(nexus);
+        let user = Principal::from_slice(&[1; 29]);
+        let thread = nexus
+            .create_thread(user, "Test".to_string(), None, None)
+            .await
+            .unwrap();
+        nexus
+            .add_message(
+                &user,
+                thread._id,
+                0,
+                "user",
+                "Hello".to_string(),
+                vec![],
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(nexus.pending_flushes.read().contains_key(&thread._id));
+
+        let cancel_token = CancellationToken::new();
+        let ticker = nexus.clone().spawn_flush_ticker(cancel_token.clone());
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(!nexus.pending_flushes.read().contains_key(&thread._id));
+
+        cancel_token.cancel();
+        ticker.await.unwrap();
+    }
+
+    /// Replies with a fixed title, optionally renaming the thread while "generating" it.
+    struct TitleCompleter {
+        title: String,
+        rename: Option<(Arc<NexusNode>, Principal, u64)>,
+    }
+
+    impl anda_engine::model::CompletionFeaturesDyn for TitleCompleter {
+        fn completion(
+            &self,
+            req: CompletionRequest,
+        ) -> anda_core::BoxPinFut<Result<anda_core::AgentOutput, BoxError>> {
+            let title = self.title.clone();
+            let rename = self.rename.clone();
+            Box::pin(async move {
+                assert_eq!(req.prompt, "How do I bake bread?");
+                if let Some((nexus, user, thread_id)) = rename {
+                    nexus
+                        .update_thread(
+                            &user,
+                            thread_id,
+                            UpdateThreadInfo {
+                                name: Some("Renamed".to_string()),
+                                ..Default::default()
+                            },
+                        )
+                        .await?;
+                }
+                Ok(anda_core::AgentOutput {
+                    content: title,
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auto_title_thread() {
+        let nexus = Arc::new(nexus_node().await);
+        let user = Principal::from_slice(&[1; 29]);
+        let untitled = DEFAULT_UNTITLED_NAMES
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>();
+        let prompt = "How do I bake bread?";
+        let model = |title: &str, rename: Option<u64>| {
+            Model::with_completer(Arc::new(TitleCompleter {
+                title: title.to_string(),
+                rename: rename.map(|id| (nexus.clone(), user, id)),
+            }))
+        };
+        let new_thread = async || {
+            nexus
+                .create_thread(user, "New chat".to_string(), None, None)
+                .await
+                .unwrap()
+        };
+
+        // the first non-empty line is trimmed of quotes and markdown
+        let thread = new_thread().await;
+        let res = nexus
+            .auto_title_thread(
+                &model("\n  \"Baking Bread\"  \nmore", None),
+                thread._id,
+                prompt,
+                &untitled,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(res.name, "Baking Bread");
+        // a named thread is not titled again
+        let res = nexus
+            .auto_title_thread(&model("Other", None), thread._id, prompt, &untitled)
+            .await
+            .unwrap();
+        assert!(res.is_none());
+
+        // long titles are truncated on a char boundary
+        let thread = new_thread().await;
+        let res = nexus
+            .auto_title_thread(
+                &model(&"面包".repeat(100), None),
+                thread._id,
+                prompt,
+                &untitled,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(res.name.len() <= MAX_THREAD_NAME_LEN);
+        assert_eq!(res.name, "面包".repeat(100)[..126]);
+
+        let thread = new_thread().await;
+        assert!(
+            nexus
+                .auto_title_thread(&model("**\"\"**", None), thread._id, prompt, &untitled)
+                .await
+                .is_err()
+        );
+
+        // the thread was renamed while the title was generated
+        let res = nexus
+            .auto_title_thread(
+                &model("Baking Bread", Some(thread._id)),
+                thread._id,
+                prompt,
+                &untitled,
+            )
+            .await
+            .unwrap();
+        assert!(res.is_none());
+        let thread: Thread = nexus.threads().get_as(thread._id).await.unwrap();
+        assert_eq!(thread.name, "Renamed");
+    }
+
+    async fn tool_engine(tools: ToolSet<BaseCtx>) -> Engine {
+        let names = tools.names();
+        let agent = EchoEngineInfo::new(AgentInfo {
+            handle: "nexus".to_string(),
+            handle_canister: None,
+            name: "Nexus".to_string(),
+            description: "Test Nexus".to_string(),
+            endpoint: "https://localhost:8443/default".to_string(),
+            protocols: BTreeMap::new(),
+            payments: BTreeSet::new(),
+            provider: None,
+        });
+        EngineBuilder::for_test()
+            .register_tools(tools)
+            .unwrap()
+            .register_agent(agent)
+            .unwrap()
+            .export_tools(names)
+            .build("nexus".to_string())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_my_activity_feed() {
+        let nexus = Arc::new(nexus_node().await);
+        let user = Principal::from_slice(&[1; 29]);
+        let mut thread_ids = Vec::new();
+        for i in 0..12 {
+            let thread = nexus
+                .create_thread(user, format!("Thread {i}"), None, None)
+                .await
+                .unwrap();
+            thread_ids.push(thread._id);
+        }
+        // older threads get newer messages
+        for id in thread_ids.iter().rev() {
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+            nexus
+                .add_message(&user, *id, 0, "user", "Hello".to_string(), vec![], None)
+                .await
+                .unwrap();
+        }
+
+        let feed = nexus.my_activity_feed(&user, None).await;
+        assert_eq!(feed.iter().map(|f| f.0).collect::<Vec<_>>(), thread_ids);
+        assert!(feed.iter().all(|f| f.1 == Some(user)));
+        assert!(feed.windows(2).all(|w| w[0].2 > w[1].2));
+        assert_eq!(nexus.my_activity_feed(&user, Some(3)).await, feed[..3]);
+        let other = Principal::from_slice(&[2; 29]);
+        assert!(nexus.my_activity_feed(&other, None).await.is_empty());
+
+        let engine = tool_engine(NexusNode::tools(nexus.clone()).unwrap()).await;
+        let output = engine
+            .tool_call(
+                user,
+                ToolInput::new(
+                    ThreadTool::NAME.to_string(),
+                    json!({"type": "activityFeed", "limit": 2}),
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            output.output["result"],
+            json!([
+                {"thread_id": feed[0].0, "latest_message_by": user.to_text(), "latest_message_at": feed[0].2},
+                {"thread_id": feed[1].0, "latest_message_by": user.to_text(), "latest_message_at": feed[1].2},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_message_thread() {
+        let nexus = Arc::new(nexus_node().await);
+        let user = Principal::from_slice(&[1; 29]);
+        let indexed = nexus
+            .create_thread(user, "Indexed".to_string(), None, None)
+            .await
+            .unwrap();
+        // a thread created before the reply_to index was added
+        let legacy = nexus
+            .create_thread(user, "Legacy".to_string(), None, None)
+            .await
+            .unwrap();
+        let name = NexusNode::thread_message_collection_name(legacy._id);
+        nexus.db.delete_collection(&name).await.unwrap();
+        let _ = nexus
+            .db
+            .create_collection(
+                Message::schema().unwrap(),
+                CollectionConfig {
+                    name,
+                    description: "Thread messages collection".to_string(),
+                },
+                async |collection| {
+                    collection.set_tokenizer(jieba_tokenizer());
+                    collection.create_btree_index_nx(&["user"]).await?;
+                    collection
+                        .create_btree_index_nx(&["idempotency_key"])
+                        .await?;
+                    collection.create_bm25_index_nx(&["content"]).await?;
+                    Ok::<(), DBError>(())
+                },
+            )
+            .await
+            .unwrap();
+
+        for (thread_id, has_index) in [(indexed._id, true), (legacy._id, false)] {
+            let collection = nexus.get_message_collection(thread_id).await.unwrap();
+            assert_eq!(
+                collection.metadata().btree_indexes.contains_key("reply_to"),
+                has_index
+            );
+            // 1 <- 2 <- 4, 1 <- 5, 3 <- 6
+            for reply_to in [0, 1, 0, 2, 1, 3] {
+                nexus
+                    .add_message(
+                        &user,
+                        thread_id,
+                        reply_to,
+                        "user",
+                        "Hello".to_string(),
+                        vec![],
+                        None,
+                    )
+                    .await
+                    .unwrap();
+            }
+            let tree_ids = async |root: u64| {
+                nexus
+                    .get_message_thread(&user, thread_id, root)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|m| m._id)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(tree_ids(1).await, vec![1, 2, 4, 5]);
+            assert_eq!(tree_ids(2).await, vec![2, 4]);
+            assert_eq!(tree_ids(3).await, vec![3, 6]);
+            assert_eq!(tree_ids(6).await, vec![6]);
+            assert!(nexus.get_message_thread(&user, thread_id, 7).await.is_err());
+            let other = Principal::from_slice(&[2; 29]);
+            assert!(
+                nexus
+                    .get_message_thread(&other, thread_id, 1)
+                    .await
+                    .is_err()
+            );
+        }
+
+        let engine = tool_engine(NexusNode::tools(nexus.clone()).unwrap()).await;
+        let output = engine
+            .tool_call(
+                user,
+                ToolInput::new(
+                    MessageTool::NAME.to_string(),
+                    json!({"type": "getReplies", "thread_id": indexed._id, "message_id": 2}),
+                ),
+            )
+            .await
+            .unwrap();
+        let ids: Vec<u64> = output.output["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["_id"].as_u64().unwrap())
+            .collect();
+        assert_eq!(ids, vec![2, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_search_my_messages() {
+        let nexus = Arc::new(nexus_node().await);
+        let user = Principal::from_slice(&[1; 29]);
+        let other = Principal::from_slice(&[2; 29]);
+        let mut threads = Vec::new();
+        for (owner, name) in [(user, "A"), (user, "B"), (other, "C")] {
+            let thread = nexus
+                .create_thread(owner, name.to_string(), None, None)
+                .await
+                .unwrap();
+            threads.push(thread._id);
+        }
+        for (thread_id, owner, content) in [
+            (threads[0], user, "the budget for next year"),
+            (threads[1], user, "lunch at noon"),
+            (threads[2], other, "the budget is secret"),
+            (threads[1], user, "budget review on Friday"),
+        ] {
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+            nexus
+                .add_message(
+                    &owner,
+                    thread_id,
+                    0,
+                    "user",
+                    content.to_string(),
+                    vec![],
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        // newest first, and only from the user's threads
+        let hits = nexus
+            .search_my_messages(&user, "budget".to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            hits.iter().map(|(id, m)| (*id, m._id)).collect::<Vec<_>>(),
+            vec![(threads[1], 2), (threads[0], 1)]
+        );
+        let hits = nexus
+            .search_my_messages(&user, "budget".to_string(), Some(1))
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].0, threads[1]);
+        assert!(
+            nexus
+                .search_my_messages(&user, "dinner".to_string(), None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            nexus
+                .search_my_messages(&user, " ".to_string(), None)
+                .await
+                .is_err()
+        );
+
+        let engine = tool_engine(NexusNode::tools(nexus.clone()).unwrap()).await;
+        let output = engine
+            .tool_call(
+                other,
+                ToolInput::new(
+                    MessageTool::NAME.to_string(),
+                    json!({"type": "search", "query": "budget", "limit": null}),
+                ),
+            )
+            .await
+            .unwrap();
+        let hits = output.output["result"].as_array().unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0]["thread_id"], json!(threads[2]));
+        assert_eq!(hits[0]["message"]["_id"], json!(1));
+    }
+
+    /// Records the prompts and replies with a fixed summary and usage.
+    struct SummaryCompleter {
+        prompts: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl anda_engine::model::CompletionFeaturesDyn for SummaryCompleter {
+        fn completion(
+            &self,
+            req: CompletionRequest,
+        ) -> anda_core::BoxPinFut<Result<anda_core::AgentOutput, BoxError>> {
+            self.prompts.lock().push(req.prompt);
+            Box::pin(futures::future::ready(Ok(anda_core::AgentOutput {
+                content: "TL;DR".to_string(),
+                usage: anda_core::Usage {
+                    input_tokens: 10,
+                    output_tokens: 5,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summarize_thread_tool() {
+        let nexus = Arc::new(nexus_node().await);
+        let user = Principal::from_slice(&[1; 29]);
+        let thread = nexus
+            .create_thread(user, "Test".to_string(), None, None)
+            .await
+            .unwrap();
+        let prompts = Arc::new(Mutex::new(Vec::new()));
+        let model = Model::new(
+            Arc::new(SummaryCompleter {
+                prompts: prompts.clone(),
+            }),
+            Arc::new(anda_engine::model::NotImplemented),
+        );
+        let mut tools = ToolSet::new();
+        tools
+            .add(SummarizeThreadTool::new(nexus.clone(), model).with_max_messages(2))
+            .unwrap();
+        let engine = tool_engine(tools).await;
+        let summarize = async |caller: Principal, limit: Option<usize>| {
+            engine
+                .tool_call(
+                    caller,
+                    ToolInput::new(
+                        SummarizeThreadTool::NAME.to_string(),
+                        json!({"threadId": thread._id, "limit": limit}),
+                    ),
+                )
+                .await
+        };
+
+        // nothing to summarize
+        let output = summarize(user, None).await.unwrap();
+        assert_eq!(
+            output.output["result"],
+            json!({"summary": "", "messages": 0})
+        );
+        assert!(prompts.lock().is_empty());
+
+        for content in ["one", "two", "three"] {
+            nexus
+                .add_message(
+                    &user,
+                    thread._id,
+                    0,
+                    "user",
+                    content.to_string(),
+                    vec![],
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        // the limit is capped by max_messages
+        let output = summarize(user, Some(10)).await.unwrap();
+        assert_eq!(
+            output.output["result"],
+            json!({"summary": "TL;DR", "messages": 2})
+        );
+        assert_eq!(output.usage.input_tokens, 10);
+        assert_eq!(output.usage.output_tokens, 5);
+        let prompt = prompts.lock().pop().unwrap();
+        assert!(prompt.contains("two") && prompt.contains("three"));
+        assert!(!prompt.contains("one"));
+        assert!(prompt.contains(&user.to_text()));
+
+        let other = Principal::from_slice(&[2; 29]);
+        assert!(summarize(other, None).await.is_err());
+        assert!(summarize(Principal::anonymous(), None).await.is_err());
+        assert!(prompts.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unread_count_many_own_messages() {
+        let nexus = nexus_node().await;
+        let owner = Principal::from_slice(&[1; 29]);
+        let other = Principal::from_slice(&[2; 29]);
+        let thread = nexus
+            .create_thread(owner, "Test".to_string(), None, None)
+            .await
+            .unwrap();
+        nexus
+            .add_thread_participants(&owner, thread._id, BTreeSet::from([other]))
+            .await
+            .unwrap();
+        for i in 0..30 {
+            let user = if i % 10 == 0 { &other } else { &owner };
+            nexus
+                .add_message(
+                    user,
+                    thread._id,
+                    0,
+                    "user",
+                    "Hello".to_string(),
+                    vec![],
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(nexus.unread_count(&owner, thread._id).await.unwrap(), 3);
+        assert_eq!(nexus.unread_count(&other, thread._id).await.unwrap(), 27);
+    }
+
+    #[tokio::test]
+    async fn test_unread_count_with_deleted_message() {
+        let nexus = nexus_node().await;
+        let owner = Principal::from_slice(&[1; 29]);
+        let other = Principal::from_slice(&[2; 29]);
+        let thread = nexus
+            .create_thread(owner, "Test".to_string(), None, None)
+            .await
+            .unwrap();
+        nexus
+            .add_thread_participants(&owner, thread._id, BTreeSet::from([other]))
+            .await
+            .unwrap();
+        let add = async |user: &Principal| {
+            nexus
+                .add_message(
+                    user,
+                    thread._id,
+                    0,
+                    "user",
+                    "Hello".to_string(),
+                    vec![],
+                    None,
+                )
+                .await
+                .unwrap()
+                ._id
+        };
+
+        add(&owner).await;
+        add(&other).await;
+        let id = add(&owner).await;
+        assert_eq!(nexus.unread_count(&other, thread._id).await.unwrap(), 2);
+        nexus.delete_message(&owner, thread._id, id).await.unwrap();
+        assert_eq!(nexus.unread_count(&other, thread._id).await.unwrap(), 1);
+        assert_eq!(nexus.unread_count(&owner, thread._id).await.unwrap(), 1);
+
+        // the ID of the deleted message is not reused
+        let id = add(&owner).await;
+        let id2 = add(&owner).await;
+        assert_eq!(nexus.unread_count(&other, thread._id).await.unwrap(), 3);
+        nexus.delete_message(&owner, thread._id, id2).await.unwrap();
+        assert_eq!(nexus.unread_count(&other, thread._id).await.unwrap(), 2);
+
+        // the deleted message after the last-read one is not unread
+        nexus.mark_read(&other, thread._id, id).await.unwrap();
+        assert_eq!(nexus.unread_count(&other, thread._id).await.unwrap(), 0);
+        assert_eq!(nexus.unread_count(&owner, thread._id).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_thread_storage_bytes() {
+        let nexus = nexus_node().await;
+        let user = Principal::from_slice(&[1; 29]);
+        let thread = nexus
+            .create_thread(user, "Test".to_string(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(nexus.thread_storage_bytes(thread._id).await.unwrap(), 0);
+
+        let add = async |content: &str| {
+            nexus
+                .add_message(
+                    &user,
+                    thread._id,
+                    0,
+                    "user",
+                    content.to_string(),
+                    vec![],
+                    None,
+                )
+                .await
+                .unwrap()
+        };
+        let encoded_size = async |id: u64| {
+            let collection = nexus.get_message_collection(thread._id).await.unwrap();
+            let doc = collection.get(id).await.unwrap();
+            let mut buf = Vec::new();
+            ciborium::into_writer(&doc, &mut buf).unwrap();
+            buf.len() as u64
+        };
+
+        let m1 = add("Hello").await;
+        let size1 = nexus.thread_storage_bytes(thread._id).await.unwrap();
+        assert_eq!(size1, encoded_size(m1._id).await);
+
+        let m2 = add(&"x".repeat(1000)).await;
+        let size2 = nexus.thread_storage_bytes(thread._id).await.unwrap();
+        assert_eq!(size2, size1 + encoded_size(m2._id).await);
+        assert!(size2 > size1 + 1000);
+
+        nexus
+            .delete_message(&user, thread._id, m2._id)
+            .await
+            .unwrap();
+        assert_eq!(nexus.thread_storage_bytes(thread._id).await.unwrap(), size1);
+        assert!(nexus.thread_storage_bytes(thread._id + 1).await.is_err());
+    }
+
+    #[test]
+    fn test_principals_set_schema() {
+        fn collect<'a>(schema: &'a Json, out: &mut Vec<&'a Json>) {
+            match schema {
+                Json::Object(obj) => {
+                    if let Some(user_ids) = obj.get("properties").and_then(|p| p.get("user_ids")) {
+                        out.push(user_ids);
+                    }
+                    obj.values().for_each(|v| collect(v, out));
+                }
+                Json::Array(arr) => arr.iter().for_each(|v| collect(v, out)),
+                _ => {}
+            }
+        }
+
+        let schema = gen_schema_for::<ThreadToolArgs>();
+        let mut user_ids = Vec::new();
+        collect(&schema, &mut user_ids);
+        assert_eq!(user_ids.len(), 4);
+        for schema in user_ids {
+            assert_eq!(schema["type"], "array");
+            assert_eq!(schema["uniqueItems"], true);
+            assert_eq!(schema["items"]["type"], "string");
+            assert!(schema["items"].get("format").is_none());
+            assert!(
+                schema["items"]["description"]
+                    .as_str()
+                    .unwrap()
+                    .contains("ICP principal")
+            );
+            assert_eq!(
+                schema["items"]["pattern"],
+                "^[a-z2-7]{1,5}(-[a-z2-7]{1,5})*$"
+            );
+        }
+    }
 }
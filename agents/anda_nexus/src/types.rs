@@ -52,6 +52,7 @@ pub struct Thread {
     #[field_type = "Array<Bytes>"]
     pub managers: BTreeSet<Principal>,
 
+    /// The participants with the ID of the last message they have read, 0 if none.
     #[field_type = "Map<Bytes, U64>"]
     pub participants: BTreeMap<Principal, u64>,
 
@@ -101,6 +102,13 @@ impl Thread {
         }
     }
 
+    /// Returns true if the thread name is empty or one of the placeholder names,
+    /// compared case-insensitively.
+    pub fn is_untitled(&self, untitled_names: &[String]) -> bool {
+        let name = self.name.trim();
+        name.is_empty() || untitled_names.iter().any(|n| n.eq_ignore_ascii_case(name))
+    }
+
     pub fn to_state(&self) -> ThreadState {
         ThreadState {
             visibility: self.visibility,
@@ -145,6 +153,10 @@ pub struct UpdateThreadInfo {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub visibility: Option<ThreadVisibility>,
+
+    /// The maximum number of participants, capped by the engine limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_participants: Option<u64>,
 }
 
 impl UpdateThreadInfo {
@@ -241,7 +253,8 @@ pub struct Message {
     pub content: Vec<ContentPart>,
 
     /// The resources associated with the message.
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Always serialized, the collection schema requires the field.
+    #[serde(default)]
     pub resources: Vec<Resource>,
 
     /// The user ID of the message sender.
@@ -255,6 +268,36 @@ pub struct Message {
 
     #[serde(default)]
     pub reply_to: u64, // 0 means not a reply
+
+    /// The client-supplied idempotency key. Retrying an add with the same key returns
+    /// the existing message instead of adding a new one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+}
+
+/// A webhook subscription of a user to new messages in a thread,
+/// stored in the collection "subscriptions".
+#[derive(Debug, Clone, Deserialize, Serialize, AndaDBSchema)]
+pub struct Subscription {
+    #[serde(default)]
+    pub _id: u64,
+
+    pub thread_id: u64,
+
+    #[field_type = "Bytes"]
+    pub user: Principal,
+
+    /// The HTTPS URL notifications are POSTed to.
+    pub url: String,
+
+    pub created_at: u64,
+}
+
+/// The webhook payload POSTed to thread subscribers when a message is added.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MessageNotification {
+    pub thread_id: u64,
+    pub message: Message,
 }
 
 #[cfg(test)]
@@ -279,4 +322,34 @@ mod tests {
         let t: Thread = serde_json::from_str(&rt).unwrap();
         assert_eq!(t.status, ThreadStatus::Active);
     }
+
+    #[test]
+    fn test_message_idempotency_key() {
+        let schema = Message::schema().unwrap();
+        assert!(schema.get_field("idempotency_key").is_some());
+
+        let mut msg = Message {
+            role: "user".to_string(),
+            ..Default::default()
+        };
+        let rt = serde_json::to_string(&msg).unwrap();
+        assert!(!rt.contains("idempotency_key"));
+
+        msg.idempotency_key = Some("req-1".to_string());
+        let rt = serde_json::to_string(&msg).unwrap();
+        let m: Message = serde_json::from_str(&rt).unwrap();
+        assert_eq!(m.idempotency_key.as_deref(), Some("req-1"));
+    }
+
+    #[test]
+    fn test_thread_is_untitled() {
+        let names = vec!["New chat".to_string()];
+        let mut thread = Thread::default();
+        assert!(thread.is_untitled(&names));
+        thread.name = " new CHAT ".to_string();
+        assert!(thread.is_untitled(&names));
+        thread.name = "Trip to Tokyo".to_string();
+        assert!(!thread.is_untitled(&names));
+        assert!(!thread.is_untitled(&[]));
+    }
 }
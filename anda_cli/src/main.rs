@@ -1,5 +1,8 @@
-use anda_core::{AgentInput, AgentOutput, BoxError, HttpFeatures, ToolInput, ToolOutput};
-use anda_web3_client::client::{Client as Web3Client, load_identity};
+use anda_core::{AgentInput, BoxError, Function, HttpFeatures, ToolInput};
+use anda_web3_client::{
+    client::{Client as Web3Client, SignatureScheme, load_identity_with},
+    rpc::EngineClient,
+};
 use base64::{Engine, prelude::BASE64_URL_SAFE};
 use ciborium::value::Value;
 use clap::{Parser, Subcommand};
@@ -16,6 +19,10 @@ struct Cli {
     #[arg(long, env = "ID_SECRET", default_value = "Anonymous")]
     id: String,
 
+    /// Signature scheme of a 32 bytes identity secret: ed25519 or secp256k1.
+    #[arg(long, env = "ID_SCHEME", default_value = "ed25519")]
+    id_scheme: SignatureScheme,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -71,13 +78,128 @@ pub enum Commands {
         #[arg(short, long)]
         args: String,
     },
+
+    /// List the exported tools on the endpoint.
+    ListTools {
+        #[arg(short, long, default_value = "http://127.0.0.1:8042/default")]
+        endpoint: String,
+    },
+
+    /// List the exported agents on the endpoint.
+    ListAgents {
+        #[arg(short, long, default_value = "http://127.0.0.1:8042/default")]
+        endpoint: String,
+    },
+
+    /// Check that the identity loads, the endpoint is reachable and signed requests are accepted.
+    Doctor {
+        #[arg(short, long, default_value = "http://127.0.0.1:8042/default")]
+        endpoint: String,
+    },
+}
+
+fn print_functions(functions: &[Function]) {
+    for f in functions {
+        println!("{}: {}", f.definition.name, f.definition.description);
+    }
+}
+
+fn report<T>(step: &str, res: Result<T, BoxError>) -> Result<T, BoxError> {
+    match res {
+        Ok(v) => {
+            println!("[ok] {step}");
+            Ok(v)
+        }
+        Err(err) => {
+            println!("[failed] {step}: {err}");
+            Err(format!("doctor failed at step: {step}").into())
+        }
+    }
+}
+
+async fn doctor(
+    host: &str,
+    id: &str,
+    scheme: SignatureScheme,
+    endpoint: &str,
+) -> Result<(), BoxError> {
+    let identity = report("load identity", load_identity_with(id, scheme))?;
+    let principal = report(
+        "resolve principal",
+        identity.sender().map_err(BoxError::from),
+    )?;
+    println!("  principal: {principal}");
+
+    let web3 = report(
+        "build client",
+        Web3Client::builder()
+            .with_ic_host(host)
+            .with_identity(Arc::new(identity))
+            .with_allow_http(true)
+            .build()
+            .await,
+    )?;
+
+    let url = report(
+        "parse endpoint",
+        reqwest::Url::parse(endpoint)
+            .and_then(|u| u.join("/.well-known/information"))
+            .map_err(BoxError::from),
+    )?
+    .to_string();
+    let info: serde_json::Value = report(
+        &format!("ping {url}"),
+        async {
+            let res = web3.https_call(&url, http::Method::GET, None, None).await?;
+            let status = res.status();
+            if !status.is_success() {
+                return Err(format!("unexpected status {status}").into());
+            }
+            Ok(res.json().await?)
+        }
+        .await,
+    )?;
+    println!(
+        "  app: {} {}, commit: {}",
+        info["app_name"].as_str().unwrap_or_default(),
+        info["app_version"].as_str().unwrap_or_default(),
+        info["git_commit"].as_str().unwrap_or_default()
+    );
+
+    let engine = EngineClient::new(web3, endpoint.to_string());
+    let me = report("signed RPC whoami", engine.whoami().await)?;
+    println!(
+        "  caller: {}, controller: {}, manager: {}",
+        me.caller, me.is_controller, me.is_manager
+    );
+    if let Some(reason) = &me.denied_reason {
+        println!("  access denied: {reason}");
+    }
+    report(
+        "verify signature",
+        if me.caller == principal {
+            Ok(())
+        } else {
+            Err(format!(
+                "engine resolved caller {} instead of {principal}",
+                me.caller
+            )
+            .into())
+        },
+    )?;
+
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), BoxError> {
     dotenv::dotenv().ok();
     let cli = Cli::parse();
-    let identity = load_identity(&cli.id)?;
+    if let Some(Commands::Doctor { endpoint }) = &cli.command {
+        return doctor(&cli.host, &cli.id, cli.id_scheme, endpoint).await;
+    }
+
+    let identity = load_identity_with(&cli.id, cli.id_scheme)?;
     println!("principal: {}", identity.sender()?);
 
     match &cli.command {
@@ -136,16 +258,13 @@ async fn main() -> Result<(), BoxError> {
 
             println!("principal: {}", web3.get_principal());
 
-            let res: AgentOutput = web3
-                .https_signed_rpc(
-                    endpoint,
-                    "agent_run",
-                    &(&AgentInput {
-                        name: name.clone().unwrap_or_else(|| "".to_string()),
-                        prompt: prompt.clone(),
-                        ..Default::default()
-                    },),
-                )
+            let engine = EngineClient::new(web3, endpoint.clone());
+            let res = engine
+                .agent_run(&AgentInput {
+                    name: name.clone().unwrap_or_else(|| "".to_string()),
+                    prompt: prompt.clone(),
+                    ..Default::default()
+                })
                 .await?;
             println!("{:?}", res);
         }
@@ -165,20 +284,35 @@ async fn main() -> Result<(), BoxError> {
             println!("principal: {}", web3.get_principal());
             let args: serde_json::Value = serde_json::from_str(args)?;
 
-            let res: ToolOutput<serde_json::Value> = web3
-                .https_signed_rpc(
-                    endpoint,
-                    "tool_call",
-                    &(&ToolInput {
-                        name: name.clone(),
-                        args,
-                        ..Default::default()
-                    },),
-                )
+            let engine = EngineClient::new(web3, endpoint.clone());
+            let res = engine
+                .tool_call(&ToolInput {
+                    name: name.clone(),
+                    args,
+                    ..Default::default()
+                })
                 .await?;
             println!("{}", serde_json::to_string_pretty(&res)?);
         }
 
+        Some(Commands::ListTools { endpoint }) | Some(Commands::ListAgents { endpoint }) => {
+            let web3 = Web3Client::builder()
+                .with_ic_host(&cli.host)
+                .with_identity(Arc::new(identity))
+                .with_allow_http(true)
+                .build()
+                .await?;
+
+            let engine = EngineClient::new(web3, endpoint.clone());
+            let res = engine.information().await?;
+            match &cli.command {
+                Some(Commands::ListTools { .. }) => print_functions(&res.tools),
+                _ => print_functions(&res.agents),
+            }
+        }
+
+        Some(Commands::Doctor { .. }) => unreachable!(),
+
         None => {
             println!("no command");
         }
@@ -88,6 +88,7 @@ where
                 "required": ["prompt"],
             }),
             strict: None,
+            examples: Vec::new(),
         }
     }
 
@@ -43,7 +43,9 @@ pub use anda_db_schema::Json;
 pub use candid::Principal;
 pub use ic_cose_types::CanisterCaller;
 pub use ic_oss_types::object_store::UpdateVersion;
-pub use object_store::{ObjectMeta, PutMode, PutResult, UpdateVersion as OsVersion, path::Path};
+pub use object_store::{
+    ListResult, ObjectMeta, PutMode, PutResult, UpdateVersion as OsVersion, path::Path,
+};
 pub use tokio_util::sync::CancellationToken;
 
 use crate::BoxError;
@@ -334,6 +336,15 @@ pub trait StoreFeatures: Sized {
         offset: &Path,
     ) -> impl Future<Output = Result<Vec<ObjectMeta>, BoxError>> + Send;
 
+    /// Lists objects and common prefixes ("directories") directly under the prefix.
+    ///
+    /// # Arguments
+    /// * `prefix` - Optional path prefix to list under.
+    fn store_list_dirs(
+        &self,
+        prefix: Option<&Path>,
+    ) -> impl Future<Output = Result<ListResult, BoxError>> + Send;
+
     /// Stores data at the specified path with a given write mode.
     ///
     /// # Arguments
@@ -80,6 +80,19 @@ pub struct CanisterRequestRef<'a> {
 /// - Err(String): Error message as a string.
 pub type RPCResponse = Result<ByteBuf, String>;
 
+/// Returns the error message of a failed RPC response body.
+/// The engine server responds with an [`RPCResponse`] error on some non-200 statuses,
+/// e.g. 404 Not Found for a tool or agent that does not exist. Other bodies are returned as text.
+pub fn rpc_error_message(body: &[u8]) -> String {
+    if let Ok(Err(err)) = from_reader::<RPCResponse, _>(body) {
+        return err;
+    }
+    if let Ok(Err(err)) = serde_json::from_slice::<Result<serde_json::Value, String>>(body) {
+        return err;
+    }
+    String::from_utf8_lossy(body).into_owned()
+}
+
 // #[derive(Debug, Deserialize, Serialize)]
 // pub struct ListPagination {
 //     pub id: String,
@@ -240,11 +253,12 @@ pub async fn cbor_rpc(
         })?;
     let status = res.status().as_u16();
     if status != 200 {
+        let body = res.bytes().await.unwrap_or_default();
         return Err(HttpRPCError::ResponseError {
             endpoint: endpoint.to_string(),
             path: path.to_string(),
             status,
-            error: res.text().await.unwrap_or_default(),
+            error: rpc_error_message(&body),
         });
     }
 
@@ -264,3 +278,29 @@ pub async fn cbor_rpc(
         error: format!("{e:?}"),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rpc_error_message() {
+        let res: RPCResponse = Err("failed to call tool: tool foo not found".to_string());
+        assert_eq!(
+            rpc_error_message(&to_cbor_bytes(&res)),
+            "failed to call tool: tool foo not found"
+        );
+        assert_eq!(
+            rpc_error_message(&serde_json::to_vec(&res).unwrap()),
+            "failed to call tool: tool foo not found"
+        );
+        assert_eq!(
+            rpc_error_message(b"engine aaaaa-aa not found"),
+            "engine aaaaa-aa not found"
+        );
+
+        let res: RPCResponse = Ok(ByteBuf::from(b"ok".to_vec()));
+        let body = to_cbor_bytes(&res);
+        assert_eq!(rpc_error_message(&body), String::from_utf8_lossy(&body));
+    }
+}
@@ -1,4 +1,7 @@
 use schemars::{JsonSchema, Schema, generate::SchemaSettings, transform::RestrictFormats};
+use serde_json::Value;
+
+use crate::BoxError;
 
 /// Generate JSON schema for a given type T.
 pub fn root_schema_for<T: JsonSchema>() -> Schema {
@@ -19,6 +22,144 @@ pub fn gen_schema_for<T: JsonSchema>() -> serde_json::Value {
     root_schema_for::<T>().to_value()
 }
 
+/// Validates a JSON value against a JSON schema, such as one generated by [`gen_schema_for`].
+///
+/// Only a subset of draft 2020-12 is supported: `type`, `enum`, `const`, `anyOf`, `oneOf`,
+/// `allOf`, `required`, `properties`, `additionalProperties`, `items`, `minimum` and `maximum`.
+/// Other keywords are ignored. The error message contains the path of the invalid value.
+pub fn validate_json_schema(schema: &Value, value: &Value) -> Result<(), BoxError> {
+    validate_value(schema, value, "$").map_err(|err| err.into())
+}
+
+fn validate_value(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    // `true` or unknown schemas accept everything
+    let Some(schema) = schema.as_object() else {
+        return Ok(());
+    };
+
+    if let Some(ty) = schema.get("type") {
+        let ok = match ty {
+            Value::String(t) => type_matches(t, value),
+            Value::Array(ts) => ts
+                .iter()
+                .filter_map(|t| t.as_str())
+                .any(|t| type_matches(t, value)),
+            _ => true,
+        };
+        if !ok {
+            return Err(format!(
+                "{path}: expected type {ty}, got {}",
+                type_name(value)
+            ));
+        }
+    }
+
+    if let Some(Value::Array(vals)) = schema.get("enum")
+        && !vals.contains(value)
+    {
+        return Err(format!("{path}: value {value} is not one of {vals:?}"));
+    }
+
+    if let Some(val) = schema.get("const")
+        && val != value
+    {
+        return Err(format!("{path}: expected {val}, got {value}"));
+    }
+
+    for key in ["anyOf", "oneOf"] {
+        if let Some(Value::Array(subs)) = schema.get(key)
+            && !subs
+                .iter()
+                .any(|sub| validate_value(sub, value, path).is_ok())
+        {
+            return Err(format!("{path}: value does not match any schema in {key}"));
+        }
+    }
+
+    if let Some(Value::Array(subs)) = schema.get("allOf") {
+        for sub in subs {
+            validate_value(sub, value, path)?;
+        }
+    }
+
+    match value {
+        Value::Object(obj) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for field in required.iter().filter_map(|f| f.as_str()) {
+                    if !obj.contains_key(field) {
+                        return Err(format!("{path}: missing required field {field:?}"));
+                    }
+                }
+            }
+
+            let props = schema.get("properties").and_then(|p| p.as_object());
+            for (key, val) in obj {
+                let child = format!("{path}.{key}");
+                match props.and_then(|p| p.get(key)) {
+                    Some(sub) => validate_value(sub, val, &child)?,
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            return Err(format!("{path}: unknown field {key:?}"));
+                        }
+                        Some(sub @ Value::Object(_)) => validate_value(sub, val, &child)?,
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Value::Array(arr) => {
+            if let Some(items) = schema.get("items") {
+                for (i, val) in arr.iter().enumerate() {
+                    validate_value(items, val, &format!("{path}[{i}]"))?;
+                }
+            }
+        }
+        Value::Number(n) => {
+            if let Some(n) = n.as_f64() {
+                if let Some(min) = schema.get("minimum").and_then(|m| m.as_f64())
+                    && n < min
+                {
+                    return Err(format!("{path}: {n} is less than the minimum {min}"));
+                }
+                if let Some(max) = schema.get("maximum").and_then(|m| m.as_f64())
+                    && n > max
+                {
+                    return Err(format!("{path}: {n} is greater than the maximum {max}"));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn type_matches(ty: &str, value: &Value) -> bool {
+    match ty {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|f| f.fract() == 0.0)
+        }
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -40,4 +181,49 @@ mod tests {
             r#"{"title":"TestStruct","type":"object","properties":{"age":{"type":["integer","null"],"maximum":255,"minimum":0},"name":{"type":"string"}},"required":["name"]}"#
         );
     }
+
+    #[test]
+    fn test_validate_json_schema() {
+        let schema = gen_schema_for::<TestStruct>();
+        assert!(validate_json_schema(&schema, &serde_json::json!({"name": "anda"})).is_ok());
+        assert!(
+            validate_json_schema(&schema, &serde_json::json!({"name": "anda", "age": null}))
+                .is_ok()
+        );
+
+        let err = validate_json_schema(&schema, &serde_json::json!({"age": 18})).unwrap_err();
+        assert_eq!(err.to_string(), r#"$: missing required field "name""#);
+
+        let err = validate_json_schema(&schema, &serde_json::json!({"name": 1})).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"$.name: expected type "string", got number"#
+        );
+
+        let err = validate_json_schema(&schema, &serde_json::json!({"name": "anda", "age": 256}))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "$.age: 256 is greater than the maximum 255"
+        );
+
+        let err = validate_json_schema(&schema, &serde_json::json!("anda")).unwrap_err();
+        assert_eq!(err.to_string(), r#"$: expected type "object", got string"#);
+
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "additionalProperties": false
+        });
+        let err =
+            validate_json_schema(&schema, &serde_json::json!({"tags": ["a", 1]})).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"$.tags[1]: expected type "string", got number"#
+        );
+        let err = validate_json_schema(&schema, &serde_json::json!({"other": 1})).unwrap_err();
+        assert_eq!(err.to_string(), r#"$: unknown field "other""#);
+    }
 }
@@ -19,6 +19,24 @@ pub use tool::*;
 /// This is commonly used as a return type for functions that can return various error types.
 pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
+/// Error returned when a called tool or agent does not exist, as opposed to a call that
+/// ran and failed.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum NotFoundError {
+    #[error("tool {0} not found")]
+    Tool(String),
+
+    #[error("agent {0} not found")]
+    Agent(String),
+}
+
+impl NotFoundError {
+    /// Returns the [`NotFoundError`] if the error is one.
+    pub fn from_error(err: &BoxError) -> Option<&NotFoundError> {
+        err.downcast_ref::<NotFoundError>()
+    }
+}
+
 /// A type alias for a boxed future that is thread-safe and sendable across threads.
 pub type BoxPinFut<T> = Pin<Box<dyn Future<Output = T> + Send>>;
 
@@ -86,4 +104,17 @@ mod tests {
         assert!(validate_path_part("foo/bar").is_err());
         assert!(validate_path_part("foo/bar/").is_err());
     }
+
+    #[test]
+    fn test_not_found_error() {
+        let err: BoxError = NotFoundError::Tool("foo".to_string()).into();
+        assert_eq!(err.to_string(), "tool foo not found");
+        assert_eq!(
+            NotFoundError::from_error(&err),
+            Some(&NotFoundError::Tool("foo".to_string()))
+        );
+
+        let err: BoxError = "tool foo not found".into();
+        assert!(NotFoundError::from_error(&err).is_none());
+    }
 }
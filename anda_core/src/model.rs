@@ -65,6 +65,8 @@ pub struct AgentOutput {
 
     /// Indicates failure reason if present, None means successful execution.
     /// Should be None when finish_reason is "stop" or "tool_calls".
+    /// It starts with [`FINISH_REASON_LENGTH`] when the response was cut off, the partial content
+    /// is kept. Tool calls in a cut off response are not executed.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub failed_reason: Option<String>,
 
@@ -91,6 +93,20 @@ pub struct AgentOutput {
     pub conversation: Option<u64>,
 }
 
+/// The failed reason set when a response was cut off at the maximum number of output tokens.
+pub const FINISH_REASON_LENGTH: &str = "length";
+
+impl AgentOutput {
+    /// Returns true if the response was cut off at the maximum number of output tokens.
+    pub fn is_truncated(&self) -> bool {
+        self.failed_reason.as_deref().is_some_and(|reason| {
+            reason
+                .strip_prefix(FINISH_REASON_LENGTH)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+        })
+    }
+}
+
 /// Represents a message send to LLM for completion.
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Message {
@@ -298,6 +314,21 @@ pub struct RequestMeta {
     /// of the user interacting with the bot.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
+
+    /// The maximum time in milliseconds the request may run, measured by
+    /// [`StateFeatures::time_elapsed`](crate::StateFeatures::time_elapsed).
+    /// Agents and tools are aborted with a "deadline exceeded" failure once it is exceeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline_ms: Option<u64>,
+
+    /// Restricts the tool definitions offered to the model to the given tool names,
+    /// e.g. the tools chosen by a router agent. All tools are offered if None.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<String>>,
+
+    /// Overrides the agent's [`ToolStrategy`] for the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_strategy: Option<ToolStrategy>,
 }
 
 /// Represents the usage statistics for the agent or tool execution.
@@ -311,6 +342,18 @@ pub struct Usage {
 
     /// number of requests made to agents and tools
     pub requests: u64,
+
+    /// bytes of the instructions, prompt, content, documents and tool definitions sent to the LLM
+    #[serde(default)]
+    pub prompt_bytes: u64,
+
+    /// bytes of the chat history sent to the LLM, including tool results
+    #[serde(default)]
+    pub history_bytes: u64,
+
+    /// bytes of the content and tool calls received from the LLM
+    #[serde(default)]
+    pub response_bytes: u64,
 }
 
 impl Usage {
@@ -319,6 +362,9 @@ impl Usage {
         self.input_tokens = self.input_tokens.saturating_add(other.input_tokens);
         self.output_tokens = self.output_tokens.saturating_add(other.output_tokens);
         self.requests = self.requests.saturating_add(other.requests);
+        self.prompt_bytes = self.prompt_bytes.saturating_add(other.prompt_bytes);
+        self.history_bytes = self.history_bytes.saturating_add(other.history_bytes);
+        self.response_bytes = self.response_bytes.saturating_add(other.response_bytes);
     }
 }
 
@@ -369,6 +415,10 @@ pub struct FunctionDefinition {
     /// Whether to enable strict schema adherence when generating the function call. If set to true, the model will follow the exact schema defined in the parameters field. Only a subset of JSON Schema is supported when strict is true.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub strict: Option<bool>,
+
+    /// Example arguments showing the expected shape of the parameters.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<Json>,
 }
 
 impl FunctionDefinition {
@@ -377,6 +427,18 @@ impl FunctionDefinition {
         self.name = format!("{}{}", prefix, self.name);
         self
     }
+
+    /// Moves the examples into the description, for model APIs that have no examples field.
+    pub fn examples_in_description(mut self) -> Self {
+        if !self.examples.is_empty() {
+            self.description.push_str("\n\nExamples of arguments:");
+            for example in self.examples.drain(..) {
+                self.description.push('\n');
+                self.description.push_str(&example.to_string());
+            }
+        }
+        self
+    }
 }
 
 /// Returns the number of tokens in the given content in the simplest way.
@@ -410,7 +472,7 @@ impl From<&Resource> for Document {
 }
 
 /// Collection of knowledge documents.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Documents {
     /// The tag of the document collection. Defaults to "documents".
     tag: String,
@@ -529,6 +591,45 @@ impl std::fmt::Display for Documents {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_embedding_normalize() {
+        let mut embedding = Embedding {
+            text: "test".to_string(),
+            vec: vec![3.0, 4.0],
+        };
+        embedding.normalize();
+        assert_eq!(embedding.vec, vec![0.6, 0.8]);
+
+        let mut embedding = Embedding {
+            text: "zero".to_string(),
+            vec: vec![0.0, 0.0],
+        };
+        embedding.normalize();
+        assert_eq!(embedding.vec, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_examples_in_description() {
+        let def = FunctionDefinition {
+            name: "thread_api".to_string(),
+            description: "Thread API".to_string(),
+            parameters: json!({"type": "object"}),
+            strict: None,
+            examples: vec![json!({"type": "get", "thread_id": 1})],
+        };
+        let value = serde_json::to_value(&def).unwrap();
+        assert_eq!(value["examples"], json!([{"type": "get", "thread_id": 1}]));
+
+        let def = def.examples_in_description();
+        assert!(def.examples.is_empty());
+        assert_eq!(
+            def.description,
+            "Thread API\n\nExamples of arguments:\n{\"thread_id\":1,\"type\":\"get\"}"
+        );
+        let value = serde_json::to_value(&def).unwrap();
+        assert!(value.get("examples").is_none());
+    }
+
     #[test]
     fn test_prompt() {
         let documents: Documents = vec![
@@ -720,4 +821,23 @@ mod tests {
         assert_eq!(calls[1].name, "echo");
         assert_eq!(calls[1].args, serde_json::json!({"text":"hi"}));
     }
+
+    #[test]
+    fn test_resolved_tool_choice() {
+        let mut req = CompletionRequest::default();
+        assert_eq!(req.resolved_tool_choice(), ToolChoice::Auto);
+        req.tool_choice_required = true;
+        assert_eq!(req.resolved_tool_choice(), ToolChoice::Required);
+        req.tool_choice = Some(ToolChoice::Function("sum".into()));
+        assert_eq!(
+            req.resolved_tool_choice(),
+            ToolChoice::Function("sum".into())
+        );
+
+        assert_eq!(json!(ToolChoice::None), json!("none"));
+        assert_eq!(
+            json!(ToolChoice::Function("sum".into())),
+            json!({"function": "sum"})
+        );
+    }
 }
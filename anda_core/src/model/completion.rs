@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
 use crate::{
@@ -16,7 +17,8 @@ pub trait CompletionFeatures: Sized {
 }
 
 /// Represents a general completion request that can be sent to a completion model provider.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct CompletionRequest {
     /// The system instructions to be sent to the completion model provider, as the "system" role.
     pub instructions: String,
@@ -48,6 +50,9 @@ pub struct CompletionRequest {
     /// Whether the tool choice is required.
     pub tool_choice_required: bool,
 
+    /// Controls which tool the model calls, overrides `tool_choice_required` if set.
+    pub tool_choice: Option<ToolChoice>,
+
     /// The temperature to be sent to the completion model provider. [0.0, 2.0]
     pub temperature: Option<f64>,
 
@@ -61,6 +66,34 @@ pub struct CompletionRequest {
     pub stop: Option<Vec<String>>,
 }
 
+/// Controls which tool, if any, is called by the model.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// The model decides whether to call tools or respond with a message.
+    #[default]
+    Auto,
+    /// The model does not call any tool.
+    None,
+    /// The model must call one or more tools.
+    Required,
+    /// The model must call the function with the given name.
+    Function(String),
+}
+
+/// Whether the model tries tools before answering, or answers directly with tools as a fallback.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolStrategy {
+    /// Leaves it to the agent's instructions and tool choice.
+    #[default]
+    Auto,
+    /// The model must call tools in the first round, then decides whether to answer.
+    ToolsFirst,
+    /// The model is instructed to answer directly and call tools only when it can not.
+    AnswerFirst,
+}
+
 impl CompletionRequest {
     /// Adds a document to the request.
     pub fn context(mut self, id: String, text: String) -> Self {
@@ -82,4 +115,39 @@ impl CompletionRequest {
         self.tools.extend(tools);
         self
     }
+
+    /// Returns the tool choice of the request, falling back to `tool_choice_required`.
+    pub fn resolved_tool_choice(&self) -> ToolChoice {
+        match &self.tool_choice {
+            Some(choice) => choice.clone(),
+            None if self.tool_choice_required => ToolChoice::Required,
+            None => ToolChoice::Auto,
+        }
+    }
+
+    /// Returns the size in bytes of the instructions, prompt, content, documents
+    /// and tool definitions, with structured parts measured as JSON.
+    pub fn prompt_bytes(&self) -> usize {
+        self.instructions.len()
+            + self.prompt.len()
+            + json_bytes(&self.content)
+            + json_bytes(&self.documents)
+            + json_bytes(&self.tools)
+    }
+
+    /// Returns the size in bytes of the chat history and raw history, measured as JSON.
+    pub fn history_bytes(&self) -> usize {
+        json_bytes(&self.chat_history) + json_bytes(&self.raw_history)
+    }
+}
+
+impl AgentOutput {
+    /// Returns the size in bytes of the content and tool calls, with tool calls measured as JSON.
+    pub fn response_bytes(&self) -> usize {
+        self.content.len() + json_bytes(&self.tool_calls)
+    }
+}
+
+fn json_bytes<T: Serialize>(value: &T) -> usize {
+    serde_json::to_vec(value).map(|v| v.len()).unwrap_or(0)
 }
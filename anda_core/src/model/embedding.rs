@@ -13,6 +13,16 @@ pub struct Embedding {
     pub vec: Vec<f32>,
 }
 
+impl Embedding {
+    /// Scales the vector to unit L2 norm, zero vectors are left unchanged.
+    pub fn normalize(&mut self) {
+        let norm = self.vec.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            self.vec.iter_mut().for_each(|v| *v /= norm);
+        }
+    }
+}
+
 /// Provides text embedding capabilities for agents.
 pub trait EmbeddingFeatures: Sized {
     /// The number of dimensions in the embedding vector.
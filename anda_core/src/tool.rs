@@ -78,6 +78,13 @@ where
         Vec::new()
     }
 
+    /// Whether the tool can be called by an anonymous caller.
+    /// The engine rejects anonymous calls to the tool before dispatching if it returns false.
+    /// By default, it returns false, tools that are safe for anonymous callers opt in.
+    fn allow_anonymous(&self) -> bool {
+        false
+    }
+
     /// Selects resources based on the tool's supported tags.
     /// This method filters the provided resources based on the tags that the tool supports.
     fn select_resources(&self, resources: &mut Vec<Resource>) -> Vec<Resource> {
@@ -150,6 +157,8 @@ where
 
     fn supported_resource_tags(&self) -> Vec<String>;
 
+    fn allow_anonymous(&self) -> bool;
+
     fn init(&self, ctx: C) -> BoxPinFut<Result<(), BoxError>>;
 
     fn call(
@@ -183,6 +192,10 @@ where
         self.0.supported_resource_tags()
     }
 
+    fn allow_anonymous(&self) -> bool {
+        self.0.allow_anonymous()
+    }
+
     fn init(&self, ctx: C) -> BoxPinFut<Result<(), BoxError>> {
         let tool = self.0.clone();
         Box::pin(async move { tool.init(ctx).await })
@@ -6,11 +6,13 @@ mod agent;
 mod base;
 mod cache;
 mod engine;
+mod http_policy;
 mod web3;
 
 pub use agent::*;
 pub use base::*;
 pub use engine::*;
+pub use http_policy::*;
 pub use web3::*;
 
 /// Mock implementations for testing purposes.
@@ -167,4 +169,78 @@ mod tests {
         assert_eq!(res.method, "canister_update");
         assert_eq!(res.args, empty_args);
     }
+
+    /// Fails the first `failures` update calls with `error`.
+    struct FlakyCaller {
+        failures: usize,
+        error: &'static str,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl CanisterCaller for FlakyCaller {
+        async fn canister_query<
+            In: candid::utils::ArgumentEncoder + Send,
+            Out: CandidType + for<'a> Deserialize<'a>,
+        >(
+            &self,
+            _canister: &Principal,
+            _method: &str,
+            _args: In,
+        ) -> Result<Out, anda_core::BoxError> {
+            Err("not implemented".into())
+        }
+
+        async fn canister_update<
+            In: candid::utils::ArgumentEncoder + Send,
+            Out: CandidType + for<'a> Deserialize<'a>,
+        >(
+            &self,
+            _canister: &Principal,
+            _method: &str,
+            _args: In,
+        ) -> Result<Out, anda_core::BoxError> {
+            let n = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if n < self.failures {
+                return Err(self.error.into());
+            }
+            let res = encode_args((n as u64,))?;
+            let (res,): (Out,) = candid::decode_args(&res)?;
+            Ok(res)
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_update_retry() {
+        const SYS_TRANSIENT: &str = "The replica returned a rejection error: reject code SysTransient, reject message canister queue is full, error code None";
+        const HTTP_ERROR: &str =
+            "The replica returned an HTTP Error: Http Error: status 503 Service Unavailable";
+        let retry = UpdateRetry {
+            max_attempts: 3,
+            initial_delay: std::time::Duration::from_millis(1),
+        };
+        let canister = Principal::anonymous();
+        let call = async |failures, error, idempotent| {
+            let caller = FlakyCaller {
+                failures,
+                error,
+                calls: Default::default(),
+            };
+            let res: Result<u64, _> = retry
+                .canister_update(&caller, &canister, "update", (), idempotent)
+                .await;
+            (res.ok(), caller.calls.into_inner())
+        };
+
+        assert_eq!(call(2, SYS_TRANSIENT, false).await, (Some(2), 3));
+        assert_eq!(call(3, SYS_TRANSIENT, false).await, (None, 3));
+        // the call may have been executed
+        assert_eq!(call(1, HTTP_ERROR, false).await, (None, 1));
+        assert_eq!(call(1, "canister busy", false).await, (None, 1));
+        assert_eq!(call(2, HTTP_ERROR, true).await, (Some(2), 3));
+
+        assert!(is_retryable_update_error(&SYS_TRANSIENT.into()));
+        assert!(!is_retryable_update_error(
+            &"The replica returned a rejection error: reject code CanisterError, reject message SysTransient, error code None".into()
+        ));
+    }
 }
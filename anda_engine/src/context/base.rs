@@ -24,13 +24,16 @@
 //! - Time tracking for operation duration.
 
 use anda_core::{
-    BaseContext, BoxError, CacheExpiry, CacheFeatures, CacheStoreFeatures, CancellationToken,
-    CanisterCaller, HttpFeatures, Json, KeysFeatures, ObjectMeta, Path, PutMode, PutResult,
-    RequestMeta, StateFeatures, StoreFeatures, ToolInput, ToolOutput, derivation_path_with,
+    BaseContext, BoxError, CONTENT_TYPE_CBOR, CacheExpiry, CacheFeatures, CacheStoreFeatures,
+    CancellationToken, CanisterCaller, HttpFeatures, Json, KeysFeatures, ListResult, ObjectMeta,
+    Path, PutMode, PutResult, RPCRequestRef, RPCResponse, RequestMeta, StateFeatures,
+    StoreFeatures, ToolInput, ToolOutput, derivation_path_with, rpc_error_message,
 };
 use bytes::Bytes;
 use candid::{CandidType, Principal, utils::ArgumentEncoder};
+use ciborium::from_reader;
 use http::Extensions;
+use ic_cose_types::{cose::sha3_256, to_cbor_bytes};
 use parking_lot::RwLock;
 use serde::{Serialize, de::DeserializeOwned};
 use std::{
@@ -40,16 +43,111 @@ use std::{
     time::{Duration, Instant},
 };
 
-const CONTEXT_MAX_DEPTH: u8 = 42;
-const CACHE_MAX_CAPACITY: u64 = 1000000;
-
 use super::{
     RemoteEngines,
     cache::CacheService,
+    http_policy::HttpPolicy,
     web3::{Web3Client, Web3SDK},
 };
 use crate::store::Store;
 
+const CONTEXT_MAX_DEPTH: u8 = 42;
+const CACHE_MAX_CAPACITY: u64 = 1000000;
+
+/// Retry policy for [`BaseCtx::canister_update_with_retry`].
+#[derive(Clone, Debug)]
+pub struct UpdateRetry {
+    /// Maximum number of attempts, including the first call.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after each retry.
+    pub initial_delay: Duration,
+}
+
+impl Default for UpdateRetry {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl UpdateRetry {
+    /// Performs an update call to a canister, retrying with exponential backoff.
+    ///
+    /// A non-idempotent call is only retried when the IC rejected it without executing it
+    /// (see [`is_retryable_update_error`]), an idempotent call is retried on any error.
+    ///
+    /// # Arguments
+    /// * `caller` - The canister caller, e.g. a [`BaseCtx`];
+    /// * `canister` - Target canister principal;
+    /// * `method` - Method name to call;
+    /// * `args` - Input arguments encoded in Candid format;
+    /// * `idempotent` - Whether calling the method more than once has the same effect as once.
+    pub async fn canister_update<
+        In: ArgumentEncoder + Clone + Send,
+        Out: CandidType + for<'a> candid::Deserialize<'a>,
+    >(
+        &self,
+        caller: &impl CanisterCaller,
+        canister: &Principal,
+        method: &str,
+        args: In,
+        idempotent: bool,
+    ) -> Result<Out, BoxError> {
+        let mut delay = self.initial_delay;
+        let mut attempt = 1;
+        loop {
+            match caller.canister_update(canister, method, args.clone()).await {
+                Ok(res) => return Ok(res),
+                Err(err)
+                    if attempt < self.max_attempts
+                        && (idempotent || is_retryable_update_error(&err)) =>
+                {
+                    log::warn!(
+                        "canister {} update {} failed: {}, attempt {}",
+                        canister.to_text(),
+                        method,
+                        err,
+                        attempt
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Returns true if the error is a `SYS_TRANSIENT` reject of an update call, which the IC
+/// returns when it did not execute the call, e.g. the canister queue is full. Such a call
+/// is safe to retry. Other errors, e.g. HTTP errors while polling for the result, do not
+/// guarantee that the call was not executed.
+pub fn is_retryable_update_error(err: &BoxError) -> bool {
+    // the rejection format of ic-agent, also forwarded by the TEE gateway
+    err.to_string().contains("reject code SysTransient,")
+}
+
+/// Limits of RPC calls to remote engines, for remote tool calls and agent runs.
+#[derive(Clone, Debug)]
+pub struct RemoteCallLimits {
+    /// Maximum duration of a call, further bounded by the remaining time of the request.
+    pub timeout: Duration,
+    /// Maximum size in bytes of a response body.
+    pub max_response_bytes: usize,
+}
+
+impl Default for RemoteCallLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(300),
+            max_response_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct BaseCtx {
     pub(crate) id: Principal,
@@ -64,6 +162,9 @@ pub struct BaseCtx {
     pub(crate) remote: Arc<RemoteEngines>,
     pub(crate) state: Arc<RwLock<Extensions>>,
     pub(crate) meta: RequestMeta,
+    pub(crate) update_retry: Option<UpdateRetry>,
+    pub(crate) http_policy: Option<Arc<HttpPolicy>>,
+    pub(crate) remote_limits: RemoteCallLimits,
 
     cache: Arc<CacheService>,
     store: Store,
@@ -108,9 +209,17 @@ impl BaseCtx {
             remote,
             state: Arc::new(RwLock::new(Extensions::default())),
             meta: RequestMeta::default(),
+            update_retry: None,
+            http_policy: None,
+            remote_limits: RemoteCallLimits::default(),
         }
     }
 
+    /// Returns the approximate number of cached entries shared by the engine's agents and tools.
+    pub(crate) fn cache_entry_count(&self) -> u64 {
+        self.cache.entry_count()
+    }
+
     /// Creates a child context with a new path.
     ///
     /// This is used to create nested contexts while maintaining the parent's state.
@@ -140,6 +249,9 @@ impl BaseCtx {
             remote: self.remote.clone(),
             state: self.state.clone(),
             meta: self.meta.clone(),
+            update_retry: self.update_retry.clone(),
+            http_policy: self.http_policy.clone(),
+            remote_limits: self.remote_limits.clone(),
         };
 
         if child.depth >= CONTEXT_MAX_DEPTH {
@@ -182,6 +294,9 @@ impl BaseCtx {
             remote: self.remote.clone(),
             state: self.state.clone(),
             meta,
+            update_retry: self.update_retry.clone(),
+            http_policy: self.http_policy.clone(),
+            remote_limits: self.remote_limits.clone(),
         };
 
         if child.depth >= CONTEXT_MAX_DEPTH {
@@ -195,6 +310,143 @@ impl BaseCtx {
             engine: Some(target),
             thread: None,
             user: Some(self.name.clone()),
+            // the remote engine gets the remaining time of this request
+            deadline_ms: self.time_remaining().map(|d| d.as_millis() as u64),
+            tools: None,
+            tool_strategy: None,
+        }
+    }
+
+    /// Makes a signed CBOR-encoded RPC call to a remote engine, bounded by the
+    /// [`RemoteCallLimits`] of the engine.
+    pub(crate) async fn remote_rpc<T>(
+        &self,
+        endpoint: &str,
+        method: &str,
+        args: impl Serialize,
+    ) -> Result<T, BoxError>
+    where
+        T: DeserializeOwned,
+    {
+        let params = to_cbor_bytes(&args);
+        let body = to_cbor_bytes(&RPCRequestRef {
+            method,
+            params: &params.into(),
+        });
+        let digest = sha3_256(&body);
+        let mut headers = http::HeaderMap::new();
+        let ct: http::HeaderValue = CONTENT_TYPE_CBOR.parse().unwrap();
+        headers.insert(http::header::CONTENT_TYPE, ct.clone());
+        headers.insert(http::header::ACCEPT, ct);
+
+        let max_bytes = self.remote_limits.max_response_bytes;
+        let call = async {
+            let mut res = self
+                .https_signed_call(
+                    endpoint,
+                    http::Method::POST,
+                    digest,
+                    Some(headers),
+                    Some(body),
+                )
+                .await?;
+            let status = res.status();
+            if res
+                .content_length()
+                .is_some_and(|len| len > max_bytes as u64)
+            {
+                return Err(
+                    format!("remote engine {endpoint} response exceeds {max_bytes} bytes").into(),
+                );
+            }
+
+            let mut data: Vec<u8> = Vec::new();
+            while let Some(chunk) = res.chunk().await? {
+                if data.len() + chunk.len() > max_bytes {
+                    return Err(format!(
+                        "remote engine {endpoint} response exceeds {max_bytes} bytes"
+                    )
+                    .into());
+                }
+                data.extend_from_slice(&chunk);
+            }
+            if !status.is_success() {
+                let err = rpc_error_message(&data);
+                return Err(
+                    format!("remote engine {endpoint} returned status {status}: {err}").into(),
+                );
+            }
+
+            let res: RPCResponse = from_reader(&data[..])?;
+            let res = res.map_err(|err| format!("remote engine {endpoint} error: {err}"))?;
+            let res: T = from_reader(&res[..])?;
+            Ok::<T, BoxError>(res)
+        };
+
+        let timeout = match self.time_remaining() {
+            Some(remaining) => remaining.min(self.remote_limits.timeout),
+            None => self.remote_limits.timeout,
+        };
+        tokio::time::timeout(timeout, call)
+            .await
+            .map_err(|_| format!("remote engine {endpoint} timed out after {timeout:?}"))?
+    }
+
+    /// Returns the retry policy of canister update calls set by
+    /// [`crate::engine::EngineBuilder::with_canister_update_retry`].
+    pub fn update_retry(&self) -> Option<&UpdateRetry> {
+        self.update_retry.as_ref()
+    }
+
+    /// Performs an update call to a canister, retrying with exponential backoff
+    /// according to the engine's [`UpdateRetry`] policy, see [`UpdateRetry::canister_update`].
+    /// Without a retry policy the call is made once, like [`CanisterCaller::canister_update`].
+    pub async fn canister_update_with_retry<
+        In: ArgumentEncoder + Clone + Send,
+        Out: CandidType + for<'a> candid::Deserialize<'a>,
+    >(
+        &self,
+        canister: &Principal,
+        method: &str,
+        args: In,
+        idempotent: bool,
+    ) -> Result<Out, BoxError> {
+        match &self.update_retry {
+            Some(retry) => {
+                retry
+                    .canister_update(self, canister, method, args, idempotent)
+                    .await
+            }
+            None => self.canister_update(canister, method, args).await,
+        }
+    }
+
+    /// Returns the remaining time before the request deadline, or `None` if there is no deadline.
+    pub fn time_remaining(&self) -> Option<Duration> {
+        self.meta
+            .deadline_ms
+            .map(|ms| Duration::from_millis(ms).saturating_sub(self.time_elapsed()))
+    }
+
+    /// Completes when the request deadline is exceeded, never completes if there is no deadline.
+    pub(crate) async fn deadline_exceeded(&self) {
+        match self.time_remaining() {
+            Some(remaining) => tokio::time::sleep(remaining).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Runs the future, fails with "deadline exceeded" if the request deadline is exceeded first.
+    pub(crate) async fn with_deadline<T>(
+        &self,
+        fut: impl Future<Output = Result<T, BoxError>>,
+    ) -> Result<T, BoxError> {
+        if self.time_remaining() == Some(Duration::ZERO) {
+            return Err("deadline exceeded".into());
+        }
+        tokio::select! {
+            _ = self.deadline_exceeded() => Err("deadline exceeded".into()),
+            res = fut => res,
         }
     }
 
@@ -232,8 +484,7 @@ impl BaseContext for BaseCtx {
             .get_id_by_endpoint(endpoint)
             .ok_or_else(|| format!("remote engine endpoint {} not found", endpoint))?;
         args.meta = Some(self.self_meta(target));
-        self.https_signed_rpc(endpoint, "tool_call", &(&args,))
-            .await
+        self.remote_rpc(endpoint, "tool_call", &(&args,)).await
     }
 }
 
@@ -506,6 +757,14 @@ impl StoreFeatures for BaseCtx {
         self.store.store_list(&self.path, prefix, offset).await
     }
 
+    /// Lists objects and common prefixes ("directories") directly under the prefix.
+    ///
+    /// # Arguments
+    /// * `prefix` - Optional path prefix to list under.
+    async fn store_list_dirs(&self, prefix: Option<&Path>) -> Result<ListResult, BoxError> {
+        self.store.store_list_dirs(&self.path, prefix).await
+    }
+
     /// Stores data at the specified path with a given write mode.
     ///
     /// # Arguments
@@ -654,6 +913,9 @@ impl HttpFeatures for BaseCtx {
         headers: Option<http::HeaderMap>,
         body: Option<Vec<u8>>,
     ) -> Result<reqwest::Response, BoxError> {
+        if let Some(policy) = &self.http_policy {
+            return policy.send(url, method, headers, body).await;
+        }
         self.web3
             .as_ref()
             .https_call(url, method, headers, body)
@@ -676,6 +938,13 @@ impl HttpFeatures for BaseCtx {
         headers: Option<http::HeaderMap>,
         body: Option<Vec<u8>>, // default is empty
     ) -> Result<reqwest::Response, BoxError> {
+        if let Some(policy) = &self.http_policy {
+            policy.check(url).await?;
+            let se = self.web3.sign_envelope(message_digest).await?;
+            let mut headers = headers.unwrap_or_default();
+            se.to_authorization(&mut headers)?;
+            return policy.send(url, method, Some(headers), body).await;
+        }
         self.web3
             .as_ref()
             .https_signed_call(url, method, message_digest, headers, body)
@@ -697,6 +966,37 @@ impl HttpFeatures for BaseCtx {
     where
         T: DeserializeOwned,
     {
+        if self.http_policy.is_some() {
+            let params = to_cbor_bytes(&args);
+            let body = to_cbor_bytes(&RPCRequestRef {
+                method,
+                params: &params.into(),
+            });
+            let digest = sha3_256(&body);
+            let mut headers = http::HeaderMap::new();
+            let ct: http::HeaderValue = CONTENT_TYPE_CBOR.parse().unwrap();
+            headers.insert(http::header::CONTENT_TYPE, ct.clone());
+            headers.insert(http::header::ACCEPT, ct);
+            let res = self
+                .https_signed_call(
+                    endpoint,
+                    http::Method::POST,
+                    digest,
+                    Some(headers),
+                    Some(body),
+                )
+                .await?;
+            let status = res.status();
+            if !status.is_success() {
+                let body = res.bytes().await.unwrap_or_default();
+                let err = rpc_error_message(&body);
+                return Err(format!("{endpoint} returned status {status}: {err}").into());
+            }
+            let data = res.bytes().await?;
+            let res: RPCResponse = from_reader(&data[..])?;
+            let res = res?;
+            return Ok(from_reader(&res[..])?);
+        }
         self.web3
             .as_ref()
             .https_signed_rpc(endpoint, method, args)
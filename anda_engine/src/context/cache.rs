@@ -85,6 +85,11 @@ impl CacheService {
 }
 
 impl CacheService {
+    /// Returns the approximate number of entries across all namespaces.
+    pub fn entry_count(&self) -> u64 {
+        self.cache_store.values().map(|c| c.entry_count()).sum()
+    }
+
     /// Checks if a key exists in the cache.
     ///
     /// # Arguments
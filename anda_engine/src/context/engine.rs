@@ -24,6 +24,46 @@ pub struct EngineCard {
     pub tools: Vec<Function>,
 }
 
+/// A health snapshot of the engine.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct EngineStatus {
+    /// The number of agents registered in the engine.
+    pub agents: u64,
+    /// The number of tools registered in the engine.
+    pub tools: u64,
+    /// The approximate number of entries in the engine's cache.
+    pub cache_entries: u64,
+    /// The number of free call slots if the engine has a concurrency limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available_permits: Option<u64>,
+    /// Whether the engine has been cancelled and is shutting down.
+    pub cancelled: bool,
+}
+
+/// The engine principal and the public addresses derived from its keys.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EngineIdentities {
+    /// The principal ID of the engine.
+    pub id: Principal,
+    /// Public addresses keyed by chain name, e.g. "bnb".
+    pub addresses: BTreeMap<String, String>,
+}
+
+/// The caller identity as resolved by the engine.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CallerIdentity {
+    /// The caller principal resolved from the signed request, anonymous if not signed.
+    pub caller: Principal,
+    /// Whether the caller is the controller of the engine.
+    pub is_controller: bool,
+    /// Whether the caller is the controller or a manager of the engine.
+    pub is_manager: bool,
+    /// The engine visibility ("private", "protected" or "public") if the caller can access it.
+    pub visibility: Option<String>,
+    /// The reason the caller can not access the engine.
+    pub denied_reason: Option<String>,
+}
+
 /// Collection of remote engines.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct RemoteEngines {
@@ -41,6 +81,9 @@ pub struct RemoteEngineArgs {
     pub tools: Vec<String>,
     /// Optional handle for the engine. If not provided, the engine handle is used.
     pub handle: Option<String>,
+    /// Optional principal ID of the engine. If provided, the registration fails on mismatch.
+    #[serde(default)]
+    pub id: Option<Principal>,
 }
 
 impl Default for RemoteEngines {
@@ -65,6 +108,18 @@ impl RemoteEngines {
         let mut engine: EngineCard = ctx
             .https_signed_rpc(&args.endpoint, "information", &(true,))
             .await?;
+        if let Some(id) = args.id
+            && id != engine.id
+        {
+            return Err(format!(
+                "remote engine {} id mismatch, expected {}, got {}",
+                args.endpoint,
+                id.to_text(),
+                engine.id.to_text()
+            )
+            .into());
+        }
+
         let handle = args
             .handle
             .unwrap_or_else(|| engine.info.handle.to_ascii_lowercase());
@@ -0,0 +1,357 @@
+//! Egress policy for outbound HTTPs requests.
+//!
+//! A [`HttpPolicy`] is checked before a request is sent, and enforced again while it is
+//! in flight by the policy's own client:
+//! - Domain names are resolved by a resolver that rejects denied addresses, so the
+//!   connection is made to the addresses that were checked;
+//! - Every redirect hop is checked before it is followed.
+
+use anda_core::BoxError;
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect,
+};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use crate::APP_USER_AGENT;
+
+/// Maximum number of redirects followed by the policy client.
+const MAX_REDIRECTS: usize = 10;
+
+/// CIDRs of loopback, private, link-local and other non-public networks.
+const PRIVATE_CIDRS: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "::/128",
+    "::1/128",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+/// Egress policy enforced on outbound HTTPs requests made through [`HttpFeatures`](anda_core::HttpFeatures).
+///
+/// Host patterns are exact host names or `*.example.com` wildcards matching any
+/// subdomain. CIDRs (e.g. `10.0.0.0/8`, `fd00::/8`) are matched against the host
+/// if it is an IP address, otherwise against the addresses it resolves to.
+///
+/// A denied match always rejects the request. If any allow rule is set, the request
+/// must match an allowed host, or all of its addresses must be in allowed CIDRs.
+///
+/// Requests under a policy are sent by the policy's own client, which only speaks
+/// HTTPs, pins connections to the checked addresses and checks every redirect.
+#[derive(Clone, Debug, Default)]
+pub struct HttpPolicy {
+    rules: Rules,
+    client: OnceLock<reqwest::Client>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Rules {
+    allow_hosts: Vec<String>,
+    deny_hosts: Vec<String>,
+    allow_cidrs: Vec<(IpAddr, u8)>,
+    deny_cidrs: Vec<(IpAddr, u8)>,
+}
+
+impl HttpPolicy {
+    /// Creates an empty policy that allows all targets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds allowed host patterns.
+    pub fn with_allow_hosts(mut self, hosts: Vec<String>) -> Self {
+        self.rules
+            .allow_hosts
+            .extend(hosts.into_iter().map(|h| h.to_ascii_lowercase()));
+        self
+    }
+
+    /// Adds denied host patterns.
+    pub fn with_deny_hosts(mut self, hosts: Vec<String>) -> Self {
+        self.rules
+            .deny_hosts
+            .extend(hosts.into_iter().map(|h| h.to_ascii_lowercase()));
+        self
+    }
+
+    /// Adds allowed CIDRs.
+    pub fn with_allow_cidrs(mut self, cidrs: &[&str]) -> Result<Self, BoxError> {
+        for cidr in cidrs {
+            self.rules.allow_cidrs.push(parse_cidr(cidr)?);
+        }
+        Ok(self)
+    }
+
+    /// Adds denied CIDRs.
+    pub fn with_deny_cidrs(mut self, cidrs: &[&str]) -> Result<Self, BoxError> {
+        for cidr in cidrs {
+            self.rules.deny_cidrs.push(parse_cidr(cidr)?);
+        }
+        Ok(self)
+    }
+
+    /// Denies loopback, private, link-local and other non-public networks,
+    /// e.g. cloud metadata endpoints.
+    pub fn with_deny_private_networks(self) -> Self {
+        self.with_deny_cidrs(PRIVATE_CIDRS)
+            .expect("private CIDRs should be valid")
+    }
+
+    /// Checks whether the URL is an allowed target.
+    pub async fn check(&self, url: &str) -> Result<(), BoxError> {
+        let u = url::Url::parse(url).map_err(|err| format!("invalid url {url:?}: {err}"))?;
+        let host = url_host(&u).ok_or_else(|| format!("url {url:?} has no host"))?;
+        let addrs: Vec<IpAddr> = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) if self.rules.needs_addrs() => {
+                let port = u.port_or_known_default().unwrap_or(443);
+                tokio::net::lookup_host((host.as_str(), port))
+                    .await
+                    .map_err(|err| format!("failed to resolve host {host:?}: {err}"))?
+                    .map(|addr| addr.ip())
+                    .collect()
+            }
+            Err(_) => Vec::new(),
+        };
+        self.rules.check_target(&host, &addrs)
+    }
+
+    /// Returns the client that enforces the policy on connections and redirects.
+    pub fn client(&self) -> &reqwest::Client {
+        self.client.get_or_init(|| {
+            let rules = Arc::new(self.rules.clone());
+            let redirect_rules = rules.clone();
+            reqwest::Client::builder()
+                .use_rustls_tls()
+                .https_only(true)
+                // a proxy would resolve the target itself, bypassing the resolver
+                .no_proxy()
+                .dns_resolver(Arc::new(PolicyResolver(rules)))
+                .redirect(redirect::Policy::custom(move |attempt| {
+                    if attempt.previous().len() >= MAX_REDIRECTS {
+                        return attempt.error("too many redirects");
+                    }
+                    match redirect_rules.check_redirect(attempt.url()) {
+                        Ok(()) => attempt.follow(),
+                        Err(err) => attempt.error(err),
+                    }
+                }))
+                .connect_timeout(Duration::from_secs(10))
+                .timeout(Duration::from_secs(300))
+                .gzip(true)
+                .user_agent(APP_USER_AGENT)
+                .build()
+                .expect("HttpPolicy reqwest client should build")
+        })
+    }
+
+    /// Checks the URL and sends the request with the policy client.
+    pub async fn send(
+        &self,
+        url: &str,
+        method: http::Method,
+        headers: Option<http::HeaderMap>,
+        body: Option<Vec<u8>>,
+    ) -> Result<reqwest::Response, BoxError> {
+        self.check(url).await?;
+        let mut req = self.client().request(method, url);
+        if let Some(headers) = headers {
+            req = req.headers(headers);
+        }
+        if let Some(body) = body {
+            req = req.body(body);
+        }
+        Ok(req.send().await?)
+    }
+}
+
+impl Rules {
+    fn needs_addrs(&self) -> bool {
+        !self.allow_cidrs.is_empty() || !self.deny_cidrs.is_empty()
+    }
+
+    /// Checks a host and the addresses it resolves to.
+    fn check_target(&self, host: &str, addrs: &[IpAddr]) -> Result<(), BoxError> {
+        if self.deny_hosts.iter().any(|p| host_matches(p, host)) {
+            return Err(format!("host {host:?} is denied").into());
+        }
+
+        if let Some(ip) = addrs
+            .iter()
+            .find(|ip| self.deny_cidrs.iter().any(|c| cidr_contains(c, ip)))
+        {
+            return Err(format!("address {ip} of host {host:?} is denied").into());
+        }
+
+        if self.allow_hosts.is_empty() && self.allow_cidrs.is_empty() {
+            return Ok(());
+        }
+        if self.allow_hosts.iter().any(|p| host_matches(p, host)) {
+            return Ok(());
+        }
+        if !addrs.is_empty()
+            && addrs
+                .iter()
+                .all(|ip| self.allow_cidrs.iter().any(|c| cidr_contains(c, ip)))
+        {
+            return Ok(());
+        }
+        Err(format!("host {host:?} is not allowed").into())
+    }
+
+    /// Checks a redirect target. The addresses of a domain are checked by the
+    /// resolver when the connection is made.
+    fn check_redirect(&self, url: &url::Url) -> Result<(), BoxError> {
+        let host = url_host(url).ok_or_else(|| format!("redirect url {url} has no host"))?;
+        match host.parse::<IpAddr>() {
+            Ok(ip) => self.check_target(&host, &[ip]),
+            Err(_) if self.needs_addrs() => {
+                if self.deny_hosts.iter().any(|p| host_matches(p, &host)) {
+                    return Err(format!("host {host:?} is denied").into());
+                }
+                Ok(())
+            }
+            Err(_) => self.check_target(&host, &[]),
+        }
+    }
+}
+
+/// Resolves domain names and rejects the ones whose addresses the policy denies.
+struct PolicyResolver(Arc<Rules>);
+
+impl Resolve for PolicyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let rules = self.0.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_ascii_lowercase();
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            let ips: Vec<IpAddr> = addrs.iter().map(|addr| addr.ip()).collect();
+            rules.check_target(&host, &ips)?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn url_host(u: &url::Url) -> Option<String> {
+    match u.host()? {
+        url::Host::Domain(host) => Some(host.to_ascii_lowercase()),
+        url::Host::Ipv4(ip) => Some(ip.to_string()),
+        url::Host::Ipv6(ip) => Some(ip.to_string()),
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+fn parse_cidr(cidr: &str) -> Result<(IpAddr, u8), BoxError> {
+    let (ip, bits) = match cidr.split_once('/') {
+        Some((ip, bits)) => (ip, Some(bits)),
+        None => (cidr, None),
+    };
+    let ip: IpAddr = ip
+        .parse()
+        .map_err(|err| format!("invalid cidr {cidr:?}: {err}"))?;
+    let max = if ip.is_ipv4() { 32 } else { 128 };
+    let bits = match bits {
+        Some(bits) => bits
+            .parse::<u8>()
+            .map_err(|err| format!("invalid cidr {cidr:?}: {err}"))?,
+        None => max,
+    };
+    if bits > max {
+        return Err(format!("invalid cidr {cidr:?}: prefix length exceeds {max}").into());
+    }
+    Ok((ip, bits))
+}
+
+fn cidr_contains((net, bits): &(IpAddr, u8), ip: &IpAddr) -> bool {
+    match (net, ip) {
+        (IpAddr::V4(net), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - *bits as u32).unwrap_or(0);
+            u32::from(*net) & mask == u32::from(*ip) & mask
+        }
+        (IpAddr::V6(net), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - *bits as u32).unwrap_or(0);
+            u128::from(*net) & mask == u128::from(*ip) & mask
+        }
+        (IpAddr::V4(_), IpAddr::V6(ip)) => match ip.to_ipv4_mapped() {
+            Some(ip) => cidr_contains(&(*net, *bits), &IpAddr::V4(ip)),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_policy_resolver() {
+        let policy = HttpPolicy::new().with_deny_private_networks();
+        let resolver = PolicyResolver(Arc::new(policy.rules.clone()));
+        let res = resolver.resolve(Name::from_str("localhost").unwrap()).await;
+        assert!(res.err().unwrap().to_string().contains("is denied"));
+
+        let policy = HttpPolicy::new().with_allow_hosts(vec!["localhost".to_string()]);
+        let resolver = PolicyResolver(Arc::new(policy.rules.clone()));
+        let addrs: Vec<SocketAddr> = resolver
+            .resolve(Name::from_str("localhost").unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+    }
+
+    #[test]
+    fn test_check_redirect() {
+        let rules = HttpPolicy::new()
+            .with_deny_private_networks()
+            .with_deny_hosts(vec!["*.internal.example.com".to_string()])
+            .rules;
+        let check = |url: &str| rules.check_redirect(&url::Url::parse(url).unwrap());
+        assert!(check("https://example.com/next").is_ok());
+        assert!(check("https://169.254.169.254/latest/meta-data").is_err());
+        assert!(check("https://[::1]/").is_err());
+        assert!(check("https://api.internal.example.com/").is_err());
+
+        let rules = HttpPolicy::new()
+            .with_allow_hosts(vec!["example.com".to_string()])
+            .rules;
+        let check = |url: &str| rules.check_redirect(&url::Url::parse(url).unwrap());
+        assert!(check("https://example.com/next").is_ok());
+        assert!(check("https://evil.example.org/").is_err());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_send_rejects_denied_targets() {
+        let policy = HttpPolicy::new().with_deny_private_networks();
+        let res = policy
+            .send("https://127.0.0.1:1/", http::Method::GET, None, None)
+            .await;
+        assert!(res.unwrap_err().to_string().contains("is denied"));
+
+        let res = policy
+            .send("https://localhost:1/", http::Method::GET, None, None)
+            .await;
+        assert!(res.unwrap_err().to_string().contains("is denied"));
+    }
+}
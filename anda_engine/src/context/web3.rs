@@ -5,7 +5,14 @@ use candid::{
 };
 use ciborium::from_reader;
 use ic_auth_verifier::envelope::SignedEnvelope;
-use ic_cose_types::to_cbor_bytes;
+use ic_cose_types::{
+    cose::{
+        ed25519::ed25519_verify,
+        k256::{secp256k1_verify_bip340, secp256k1_verify_ecdsa},
+    },
+    to_cbor_bytes,
+};
+use ic_tee_gateway_sdk::crypto;
 use serde::{Serialize, de::DeserializeOwned};
 use std::sync::Arc;
 
@@ -30,12 +37,30 @@ impl Web3SDK {
         Self::Web3(Web3Client::not_implemented())
     }
 
+    /// Creates a Web3 SDK backed by a [`MockWeb3Client`] for tests.
+    pub fn mock(root_secret: [u8; 48]) -> Self {
+        Self::Web3(Web3Client {
+            client: Arc::new(MockWeb3Client::new(root_secret)),
+        })
+    }
+
     pub fn get_principal(&self) -> Principal {
         match self {
             Web3SDK::Tee(cli) => cli.get_principal(),
             Web3SDK::Web3(Web3Client { client }) => client.get_principal(),
         }
     }
+
+    /// Signs a message digest with the identity of the client, for signed HTTPs requests.
+    pub async fn sign_envelope(
+        &self,
+        message_digest: [u8; 32],
+    ) -> Result<SignedEnvelope, BoxError> {
+        match self {
+            Web3SDK::Tee(cli) => cli.sign_envelope(message_digest).await,
+            Web3SDK::Web3(Web3Client { client }) => client.sign_envelope(message_digest).await,
+        }
+    }
 }
 
 pub trait Web3ClientFeatures: Send + Sync + 'static {
@@ -326,6 +351,187 @@ impl Web3ClientFeatures for NotImplemented {
     }
 }
 
+/// A Web3 client for tests that derives keys from a fixed root secret in process, so
+/// public keys, addresses and signatures are deterministic and neither a TEE service nor
+/// the network is needed. Envelope signing, canister and HTTPs calls are not implemented.
+///
+/// # Example
+/// ```rust,ignore
+/// let ctx = EngineBuilder::new()
+///     .with_web3_client(Arc::new(Web3SDK::mock([7u8; 48])))
+///     .mock_ctx();
+/// let signer = AndaEvmSigner::new(ctx.base, derivation_path, Some(chain_id)).await?;
+/// ```
+pub struct MockWeb3Client {
+    principal: Principal,
+    root_secret: [u8; 48],
+}
+
+impl MockWeb3Client {
+    pub fn new(root_secret: [u8; 48]) -> Self {
+        let pubkey = crypto::ed25519_public_key(&root_secret, Vec::new());
+        Self {
+            principal: Principal::self_authenticating(pubkey.0),
+            root_secret,
+        }
+    }
+}
+
+impl Web3ClientFeatures for MockWeb3Client {
+    fn get_principal(&self) -> Principal {
+        self.principal
+    }
+
+    fn sign_envelope(
+        &self,
+        _message_digest: [u8; 32],
+    ) -> BoxPinFut<Result<SignedEnvelope, BoxError>> {
+        Box::pin(futures::future::ready(Err("not implemented".into())))
+    }
+
+    fn a256gcm_key(&self, derivation_path: Vec<Vec<u8>>) -> BoxPinFut<Result<[u8; 32], BoxError>> {
+        let res = crypto::a256gcm_key(&self.root_secret, derivation_path);
+        Box::pin(futures::future::ready(Ok(res)))
+    }
+
+    fn ed25519_sign_message(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+        message: &[u8],
+    ) -> BoxPinFut<Result<[u8; 64], BoxError>> {
+        let res = crypto::ed25519_sign_message(&self.root_secret, derivation_path, message);
+        Box::pin(futures::future::ready(Ok(res)))
+    }
+
+    fn ed25519_verify(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+        message: &[u8],
+        signature: &[u8],
+    ) -> BoxPinFut<Result<(), BoxError>> {
+        let pk = crypto::ed25519_public_key(&self.root_secret, derivation_path);
+        let res = ed25519_verify(&pk.0, message, signature).map_err(|e| e.into());
+        Box::pin(futures::future::ready(res))
+    }
+
+    fn ed25519_public_key(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+    ) -> BoxPinFut<Result<[u8; 32], BoxError>> {
+        let res = crypto::ed25519_public_key(&self.root_secret, derivation_path);
+        Box::pin(futures::future::ready(Ok(res.0)))
+    }
+
+    fn secp256k1_sign_message_bip340(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+        message: &[u8],
+    ) -> BoxPinFut<Result<[u8; 64], BoxError>> {
+        let res =
+            crypto::secp256k1_sign_message_bip340(&self.root_secret, derivation_path, message);
+        Box::pin(futures::future::ready(Ok(res)))
+    }
+
+    fn secp256k1_verify_bip340(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+        message: &[u8],
+        signature: &[u8],
+    ) -> BoxPinFut<Result<(), BoxError>> {
+        let pk = crypto::secp256k1_public_key(&self.root_secret, derivation_path);
+        let res =
+            secp256k1_verify_bip340(pk.0.as_slice(), message, signature).map_err(|e| e.into());
+        Box::pin(futures::future::ready(res))
+    }
+
+    fn secp256k1_sign_message_ecdsa(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+        message: &[u8],
+    ) -> BoxPinFut<Result<[u8; 64], BoxError>> {
+        let res = crypto::secp256k1_sign_message_ecdsa(&self.root_secret, derivation_path, message);
+        Box::pin(futures::future::ready(Ok(res)))
+    }
+
+    fn secp256k1_sign_digest_ecdsa(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+        message_hash: &[u8],
+    ) -> BoxPinFut<Result<[u8; 64], BoxError>> {
+        let res =
+            crypto::secp256k1_sign_digest_ecdsa(&self.root_secret, derivation_path, message_hash);
+        Box::pin(futures::future::ready(Ok(res)))
+    }
+
+    fn secp256k1_verify_ecdsa(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+        message_hash: &[u8],
+        signature: &[u8],
+    ) -> BoxPinFut<Result<(), BoxError>> {
+        let pk = crypto::secp256k1_public_key(&self.root_secret, derivation_path);
+        let res =
+            secp256k1_verify_ecdsa(pk.0.as_slice(), message_hash, signature).map_err(|e| e.into());
+        Box::pin(futures::future::ready(res))
+    }
+
+    fn secp256k1_public_key(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+    ) -> BoxPinFut<Result<[u8; 33], BoxError>> {
+        let res = crypto::secp256k1_public_key(&self.root_secret, derivation_path);
+        Box::pin(futures::future::ready(Ok(res.0)))
+    }
+
+    fn canister_query_raw(
+        &self,
+        _canister: Principal,
+        _method: String,
+        _args: Vec<u8>,
+    ) -> BoxPinFut<Result<Vec<u8>, BoxError>> {
+        Box::pin(futures::future::ready(Err("not implemented".into())))
+    }
+
+    fn canister_update_raw(
+        &self,
+        _canister: Principal,
+        _method: String,
+        _args: Vec<u8>,
+    ) -> BoxPinFut<Result<Vec<u8>, BoxError>> {
+        Box::pin(futures::future::ready(Err("not implemented".into())))
+    }
+
+    fn https_call(
+        &self,
+        _url: String,
+        _method: http::Method,
+        _headers: Option<http::HeaderMap>,
+        _body: Option<Vec<u8>>, // default is empty
+    ) -> BoxPinFut<Result<reqwest::Response, BoxError>> {
+        Box::pin(futures::future::ready(Err("not implemented".into())))
+    }
+
+    fn https_signed_call(
+        &self,
+        _url: String,
+        _method: http::Method,
+        _message_digest: [u8; 32],
+        _headers: Option<http::HeaderMap>,
+        _body: Option<Vec<u8>>, // default is empty
+    ) -> BoxPinFut<Result<reqwest::Response, BoxError>> {
+        Box::pin(futures::future::ready(Err("not implemented".into())))
+    }
+
+    fn https_signed_rpc_raw(
+        &self,
+        _endpoint: String,
+        _method: String,
+        _params: Vec<u8>,
+    ) -> BoxPinFut<Result<Vec<u8>, BoxError>> {
+        Box::pin(futures::future::ready(Err("not implemented".into())))
+    }
+}
+
 #[derive(Clone)]
 pub struct Web3Client {
     pub client: Arc<dyn Web3ClientFeatures>,
@@ -31,28 +31,61 @@
 
 use anda_cloud_cdk::{ChallengeEnvelope, ChallengeRequest, TEEInfo, TEEKind};
 use anda_core::{
-    Agent, AgentInput, AgentOutput, AgentSet, BoxError, Function, Json, Path, RequestMeta,
-    Resource, Tool, ToolInput, ToolOutput, ToolSet, validate_function_name,
+    Agent, AgentInput, AgentOutput, AgentSet, BoxError, Function, Json, KeysFeatures,
+    NotFoundError, Path, PutMode, RequestMeta, Resource, StateFeatures, StoreFeatures, Tool,
+    ToolInput, ToolOutput, ToolSet, validate_function_name, validate_json_schema,
 };
 use async_trait::async_trait;
 use candid::Principal;
+use futures::stream::{self, StreamExt};
 use ic_tee_cdk::AttestationRequest;
 use object_store::memory::InMemory;
+use parking_lot::RwLock;
 use std::{
     collections::{BTreeMap, BTreeSet},
     sync::Arc,
+    time::Duration,
 };
 use structured_logger::unix_ms;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc::UnboundedSender};
 use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
 
 use crate::{
-    context::{AgentCtx, BaseCtx, Web3Client, Web3SDK},
+    ANONYMOUS,
+    context::{
+        AgentCtx, AgentEvent, BaseCtx, HttpPolicy, RemoteCallLimits, UpdateRetry, Web3Client,
+        Web3SDK,
+    },
+    logger::{LogFilter, log_filter, set_log_filter},
     management::{BaseManagement, Management, SYSTEM_PATH, UserState, Visibility},
     model::Model,
-    store::Store,
+    store::{ArtifactStore, Store},
 };
 
-pub use crate::context::{AgentInfo, EngineCard, RemoteEngineArgs, RemoteEngines};
+pub use crate::context::{
+    AgentInfo, CallerIdentity, EngineCard, EngineIdentities, EngineStatus, RemoteEngineArgs,
+    RemoteEngines,
+};
+
+/// Maximum number of tool calls in a batch.
+pub const MAX_TOOL_CALL_BATCH_SIZE: usize = 64;
+
+/// Maximum number of tool calls in a batch executed concurrently.
+const TOOL_CALL_BATCH_CONCURRENCY: usize = 8;
+
+/// Store path of the visibility set at runtime, under [`SYSTEM_PATH`].
+const VISIBILITY_PATH: &str = "visibility";
+
+/// Derives a public address from a compressed SEC1-encoded Secp256k1 public key.
+pub type AddressFn = fn(&[u8]) -> Result<String, BoxError>;
+
+/// A chain identity of the engine, derived from a Secp256k1 key.
+#[derive(Clone)]
+struct Identity {
+    namespace: String,
+    derivation_path: Vec<Vec<u8>>,
+    address: AddressFn,
+}
 
 /// Engine is the core component that manages agents, tools, and execution context.
 /// It provides methods to interact with agents, call tools, and manage execution.
@@ -61,11 +94,16 @@ pub struct Engine {
     id: Principal,
     ctx: AgentCtx,
     info: AgentInfo,
-    default_agent: String,
+    default_agent: Arc<RwLock<String>>,
     export_agents: BTreeSet<String>,
     export_tools: BTreeSet<String>,
     hooks: Arc<Hooks>,
     management: Arc<dyn Management>,
+    // the visibility set at runtime by the controller, overrides the one of the management
+    visibility: Arc<RwLock<Option<Visibility>>>,
+    identities: BTreeMap<String, Identity>,
+    artifacts: Option<ArtifactStore>,
+    concurrency_limit: Option<(Arc<Semaphore>, Duration)>,
 }
 
 /// Hook trait for customizing engine behavior.
@@ -208,7 +246,66 @@ impl Engine {
 
     /// Returns the name of the default agent.
     pub fn default_agent(&self) -> String {
-        self.default_agent.clone()
+        self.default_agent.read().clone()
+    }
+
+    /// Sets the agent that handles requests without an agent name, only the controller can call it.
+    ///
+    /// The change is in memory only and shared by all clones of the engine.
+    ///
+    /// # Arguments
+    /// * `caller` - The principal of the caller
+    /// * `name` - The name of a registered and exported agent
+    pub fn set_default_agent(&self, caller: &Principal, name: &str) -> Result<(), BoxError> {
+        if !self.management.is_controller(caller) {
+            return Err("caller is not the controller".into());
+        }
+
+        let name = name.to_ascii_lowercase();
+        if !self.export_agents.contains(&name) || !self.ctx.agents.contains(&name) {
+            return Err(NotFoundError::Agent(name).into());
+        }
+
+        *self.default_agent.write() = name.clone();
+        log::warn!(
+            caller = caller.to_text();
+            "default agent set to {name}",
+        );
+        Ok(())
+    }
+
+    /// Changes the engine visibility at runtime, only the controller can call it.
+    ///
+    /// It overrides the visibility of the [`Management`] and is shared by all clones of the
+    /// engine. The choice is persisted in the store, it is restored when the engine is built
+    /// again only if [`EngineBuilder::with_restore_visibility`] is enabled.
+    ///
+    /// # Arguments
+    /// * `caller` - The principal of the caller
+    /// * `visibility` - The visibility, one of "private", "protected", "public"
+    pub async fn set_visibility(
+        &self,
+        caller: &Principal,
+        visibility: &str,
+    ) -> Result<(), BoxError> {
+        if !self.management.is_controller(caller) {
+            return Err("caller is not the controller".into());
+        }
+
+        let visibility: Visibility = visibility.parse()?;
+        let ctx = self.ctx.base.child(SYSTEM_PATH.to_string())?;
+        ctx.store_put(
+            &Path::from(VISIBILITY_PATH),
+            PutMode::Overwrite,
+            visibility.as_str().as_bytes().to_vec().into(),
+        )
+        .await?;
+        *self.visibility.write() = Some(visibility);
+        log::warn!(
+            caller = caller.to_text();
+            "visibility set to {}", visibility.as_str(),
+        );
+        Ok(())
     }
 
     /// Cancels all tasks in the engine by triggering the cancellation token.
@@ -248,7 +345,7 @@ impl Engine {
     ) -> Result<AgentCtx, BoxError> {
         let name = agent_name.to_ascii_lowercase();
         if !self.export_agents.contains(&name) || !self.ctx.agents.contains(&name) {
-            return Err(format!("agent {} not found", name).into());
+            return Err(NotFoundError::Agent(name).into());
         }
 
         self.ctx.child_with(caller, &name, meta)
@@ -258,9 +355,34 @@ impl Engine {
     /// If no agent name is provided, uses the default agent.
     /// Returns the agent's output or an error if the agent is not found.
     pub async fn agent_run(
+        &self,
+        caller: Principal,
+        input: AgentInput,
+    ) -> Result<AgentOutput, BoxError> {
+        self.run_agent(caller, input, None).await
+    }
+
+    /// Executes an agent like [`Engine::agent_run`] and streams its progress to `events`: the
+    /// text content of each model call as it is generated, and the output of each
+    /// intermediate completion step.
+    ///
+    /// Only the completions the agent runs through [`AgentCtx::completion_iter`] or
+    /// [`anda_core::CompletionFeatures::completion`] on its own context are streamed, not those
+    /// of the agents it calls.
+    pub async fn agent_run_streaming(
+        &self,
+        caller: Principal,
+        input: AgentInput,
+        events: UnboundedSender<AgentEvent>,
+    ) -> Result<AgentOutput, BoxError> {
+        self.run_agent(caller, input, Some(events)).await
+    }
+
+    async fn run_agent(
         &self,
         caller: Principal,
         mut input: AgentInput,
+        events: Option<UnboundedSender<AgentEvent>>,
     ) -> Result<AgentOutput, BoxError> {
         let meta = input.meta.unwrap_or_default();
         if meta.engine.is_some() && meta.engine != Some(self.id) {
@@ -273,7 +395,7 @@ impl Engine {
         }
 
         input.name = if input.name.is_empty() {
-            self.default_agent.clone()
+            self.default_agent()
         } else {
             input.name.to_ascii_lowercase()
         };
@@ -281,9 +403,9 @@ impl Engine {
             .ctx
             .agents
             .get(&input.name)
-            .ok_or_else(|| format!("agent {} not found", input.name))?;
+            .ok_or_else(|| NotFoundError::Agent(input.name.clone()))?;
 
-        let visibility = self.management.check_visibility(&caller)?;
+        let visibility = self.check_visibility(&caller)?;
         let now_ms = unix_ms();
         let user_state = self.management.load_user(&caller).await?;
         let user_state = Arc::new(user_state);
@@ -294,7 +416,11 @@ impl Engine {
             return Err("caller does not have permission".into());
         }
 
-        let ctx = self.ctx_with(caller, &input.name, meta)?;
+        let _permit = self.acquire_permit().await?;
+        let mut ctx = self.ctx_with(caller, &input.name, meta)?;
+        if let Some(events) = events {
+            ctx = ctx.with_events(events);
+        }
         self.hooks
             .on_agent_start(&ctx, &input.name, user_state.as_ref())
             .await?;
@@ -304,12 +430,16 @@ impl Engine {
         // Save the user state after incrementing requests
         self.management.update_user(user_state.as_ref()).await?;
 
-        let output = agent
-            .run(ctx.clone(), input.prompt, input.resources)
+        let output = ctx
+            .base
+            .with_deadline(agent.run(ctx.clone(), input.prompt, input.resources))
             .await?;
         let mut output = self.hooks.on_agent_end(&ctx, &input.name, output).await?;
         self.management.update_user(user_state.as_ref()).await?;
         output.raw_history.clear(); // clear raw history
+        if let Some(artifacts) = &self.artifacts {
+            artifacts.persist(&caller, &mut output.artifacts).await?;
+        }
         Ok(output)
     }
 
@@ -331,15 +461,15 @@ impl Engine {
         }
 
         if !self.export_tools.contains(&input.name) || !self.ctx.tools.contains(&input.name) {
-            return Err(format!("tool {} not found", &input.name).into());
+            return Err(NotFoundError::Tool(input.name).into());
         }
         let tool = self
             .ctx
             .tools
             .get(&input.name)
-            .ok_or_else(|| format!("tool {} not found", &input.name))?;
+            .ok_or_else(|| NotFoundError::Tool(input.name.clone()))?;
 
-        let visibility = self.management.check_visibility(&caller)?;
+        let visibility = self.check_visibility(&caller)?;
         let now_ms = unix_ms();
         let user_state = self.management.load_user(&caller).await?;
         let user_state = Arc::new(user_state);
@@ -350,6 +480,16 @@ impl Engine {
             return Err("caller does not have permission".into());
         }
 
+        if !tool.allow_anonymous() && caller == ANONYMOUS {
+            return Err(format!("tool {} does not allow anonymous caller", input.name).into());
+        }
+
+        if self.ctx.validate_tool_args {
+            validate_json_schema(&tool.definition().parameters, &input.args)
+                .map_err(|err| format!("tool {}, invalid args: {}", input.name, err))?;
+        }
+
+        let _permit = self.acquire_permit().await?;
         let ctx = self.ctx.child_base_with(caller, &input.name, meta)?;
         self.hooks
             .on_tool_start(&ctx, &input.name, user_state.as_ref())
@@ -359,9 +499,70 @@ impl Engine {
         // Save the user state after incrementing requests
         self.management.update_user(user_state.as_ref()).await?;
 
-        let output = tool.call(ctx.clone(), input.args, input.resources).await?;
-        let res = self.hooks.on_tool_end(&ctx, &input.name, output).await?;
+        let output = ctx
+            .with_deadline(async {
+                let _permit = self.ctx.acquire_tool_permit(&input.name).await?;
+                tool.call(ctx.clone(), input.args, input.resources).await
+            })
+            .await?;
+        let mut res = self.hooks.on_tool_end(&ctx, &input.name, output).await?;
         self.management.update_user(user_state.as_ref()).await?;
+        if let Some(artifacts) = &self.artifacts {
+            artifacts.persist(&caller, &mut res.artifacts).await?;
+        }
+        Ok(res)
+    }
+
+    /// Waits for a permit to run an agent or call a tool if the engine-wide concurrency is
+    /// limited, fails if none is available within the queue timeout.
+    /// The permit should be held until the run or call finishes.
+    async fn acquire_permit(&self) -> Result<Option<OwnedSemaphorePermit>, BoxError> {
+        match &self.concurrency_limit {
+            Some((sem, timeout)) => {
+                let permit = tokio::time::timeout(*timeout, sem.clone().acquire_owned())
+                    .await
+                    .map_err(|_| {
+                        format!("engine is busy, no request slot available within {timeout:?}")
+                    })?
+                    .map_err(|err| format!("engine concurrency limit: {err}"))?;
+                Ok(Some(permit))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the blob of an artifact persisted for the caller by an agent run or tool call,
+    /// `name` is the last segment of the artifact's `store://` URI.
+    pub async fn get_artifact(&self, caller: Principal, name: &str) -> Result<Vec<u8>, BoxError> {
+        let artifacts = self
+            .artifacts
+            .as_ref()
+            .ok_or("artifact persistence is not enabled")?;
+        self.check_visibility(&caller)?;
+        artifacts.get(&caller, name).await
+    }
+
+    /// Calls multiple tools with bounded concurrency.
+    /// Returns the results in the same order as the inputs, a failed call does not affect the others.
+    pub async fn tool_call_batch(
+        &self,
+        caller: Principal,
+        inputs: Vec<ToolInput<Json>>,
+    ) -> Result<Vec<Result<ToolOutput<Json>, BoxError>>, BoxError> {
+        if inputs.len() > MAX_TOOL_CALL_BATCH_SIZE {
+            return Err(format!(
+                "too many tool calls in a batch, expected at most {}, got {}",
+                MAX_TOOL_CALL_BATCH_SIZE,
+                inputs.len()
+            )
+            .into());
+        }
+
+        let res = stream::iter(inputs)
+            .map(|input| self.tool_call(caller, input))
+            .buffered(TOOL_CALL_BATCH_CONCURRENCY)
+            .collect()
+            .await;
         Ok(res)
     }
 
@@ -371,12 +572,53 @@ impl Engine {
         self.ctx.agents.functions(names)
     }
 
+    /// Returns the number of agents registered in the engine.
+    pub fn agent_count(&self) -> usize {
+        self.ctx.agents.set.len()
+    }
+
+    /// Returns the number of tools registered in the engine.
+    pub fn tool_count(&self) -> usize {
+        self.ctx.tools.set.len()
+    }
+
+    /// Returns a health snapshot of the engine.
+    pub fn status(&self) -> EngineStatus {
+        EngineStatus {
+            agents: self.agent_count() as u64,
+            tools: self.tool_count() as u64,
+            cache_entries: self.ctx.base.cache_entry_count(),
+            available_permits: self
+                .concurrency_limit
+                .as_ref()
+                .map(|(sem, _)| sem.available_permits() as u64),
+            cancelled: self.is_cancelled(),
+        }
+    }
+
     /// Returns function definitions for the specified tools.
     /// If no names are provided, returns definitions for all tools.
     pub fn tools(&self, names: Option<&[&str]>) -> Vec<Function> {
         self.ctx.tools.functions(names)
     }
 
+    /// Returns the function definition, including the parameters schema, of an exported
+    /// agent or tool by name. Agents take precedence over tools with the same name.
+    pub fn describe(&self, name: &str) -> Option<Function> {
+        let agent = name.to_ascii_lowercase();
+        if self.export_agents.contains(&agent)
+            && let Some(function) = self.agents(Some(&[agent.as_str()])).pop()
+        {
+            return Some(function);
+        }
+
+        if self.export_tools.contains(name) {
+            return self.tools(Some(&[name])).pop();
+        }
+
+        None
+    }
+
     pub async fn challenge(
         &self,
         request: ChallengeRequest,
@@ -420,6 +662,80 @@ impl Engine {
         Ok(res)
     }
 
+    /// Returns the engine principal and the public addresses of the configured chain identities.
+    pub async fn identities(&self) -> Result<EngineIdentities, BoxError> {
+        let mut addresses = BTreeMap::new();
+        for (chain, identity) in &self.identities {
+            let ctx = self.ctx.base.child(identity.namespace.clone())?;
+            let pubkey = ctx
+                .secp256k1_public_key(identity.derivation_path.clone())
+                .await?;
+            addresses.insert(chain.clone(), (identity.address)(&pubkey)?);
+        }
+
+        Ok(EngineIdentities {
+            id: self.id,
+            addresses,
+        })
+    }
+
+    /// Checks the visibility set at runtime if any, or the one of the management.
+    fn check_visibility(&self, caller: &Principal) -> Result<Visibility, BoxError> {
+        match *self.visibility.read() {
+            Some(visibility) => visibility.check(caller, self.management.is_manager(caller)),
+            None => self.management.check_visibility(caller),
+        }
+    }
+
+    /// Returns how the engine resolves the caller and what access the caller has.
+    pub fn whoami(&self, caller: Principal) -> CallerIdentity {
+        let (visibility, denied_reason) = match self.check_visibility(&caller) {
+            Ok(v) => (Some(v.as_str().to_string()), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+
+        CallerIdentity {
+            caller,
+            is_controller: self.management.is_controller(&caller),
+            is_manager: self.management.is_manager(&caller),
+            visibility,
+            denied_reason,
+        }
+    }
+
+    /// Sets the log level and target filters at runtime, only the controller can call it.
+    ///
+    /// `directives` is parsed as a [`LogFilter`], e.g. "info,anda_engine=debug". If the logger
+    /// was installed with [`crate::logger::init_filtered_logger`], the filter replaces the
+    /// current one. Otherwise only a bare level is accepted and it sets [`log::set_max_level`],
+    /// which can not raise the level the logger was initialized with.
+    ///
+    /// # Arguments
+    /// * `caller` - The principal of the caller
+    /// * `directives` - A level ("off", "error", "warn", "info", "debug", "trace") optionally
+    ///   followed by comma-separated `target=level` directives
+    pub fn set_log_level(&self, caller: &Principal, directives: &str) -> Result<(), BoxError> {
+        if !self.management.is_controller(caller) {
+            return Err("caller is not the controller".into());
+        }
+
+        let filter: LogFilter = directives.parse()?;
+        if log_filter().is_some() {
+            set_log_filter(filter.clone())?;
+        } else if filter.has_targets() {
+            return Err(
+                "target filters need the logger to be installed with init_filtered_logger".into(),
+            );
+        } else {
+            log::set_max_level(filter.max_level());
+        }
+        log::warn!(
+            caller = caller.to_text();
+            "log filter set to {filter}",
+        );
+        Ok(())
+    }
+
     /// Returns information about the engine, including agent and tool definitions.
     pub fn information(&self) -> EngineCard {
         EngineCard {
@@ -459,6 +775,19 @@ pub struct EngineBuilder {
     export_agents: BTreeSet<String>,
     export_tools: BTreeSet<String>,
     management: Option<Arc<dyn Management>>,
+    restore_visibility: bool,
+    validate_tool_args: bool,
+    max_tool_output_size: Option<usize>,
+    auto_continue: usize,
+    tool_limits: BTreeMap<String, usize>,
+    update_retry: Option<UpdateRetry>,
+    http_policy: Option<Arc<HttpPolicy>>,
+    remote_limits: RemoteCallLimits,
+    identities: BTreeMap<String, Identity>,
+    artifacts: Option<(Path, usize)>,
+    max_tools: Option<usize>,
+    max_agents: Option<usize>,
+    concurrency_limit: Option<(usize, Duration)>,
 }
 
 impl Default for EngineBuilder {
@@ -493,9 +822,47 @@ impl EngineBuilder {
             export_agents: BTreeSet::new(),
             export_tools: BTreeSet::new(),
             management: None,
+            restore_visibility: false,
+            validate_tool_args: false,
+            max_tool_output_size: None,
+            auto_continue: 0,
+            tool_limits: BTreeMap::new(),
+            update_retry: None,
+            http_policy: None,
+            remote_limits: RemoteCallLimits::default(),
+            identities: BTreeMap::new(),
+            artifacts: None,
+            max_tools: None,
+            max_agents: None,
+            concurrency_limit: None,
         }
     }
 
+    /// Creates a new EngineBuilder for tests, with a mock model that echoes the prompt,
+    /// a fresh in-memory store, a not-implemented web3 client that signs as the anonymous
+    /// principal and a public management controlled by the anonymous principal, so any
+    /// caller can run the agents and tools.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let engine = EngineBuilder::for_test()
+    ///     .register_agent(my_agent)?
+    ///     .build("my_agent".to_string())
+    ///     .await?;
+    /// let output = engine.agent_run(Principal::anonymous(), input).await?;
+    /// ```
+    pub fn for_test() -> Self {
+        Self::new()
+            .with_model(Model::mock_implemented())
+            .with_store(Store::new(Arc::new(InMemory::new())))
+            .with_web3_client(Arc::new(Web3SDK::Web3(Web3Client::not_implemented())))
+            .with_management(Arc::new(BaseManagement {
+                controller: Principal::anonymous(),
+                managers: BTreeSet::new(),
+                visibility: Visibility::Public,
+            }))
+    }
+
     /// Sets the engine information.
     pub fn with_info(mut self, info: AgentInfo) -> Self {
         self.info = info;
@@ -526,12 +893,121 @@ impl EngineBuilder {
         self
     }
 
+    /// Persists the artifacts of agent runs and tool calls larger than `threshold` bytes to the store
+    /// under `{namespace}/{caller}`, and returns references to them instead of the blobs.
+    /// See [`ArtifactStore`]. Artifacts are returned in-band by default.
+    pub fn with_artifact_persistence(mut self, namespace: Path, threshold: usize) -> Self {
+        self.artifacts = Some((namespace, threshold));
+        self
+    }
+
     /// Sets the management builder for the engine.
     pub fn with_management(mut self, management: Arc<dyn Management>) -> Self {
         self.management = Some(management);
         self
     }
 
+    /// Sets whether the visibility persisted by [`Engine::set_visibility`] overrides the
+    /// visibility of the management when the engine is built.
+    /// Disabled by default: the configured visibility wins and the persisted one is ignored.
+    pub fn with_restore_visibility(mut self, restore: bool) -> Self {
+        self.restore_visibility = restore;
+        self
+    }
+
+    /// Enables or disables validating tool arguments against the tool's parameters schema
+    /// before the tool is called. Disabled by default.
+    pub fn with_tool_args_validation(mut self, enabled: bool) -> Self {
+        self.validate_tool_args = enabled;
+        self
+    }
+
+    /// Sets the maximum size in bytes of a tool output fed back to the model.
+    /// Larger outputs are truncated with a note. Unlimited by default.
+    pub fn with_max_tool_output_size(mut self, size: usize) -> Self {
+        self.max_tool_output_size = Some(size);
+        self
+    }
+
+    /// Sets the maximum number of follow-up requests sent to continue a model response
+    /// cut off at the output token limit. See [`crate::context::CompletionRunner::with_auto_continue`].
+    /// Disabled by default.
+    pub fn with_auto_continue(mut self, max: usize) -> Self {
+        self.auto_continue = max;
+        self
+    }
+
+    /// Sets a soft limit on the number of registered tools.
+    /// Exceeding it only logs a warning when the engine is built, as every tool definition
+    /// offered to the model adds to the prompt size. See [`anda_core::RequestMeta::tools`]
+    /// for offering a subset of the tool definitions per request.
+    pub fn with_max_tools(mut self, max: usize) -> Self {
+        self.max_tools = Some(max);
+        self
+    }
+
+    /// Sets a soft limit on the number of registered agents.
+    /// Exceeding it only logs a warning when the engine is built.
+    pub fn with_max_agents(mut self, max: usize) -> Self {
+        self.max_agents = Some(max);
+        self
+    }
+
+    /// Limits the number of agent runs and tool calls executed concurrently across the
+    /// engine. Excess requests wait in a queue for up to `queue_timeout` and then fail.
+    /// Per-tool limits set by [`Self::register_tool_with_concurrency`] still apply.
+    /// Unlimited by default.
+    pub fn with_concurrency_limit(mut self, max: usize, queue_timeout: Duration) -> Self {
+        self.concurrency_limit = Some((max.max(1), queue_timeout));
+        self
+    }
+
+    /// Sets the retry policy for [`BaseCtx::canister_update_with_retry`].
+    /// Update calls are not retried by default.
+    pub fn with_canister_update_retry(mut self, retry: UpdateRetry) -> Self {
+        self.update_retry = Some(retry);
+        self
+    }
+
+    /// Sets the egress policy enforced on outbound HTTPs requests of agents and tools.
+    /// All targets are allowed by default.
+    pub fn with_http_policy(mut self, policy: HttpPolicy) -> Self {
+        self.http_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Sets the timeout and response size cap of RPC calls to remote engines,
+    /// default to [`RemoteCallLimits::default`].
+    pub fn with_remote_call_limits(mut self, limits: RemoteCallLimits) -> Self {
+        self.remote_limits = limits;
+        self
+    }
+
+    /// Registers a chain identity exposed by [`Engine::identities`].
+    ///
+    /// # Arguments
+    /// * `chain` - Chain name, e.g. "bnb";
+    /// * `namespace` - Context path the key is derived in, e.g. "T:{tool_name}" for a tool's key;
+    /// * `derivation_path` - Derivation path of the Secp256k1 key within the namespace;
+    /// * `address` - Function deriving the chain address from the public key.
+    pub fn with_identity(
+        mut self,
+        chain: String,
+        namespace: String,
+        derivation_path: Vec<Vec<u8>>,
+        address: AddressFn,
+    ) -> Self {
+        self.identities.insert(
+            chain,
+            Identity {
+                namespace,
+                derivation_path,
+                address,
+            },
+        );
+        self
+    }
+
     /// Registers a single tool with the engine.
     /// Returns an error if the tool cannot be added.
     pub fn register_tool<T>(mut self, tool: T) -> Result<Self, BoxError>
@@ -542,6 +1018,26 @@ impl EngineBuilder {
         Ok(self)
     }
 
+    /// Registers a single tool with at most `max_concurrency` calls in flight at once,
+    /// across all requests. Further calls wait for a running call to finish.
+    /// Returns an error if the tool already exists or `max_concurrency` is zero.
+    pub fn register_tool_with_concurrency<T>(
+        mut self,
+        tool: T,
+        max_concurrency: usize,
+    ) -> Result<Self, BoxError>
+    where
+        T: Tool<BaseCtx> + Send + Sync + 'static,
+    {
+        if max_concurrency == 0 {
+            return Err("max_concurrency must be greater than 0".into());
+        }
+        let name = tool.name();
+        self.tools.add(tool)?;
+        self.tool_limits.insert(name, max_concurrency);
+        Ok(self)
+    }
+
     /// Registers multiple tools with the engine.
     /// Returns an error if any tool already exists.
     pub fn register_tools(mut self, tools: ToolSet<BaseCtx>) -> Result<Self, BoxError> {
@@ -606,6 +1102,31 @@ impl EngineBuilder {
         Ok(self)
     }
 
+    /// Registers a remote engine with known principal ID, endpoint, tools and agents.
+    /// The engine information is fetched and verified at build time.
+    pub fn with_remote_engine(
+        self,
+        id: Principal,
+        endpoint: String,
+        tools: Vec<String>,
+        agents: Vec<String>,
+    ) -> Result<Self, BoxError> {
+        self.register_remote_engine(RemoteEngineArgs {
+            endpoint,
+            agents,
+            tools,
+            handle: None,
+            id: Some(id),
+        })
+    }
+
+    /// Registers and exports the [`PingAgent`] for connectivity testing.
+    pub fn with_ping_agent(mut self) -> Result<Self, BoxError> {
+        self.agents.add(PingAgent)?;
+        self.export_agents.insert(PingAgent::NAME.to_string());
+        Ok(self)
+    }
+
     /// Exports agents by name.
     pub fn export_agents(mut self, agents: Vec<String>) -> Self {
         for mut agent in agents {
@@ -650,7 +1171,7 @@ impl EngineBuilder {
             id,
             ctx,
             info: self.info,
-            default_agent: String::new(),
+            default_agent: Arc::new(RwLock::new(String::new())),
             export_agents: self.export_agents,
             export_tools: self.export_tools,
             hooks: self.hooks,
@@ -661,6 +1182,10 @@ impl EngineBuilder {
                     visibility: Visibility::Private, // default visibility
                 })
             }),
+            visibility: Arc::new(RwLock::new(None)),
+            identities: self.identities,
+            artifacts: None,
+            concurrency_limit: None,
         }
     }
 
@@ -674,6 +1199,22 @@ impl EngineBuilder {
         }
 
         self.export_agents.insert(default_agent.clone());
+        if let Some(max) = self.max_tools
+            && self.tools.set.len() > max
+        {
+            log::warn!(
+                "engine has {} tools, more than the soft limit of {max}",
+                self.tools.set.len()
+            );
+        }
+        if let Some(max) = self.max_agents
+            && self.agents.set.len() > max
+        {
+            log::warn!(
+                "engine has {} agents, more than the soft limit of {max}",
+                self.agents.set.len()
+            );
+        }
 
         self.info.validate()?;
         let id = self.web3.as_ref().get_principal();
@@ -696,19 +1237,31 @@ impl EngineBuilder {
             remote.register(self.web3.as_ref(), engine).await?;
         }
 
-        let ctx = BaseCtx::new(
+        let mut ctx = BaseCtx::new(
             id,
             self.info.name.clone(),
             self.cancellation_token,
             names,
             self.web3,
-            self.store,
+            self.store.clone(),
             Arc::new(remote),
         );
+        ctx.update_retry = self.update_retry;
+        ctx.http_policy = self.http_policy;
+        ctx.remote_limits = self.remote_limits;
 
         let tools = Arc::new(self.tools);
         let agents = Arc::new(self.agents);
-        let ctx = AgentCtx::new(ctx, self.model, tools.clone(), agents.clone());
+        let mut ctx = AgentCtx::new(ctx, self.model, tools.clone(), agents.clone());
+        ctx.validate_tool_args = self.validate_tool_args;
+        ctx.max_tool_output_size = self.max_tool_output_size;
+        ctx.auto_continue = self.auto_continue;
+        ctx.tool_limits = Arc::new(
+            self.tool_limits
+                .into_iter()
+                .map(|(name, max)| (name, Arc::new(Semaphore::new(max))))
+                .collect(),
+        );
 
         let meta = RequestMeta::default();
         for (name, tool) in &tools.set {
@@ -721,21 +1274,49 @@ impl EngineBuilder {
             agent.init(ct).await?;
         }
 
+        let management = self.management.unwrap_or_else(|| {
+            Arc::new(BaseManagement {
+                controller: id,
+                managers: BTreeSet::new(),
+                visibility: Visibility::Private, // default visibility
+            })
+        });
+        // the visibility set at runtime by the controller
+        let mut visibility = None;
+        let system = ctx.base.child(SYSTEM_PATH.to_string())?;
+        if let Ok((data, _)) = system.store_get(&Path::from(VISIBILITY_PATH)).await {
+            let persisted: Visibility = std::str::from_utf8(&data)?.parse()?;
+            if self.restore_visibility {
+                log::warn!(
+                    "restored visibility {} set at runtime, it overrides the configured visibility",
+                    persisted.as_str()
+                );
+                visibility = Some(persisted);
+            } else {
+                log::warn!(
+                    "ignored visibility {} set at runtime, the configured visibility is used",
+                    persisted.as_str()
+                );
+            }
+        }
+
         Ok(Engine {
             id,
             ctx,
             info: self.info,
-            default_agent,
+            default_agent: Arc::new(RwLock::new(default_agent)),
             export_agents: self.export_agents,
             export_tools: self.export_tools,
             hooks: self.hooks,
-            management: self.management.unwrap_or_else(|| {
-                Arc::new(BaseManagement {
-                    controller: id,
-                    managers: BTreeSet::new(),
-                    visibility: Visibility::Private, // default visibility
-                })
-            }),
+            management,
+            visibility: Arc::new(RwLock::new(visibility)),
+            identities: self.identities,
+            artifacts: self
+                .artifacts
+                .map(|(namespace, threshold)| ArtifactStore::new(self.store, namespace, threshold)),
+            concurrency_limit: self
+                .concurrency_limit
+                .map(|(max, timeout)| (Arc::new(Semaphore::new(max)), timeout)),
         })
     }
 
@@ -750,7 +1331,7 @@ impl EngineBuilder {
             .map(|s| Path::from(s.as_str()))
             .collect();
         names.insert(Path::from(SYSTEM_PATH));
-        let ctx = BaseCtx::new(
+        let mut ctx = BaseCtx::new(
             Principal::anonymous(),
             "Mocker".to_string(),
             self.cancellation_token,
@@ -759,8 +1340,21 @@ impl EngineBuilder {
             self.store,
             Arc::new(RemoteEngines::new()),
         );
-
-        AgentCtx::new(ctx, self.model, Arc::new(self.tools), Arc::new(self.agents))
+        ctx.update_retry = self.update_retry;
+        ctx.http_policy = self.http_policy;
+        ctx.remote_limits = self.remote_limits;
+
+        let mut ctx = AgentCtx::new(ctx, self.model, Arc::new(self.tools), Arc::new(self.agents));
+        ctx.validate_tool_args = self.validate_tool_args;
+        ctx.max_tool_output_size = self.max_tool_output_size;
+        ctx.auto_continue = self.auto_continue;
+        ctx.tool_limits = Arc::new(
+            self.tool_limits
+                .into_iter()
+                .map(|(name, max)| (name, Arc::new(Semaphore::new(max))))
+                .collect(),
+        );
+        ctx
     }
 }
 
@@ -800,3 +1394,320 @@ impl Agent<AgentCtx> for EchoEngineInfo {
         })
     }
 }
+
+/// A ping agent that returns the prompt and the caller principal as JSON without calling the model,
+/// for verifying authentication and routing end to end.
+pub struct PingAgent;
+
+impl PingAgent {
+    pub const NAME: &'static str = "ping";
+}
+
+impl Agent<AgentCtx> for PingAgent {
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        "Returns the prompt and the caller principal for connectivity testing".to_string()
+    }
+
+    async fn run(
+        &self,
+        ctx: AgentCtx,
+        prompt: String,
+        _resources: Vec<Resource>,
+    ) -> Result<AgentOutput, BoxError> {
+        let content = serde_json::json!({
+            "prompt": prompt,
+            "caller": ctx.caller().to_text(),
+        });
+        Ok(AgentOutput {
+            content: content.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anda_core::{CompletionFeatures, CompletionRequest, HttpFeatures};
+    use serde::{Serialize, de::DeserializeOwned};
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_set_log_level() {
+        let engine = EngineBuilder::for_test()
+            .with_ping_agent()
+            .unwrap()
+            .build(PingAgent::NAME.to_string())
+            .await
+            .unwrap();
+        let controller = Principal::anonymous();
+        let user = Principal::from_slice(&[2; 29]);
+        let max_level = log::max_level();
+
+        let err = engine.set_log_level(&user, "debug").unwrap_err();
+        assert!(err.to_string().contains("not the controller"), "{err}");
+        let err = engine.set_log_level(&controller, "verbose").unwrap_err();
+        assert!(err.to_string().contains("invalid log level"), "{err}");
+        // target filters need the filtered logger, which is not installed in tests
+        let err = engine
+            .set_log_level(&controller, "info,anda_engine=debug")
+            .unwrap_err();
+        assert!(err.to_string().contains("init_filtered_logger"), "{err}");
+
+        engine.set_log_level(&controller, "error").unwrap();
+        assert_eq!(log::max_level(), log::LevelFilter::Error);
+        log::set_max_level(max_level);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_set_visibility() {
+        let controller = Principal::from_slice(&[1; 29]);
+        let user = Principal::from_slice(&[2; 29]);
+        let store = Store::new(Arc::new(InMemory::new()));
+        let build = async |restore: bool| {
+            EngineBuilder::new()
+                .with_store(store.clone())
+                .with_management(Arc::new(BaseManagement {
+                    controller,
+                    managers: BTreeSet::new(),
+                    visibility: Visibility::Private,
+                }))
+                .with_restore_visibility(restore)
+                .register_agent(PingAgent)
+                .unwrap()
+                .build(PingAgent::NAME.to_string())
+                .await
+                .unwrap()
+        };
+
+        let engine = build(false).await;
+        assert!(engine.whoami(user).denied_reason.is_some());
+        assert!(engine.set_visibility(&user, "public").await.is_err());
+        assert!(engine.set_visibility(&controller, "open").await.is_err());
+        engine.set_visibility(&controller, "public").await.unwrap();
+        assert_eq!(engine.whoami(user).visibility.as_deref(), Some("public"));
+        assert_eq!(
+            engine
+                .clone()
+                .whoami(Principal::anonymous())
+                .visibility
+                .as_deref(),
+            Some("public")
+        );
+
+        // the configured visibility wins by default
+        let engine = build(false).await;
+        assert!(engine.whoami(user).denied_reason.is_some());
+
+        let engine = build(true).await;
+        assert_eq!(engine.whoami(user).visibility.as_deref(), Some("public"));
+    }
+
+    struct CompletionAgent;
+
+    impl Agent<AgentCtx> for CompletionAgent {
+        fn name(&self) -> String {
+            "completion".to_string()
+        }
+
+        fn description(&self) -> String {
+            "Completes the prompt with the model".to_string()
+        }
+
+        async fn run(
+            &self,
+            ctx: AgentCtx,
+            prompt: String,
+            resources: Vec<Resource>,
+        ) -> Result<AgentOutput, BoxError> {
+            ctx.completion(
+                CompletionRequest {
+                    prompt,
+                    ..Default::default()
+                },
+                resources,
+            )
+            .await
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_for_test() {
+        let engine = EngineBuilder::for_test()
+            .register_agent(CompletionAgent)
+            .unwrap()
+            .build("completion".to_string())
+            .await
+            .unwrap();
+        let output = engine
+            .agent_run(
+                Principal::anonymous(),
+                AgentInput::new("completion".to_string(), "hello".to_string()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(output.content, "hello");
+        assert!(output.failed_reason.is_none());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_ping_agent() {
+        // the model is not implemented, so the ping must not touch it
+        let engine = EngineBuilder::new()
+            .with_management(Arc::new(BaseManagement {
+                controller: Principal::from_slice(&[1; 29]),
+                managers: BTreeSet::new(),
+                visibility: Visibility::Public,
+            }))
+            .with_ping_agent()
+            .unwrap()
+            .build(PingAgent::NAME.to_string())
+            .await
+            .unwrap();
+        let card = engine.information();
+        assert_eq!(card.agents.len(), 1);
+        assert_eq!(card.agents[0].definition.name, PingAgent::NAME);
+
+        let user = Principal::from_slice(&[2; 29]);
+        for caller in [user, Principal::anonymous()] {
+            let output = engine
+                .agent_run(
+                    caller,
+                    AgentInput::new(PingAgent::NAME.to_string(), "hello".to_string()),
+                )
+                .await
+                .unwrap();
+            let content: Json = serde_json::from_str(&output.content).unwrap();
+            assert_eq!(
+                content,
+                serde_json::json!({"prompt": "hello", "caller": caller.to_text()})
+            );
+        }
+        assert!(
+            EngineBuilder::new()
+                .with_ping_agent()
+                .unwrap()
+                .with_ping_agent()
+                .is_err()
+        );
+    }
+
+    /// Answers the `information` RPC with a fixed engine card.
+    struct RemoteEngine(EngineCard);
+
+    impl HttpFeatures for &RemoteEngine {
+        async fn https_call(
+            &self,
+            _url: &str,
+            _method: http::Method,
+            _headers: Option<http::HeaderMap>,
+            _body: Option<Vec<u8>>,
+        ) -> Result<reqwest::Response, BoxError> {
+            Err("not implemented".into())
+        }
+
+        async fn https_signed_call(
+            &self,
+            _url: &str,
+            _method: http::Method,
+            _message_digest: [u8; 32],
+            _headers: Option<http::HeaderMap>,
+            _body: Option<Vec<u8>>,
+        ) -> Result<reqwest::Response, BoxError> {
+            Err("not implemented".into())
+        }
+
+        async fn https_signed_rpc<T>(
+            &self,
+            _endpoint: &str,
+            method: &str,
+            _args: impl Serialize + Send,
+        ) -> Result<T, BoxError>
+        where
+            T: DeserializeOwned,
+        {
+            if method != "information" {
+                return Err(format!("unknown method {method}").into());
+            }
+            Ok(serde_json::from_value(serde_json::to_value(&self.0)?)?)
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_with_remote_engine() {
+        let remote = EngineBuilder::for_test()
+            .register_agent(PingAgent)
+            .unwrap()
+            .export_agents(vec![PingAgent::NAME.to_string()])
+            .build(PingAgent::NAME.to_string())
+            .await
+            .unwrap();
+        let card = remote.information();
+        let endpoint = card.info.endpoint.clone();
+
+        let builder = EngineBuilder::new()
+            .with_remote_engine(
+                card.id,
+                endpoint.clone(),
+                vec![],
+                vec![PingAgent::NAME.to_string()],
+            )
+            .unwrap();
+        let args = builder.remote.get(&endpoint).unwrap().clone();
+        assert_eq!(args.id, Some(card.id));
+        assert_eq!(args.agents, vec![PingAgent::NAME.to_string()]);
+        assert!(
+            builder
+                .with_remote_engine(card.id, endpoint.clone(), vec![], vec![])
+                .is_err()
+        );
+
+        let http = RemoteEngine(card.clone());
+        let mut engines = RemoteEngines::new();
+        engines.register(&http, args.clone()).await.unwrap();
+        let handle = card.info.handle.to_ascii_lowercase();
+        assert_eq!(
+            engines.get_agent_endpoint(&format!("RA_{handle}_{}", PingAgent::NAME)),
+            Some((card.id, endpoint.clone(), PingAgent::NAME.to_string()))
+        );
+
+        let err = RemoteEngines::new()
+            .register(
+                &http,
+                RemoteEngineArgs {
+                    id: Some(Principal::from_slice(&[9; 29])),
+                    ..args.clone()
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("id mismatch"));
+        assert!(
+            RemoteEngines::new()
+                .register(
+                    &http,
+                    RemoteEngineArgs {
+                        id: None,
+                        ..args.clone()
+                    }
+                )
+                .await
+                .is_ok()
+        );
+        assert!(
+            RemoteEngines::new()
+                .register(
+                    &http,
+                    RemoteEngineArgs {
+                        agents: vec!["unknown".to_string()],
+                        ..args
+                    }
+                )
+                .await
+                .is_err()
+        );
+    }
+}
@@ -7,8 +7,10 @@
 //! - **Extraction Tools**: Enables structured data extraction from unstructured text
 //! - **Fetch Tools**: Fetch Resources Extension for Anda Engine.
 //! - **Google Web Search Tool**: Enables web searches and retrieve results.
+//! - **Store Read Tool**: Reads stored objects from the caller's namespace.
 //!
 
 pub mod extractor;
 pub mod fetch;
 pub mod google;
+pub mod store;
@@ -129,9 +129,15 @@ where
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: Vec::new(),
         }
     }
 
+    /// Submitting extracted data has no side effects, so it is safe for anonymous callers.
+    fn allow_anonymous(&self) -> bool {
+        true
+    }
+
     async fn call(
         &self,
         _ctx: BaseCtx,
@@ -7,6 +7,7 @@
 //! - Fetch resources from any HTTPS URL
 //! - Automatic content type handling
 //! - UTF-8 string conversion with base64 fallback for binary content
+//! - Optional custom request headers per origin, e.g. an API key or `Accept-Language`
 //! - Integration with Anda's HTTP features
 //!
 //! # Usage
@@ -31,6 +32,8 @@ use ic_auth_types::ByteBufB64;
 use mime::Mime;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use url::Url;
 
 use crate::context::BaseCtx;
 
@@ -59,6 +62,8 @@ pub struct FetchWebResourcesArgs {
 pub struct FetchWebResourcesTool {
     /// JSON schema for the fetch arguments
     schema: Json,
+    /// Custom headers added to the requests of the tool, by origin
+    headers: BTreeMap<String, header::HeaderMap>,
 }
 
 impl Default for FetchWebResourcesTool {
@@ -73,7 +78,45 @@ impl FetchWebResourcesTool {
     /// Creates a new FetchWebResourcesTool instance
     pub fn new() -> Self {
         let schema = gen_schema_for::<FetchWebResourcesArgs>();
-        Self { schema }
+        Self {
+            schema,
+            headers: BTreeMap::new(),
+        }
+    }
+
+    /// Adds custom headers to the requests of the tool to an origin such as
+    /// "https://api.example.com", e.g. an API key or `Accept-Language`.
+    /// They override the default headers of the same name.
+    ///
+    /// The URL to fetch comes from the model, so the headers are only sent to URLs of the
+    /// same scheme, host and port. Redirects are followed with the headers, except for
+    /// `Authorization` and `Cookie` on a redirect to another host or port, so prefer
+    /// `Authorization` for credentials.
+    pub fn with_headers(
+        mut self,
+        origin: &str,
+        headers: header::HeaderMap,
+    ) -> Result<Self, BoxError> {
+        let origin = Url::parse(origin)
+            .map_err(|err| format!("invalid origin {origin:?}: {err}"))?
+            .origin();
+        if !origin.is_tuple() {
+            return Err(format!("invalid origin {:?}", origin.ascii_serialization()).into());
+        }
+        self.headers
+            .entry(origin.ascii_serialization())
+            .or_default()
+            .extend(headers);
+        Ok(self)
+    }
+
+    /// Returns the custom headers for the URL's origin.
+    fn headers_for(&self, url: &str) -> header::HeaderMap {
+        Url::parse(url)
+            .ok()
+            .and_then(|url| self.headers.get(&url.origin().ascii_serialization()))
+            .cloned()
+            .unwrap_or_default()
     }
 
     /// Fetches content from the specified URL
@@ -88,14 +131,25 @@ impl FetchWebResourcesTool {
         ctx: &impl HttpFeatures,
         url: &str,
     ) -> Result<(header::HeaderMap, Vec<u8>), BoxError> {
-        let mut headers = header::HeaderMap::new();
+        Self::fetch_with_headers(ctx, url, header::HeaderMap::new()).await
+    }
 
-        headers.insert(
-            header::ACCEPT,
-            "application/json, text/*, */*;q=0.9"
-                .parse()
-                .expect("invalid header value"),
-        );
+    /// Fetches content from the specified URL with custom headers,
+    /// which override the default headers of the same name
+    ///
+    /// # Arguments
+    /// * `ctx` - HTTP context for making requests
+    /// * `url` - The URL to fetch content from
+    /// * `extra` - Custom headers of the request
+    ///
+    /// # Returns
+    /// Response headers and raw bytes of the fetched content or an error
+    pub async fn fetch_with_headers(
+        ctx: &impl HttpFeatures,
+        url: &str,
+        extra: header::HeaderMap,
+    ) -> Result<(header::HeaderMap, Vec<u8>), BoxError> {
+        let headers = Self::request_headers(extra);
 
         let response = ctx
             .https_call(url, http::Method::GET, Some(headers), None)
@@ -123,11 +177,27 @@ impl FetchWebResourcesTool {
     /// String content (UTF-8 or base64-url encoded) or an error
     pub async fn fetch_as_text(ctx: &impl HttpFeatures, url: &str) -> Result<String, BoxError> {
         let (headers, body) = Self::fetch(ctx, url).await?;
-        match Self::decode_text(&headers, &body) {
-            Some(text) => Ok(text),
+        Ok(Self::into_text(&headers, body))
+    }
+
+    fn request_headers(extra: header::HeaderMap) -> header::HeaderMap {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            "application/json, text/*, */*;q=0.9"
+                .parse()
+                .expect("invalid header value"),
+        );
+        headers.extend(extra);
+        headers
+    }
+
+    fn into_text(headers: &header::HeaderMap, body: Vec<u8>) -> String {
+        match Self::decode_text(headers, &body) {
+            Some(text) => text,
             None => match String::from_utf8(body) {
-                Ok(text) => Ok(text),
-                Err(e) => Ok(ByteBufB64(e.into_bytes()).to_string()),
+                Ok(text) => text,
+                Err(e) => ByteBufB64(e.into_bytes()).to_string(),
             },
         }
     }
@@ -198,6 +268,7 @@ impl Tool<BaseCtx> for FetchWebResourcesTool {
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: Vec::new(),
         }
     }
 
@@ -216,8 +287,9 @@ impl Tool<BaseCtx> for FetchWebResourcesTool {
         args: Self::Args,
         _resources: Vec<Resource>,
     ) -> Result<ToolOutput<Self::Output>, BoxError> {
-        let text = FetchWebResourcesTool::fetch_as_text(&ctx, &args.url).await?;
-        Ok(ToolOutput::new(text))
+        let (headers, body) =
+            Self::fetch_with_headers(&ctx, &args.url, self.headers_for(&args.url)).await?;
+        Ok(ToolOutput::new(Self::into_text(&headers, body)))
     }
 }
 
@@ -247,4 +319,41 @@ mod tests {
             .unwrap();
         print!("{:?}", res);
     }
+
+    #[test]
+    fn test_headers_for() {
+        let mut headers = header::HeaderMap::new();
+        headers.insert("x-api-key", "secret".parse().unwrap());
+        let tool = FetchWebResourcesTool::new()
+            .with_headers("https://api.example.com/v1", headers)
+            .unwrap();
+
+        for url in [
+            "https://api.example.com/data",
+            "https://API.example.com:443/data?q=1",
+        ] {
+            assert_eq!(tool.headers_for(url)["x-api-key"], "secret", "{url}");
+        }
+        for url in [
+            "http://api.example.com/data",
+            "https://api.example.com:8443/data",
+            "https://example.com/data",
+            "https://evil.api.example.com/data",
+            "https://api.example.com.evil.com/data",
+            "not a url",
+        ] {
+            assert!(tool.headers_for(url).is_empty(), "{url}");
+        }
+
+        assert!(
+            FetchWebResourcesTool::new()
+                .with_headers("api.example.com", header::HeaderMap::new())
+                .is_err()
+        );
+        assert!(
+            FetchWebResourcesTool::new()
+                .with_headers("data:text/plain,hi", header::HeaderMap::new())
+                .is_err()
+        );
+    }
 }
@@ -7,6 +7,8 @@
 //! - Perform web searches using Google's Custom Search API
 //! - Parse and return structured search results
 //! - Configurable number of results
+//! - Optional TTL cache of results and per-caller rate limit of API calls
+//! - Optional custom request headers, e.g. `Accept-Language` for localized results
 //! - Integration with Anda's HTTP features
 //!
 //! # Configuration
@@ -16,7 +18,9 @@
 //!
 //! # Usage
 //! ```rust,ignore
-//! let google = GoogleSearchTool::new(api_key, search_engine_id, Some(5));
+//! let google = GoogleSearchTool::new(api_key, search_engine_id, Some(5))
+//!     .with_cache(1000, Duration::from_secs(3600))
+//!     .with_rate_limit(10, Duration::from_secs(60));
 //! // Manual invocation within an agent
 //! let results = google.search(ctx, SearchArgs { query: "ICPanda" }).await?;
 //! // Or register with Engine for automatic invocation
@@ -28,12 +32,22 @@
 //! ```
 
 use anda_core::{
-    BoxError, FunctionDefinition, HttpFeatures, Resource, Tool, ToolOutput, gen_schema_for,
+    BoxError, FunctionDefinition, HttpFeatures, Resource, StateFeatures, Tool, ToolOutput,
+    gen_schema_for,
 };
+use candid::Principal;
 use http::header;
+use moka::future::Cache;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
 use url::Url;
 
 use crate::context::BaseCtx;
@@ -76,6 +90,12 @@ pub struct GoogleSearchTool {
     result_number: u8,
     /// JSON schema for the search arguments
     schema: Value,
+    /// Cached search results keyed by the result number and query
+    cache: Option<Cache<String, Arc<Vec<SearchResultItem>>>>,
+    /// Maximum number of API calls per caller in a window, and the counters of the current windows
+    rate_limit: Option<(u32, Cache<Principal, Arc<AtomicU32>>)>,
+    /// Custom headers added to each API request
+    headers: header::HeaderMap,
 }
 
 impl GoogleSearchTool {
@@ -94,7 +114,83 @@ impl GoogleSearchTool {
             search_engine_id,
             result_number: result_number.unwrap_or(5),
             schema,
+            cache: None,
+            rate_limit: None,
+            headers: header::HeaderMap::new(),
+        }
+    }
+
+    /// Adds custom headers to each API request, e.g. `Accept-Language` for localized results.
+    /// They override the default headers of the same name.
+    pub fn with_headers(mut self, headers: header::HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Caches up to `capacity` search results for `ttl`, so repeated identical searches
+    /// don't call the API again.
+    pub fn with_cache(mut self, capacity: u64, ttl: Duration) -> Self {
+        self.cache = Some(
+            Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(ttl)
+                .build(),
+        );
+        self
+    }
+
+    /// Limits each caller to `max_calls` API calls per `window`, cached results are not counted.
+    pub fn with_rate_limit(mut self, max_calls: u32, window: Duration) -> Self {
+        self.rate_limit = Some((
+            max_calls,
+            Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(window)
+                .build(),
+        ));
+        self
+    }
+
+    fn request_headers(&self) -> header::HeaderMap {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            "application/json".parse().expect("invalid header value"),
+        );
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            "gzip".parse().expect("invalid header value"),
+        );
+        headers.extend(self.headers.clone());
+        headers
+    }
+
+    fn cache_key(&self, query: &str) -> String {
+        format!("{}:{}", self.result_number, query.trim())
+    }
+
+    /// Counts an API call of the caller, returns an error if the caller exceeds the rate limit.
+    async fn check_rate_limit(&self, caller: &Principal, query: &str) -> Result<(), BoxError> {
+        let Some((max_calls, counters)) = &self.rate_limit else {
+            return Ok(());
+        };
+        if let Some(cache) = &self.cache
+            && cache.contains_key(&self.cache_key(query))
+        {
+            return Ok(());
         }
+
+        let counter = counters
+            .get_with(*caller, async { Arc::new(AtomicU32::new(0)) })
+            .await;
+        if counter.fetch_add(1, Ordering::Relaxed) >= *max_calls {
+            return Err(format!(
+                "Google search rate limit exceeded, at most {} searches per window",
+                max_calls
+            )
+            .into());
+        }
+        Ok(())
     }
 
     /// Performs a Google search using the provided query
@@ -110,16 +206,15 @@ impl GoogleSearchTool {
         ctx: &impl HttpFeatures,
         args: SearchArgs,
     ) -> Result<Vec<SearchResultItem>, BoxError> {
+        let key = self.cache_key(&args.query);
+        if let Some(cache) = &self.cache
+            && let Some(res) = cache.get(&key).await
+        {
+            return Ok(res.as_ref().clone());
+        }
+
         let mut url = Url::parse("https://www.googleapis.com/customsearch/v1")?;
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            "application/json".parse().expect("invalid header value"),
-        );
-        headers.insert(
-            header::ACCEPT_ENCODING,
-            "gzip".parse().expect("invalid header value"),
-        );
+        let headers = self.request_headers();
 
         url.query_pairs_mut()
             .append_pair("key", &self.api_key)
@@ -157,6 +252,9 @@ impl GoogleSearchTool {
             }
         }
 
+        if let Some(cache) = &self.cache {
+            cache.insert(key, Arc::new(res.clone())).await;
+        }
         Ok(res)
     }
 }
@@ -179,6 +277,7 @@ impl Tool<BaseCtx> for GoogleSearchTool {
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: Vec::new(),
         }
     }
 
@@ -196,6 +295,7 @@ impl Tool<BaseCtx> for GoogleSearchTool {
         args: Self::Args,
         _resources: Vec<Resource>,
     ) -> Result<ToolOutput<Self::Output>, BoxError> {
+        self.check_rate_limit(ctx.caller(), &args.query).await?;
         let res = self.search(&ctx, args).await?;
         Ok(ToolOutput::new(res))
     }
@@ -251,4 +351,64 @@ mod tests {
             .unwrap();
         print!("{:?}", res);
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_google_search_cache_and_rate_limit() {
+        let tool = GoogleSearchTool::new("key".to_string(), "cx".to_string(), None)
+            .with_cache(10, Duration::from_secs(60))
+            .with_rate_limit(0, Duration::from_secs(60));
+        let cached = vec![SearchResultItem {
+            title: "ICPanda".to_string(),
+            link: "https://panda.fans".to_string(),
+            snippet: "ICPanda DAO".to_string(),
+        }];
+        tool.cache
+            .as_ref()
+            .unwrap()
+            .insert(tool.cache_key("ICPanda"), Arc::new(cached.clone()))
+            .await;
+
+        let ctx = EngineBuilder::new().mock_ctx();
+        let res = tool
+            .call(
+                ctx.base.clone(),
+                SearchArgs {
+                    query: " ICPanda ".to_string(),
+                },
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.output.len(), 1);
+        assert_eq!(res.output[0].link, cached[0].link);
+
+        let res = tool
+            .call(
+                ctx.base,
+                SearchArgs {
+                    query: "Anda".to_string(),
+                },
+                Vec::new(),
+            )
+            .await;
+        assert!(res.unwrap_err().to_string().contains("rate limit exceeded"));
+    }
+
+    #[test]
+    fn test_google_search_headers() {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(header::ACCEPT_LANGUAGE, "zh-CN".parse().unwrap());
+        headers.insert(header::ACCEPT_ENCODING, "br".parse().unwrap());
+        let tool =
+            GoogleSearchTool::new("key".to_string(), "cx".to_string(), None).with_headers(headers);
+
+        let headers = tool.request_headers();
+        assert_eq!(headers.get(header::ACCEPT_LANGUAGE).unwrap(), "zh-CN");
+        assert_eq!(headers.get_all(header::ACCEPT_ENCODING).iter().count(), 1);
+        assert_eq!(headers.get(header::ACCEPT_ENCODING).unwrap(), "br");
+        assert_eq!(
+            headers.get(header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+    }
 }
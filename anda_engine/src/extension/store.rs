@@ -0,0 +1,174 @@
+//! Store Read Extension for Anda Engine
+//!
+//! This module provides a tool for agents to read objects that were previously
+//! written to the [`Store`], so artifacts produced in one turn can be referenced
+//! by name in a later turn.
+//!
+//! Objects are read from the caller's namespace, i.e. `{namespace}/{caller}`,
+//! so one caller can never read another caller's objects.
+//!
+//! # Usage
+//! ```rust,ignore
+//! let store = Store::new(Arc::new(InMemory::new()));
+//! let store_tool = StoreReadTool::new(store.clone(), Path::from("artifacts"));
+//! let engine = Engine::builder()
+//!     .with_name("MyEngine".to_string())
+//!     .with_store(store)
+//!     .register_tool(store_tool)?
+//!     .register_agent(my_agent)?
+//!     .build("default_agent".to_string())?;
+//! ```
+
+use anda_core::{
+    BoxError, FunctionDefinition, Json, Path, Resource, StateFeatures, Tool, ToolOutput,
+    gen_schema_for, validate_path_part,
+};
+use ic_auth_types::ByteBufB64;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{context::BaseCtx, store::Store};
+
+/// Arguments for reading an object from the store
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct StoreReadArgs {
+    /// The name of the object to read
+    pub path: String,
+}
+
+/// Store Read Tool implementation
+///
+/// Reads an object from the caller's namespace in the store and returns it as a string.
+/// If the content is not valid UTF-8, it will be base64-url encoded.
+#[derive(Clone)]
+pub struct StoreReadTool {
+    store: Store,
+    namespace: Path,
+    schema: Json,
+}
+
+impl StoreReadTool {
+    pub const NAME: &'static str = "store_read";
+
+    /// Creates a new StoreReadTool instance
+    ///
+    /// # Arguments
+    /// * `store` - The store to read objects from
+    /// * `namespace` - The namespace under which each caller has its own namespace
+    pub fn new(store: Store, namespace: Path) -> Self {
+        let schema = gen_schema_for::<StoreReadArgs>();
+        Self {
+            store,
+            namespace,
+            schema,
+        }
+    }
+
+    /// Reads an object from the caller's namespace and returns it as text
+    /// (base64-url encoded if not UTF-8)
+    ///
+    /// # Arguments
+    /// * `ctx` - Base context providing the caller
+    /// * `path` - The name of the object to read
+    pub async fn read_as_text(&self, ctx: &BaseCtx, path: &str) -> Result<String, BoxError> {
+        validate_path_part(path)?;
+        let namespace = self.namespace.child(ctx.caller().to_text());
+        let (data, _) = self.store.store_get(&namespace, &Path::from(path)).await?;
+        match String::from_utf8(data.to_vec()) {
+            Ok(text) => Ok(text),
+            Err(e) => Ok(ByteBufB64(e.into_bytes()).to_string()),
+        }
+    }
+}
+
+impl Tool<BaseCtx> for StoreReadTool {
+    type Args = StoreReadArgs;
+    type Output = String;
+
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        "Reads a stored object by name and returns the content as text (base64-url encoded if not UTF-8)".to_string()
+    }
+
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: self.name(),
+            description: self.description(),
+            parameters: self.schema.clone(),
+            strict: Some(true),
+            examples: Vec::new(),
+        }
+    }
+
+    async fn call(
+        &self,
+        ctx: BaseCtx,
+        args: Self::Args,
+        _resources: Vec<Resource>,
+    ) -> Result<ToolOutput<Self::Output>, BoxError> {
+        let text = self.read_as_text(&ctx, &args.path).await?;
+        Ok(ToolOutput::new(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::EngineBuilder, store::InMemory};
+    use anda_core::PutMode;
+    use std::sync::Arc;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_store_read_tool() {
+        let store = Store::new(Arc::new(InMemory::new()));
+        let tool = StoreReadTool::new(store.clone(), Path::from("artifacts"));
+        let ctx = EngineBuilder::new().with_store(store.clone()).mock_ctx();
+        let namespace = Path::from("artifacts").child(ctx.base.caller().to_text());
+        store
+            .store_put(
+                &namespace,
+                &Path::from("report.md"),
+                PutMode::Create,
+                bytes::Bytes::from_static(b"hello"),
+            )
+            .await
+            .unwrap();
+
+        let res = tool
+            .call(
+                ctx.base.clone(),
+                StoreReadArgs {
+                    path: "report.md".to_string(),
+                },
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.output, "hello");
+
+        let res = tool
+            .call(
+                ctx.base.clone(),
+                StoreReadArgs {
+                    path: "../report.md".to_string(),
+                },
+                Vec::new(),
+            )
+            .await;
+        assert!(res.is_err());
+
+        let res = tool
+            .call(
+                ctx.base,
+                StoreReadArgs {
+                    path: "missing.md".to_string(),
+                },
+                Vec::new(),
+            )
+            .await;
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! Log filtering that can be changed at runtime.
+//!
+//! A [`LogFilter`] is parsed from comma-separated directives like `RUST_LOG`, e.g.
+//! `"info,anda_engine=debug,anda_nexus::nexus=trace"`: a bare level sets the default level,
+//! and `target=level` sets the level of a target and its submodules. The most specific target
+//! wins.
+//!
+//! [`init_filtered_logger`] installs a logger that applies the filter before passing records
+//! to the inner logger, and [`set_log_filter`] replaces the filter without a restart, e.g.
+//! through [`crate::engine::Engine::set_log_level`].
+
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use parking_lot::RwLock;
+use std::{fmt, str::FromStr};
+
+/// The filter of the logger installed by [`init_filtered_logger`].
+static LOG_FILTER: RwLock<Option<LogFilter>> = RwLock::new(None);
+
+/// A default log level and levels for targets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    /// Sorted by target length in descending order, so the most specific target matches first.
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            default: LevelFilter::Info,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl LogFilter {
+    /// Creates a filter with a default level and no target levels.
+    pub fn new(default: LevelFilter) -> Self {
+        Self {
+            default,
+            targets: Vec::new(),
+        }
+    }
+
+    /// Returns whether the filter has levels for targets.
+    pub fn has_targets(&self) -> bool {
+        !self.targets.is_empty()
+    }
+
+    /// Returns the level of a target.
+    pub fn level(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .find(|(t, _)| {
+                target
+                    .strip_prefix(t.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    /// Returns whether a record of the target and level is logged.
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        level <= self.level(target)
+    }
+
+    /// Returns the most verbose level of the filter, for [`log::set_max_level`].
+    pub fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_level = |level: &str| {
+            level
+                .trim()
+                .parse::<LevelFilter>()
+                .map_err(|_| format!("invalid log level: {level:?}"))
+        };
+
+        let mut filter = LogFilter::default();
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    let target = target.trim();
+                    if target.is_empty() {
+                        return Err(format!("invalid log directive: {directive:?}"));
+                    }
+                    let level = parse_level(level)?;
+                    filter.targets.retain(|(t, _)| t != target);
+                    filter.targets.push((target.to_string(), level));
+                }
+                None => filter.default = parse_level(directive)?,
+            }
+        }
+        filter
+            .targets
+            .sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        Ok(filter)
+    }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.default.as_str().to_ascii_lowercase())?;
+        for (target, level) in &self.targets {
+            write!(f, ",{}={}", target, level.as_str().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+struct FilteredLogger<L> {
+    inner: L,
+}
+
+impl<L: Log> Log for FilteredLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        LOG_FILTER
+            .read()
+            .as_ref()
+            .is_none_or(|filter| filter.enabled(metadata.target(), metadata.level()))
+            && self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs `logger` as the global logger behind a filter that [`set_log_filter`] can replace,
+/// and sets the maximum log level of the filter. The inner logger should log at the most
+/// verbose level, e.g. `structured_logger::Builder::with_level("trace").build()`.
+pub fn init_filtered_logger(
+    logger: impl Log + 'static,
+    filter: LogFilter,
+) -> Result<(), SetLoggerError> {
+    log::set_boxed_logger(Box::new(FilteredLogger { inner: logger }))?;
+    log::set_max_level(filter.max_level());
+    *LOG_FILTER.write() = Some(filter);
+    Ok(())
+}
+
+/// Replaces the filter of the logger installed by [`init_filtered_logger`].
+/// Returns an error if it is not installed.
+pub fn set_log_filter(filter: LogFilter) -> Result<(), String> {
+    let mut current = LOG_FILTER.write();
+    if current.is_none() {
+        return Err("the logger was not installed with init_filtered_logger".to_string());
+    }
+    log::set_max_level(filter.max_level());
+    *current = Some(filter);
+    Ok(())
+}
+
+/// Returns the filter from the first of the `LOG`, `LOG_LEVEL` and `RUST_LOG` environment
+/// variables that holds valid directives, or the level of [`structured_logger::get_env_level`].
+pub fn env_log_filter() -> LogFilter {
+    ["LOG", "LOG_LEVEL", "RUST_LOG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find_map(|directives| directives.parse().ok())
+        .unwrap_or_else(|| LogFilter::new(structured_logger::get_env_level()))
+}
+
+/// Returns the filter of the logger installed by [`init_filtered_logger`].
+pub fn log_filter() -> Option<LogFilter> {
+    LOG_FILTER.read().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_filter() {
+        let filter: LogFilter = "warn, anda_engine=debug,anda_engine::model=trace,anda_db=off"
+            .parse()
+            .unwrap();
+        assert!(filter.has_targets());
+        assert_eq!(filter.level("anda_nexus"), LevelFilter::Warn);
+        assert_eq!(filter.level("anda_engine"), LevelFilter::Debug);
+        assert_eq!(filter.level("anda_engine::engine"), LevelFilter::Debug);
+        assert_eq!(
+            filter.level("anda_engine::model::openai"),
+            LevelFilter::Trace
+        );
+        // a target matches whole path segments only
+        assert_eq!(filter.level("anda_engine_server"), LevelFilter::Warn);
+        assert!(!filter.enabled("anda_db", Level::Error));
+        assert!(filter.enabled("anda_engine", Level::Debug));
+        assert!(!filter.enabled("anda_engine", Level::Trace));
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+        assert_eq!(
+            filter.to_string(),
+            "warn,anda_engine::model=trace,anda_engine=debug,anda_db=off"
+        );
+        assert_eq!(filter.to_string().parse::<LogFilter>().unwrap(), filter);
+
+        let filter: LogFilter = "anda_engine=debug".parse().unwrap();
+        assert_eq!(filter.level("other"), LevelFilter::Info);
+        let filter: LogFilter = "error".parse().unwrap();
+        assert_eq!(filter, LogFilter::new(LevelFilter::Error));
+        assert!(!filter.has_targets());
+
+        // a later directive for the same target wins
+        let filter: LogFilter = "anda_engine=debug,anda_engine=error".parse().unwrap();
+        assert_eq!(filter.level("anda_engine"), LevelFilter::Error);
+
+        assert!("verbose".parse::<LogFilter>().is_err());
+        assert!("anda_engine=loud".parse::<LogFilter>().is_err());
+        assert!("=debug".parse::<LogFilter>().is_err());
+    }
+}
@@ -2,7 +2,7 @@ use anda_core::BoxError;
 use async_trait::async_trait;
 use candid::Principal;
 use ic_auth_verifier::ANONYMOUS_PRINCIPAL;
-use std::collections::BTreeSet;
+use std::{collections::BTreeSet, str::FromStr};
 
 mod db;
 mod user;
@@ -37,7 +37,7 @@ pub struct BaseManagement {
 }
 
 /// The visibility of the engine.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Visibility {
     /// private, can only be accessed by the controller and managers;
     Private = 0,
@@ -49,6 +49,43 @@ pub enum Visibility {
     Public = 2,
 }
 
+impl Visibility {
+    /// Returns the visibility name: "private", "protected" or "public".
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Private => "private",
+            Visibility::Protected => "protected",
+            Visibility::Public => "public",
+        }
+    }
+
+    /// Checks whether a caller can access an engine with this visibility.
+    pub fn check(self, caller: &Principal, is_manager: bool) -> Result<Self, BoxError> {
+        if self != Visibility::Public && caller == &ANONYMOUS_PRINCIPAL {
+            return Err("anonymous caller not allowed".into());
+        }
+
+        if self == Visibility::Private && !is_manager {
+            return Err("caller is not allowed".into());
+        }
+
+        Ok(self)
+    }
+}
+
+impl FromStr for Visibility {
+    type Err = BoxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "private" => Ok(Visibility::Private),
+            "protected" => Ok(Visibility::Protected),
+            "public" => Ok(Visibility::Public),
+            _ => Err(format!("invalid visibility: {s:?}").into()),
+        }
+    }
+}
+
 #[async_trait]
 impl Management for BaseManagement {
     /// Returns true if the caller is the controller of the engine.
@@ -62,15 +99,7 @@ impl Management for BaseManagement {
     }
 
     fn check_visibility(&self, caller: &Principal) -> Result<Visibility, BoxError> {
-        if self.visibility != Visibility::Public && caller == &ANONYMOUS_PRINCIPAL {
-            return Err("anonymous caller not allowed".into());
-        }
-
-        if self.visibility == Visibility::Private && !self.is_manager(caller) {
-            return Err("caller is not allowed".into());
-        }
-
-        Ok(self.visibility)
+        self.visibility.check(caller, self.is_manager(caller))
     }
 
     async fn load_user(&self, user: &Principal) -> Result<UserState, BoxError> {
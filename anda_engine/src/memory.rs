@@ -659,6 +659,7 @@ impl Tool<BaseCtx> for GetResourceContentTool {
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: Vec::new(),
         }
     }
 
@@ -727,6 +728,7 @@ impl Tool<BaseCtx> for ListConversationsTool {
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: Vec::new(),
         }
     }
 
@@ -798,6 +800,7 @@ impl Tool<BaseCtx> for SearchConversationsTool {
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: Vec::new(),
         }
     }
 
@@ -904,6 +907,7 @@ impl Tool<BaseCtx> for MemoryTool {
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: Vec::new(),
         }
     }
 
@@ -5,6 +5,8 @@
 //! - DeepSeek (completion models)
 //! - Cohere (embedding models)
 //!
+//! [`Model::from_config`] builds a [`Model`] from a [`ModelConfig`] that selects the providers.
+//!
 //! Each provider implementation includes:
 //! - Client configuration and management
 //! - API request/response handling
@@ -15,11 +17,15 @@
 //! `EmbeddingFeaturesDyn` traits.
 
 use anda_core::{
-    AgentOutput, BoxError, BoxPinFut, CONTENT_TYPE_JSON, CompletionRequest, Embedding, ToolCall,
-    Usage,
+    AgentOutput, BoxError, BoxPinFut, CONTENT_TYPE_JSON, CompletionRequest, Embedding, Json, Path,
+    PutMode, ToolCall, ToolChoice, Usage, Xid,
 };
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
 
 pub mod cohere;
 pub mod deepseek;
@@ -30,12 +36,31 @@ pub mod xai;
 
 pub use reqwest::Proxy;
 
-use crate::APP_USER_AGENT;
+use crate::{APP_USER_AGENT, store::Store};
 
 /// Trait for dynamic completion features that can be used across threads
 pub trait CompletionFeaturesDyn: Send + Sync + 'static {
     /// Performs a completion request and returns a future with the agent's output
     fn completion(&self, req: CompletionRequest) -> BoxPinFut<Result<AgentOutput, BoxError>>;
+
+    /// Performs a completion request, sending the text content to `deltas` as it is generated.
+    ///
+    /// The default implementation sends the whole content once the completion finishes,
+    /// providers that support streaming send partial content instead.
+    fn completion_streaming(
+        &self,
+        req: CompletionRequest,
+        deltas: UnboundedSender<String>,
+    ) -> BoxPinFut<Result<AgentOutput, BoxError>> {
+        let fut = self.completion(req);
+        Box::pin(async move {
+            let output = fut.await?;
+            if !output.content.is_empty() {
+                let _ = deltas.send(output.content.clone());
+            }
+            Ok(output)
+        })
+    }
 }
 
 /// Trait for dynamic embedding features that can be used across threads
@@ -132,6 +157,292 @@ impl EmbeddingFeaturesDyn for MockImplemented {
     }
 }
 
+/// An embedder wrapper that truncates each text to at most `max_chars` characters
+/// before embedding, so one over-long text does not fail the whole batch.
+/// The returned embeddings keep the original texts.
+#[derive(Clone)]
+pub struct TruncatedEmbedder {
+    inner: Arc<dyn EmbeddingFeaturesDyn>,
+    max_chars: usize,
+}
+
+impl TruncatedEmbedder {
+    /// Creates a new TruncatedEmbedder
+    ///
+    /// # Arguments
+    /// * `inner` - The embedder to wrap
+    /// * `max_chars` - Maximum number of characters of each text sent to the embedder
+    pub fn new(inner: Arc<dyn EmbeddingFeaturesDyn>, max_chars: usize) -> Self {
+        Self { inner, max_chars }
+    }
+
+    fn truncate(&self, text: &str) -> String {
+        match text.char_indices().nth(self.max_chars) {
+            Some((i, _)) => text[..i].to_string(),
+            None => text.to_string(),
+        }
+    }
+}
+
+impl EmbeddingFeaturesDyn for TruncatedEmbedder {
+    fn ndims(&self) -> usize {
+        self.inner.ndims()
+    }
+
+    fn embed(&self, texts: Vec<String>) -> BoxPinFut<Result<(Vec<Embedding>, Usage), BoxError>> {
+        let inner = self.inner.clone();
+        let truncated: Vec<String> = texts.iter().map(|t| self.truncate(t)).collect();
+        Box::pin(async move {
+            let (mut embeddings, usage) = inner.embed(truncated).await?;
+            for (embedding, text) in embeddings.iter_mut().zip(texts) {
+                embedding.text = text;
+            }
+            Ok((embeddings, usage))
+        })
+    }
+
+    fn embed_query(&self, text: String) -> BoxPinFut<Result<(Embedding, Usage), BoxError>> {
+        let inner = self.inner.clone();
+        let truncated = self.truncate(&text);
+        Box::pin(async move {
+            let (mut embedding, usage) = inner.embed_query(truncated).await?;
+            embedding.text = text;
+            Ok((embedding, usage))
+        })
+    }
+}
+
+/// An embedder wrapper that L2-normalizes each embedding vector before returning,
+/// so that embeddings from providers with different norms can be mixed in one index.
+#[derive(Clone)]
+pub struct NormalizedEmbedder {
+    inner: Arc<dyn EmbeddingFeaturesDyn>,
+}
+
+impl NormalizedEmbedder {
+    /// Creates a new NormalizedEmbedder wrapping the given embedder
+    pub fn new(inner: Arc<dyn EmbeddingFeaturesDyn>) -> Self {
+        Self { inner }
+    }
+}
+
+impl EmbeddingFeaturesDyn for NormalizedEmbedder {
+    fn ndims(&self) -> usize {
+        self.inner.ndims()
+    }
+
+    fn embed(&self, texts: Vec<String>) -> BoxPinFut<Result<(Vec<Embedding>, Usage), BoxError>> {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let (mut embeddings, usage) = inner.embed(texts).await?;
+            embeddings.iter_mut().for_each(Embedding::normalize);
+            Ok((embeddings, usage))
+        })
+    }
+
+    fn embed_query(&self, text: String) -> BoxPinFut<Result<(Embedding, Usage), BoxError>> {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let (mut embedding, usage) = inner.embed_query(text).await?;
+            embedding.normalize();
+            Ok((embedding, usage))
+        })
+    }
+}
+
+/// An embedder wrapper that tries each embedder in order and returns the first success,
+/// so a secondary provider is used transparently when the primary one fails.
+///
+/// All embedders must report the same [`EmbeddingFeaturesDyn::ndims`], as embeddings of
+/// different dimensions can not be mixed in one index. Equal dimensions are not enough though:
+/// vectors of different models live in different spaces and are not comparable, so the
+/// fallbacks should be the same model served by other providers or endpoints. Otherwise the
+/// fallback embeddings must be stored in a separate index.
+#[derive(Clone)]
+pub struct FallbackEmbedder {
+    embedders: Vec<Arc<dyn EmbeddingFeaturesDyn>>,
+}
+
+impl FallbackEmbedder {
+    /// Creates a new FallbackEmbedder, the first embedder is the primary one.
+    /// Returns an error if no embedder is given or their dimensions differ.
+    pub fn new(embedders: Vec<Arc<dyn EmbeddingFeaturesDyn>>) -> Result<Self, BoxError> {
+        let ndims = embedders
+            .first()
+            .ok_or("no embedder for FallbackEmbedder")?
+            .ndims();
+        for (i, embedder) in embedders.iter().enumerate().skip(1) {
+            if embedder.ndims() != ndims {
+                return Err(format!(
+                    "fallback embedder {i} has {} dimensions, expected {ndims}",
+                    embedder.ndims()
+                )
+                .into());
+            }
+        }
+        Ok(Self { embedders })
+    }
+}
+
+impl EmbeddingFeaturesDyn for FallbackEmbedder {
+    fn ndims(&self) -> usize {
+        self.embedders[0].ndims()
+    }
+
+    fn embed(&self, texts: Vec<String>) -> BoxPinFut<Result<(Vec<Embedding>, Usage), BoxError>> {
+        let embedders = self.embedders.clone();
+        Box::pin(async move {
+            let mut last_err: BoxError = "no embedder for FallbackEmbedder".into();
+            for (i, embedder) in embedders.iter().enumerate() {
+                match embedder.embed(texts.clone()).await {
+                    Ok(res) => return Ok(res),
+                    Err(err) => {
+                        log::warn!("embedder {i} failed, trying the next one: {err:?}");
+                        last_err = err;
+                    }
+                }
+            }
+            Err(last_err)
+        })
+    }
+
+    fn embed_query(&self, text: String) -> BoxPinFut<Result<(Embedding, Usage), BoxError>> {
+        let embedders = self.embedders.clone();
+        Box::pin(async move {
+            let mut last_err: BoxError = "no embedder for FallbackEmbedder".into();
+            for (i, embedder) in embedders.iter().enumerate() {
+                match embedder.embed_query(text.clone()).await {
+                    Ok(res) => return Ok(res),
+                    Err(err) => {
+                        log::warn!("embedder {i} failed, trying the next one: {err:?}");
+                        last_err = err;
+                    }
+                }
+            }
+            Err(last_err)
+        })
+    }
+}
+
+/// Options of capturing completion payloads for debugging, see [`Model::with_debug`].
+#[derive(Clone, Default)]
+pub struct DebugCapture {
+    /// Writes each capture as a JSON object to `{namespace}/{xid}.json` in the store if set,
+    /// logs it at info level otherwise.
+    pub store: Option<(Store, Path)>,
+    /// JSON object keys whose values are replaced with `"[REDACTED]"`, at any depth.
+    pub redact_keys: BTreeSet<String>,
+    /// Maximum number of characters of each string value, unlimited if None.
+    pub max_string_chars: Option<usize>,
+}
+
+impl DebugCapture {
+    fn redact(&self, val: &mut Json) {
+        match val {
+            Json::String(s) => {
+                if let Some(max) = self.max_string_chars
+                    && let Some((i, _)) = s.char_indices().nth(max)
+                {
+                    s.truncate(i);
+                    s.push_str("...[TRUNCATED]");
+                }
+            }
+            Json::Array(arr) => arr.iter_mut().for_each(|v| self.redact(v)),
+            Json::Object(obj) => {
+                for (k, v) in obj.iter_mut() {
+                    if self.redact_keys.contains(k) {
+                        *v = Json::from("[REDACTED]");
+                    } else {
+                        self.redact(v);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn capture(&self, req: &Json, res: &Result<AgentOutput, BoxError>, elapsed: Duration) {
+        let mut val = match res {
+            Ok(output) => json!({"request": req, "output": output}),
+            Err(err) => json!({"request": req, "error": err.to_string()}),
+        };
+        val["elapsed_ms"] = Json::from(elapsed.as_millis() as u64);
+        self.redact(&mut val);
+
+        match &self.store {
+            Some((store, namespace)) => {
+                let path = Path::from(format!("{}.json", Xid::new()));
+                if let Err(err) = store
+                    .store_put(
+                        namespace,
+                        &path,
+                        PutMode::Create,
+                        serde_json::to_vec(&val).unwrap_or_default().into(),
+                    )
+                    .await
+                {
+                    log::warn!("failed to store completion capture {path}: {err:?}");
+                }
+            }
+            None => {
+                log::info!(capture:serde = val; "completion capture");
+            }
+        }
+    }
+}
+
+/// A completer wrapper that captures each completion request with its output or error,
+/// created by [`Model::with_debug`].
+///
+/// The request is captured as sent to the provider client, and the output includes the
+/// provider-specific `raw_history`. The HTTP bodies of a provider are logged at debug
+/// level by the provider itself.
+#[derive(Clone)]
+pub struct DebugCompleter {
+    inner: Arc<dyn CompletionFeaturesDyn>,
+    capture: Arc<DebugCapture>,
+}
+
+impl DebugCompleter {
+    /// Creates a new DebugCompleter wrapping the given completer
+    pub fn new(inner: Arc<dyn CompletionFeaturesDyn>, capture: DebugCapture) -> Self {
+        Self {
+            inner,
+            capture: Arc::new(capture),
+        }
+    }
+}
+
+impl CompletionFeaturesDyn for DebugCompleter {
+    fn completion(&self, req: CompletionRequest) -> BoxPinFut<Result<AgentOutput, BoxError>> {
+        let capture = self.capture.clone();
+        let val = json!(req);
+        let fut = self.inner.completion(req);
+        Box::pin(async move {
+            let start = Instant::now();
+            let res = fut.await;
+            capture.capture(&val, &res, start.elapsed()).await;
+            res
+        })
+    }
+
+    fn completion_streaming(
+        &self,
+        req: CompletionRequest,
+        deltas: UnboundedSender<String>,
+    ) -> BoxPinFut<Result<AgentOutput, BoxError>> {
+        let capture = self.capture.clone();
+        let val = json!(req);
+        let fut = self.inner.completion_streaming(req, deltas);
+        Box::pin(async move {
+            let start = Instant::now();
+            let res = fut.await;
+            capture.capture(&val, &res, start.elapsed()).await;
+            res
+        })
+    }
+}
+
 /// Main model struct that combines embedding and completion capabilities
 #[derive(Clone)]
 pub struct Model {
@@ -139,6 +450,8 @@ pub struct Model {
     pub embedder: Arc<dyn EmbeddingFeaturesDyn>,
     /// Completion feature implementation
     pub completer: Arc<dyn CompletionFeaturesDyn>,
+    /// Named embedding feature implementations, e.g. one for code and one for prose
+    pub embedders: BTreeMap<String, Arc<dyn EmbeddingFeaturesDyn>>,
 }
 
 impl Model {
@@ -150,6 +463,7 @@ impl Model {
         Self {
             embedder,
             completer,
+            embedders: BTreeMap::new(),
         }
     }
 
@@ -158,6 +472,7 @@ impl Model {
         Self {
             completer,
             embedder: Arc::new(NotImplemented),
+            embedders: BTreeMap::new(),
         }
     }
 
@@ -166,6 +481,7 @@ impl Model {
         Self {
             completer: Arc::new(NotImplemented),
             embedder: Arc::new(NotImplemented),
+            embedders: BTreeMap::new(),
         }
     }
 
@@ -174,13 +490,53 @@ impl Model {
         Self {
             completer: Arc::new(MockImplemented),
             embedder: Arc::new(MockImplemented),
+            embedders: BTreeMap::new(),
         }
     }
 
+    /// Registers a named embedder in addition to the default one
+    pub fn with_embedder(mut self, name: &str, embedder: Arc<dyn EmbeddingFeaturesDyn>) -> Self {
+        self.embedders.insert(name.to_string(), embedder);
+        self
+    }
+
+    /// L2-normalizes the embeddings returned by the default and all named embedders
+    /// registered so far if `normalize` is true.
+    pub fn with_normalize(mut self, normalize: bool) -> Self {
+        if normalize {
+            self.embedder = Arc::new(NormalizedEmbedder::new(self.embedder));
+            for embedder in self.embedders.values_mut() {
+                *embedder = Arc::new(NormalizedEmbedder::new(embedder.clone()));
+            }
+        }
+        self
+    }
+
+    /// Captures the payload of each completion for debugging, see [`DebugCapture`].
+    /// Capturing is off unless this is called.
+    pub fn with_debug(mut self, capture: DebugCapture) -> Self {
+        self.completer = Arc::new(DebugCompleter::new(self.completer, capture));
+        self
+    }
+
+    /// Returns the named embedder if registered
+    pub fn embedder(&self, name: &str) -> Option<Arc<dyn EmbeddingFeaturesDyn>> {
+        self.embedders.get(name).cloned()
+    }
+
     pub async fn completion(&self, req: CompletionRequest) -> Result<AgentOutput, BoxError> {
         self.completer.completion(req).await
     }
 
+    /// Performs a completion request, sending the text content to `deltas` as it is generated.
+    pub async fn completion_streaming(
+        &self,
+        req: CompletionRequest,
+        deltas: UnboundedSender<String>,
+    ) -> Result<AgentOutput, BoxError> {
+        self.completer.completion_streaming(req, deltas).await
+    }
+
     pub fn ndims(&self) -> usize {
         self.embedder.ndims()
     }
@@ -195,6 +551,175 @@ impl Model {
     pub async fn embed_query(&self, text: &str) -> Result<(Embedding, Usage), BoxError> {
         self.embedder.embed_query(text.to_string()).await
     }
+
+    pub async fn embed_with(
+        &self,
+        name: &str,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<(Vec<Embedding>, Usage), BoxError> {
+        let embedder = self
+            .embedder(name)
+            .ok_or_else(|| format!("embedder {} not found", name))?;
+        embedder.embed(texts.into_iter().collect()).await
+    }
+
+    pub async fn embed_query_with(
+        &self,
+        name: &str,
+        text: &str,
+    ) -> Result<(Embedding, Usage), BoxError> {
+        let embedder = self
+            .embedder(name)
+            .ok_or_else(|| format!("embedder {} not found", name))?;
+        embedder.embed_query(text.to_string()).await
+    }
+}
+
+/// Configuration of a model provider.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ProviderConfig {
+    /// Provider name, one of "openai", "openai_responses", "deepseek", "xai", "kimi" or
+    /// "gemini" for completion, and "openai" or "cohere" for embedding.
+    pub provider: String,
+    /// API key of the provider.
+    #[serde(default)]
+    pub api_key: String,
+    /// API endpoint, the provider's default endpoint if empty.
+    #[serde(default)]
+    pub endpoint: String,
+    /// Model name, the provider's default model if empty.
+    #[serde(default)]
+    pub model: String,
+}
+
+/// Configuration of a [`Model`], shared by the binaries to select providers, e.g. in TOML:
+///
+/// ```toml
+/// [model.completion]
+/// provider = "deepseek"
+/// api_key = "sk-..."
+///
+/// [model.embedding]
+/// provider = "cohere"
+/// api_key = "..."
+/// model = "embed-multilingual-v3.0"
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ModelConfig {
+    /// The completion provider, completion is not implemented if None.
+    #[serde(default)]
+    pub completion: Option<ProviderConfig>,
+    /// The default embedding provider, embedding is not implemented if None.
+    #[serde(default)]
+    pub embedding: Option<ProviderConfig>,
+    /// Fallback providers of the default embedding provider tried in order,
+    /// see [`FallbackEmbedder`].
+    #[serde(default)]
+    pub embedding_fallbacks: Vec<ProviderConfig>,
+    /// Named embedding providers, see [`Model::with_embedder`].
+    #[serde(default)]
+    pub embedders: BTreeMap<String, ProviderConfig>,
+    /// L2-normalizes the embeddings if true, see [`Model::with_normalize`].
+    #[serde(default)]
+    pub normalize: bool,
+}
+
+impl Model {
+    /// Creates a Model with the providers selected by the configuration.
+    /// Returns an error if a provider is unknown or has no API key.
+    pub fn from_config(cfg: &ModelConfig) -> Result<Self, BoxError> {
+        let completer: Arc<dyn CompletionFeaturesDyn> = match &cfg.completion {
+            Some(p) => completer_from_config(p)?,
+            None => Arc::new(NotImplemented),
+        };
+        let embedder: Arc<dyn EmbeddingFeaturesDyn> = match &cfg.embedding {
+            Some(p) if cfg.embedding_fallbacks.is_empty() => embedder_from_config(p)?,
+            Some(p) => {
+                let mut embedders = vec![embedder_from_config(p)?];
+                for p in &cfg.embedding_fallbacks {
+                    embedders.push(embedder_from_config(p)?);
+                }
+                Arc::new(FallbackEmbedder::new(embedders)?)
+            }
+            None => Arc::new(NotImplemented),
+        };
+        let mut model = Model::new(completer, embedder);
+        for (name, p) in &cfg.embedders {
+            model = model.with_embedder(name, embedder_from_config(p)?);
+        }
+        Ok(model.with_normalize(cfg.normalize))
+    }
+}
+
+fn provider_endpoint(cfg: &ProviderConfig) -> Result<Option<String>, BoxError> {
+    if cfg.api_key.is_empty() {
+        return Err(format!("missing API key for provider {:?}", cfg.provider).into());
+    }
+    if cfg.endpoint.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(cfg.endpoint.clone()))
+    }
+}
+
+fn model_or<'a>(cfg: &'a ProviderConfig, default: &'a str) -> &'a str {
+    if cfg.model.is_empty() {
+        default
+    } else {
+        &cfg.model
+    }
+}
+
+fn completer_from_config(cfg: &ProviderConfig) -> Result<Arc<dyn CompletionFeaturesDyn>, BoxError> {
+    let endpoint = provider_endpoint(cfg)?;
+    let key = &cfg.api_key;
+    Ok(match cfg.provider.as_str() {
+        "openai" => Arc::new(openai::Client::new(key, endpoint).completion_model(&cfg.model)),
+        "openai_responses" => {
+            Arc::new(openai::Client::new(key, endpoint).completion_model_v2(&cfg.model))
+        }
+        "deepseek" => Arc::new(
+            deepseek::Client::new(key, endpoint)
+                .completion_model(model_or(cfg, deepseek::DEEKSEEK_V3)),
+        ),
+        "xai" => Arc::new(
+            xai::Client::new(key, endpoint).completion_model(model_or(cfg, xai::GROK_BETA)),
+        ),
+        "kimi" => Arc::new(
+            kimi::Client::new(key, endpoint).completion_model(model_or(cfg, kimi::KIMI_K2)),
+        ),
+        "gemini" => Arc::new(
+            gemini::Client::new(key, endpoint)
+                .completion_model(model_or(cfg, gemini::GEMINI_2_5_FLASH)),
+        ),
+        other => return Err(format!("unknown completion provider {other:?}").into()),
+    })
+}
+
+fn embedder_from_config(cfg: &ProviderConfig) -> Result<Arc<dyn EmbeddingFeaturesDyn>, BoxError> {
+    let endpoint = provider_endpoint(cfg)?;
+    let key = &cfg.api_key;
+    Ok(match cfg.provider.as_str() {
+        "openai" => Arc::new(
+            openai::Client::new(key, endpoint)
+                .embedding_model(model_or(cfg, openai::TEXT_EMBEDDING_3_SMALL)),
+        ),
+        "cohere" => Arc::new(
+            cohere::Client::new(key, endpoint)
+                .embedding_model(model_or(cfg, cohere::EMBED_MULTILINGUAL_V3)),
+        ),
+        other => return Err(format!("unknown embedding provider {other:?}").into()),
+    })
+}
+
+/// Returns the `tool_choice` value of OpenAI compatible chat completions APIs.
+pub(crate) fn chat_tool_choice(choice: &ToolChoice) -> Json {
+    match choice {
+        ToolChoice::Auto => Json::from("auto"),
+        ToolChoice::None => Json::from("none"),
+        ToolChoice::Required => Json::from("required"),
+        ToolChoice::Function(name) => json!({"type": "function", "function": {"name": name}}),
+    }
 }
 
 /// Creates a new reqwest client builder with default settings
@@ -217,3 +742,142 @@ pub fn request_client_builder() -> reqwest::ClientBuilder {
             headers
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Embeds every text as `[value; ndims]`, or fails while `failing` is set.
+    struct MockEmbedder {
+        ndims: usize,
+        value: f32,
+        failing: AtomicBool,
+        calls: AtomicUsize,
+    }
+
+    impl MockEmbedder {
+        fn new(ndims: usize, value: f32) -> Arc<Self> {
+            Arc::new(Self {
+                ndims,
+                value,
+                failing: AtomicBool::new(false),
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        fn embedding(&self, text: String) -> Result<Embedding, BoxError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(format!("embedder {} failed", self.value).into());
+            }
+            Ok(Embedding {
+                text,
+                vec: vec![self.value; self.ndims],
+            })
+        }
+    }
+
+    impl EmbeddingFeaturesDyn for MockEmbedder {
+        fn ndims(&self) -> usize {
+            self.ndims
+        }
+
+        fn embed(
+            &self,
+            texts: Vec<String>,
+        ) -> BoxPinFut<Result<(Vec<Embedding>, Usage), BoxError>> {
+            let res = texts
+                .into_iter()
+                .map(|text| self.embedding(text))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|embeddings| (embeddings, Usage::default()));
+            Box::pin(futures::future::ready(res))
+        }
+
+        fn embed_query(&self, text: String) -> BoxPinFut<Result<(Embedding, Usage), BoxError>> {
+            let res = self.embedding(text).map(|e| (e, Usage::default()));
+            Box::pin(futures::future::ready(res))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback_embedder() {
+        assert!(FallbackEmbedder::new(vec![]).is_err());
+        let err = FallbackEmbedder::new(vec![MockEmbedder::new(4, 1.0), MockEmbedder::new(8, 2.0)])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "fallback embedder 1 has 8 dimensions, expected 4"
+        );
+
+        let primary = MockEmbedder::new(4, 1.0);
+        let secondary = MockEmbedder::new(4, 2.0);
+        let embedder = FallbackEmbedder::new(vec![primary.clone(), secondary.clone()]).unwrap();
+        assert_eq!(embedder.ndims(), 4);
+
+        let (res, _) = embedder.embed(vec!["a".to_string()]).await.unwrap();
+        assert_eq!(res[0].vec, vec![1.0; 4]);
+        let (res, _) = embedder.embed_query("q".to_string()).await.unwrap();
+        assert_eq!(res.text, "q");
+        assert_eq!(res.vec, vec![1.0; 4]);
+        assert_eq!(secondary.calls.load(Ordering::SeqCst), 0);
+
+        primary.failing.store(true, Ordering::SeqCst);
+        let (res, _) = embedder
+            .embed(vec!["a".to_string(), "b".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[1].text, "b");
+        assert_eq!(res[1].vec, vec![2.0; 4]);
+        let (res, _) = embedder.embed_query("q".to_string()).await.unwrap();
+        assert_eq!(res.vec, vec![2.0; 4]);
+
+        secondary.failing.store(true, Ordering::SeqCst);
+        let err = embedder.embed_query("q".to_string()).await.err().unwrap();
+        assert_eq!(err.to_string(), "embedder 2 failed");
+        assert!(embedder.embed(vec!["a".to_string()]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_named_embedders() {
+        let model = Model::mock_implemented()
+            .with_embedder("code", MockEmbedder::new(4, 1.0))
+            .with_embedder("prose", MockEmbedder::new(8, 2.0));
+        assert_eq!(model.ndims(), 384);
+        assert_eq!(model.embedder("code").unwrap().ndims(), 4);
+        assert!(model.embedder("unknown").is_none());
+
+        let (res, _) = model
+            .embed_with("code", vec!["fn main() {}".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(res[0].vec, vec![1.0; 4]);
+        let (res, _) = model.embed_query_with("prose", "hello").await.unwrap();
+        assert_eq!(res.vec, vec![2.0; 8]);
+        let (res, _) = model.embed_query("hello").await.unwrap();
+        assert_eq!(res.vec.len(), 384);
+        let err = model
+            .embed_query_with("unknown", "hello")
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "embedder unknown not found");
+
+        // normalization applies to the named embedders registered before it
+        let model = model.with_normalize(true);
+        let (res, _) = model.embed_query_with("code", "hello").await.unwrap();
+        assert_eq!(res.vec, vec![0.5; 4]);
+
+        let ctx = crate::engine::EngineBuilder::new()
+            .with_model(model)
+            .mock_ctx();
+        let (res, _) = ctx
+            .embed_with("prose", vec!["a".to_string(), "b".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 2);
+        assert!(ctx.embed_query_with("unknown", "hello").await.is_err());
+    }
+}
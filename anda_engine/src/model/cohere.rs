@@ -128,6 +128,7 @@ impl EmbeddingResponse {
                 input_tokens: m.billed_units.input_tokens as u64,
                 output_tokens: m.billed_units.output_tokens as u64,
                 requests: 1,
+                ..Default::default()
             }),
         ))
     }
@@ -197,6 +198,10 @@ pub struct EmbeddingModel {
     client: Client,
     /// Number of dimensions in the embedding vectors
     ndims: usize,
+    /// Input type for [`EmbeddingFeaturesDyn::embed`], default to "search_document"
+    document_input_type: String,
+    /// Input type for [`EmbeddingFeaturesDyn::embed_query`], default to "search_query"
+    query_input_type: String,
 }
 
 impl EmbeddingModel {
@@ -211,8 +216,20 @@ impl EmbeddingModel {
             client,
             model: model.to_string(),
             ndims,
+            document_input_type: "search_document".to_string(),
+            query_input_type: "search_query".to_string(),
         }
     }
+
+    /// Sets the input types sent to Cohere for documents and queries,
+    /// e.g. "classification" or "clustering" when the embeddings are not used for search.
+    ///
+    /// https://docs.cohere.com/reference/embed#request.body.input_type
+    pub fn with_input_types(mut self, document_input_type: &str, query_input_type: &str) -> Self {
+        self.document_input_type = document_input_type.to_string();
+        self.query_input_type = query_input_type.to_string();
+        self
+    }
 }
 
 const MAX_DOCUMENTS: usize = 96;
@@ -236,6 +253,7 @@ impl EmbeddingFeaturesDyn for EmbeddingModel {
     fn embed(&self, texts: Vec<String>) -> BoxPinFut<Result<(Vec<Embedding>, Usage), BoxError>> {
         let model = self.model.clone();
         let client = self.client.clone();
+        let input_type = self.document_input_type.clone();
         Box::pin(async move {
             if texts.len() > MAX_DOCUMENTS {
                 return Err(format!("Too many documents, max is {}", MAX_DOCUMENTS).into());
@@ -245,7 +263,7 @@ impl EmbeddingFeaturesDyn for EmbeddingModel {
                 .post("/v1/embed")
                 .json(&json!({
                     "model": model,
-                    "input_type": "search_document",
+                    "input_type": input_type,
                     "embedding_types": ["float"],
                     "texts": texts,
                 }))
@@ -274,12 +292,13 @@ impl EmbeddingFeaturesDyn for EmbeddingModel {
     fn embed_query(&self, text: String) -> BoxPinFut<Result<(Embedding, Usage), BoxError>> {
         let model = self.model.clone();
         let client = self.client.clone();
+        let input_type = self.query_input_type.clone();
         Box::pin(async move {
             let response = client
                 .post("/v1/embed")
                 .json(&json!({
                     "model": model,
-                    "input_type": "search_query",
+                    "input_type": input_type,
                     "embedding_types": ["float"],
                     "texts": vec![text.clone()],
                 }))
@@ -294,6 +313,7 @@ impl EmbeddingFeaturesDyn for EmbeddingModel {
                             input_tokens: m.billed_units.input_tokens as u64,
                             output_tokens: m.billed_units.output_tokens as u64,
                             requests: 1,
+                            ..Default::default()
                         });
                         Ok((Embedding { text, vec: data }, usage))
                     }
@@ -7,7 +7,7 @@
 
 use anda_core::{
     AgentOutput, BoxError, BoxPinFut, CompletionFeatures, CompletionRequest, ContentPart,
-    FunctionDefinition, Json, Message, Resource, Usage as ModelUsage,
+    FINISH_REASON_LENGTH, FunctionDefinition, Json, Message, Resource, Usage as ModelUsage,
 };
 use log::{Level::Debug, log_enabled};
 use serde::{Deserialize, Serialize};
@@ -132,15 +132,23 @@ impl CompletionResponse {
                     input_tokens: u.prompt_tokens as u64,
                     output_tokens: u.completion_tokens as u64,
                     requests: 1,
+                    ..Default::default()
                 })
                 .unwrap_or_default(),
             ..Default::default()
         };
 
         let choice = self.choices.pop().ok_or("No completion choice")?;
-        if !matches!(choice.finish_reason.as_str(), "stop" | "tool_calls") {
+        if !matches!(
+            choice.finish_reason.as_str(),
+            "stop" | "tool_calls" | FINISH_REASON_LENGTH
+        ) {
             output.failed_reason = Some(choice.finish_reason);
         } else {
+            if choice.finish_reason == FINISH_REASON_LENGTH {
+                // keep the partial content, so the caller can continue
+                output.failed_reason = Some(choice.finish_reason.clone());
+            }
             output.raw_history.push(json!(&choice.message));
             let mut msg: Message = choice.message.into();
             msg.timestamp = Some(timestamp);
@@ -263,7 +271,7 @@ impl From<FunctionDefinition> for ToolDefinition {
     fn from(f: FunctionDefinition) -> Self {
         Self {
             r#type: "function".into(),
-            function: f,
+            function: f.examples_in_description(),
         }
     }
 }
@@ -314,6 +322,7 @@ impl CompletionFeaturesDyn for CompletionModel {
 
         Box::pin(async move {
             let timestamp = unix_ms();
+            let tool_choice = req.resolved_tool_choice();
             let mut raw_history: Vec<Json> = Vec::new();
             let mut chat_history: Vec<Message> = Vec::new();
 
@@ -404,11 +413,7 @@ impl CompletionFeaturesDyn for CompletionModel {
                 );
                 body.insert(
                     "tool_choice".to_string(),
-                    if req.tool_choice_required {
-                        Json::from("required")
-                    } else {
-                        Json::from("auto")
-                    },
+                    super::chat_tool_choice(&tool_choice),
                 );
             };
 
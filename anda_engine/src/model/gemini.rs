@@ -9,7 +9,10 @@ use anda_core::{
     AgentOutput, BoxError, BoxPinFut, CompletionFeatures, CompletionRequest, Json, Message,
     Resource,
 };
+use futures::StreamExt;
 use log::{Level::Debug, log_enabled};
+use serde::Deserialize;
+use tokio::sync::mpsc::UnboundedSender;
 
 use super::{CompletionFeaturesDyn, request_client_builder};
 use crate::{rfc3339_datetime, unix_ms};
@@ -91,6 +94,8 @@ pub struct CompletionModel {
     client: Client,
     /// Model identifier
     pub model: String,
+    /// Whether to stream responses in `completion_streaming`
+    streaming: bool,
 }
 
 impl CompletionModel {
@@ -103,8 +108,16 @@ impl CompletionModel {
         Self {
             client,
             model: model.to_string(),
+            streaming: false,
         }
     }
+
+    /// Enables streaming responses in [`CompletionFeaturesDyn::completion_streaming`],
+    /// so partial content is sent while the model is generating. Default is false.
+    pub fn with_streaming(mut self, streaming: bool) -> Self {
+        self.streaming = streaming;
+        self
+    }
 }
 
 impl CompletionFeatures for CompletionModel {
@@ -117,87 +130,97 @@ impl CompletionFeatures for CompletionModel {
     }
 }
 
-impl CompletionFeaturesDyn for CompletionModel {
-    fn completion(&self, req: CompletionRequest) -> BoxPinFut<Result<AgentOutput, BoxError>> {
-        let model = self.model.clone();
-        let client = self.client.clone();
+/// Builds a generate content request with the conversation it was built from.
+fn prepare_request(
+    req: CompletionRequest,
+) -> Result<(types::GenerateContentRequest, Vec<Json>, Vec<Message>), BoxError> {
+    let timestamp = unix_ms();
+    let tool_choice = req.resolved_tool_choice();
+    let mut raw_history: Vec<Json> = Vec::new();
+    let mut chat_history: Vec<Message> = Vec::new();
+    let mut greq = types::GenerateContentRequest::default();
 
-        Box::pin(async move {
-            let timestamp = unix_ms();
-            let mut raw_history: Vec<Json> = Vec::new();
-            let mut chat_history: Vec<Message> = Vec::new();
-            let mut greq = types::GenerateContentRequest::default();
-
-            if !req.instructions.is_empty() {
-                greq.system_instruction = Some(types::Content {
-                    role: Some(types::Role::Model),
-                    parts: vec![types::Part {
-                        data: types::PartKind::Text(req.instructions),
-                        ..Default::default()
-                    }],
-                });
-            };
+    if !req.instructions.is_empty() {
+        greq.system_instruction = Some(types::Content {
+            role: Some(types::Role::Model),
+            parts: vec![types::Part {
+                data: types::PartKind::Text(req.instructions),
+                ..Default::default()
+            }],
+        });
+    };
 
-            for msg in req.raw_history {
-                greq.contents.push(serde_json::from_value(msg)?);
-            }
+    for msg in req.raw_history {
+        greq.contents.push(serde_json::from_value(msg)?);
+    }
 
-            for msg in req.chat_history {
-                let val = types::Content::from(msg);
-                raw_history.push(serde_json::to_value(&val)?);
-                greq.contents.push(val);
-            }
+    for msg in req.chat_history {
+        let val = types::Content::from(msg);
+        raw_history.push(serde_json::to_value(&val)?);
+        greq.contents.push(val);
+    }
 
-            if let Some(mut msg) = req
-                .documents
-                .to_message(&rfc3339_datetime(timestamp).unwrap())
-            {
-                msg.timestamp = Some(timestamp);
-                chat_history.push(msg.clone());
-                let msg = types::Content::from(msg);
-                raw_history.push(serde_json::to_value(&msg)?);
-                greq.contents.push(msg);
-            }
+    if let Some(mut msg) = req
+        .documents
+        .to_message(&rfc3339_datetime(timestamp).unwrap())
+    {
+        msg.timestamp = Some(timestamp);
+        chat_history.push(msg.clone());
+        let msg = types::Content::from(msg);
+        raw_history.push(serde_json::to_value(&msg)?);
+        greq.contents.push(msg);
+    }
 
-            let mut content = req.content;
-            if !req.prompt.is_empty() {
-                content.push(req.prompt.into());
-            }
-            if !content.is_empty() {
-                let msg = Message {
-                    role: req.role.unwrap_or_else(|| "user".to_string()),
-                    content,
-                    timestamp: Some(timestamp),
-                    ..Default::default()
-                };
+    let mut content = req.content;
+    if !req.prompt.is_empty() {
+        content.push(req.prompt.into());
+    }
+    if !content.is_empty() {
+        let msg = Message {
+            role: req.role.unwrap_or_else(|| "user".to_string()),
+            content,
+            timestamp: Some(timestamp),
+            ..Default::default()
+        };
 
-                chat_history.push(msg.clone());
-                let msg = types::Content::from(msg);
-                raw_history.push(serde_json::to_value(&msg)?);
-                greq.contents.push(msg);
-            }
+        chat_history.push(msg.clone());
+        let msg = types::Content::from(msg);
+        raw_history.push(serde_json::to_value(&msg)?);
+        greq.contents.push(msg);
+    }
 
-            if let Some(temperature) = req.temperature {
-                greq.generation_config.temperature = Some(temperature);
-            }
+    if let Some(temperature) = req.temperature {
+        greq.generation_config.temperature = Some(temperature);
+    }
 
-            if let Some(max_tokens) = req.max_output_tokens {
-                greq.generation_config.max_output_tokens = Some(max_tokens as i32);
-            }
+    if let Some(max_tokens) = req.max_output_tokens {
+        greq.generation_config.max_output_tokens = Some(max_tokens as i32);
+    }
 
-            if let Some(output_schema) = req.output_schema {
-                greq.generation_config.response_mime_type = Some("application/json".to_string());
-                greq.generation_config.response_schema = Some(output_schema);
-            }
+    if let Some(output_schema) = req.output_schema {
+        greq.generation_config.response_mime_type = Some("application/json".to_string());
+        greq.generation_config.response_schema = Some(output_schema);
+    }
 
-            if let Some(stop) = req.stop {
-                greq.generation_config.stop_sequences = Some(stop);
-            }
+    if let Some(stop) = req.stop {
+        greq.generation_config.stop_sequences = Some(stop);
+    }
 
-            if !req.tools.is_empty() {
-                greq.tools = vec![req.tools.into()];
-                greq.tool_config = Some(types::ToolConfig::default());
-            };
+    if !req.tools.is_empty() {
+        greq.tools = vec![req.tools.into()];
+        greq.tool_config = Some((&tool_choice).into());
+    };
+
+    Ok((greq, raw_history, chat_history))
+}
+
+impl CompletionFeaturesDyn for CompletionModel {
+    fn completion(&self, req: CompletionRequest) -> BoxPinFut<Result<AgentOutput, BoxError>> {
+        let model = self.model.clone();
+        let client = self.client.clone();
+
+        Box::pin(async move {
+            let (greq, raw_history, chat_history) = prepare_request(req)?;
 
             if log_enabled!(Debug)
                 && let Ok(val) = serde_json::to_string(&greq)
@@ -244,4 +267,300 @@ impl CompletionFeaturesDyn for CompletionModel {
             }
         })
     }
+
+    fn completion_streaming(
+        &self,
+        req: CompletionRequest,
+        deltas: UnboundedSender<String>,
+    ) -> BoxPinFut<Result<AgentOutput, BoxError>> {
+        if !self.streaming {
+            let fut = CompletionFeaturesDyn::completion(self, req);
+            return Box::pin(async move {
+                let output = fut.await?;
+                if !output.content.is_empty() {
+                    let _ = deltas.send(output.content.clone());
+                }
+                Ok(output)
+            });
+        }
+
+        let model = self.model.clone();
+        let client = self.client.clone();
+
+        Box::pin(async move {
+            let (greq, raw_history, chat_history) = prepare_request(req)?;
+
+            if log_enabled!(Debug)
+                && let Ok(val) = serde_json::to_string(&greq)
+            {
+                log::debug!(request = val; "Gemini completions stream request");
+            }
+
+            let response = client
+                .post(&format!("/{}:streamGenerateContent?alt=sse", model))
+                .json(&greq)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                let status = response.status();
+                let msg = response.text().await?;
+                log::error!(
+                    request:serde = greq;
+                    "completions request failed: {status}, body: {msg}",
+                );
+                return Err(format!("Gemini completions error: {}", msg).into());
+            }
+
+            let res = read_content_stream(response, &deltas).await?;
+            if log_enabled!(Debug) {
+                log::debug!(
+                    request:serde = greq,
+                    response:serde = res;
+                    "Gemini completions stream response");
+            } else if res.maybe_failed() {
+                log::warn!(
+                    request:serde = greq,
+                    response:serde = res;
+                    "completions maybe failed");
+            }
+
+            res.try_into(raw_history, chat_history)
+        })
+    }
+}
+
+/// A chunk of a streaming generate content response
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContentChunk {
+    #[serde(default)]
+    candidates: Vec<types::Candidate>,
+    #[serde(default)]
+    prompt_feedback: Option<types::PromptFeedback>,
+    #[serde(default)]
+    usage_metadata: Option<Json>,
+    #[serde(default)]
+    model_version: Option<String>,
+    #[serde(default)]
+    response_id: Option<String>,
+}
+
+/// Reads a server-sent events stream of generate content chunks into a
+/// [`types::GenerateContentResponse`], sending the text content to `deltas` as it arrives.
+async fn read_content_stream(
+    response: reqwest::Response,
+    deltas: &UnboundedSender<String>,
+) -> Result<types::GenerateContentResponse, BoxError> {
+    let mut res = types::GenerateContentResponse {
+        candidates: Vec::new(),
+        prompt_feedback: None,
+        usage_metadata: types::UsageMetadata {
+            prompt_token_count: 0,
+            candidates_token_count: 0,
+            total_token_count: 0,
+            thoughts_token_count: 0,
+        },
+        model_version: None,
+        response_id: None,
+    };
+
+    let mut buf: Vec<u8> = Vec::new();
+    let mut stream = response.bytes_stream();
+    let mut ended = false;
+    while !ended {
+        match stream.next().await {
+            Some(bytes) => buf.extend_from_slice(&bytes?),
+            None => {
+                // the last line may not end with a newline
+                ended = true;
+                buf.push(b'\n');
+            }
+        }
+        while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buf.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let data = match line.trim().strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => continue,
+            };
+            if data.is_empty() {
+                continue;
+            }
+
+            let chunk: ContentChunk = serde_json::from_str(data).map_err(|err| {
+                format!("Gemini completions stream error: {}, data: {}", err, data)
+            })?;
+            if chunk.prompt_feedback.is_some() {
+                res.prompt_feedback = chunk.prompt_feedback;
+            }
+            if let Some(usage) = chunk
+                .usage_metadata
+                .and_then(|v| serde_json::from_value(v).ok())
+            {
+                res.usage_metadata = usage;
+            }
+            if chunk.model_version.is_some() {
+                res.model_version = chunk.model_version;
+            }
+            if chunk.response_id.is_some() {
+                res.response_id = chunk.response_id;
+            }
+
+            for candidate in chunk.candidates {
+                let parts = match res.candidates.first_mut() {
+                    Some(merged) => {
+                        if candidate.finish_reason.is_some() {
+                            merged.finish_reason = candidate.finish_reason;
+                        }
+                        &mut merged.content.parts
+                    }
+                    None => {
+                        res.candidates.push(types::Candidate {
+                            content: types::Content {
+                                role: candidate.content.role,
+                                parts: Vec::new(),
+                            },
+                            ..candidate.clone()
+                        });
+                        &mut res.candidates[0].content.parts
+                    }
+                };
+                for part in candidate.content.parts {
+                    if let types::PartKind::Text(text) = &part.data
+                        && part.thought != Some(true)
+                        && !text.is_empty()
+                    {
+                        let _ = deltas.send(text.clone());
+                    }
+                    // merges consecutive text chunks of the same kind into one part
+                    match (parts.last_mut(), part) {
+                        (
+                            Some(types::Part {
+                                thought,
+                                data: types::PartKind::Text(prev),
+                                ..
+                            }),
+                            types::Part {
+                                thought: t,
+                                thought_signature: None,
+                                data: types::PartKind::Text(text),
+                            },
+                        ) if *thought == t => prev.push_str(&text),
+                        (_, part) => parts.push(part),
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anda_core::FINISH_REASON_LENGTH;
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    /// Builds a response whose body arrives in the given byte chunks.
+    fn sse_response(chunks: &[&str]) -> reqwest::Response {
+        let chunks: Vec<Result<String, std::io::Error>> =
+            chunks.iter().map(|chunk| Ok(chunk.to_string())).collect();
+        let body = reqwest::Body::wrap_stream(futures::stream::iter(chunks));
+        http::Response::new(body).into()
+    }
+
+    async fn read_stream(chunks: &[&str]) -> (Result<AgentOutput, BoxError>, Vec<String>) {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let res = read_content_stream(sse_response(chunks), &tx)
+            .await
+            .and_then(|res| res.try_into(Vec::new(), Vec::new()));
+        drop(tx);
+        let mut deltas = Vec::new();
+        while let Some(delta) = rx.recv().await {
+            deltas.push(delta);
+        }
+        (res, deltas)
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_read_content_stream_content() {
+        let (res, deltas) = read_stream(&[
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"thought\":true,\"text\":\"Thinking\"}]}}],\"responseId\":\"r1\"}\r\n\r\n",
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hello\"}]}}]}\r\n\r\ndata: {\"candidates\":[{\"content\":{\"ro",
+            "le\":\"model\",\"parts\":[{\"text\":\", world\"}]}}]}\r\n\r\n",
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"\"}]},\"finishReason\":\"STOP\"}]}\r\n\r\n",
+            "data: {\"candidates\":[],\"usageMetadata\":{\"promptTokenCount\":9,\"candidatesTokenCount\":3,\"totalTokenCount\":12},\"modelVersion\":\"gemini-2.5-flash\"}\r\n\r\n",
+        ])
+        .await;
+        let output = res.unwrap();
+        // thoughts are not sent as deltas
+        assert_eq!(deltas, vec!["Hello".to_string(), ", world".to_string()]);
+        assert_eq!(output.content, "Hello, world");
+        assert!(output.failed_reason.is_none());
+        assert!(output.tool_calls.is_empty());
+        // the usage-only final chunk
+        assert_eq!(output.usage.input_tokens, 9);
+        assert_eq!(output.usage.output_tokens, 3);
+        assert_eq!(output.raw_history.len(), 1);
+        assert_eq!(output.raw_history[0]["parts"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_read_content_stream_tool_calls() {
+        let (res, deltas) = read_stream(&[
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"functionCall\":{\"name\":\"get_weather\",\"args\":{\"city\":\"Paris\"}}}]}}]}\n\n",
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"functionCall\":{\"name\":\"get_time\",\"args\":{}}}]},\"finishReason\":\"STOP\"}]}\n\n",
+        ])
+        .await;
+        let output = res.unwrap();
+        assert!(deltas.is_empty());
+        assert!(output.content.is_empty());
+        assert_eq!(output.tool_calls.len(), 2);
+        assert_eq!(output.tool_calls[0].name, "get_weather");
+        assert_eq!(output.tool_calls[0].args, json!({"city": "Paris"}));
+        assert_eq!(output.tool_calls[1].name, "get_time");
+        assert_eq!(output.tool_calls[1].args, json!({}));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_read_content_stream_max_tokens() {
+        let (res, deltas) = read_stream(&[
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"The answer is\"}]}}]}\n\n",
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[]},\"finishReason\":\"MAX_TOKENS\"}]}\n\n",
+        ])
+        .await;
+        let output = res.unwrap();
+        assert_eq!(deltas, vec!["The answer is".to_string()]);
+        assert_eq!(output.content, "The answer is");
+        assert_eq!(output.failed_reason.as_deref(), Some(FINISH_REASON_LENGTH));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_read_content_stream_without_trailing_newline() {
+        let (res, deltas) = read_stream(&[
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hi\"}]}}]}\n\n",
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"!\"}]},\"finishReason\":\"STOP\"}]}",
+        ])
+        .await;
+        let output = res.unwrap();
+        assert_eq!(deltas, vec!["Hi".to_string(), "!".to_string()]);
+        assert_eq!(output.content, "Hi!");
+        assert!(output.failed_reason.is_none());
+
+        // a stream cut off before the finish reason is not a completed response
+        let (res, _) = read_stream(&[
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hi\"}]}}]}\n\n",
+        ])
+        .await;
+        assert!(res.unwrap().failed_reason.is_some());
+
+        let (res, _) = read_stream(&["data: {not json}\n\n"]).await;
+        assert!(
+            res.unwrap_err()
+                .to_string()
+                .contains("Gemini completions stream error")
+        );
+    }
 }
@@ -1,6 +1,6 @@
 use anda_core::{
-    AgentOutput, BoxError, ByteBufB64, ContentPart, FunctionDefinition, Message,
-    Usage as ModelUsage,
+    AgentOutput, BoxError, ByteBufB64, ContentPart, FINISH_REASON_LENGTH, FunctionDefinition,
+    Message, ToolChoice, Usage as ModelUsage,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
@@ -70,6 +70,7 @@ impl GenerateContentResponse {
                 input_tokens: self.usage_metadata.prompt_token_count as u64,
                 output_tokens: self.usage_metadata.candidates_token_count as u64,
                 requests: 1,
+                ..Default::default()
             },
             ..Default::default()
         };
@@ -86,6 +87,12 @@ impl GenerateContentResponse {
                     output.content = msg.text().unwrap_or_default();
                     output.tool_calls = msg.tool_calls();
                 }
+                Some(FinishReason::MaxTokens) => {
+                    // keep the partial content, so the caller can continue
+                    output.content = msg.text().unwrap_or_default();
+                    output.tool_calls = msg.tool_calls();
+                    output.failed_reason = Some(FINISH_REASON_LENGTH.to_string());
+                }
                 v => {
                     output.failed_reason = serde_json::to_string(&v).ok();
                 }
@@ -377,11 +384,14 @@ impl From<Vec<FunctionDefinition>> for Tool {
         Self::FunctionDeclaration {
             function_declarations: tools
                 .into_iter()
-                .map(|v| FunctionDeclaration {
-                    name: v.name,
-                    description: v.description,
-                    parameters_json_schema: Some(v.parameters),
-                    response_json_schema: None,
+                .map(|v| {
+                    let v = v.examples_in_description();
+                    FunctionDeclaration {
+                        name: v.name,
+                        description: v.description,
+                        parameters_json_schema: Some(v.parameters),
+                        response_json_schema: None,
+                    }
                 })
                 .collect(),
         }
@@ -409,6 +419,23 @@ impl Default for ToolConfig {
     }
 }
 
+impl From<&ToolChoice> for ToolConfig {
+    fn from(choice: &ToolChoice) -> Self {
+        let (mode, allowed_function_names) = match choice {
+            ToolChoice::Auto => (FunctionCallingMode::Auto, None),
+            ToolChoice::None => (FunctionCallingMode::None, None),
+            ToolChoice::Required => (FunctionCallingMode::Any, None),
+            ToolChoice::Function(name) => (FunctionCallingMode::Any, Some(vec![name.clone()])),
+        };
+        Self {
+            function_calling_config: FunctionCallingConfig {
+                mode,
+                allowed_function_names,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct FunctionCallingConfig {
@@ -747,6 +774,25 @@ mod tests {
     use super::*;
     use serde_json::json;
 
+    #[test]
+    fn test_tool_config_from_tool_choice() {
+        let config: ToolConfig = (&ToolChoice::Function("sum".into())).into();
+        assert_eq!(
+            serde_json::to_value(&config).unwrap(),
+            json!({
+                "functionCallingConfig": {
+                    "mode": "ANY",
+                    "allowedFunctionNames": ["sum"]
+                }
+            })
+        );
+        let config: ToolConfig = (&ToolChoice::Auto).into();
+        assert_eq!(
+            config.function_calling_config.mode,
+            FunctionCallingMode::Auto
+        );
+    }
+
     #[test]
     fn test_content_part() {
         // Test Text variant
@@ -8,11 +8,13 @@
 
 use anda_core::{
     AgentOutput, BoxError, BoxPinFut, CompletionRequest, ContentPart, Embedding,
-    FunctionDefinition, Json, Message, Usage as ModelUsage,
+    FINISH_REASON_LENGTH, FunctionDefinition, Json, Message, Usage as ModelUsage,
 };
+use futures::StreamExt;
 use log::{Level::Debug, log_enabled};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tokio::sync::mpsc::UnboundedSender;
 
 pub mod types;
 
@@ -152,6 +154,7 @@ impl EmbeddingResponse {
                     .total_tokens
                     .saturating_sub(self.usage.prompt_tokens) as u64,
                 requests: 1,
+                ..Default::default()
             },
         ))
     }
@@ -265,6 +268,7 @@ impl EmbeddingFeaturesDyn for EmbeddingModel {
                                     .saturating_sub(res.usage.prompt_tokens)
                                     as u64,
                                 requests: 1,
+                                ..Default::default()
                             },
                         ))
                     }
@@ -321,15 +325,23 @@ impl CompletionResponse {
                     input_tokens: u.prompt_tokens as u64,
                     output_tokens: u.completion_tokens as u64,
                     requests: 1,
+                    ..Default::default()
                 })
                 .unwrap_or_default(),
             ..Default::default()
         };
 
         let choice = self.choices.pop().ok_or("No completion choice")?;
-        if !matches!(choice.finish_reason.as_str(), "stop" | "tool_calls") {
+        if !matches!(
+            choice.finish_reason.as_str(),
+            "stop" | "tool_calls" | FINISH_REASON_LENGTH
+        ) {
             output.failed_reason = Some(choice.finish_reason);
         } else {
+            if choice.finish_reason == FINISH_REASON_LENGTH {
+                // keep the partial content, so the caller can continue
+                output.failed_reason = Some(choice.finish_reason.clone());
+            }
             if let Some(refusal) = &choice.message.refusal {
                 output.failed_reason = Some(refusal.clone());
             }
@@ -509,7 +521,7 @@ impl From<FunctionDefinition> for ToolDefinition {
     fn from(f: FunctionDefinition) -> Self {
         Self {
             r#type: "function".into(),
-            function: f,
+            function: f.examples_in_description(),
         }
     }
 }
@@ -525,6 +537,7 @@ pub struct Function {
 pub struct CompletionModel {
     client: Client,
     pub model: String,
+    streaming: bool,
 }
 
 impl CompletionModel {
@@ -537,113 +550,142 @@ impl CompletionModel {
         Self {
             client,
             model: model.to_string(),
+            streaming: false,
         }
     }
+
+    /// Enables streaming responses in [`CompletionFeaturesDyn::completion_streaming`],
+    /// so partial content is sent while the model is generating. Default is false.
+    pub fn with_streaming(mut self, streaming: bool) -> Self {
+        self.streaming = streaming;
+        self
+    }
 }
 
-impl CompletionFeaturesDyn for CompletionModel {
-    fn completion(&self, mut req: CompletionRequest) -> BoxPinFut<Result<AgentOutput, BoxError>> {
-        let model = self.model.clone();
-        let client = self.client.clone();
+/// A chat completions request body with the conversation it was built from.
+struct PreparedRequest {
+    body: Json,
+    raw_history: Vec<Json>,
+    chat_history: Vec<Message>,
+    skip_raw: usize,
+}
 
-        Box::pin(async move {
-            let timestamp = unix_ms();
-            let mut raw_history: Vec<Json> = Vec::new();
-            let mut chat_history: Vec<Message> = Vec::new();
+fn prepare_request(model: &str, mut req: CompletionRequest) -> Result<PreparedRequest, BoxError> {
+    let timestamp = unix_ms();
+    let tool_choice = req.resolved_tool_choice();
+    let mut raw_history: Vec<Json> = Vec::new();
+    let mut chat_history: Vec<Message> = Vec::new();
+
+    if !req.instructions.is_empty() {
+        raw_history.push(json!(MessageInput {
+            role: "system".into(),
+            content: req.instructions.clone().into(),
+            tool_call_id: None,
+        }));
+    };
+
+    raw_history.append(&mut req.raw_history);
+    let skip_raw = raw_history.len();
+
+    for msg in req.chat_history {
+        let val = to_message_input(&msg);
+        for v in val {
+            raw_history.push(serde_json::to_value(&v)?);
+        }
+    }
 
-            if !req.instructions.is_empty() {
-                raw_history.push(json!(MessageInput {
-                    role: "system".into(),
-                    content: req.instructions.clone().into(),
-                    tool_call_id: None,
-                }));
-            };
+    if let Some(mut msg) = req
+        .documents
+        .to_message(&rfc3339_datetime(timestamp).unwrap())
+    {
+        msg.timestamp = Some(timestamp);
+        let val = to_message_input(&msg);
+        for v in val {
+            raw_history.push(serde_json::to_value(&v)?);
+        }
+        chat_history.push(msg);
+    }
 
-            raw_history.append(&mut req.raw_history);
-            let skip_raw = raw_history.len();
+    let mut content = req.content;
+    if !req.prompt.is_empty() {
+        content.push(req.prompt.into());
+    }
+    if !content.is_empty() {
+        let msg = Message {
+            role: req.role.unwrap_or_else(|| "user".to_string()),
+            content,
+            timestamp: Some(timestamp),
+            ..Default::default()
+        };
 
-            for msg in req.chat_history {
-                let val = to_message_input(&msg);
-                for v in val {
-                    raw_history.push(serde_json::to_value(&v)?);
-                }
-            }
+        let val = to_message_input(&msg);
+        for v in val {
+            raw_history.push(serde_json::to_value(&v)?);
+        }
+        chat_history.push(msg);
+    }
 
-            if let Some(mut msg) = req
-                .documents
-                .to_message(&rfc3339_datetime(timestamp).unwrap())
-            {
-                msg.timestamp = Some(timestamp);
-                let val = to_message_input(&msg);
-                for v in val {
-                    raw_history.push(serde_json::to_value(&v)?);
-                }
-                chat_history.push(msg);
-            }
+    let mut body = json!({
+        "model": model,
+        "messages": &raw_history,
+    });
 
-            let mut content = req.content;
-            if !req.prompt.is_empty() {
-                content.push(req.prompt.into());
-            }
-            if !content.is_empty() {
-                let msg = Message {
-                    role: req.role.unwrap_or_else(|| "user".to_string()),
-                    content,
-                    timestamp: Some(timestamp),
-                    ..Default::default()
-                };
-
-                let val = to_message_input(&msg);
-                for v in val {
-                    raw_history.push(serde_json::to_value(&v)?);
-                }
-                chat_history.push(msg);
-            }
+    let obj = body.as_object_mut().unwrap();
+    if let Some(temperature) = req.temperature {
+        obj.insert("temperature".to_string(), Json::from(temperature));
+    }
 
-            let mut body = json!({
-                "model": model,
-                "messages": &raw_history,
-            });
+    if let Some(max_tokens) = req.max_output_tokens {
+        obj.insert("max_completion_tokens".to_string(), Json::from(max_tokens));
+    }
 
-            let body = body.as_object_mut().unwrap();
-            if let Some(temperature) = req.temperature {
-                body.insert("temperature".to_string(), Json::from(temperature));
-            }
+    if let Some(output_schema) = req.output_schema {
+        obj.insert(
+            "response_format".to_string(),
+            json!({ "type": "json_schema", "json_schema": output_schema }),
+        );
+    }
 
-            if let Some(max_tokens) = req.max_output_tokens {
-                body.insert("max_completion_tokens".to_string(), Json::from(max_tokens));
-            }
+    if let Some(stop) = req.stop {
+        obj.insert("stop".to_string(), Json::from(stop));
+    }
 
-            if let Some(output_schema) = req.output_schema {
-                body.insert(
-                    "response_format".to_string(),
-                    json!({ "type": "json_schema", "json_schema": output_schema }),
-                );
-            }
+    if !req.tools.is_empty() {
+        obj.insert(
+            "tools".to_string(),
+            json!(
+                req.tools
+                    .into_iter()
+                    .map(ToolDefinition::from)
+                    .collect::<Vec<_>>()
+            ),
+        );
+        obj.insert(
+            "tool_choice".to_string(),
+            super::chat_tool_choice(&tool_choice),
+        );
+    };
+
+    Ok(PreparedRequest {
+        body,
+        raw_history,
+        chat_history,
+        skip_raw,
+    })
+}
 
-            if let Some(stop) = req.stop {
-                body.insert("stop".to_string(), Json::from(stop));
-            }
+impl CompletionFeaturesDyn for CompletionModel {
+    fn completion(&self, req: CompletionRequest) -> BoxPinFut<Result<AgentOutput, BoxError>> {
+        let model = self.model.clone();
+        let client = self.client.clone();
 
-            if !req.tools.is_empty() {
-                body.insert(
-                    "tools".to_string(),
-                    json!(
-                        req.tools
-                            .into_iter()
-                            .map(ToolDefinition::from)
-                            .collect::<Vec<_>>()
-                    ),
-                );
-                body.insert(
-                    "tool_choice".to_string(),
-                    if req.tool_choice_required {
-                        Json::from("required")
-                    } else {
-                        Json::from("auto")
-                    },
-                );
-            };
+        Box::pin(async move {
+            let PreparedRequest {
+                body,
+                mut raw_history,
+                chat_history,
+                skip_raw,
+            } = prepare_request(&model, req)?;
 
             if log_enabled!(Debug)
                 && let Ok(val) = serde_json::to_string(&body)
@@ -651,7 +693,7 @@ impl CompletionFeaturesDyn for CompletionModel {
                 log::debug!(request = val; "OpenAI completions request");
             }
 
-            let response = client.post("/chat/completions").json(body).send().await?;
+            let response = client.post("/chat/completions").json(&body).send().await?;
             if response.status().is_success() {
                 let text = response.text().await?;
                 match serde_json::from_str::<CompletionResponse>(&text) {
@@ -687,6 +729,239 @@ impl CompletionFeaturesDyn for CompletionModel {
             }
         })
     }
+
+    fn completion_streaming(
+        &self,
+        req: CompletionRequest,
+        deltas: UnboundedSender<String>,
+    ) -> BoxPinFut<Result<AgentOutput, BoxError>> {
+        if !self.streaming {
+            let fut = self.completion(req);
+            return Box::pin(async move {
+                let output = fut.await?;
+                if !output.content.is_empty() {
+                    let _ = deltas.send(output.content.clone());
+                }
+                Ok(output)
+            });
+        }
+
+        let model = self.model.clone();
+        let client = self.client.clone();
+
+        Box::pin(async move {
+            let PreparedRequest {
+                mut body,
+                mut raw_history,
+                chat_history,
+                skip_raw,
+            } = prepare_request(&model, req)?;
+            let obj = body.as_object_mut().unwrap();
+            obj.insert("stream".to_string(), Json::from(true));
+            obj.insert(
+                "stream_options".to_string(),
+                json!({ "include_usage": true }),
+            );
+
+            if log_enabled!(Debug)
+                && let Ok(val) = serde_json::to_string(&body)
+            {
+                log::debug!(request = val; "OpenAI completions stream request");
+            }
+
+            let response = client.post("/chat/completions").json(&body).send().await?;
+            if !response.status().is_success() {
+                let status = response.status();
+                let msg = response.text().await?;
+                log::error!(
+                    request:serde = body;
+                    "completions request failed: {status}, body: {msg}",
+                );
+                return Err(format!("OpenAI completions error: {}", msg).into());
+            }
+
+            let res = read_completion_stream(response, &deltas).await?;
+            if log_enabled!(Debug) {
+                log::debug!(
+                    request:serde = body,
+                    response:serde = res;
+                    "OpenAI completions stream response");
+            } else if res.maybe_failed() {
+                log::warn!(
+                    request:serde = body,
+                    response:serde = res;
+                    "completions maybe failed");
+            }
+            if skip_raw > 0 {
+                raw_history.drain(0..skip_raw);
+            }
+            res.try_into(raw_history, chat_history)
+        })
+    }
+}
+
+/// A chunk of a streaming chat completions response
+#[derive(Debug, Deserialize)]
+struct CompletionChunk {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    created: u64,
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    choices: Vec<ChoiceChunk>,
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChoiceChunk {
+    #[serde(default)]
+    delta: MessageDelta,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MessageDelta {
+    #[serde(default)]
+    role: Option<String>,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    refusal: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCallDelta {
+    index: usize,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: FunctionDelta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FunctionDelta {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+/// Reads a server-sent events stream of chat completion chunks into a [`CompletionResponse`],
+/// sending the text content to `deltas` as it arrives.
+async fn read_completion_stream(
+    response: reqwest::Response,
+    deltas: &UnboundedSender<String>,
+) -> Result<CompletionResponse, BoxError> {
+    let mut res = CompletionResponse {
+        id: String::new(),
+        object: "chat.completion".to_string(),
+        created: 0,
+        model: String::new(),
+        choices: Vec::new(),
+        usage: None,
+    };
+    let mut message = MessageOutput {
+        role: "assistant".to_string(),
+        content: None,
+        refusal: None,
+        tool_calls: None,
+    };
+    let mut tool_calls: Vec<ToolCallOutput> = Vec::new();
+    let mut finish_reason: Option<String> = None;
+
+    let mut buf: Vec<u8> = Vec::new();
+    let mut stream = response.bytes_stream();
+    let mut ended = false;
+    while !ended {
+        match stream.next().await {
+            Some(bytes) => buf.extend_from_slice(&bytes?),
+            None => {
+                // the last line may not end with a newline
+                ended = true;
+                buf.push(b'\n');
+            }
+        }
+        while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buf.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let data = match line.trim().strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => continue,
+            };
+            if data.is_empty() || data == "[DONE]" {
+                continue;
+            }
+
+            let chunk: CompletionChunk = serde_json::from_str(data).map_err(|err| {
+                format!("OpenAI completions stream error: {}, data: {}", err, data)
+            })?;
+            if res.id.is_empty() {
+                res.id = chunk.id;
+                res.created = chunk.created;
+                res.model = chunk.model;
+            }
+            if chunk.usage.is_some() {
+                res.usage = chunk.usage;
+            }
+            for choice in chunk.choices {
+                if let Some(role) = choice.delta.role {
+                    message.role = role;
+                }
+                if let Some(text) = choice.delta.content
+                    && !text.is_empty()
+                {
+                    message.content.get_or_insert_default().push_str(&text);
+                    let _ = deltas.send(text);
+                }
+                if let Some(refusal) = choice.delta.refusal {
+                    message.refusal.get_or_insert_default().push_str(&refusal);
+                }
+                for tc in choice.delta.tool_calls {
+                    while tool_calls.len() <= tc.index {
+                        tool_calls.push(ToolCallOutput {
+                            id: String::new(),
+                            r#type: "function".to_string(),
+                            function: Function {
+                                name: String::new(),
+                                arguments: String::new(),
+                            },
+                        });
+                    }
+                    let call = &mut tool_calls[tc.index];
+                    if let Some(id) = tc.id {
+                        call.id = id;
+                    }
+                    if let Some(name) = tc.function.name {
+                        call.function.name.push_str(&name);
+                    }
+                    if let Some(arguments) = tc.function.arguments {
+                        call.function.arguments.push_str(&arguments);
+                    }
+                }
+                if choice.finish_reason.is_some() {
+                    finish_reason = choice.finish_reason;
+                }
+            }
+        }
+    }
+
+    let finish_reason =
+        finish_reason.ok_or("OpenAI completions stream ended without finish reason")?;
+    if !tool_calls.is_empty() {
+        message.tool_calls = Some(tool_calls);
+    }
+    res.choices.push(Choice {
+        index: 0,
+        message,
+        finish_reason,
+    });
+    Ok(res)
 }
 
 /// Completion model implementation for OpenAI API
@@ -717,6 +992,7 @@ impl CompletionFeaturesDyn for CompletionModelV2 {
 
         Box::pin(async move {
             let timestamp = unix_ms();
+            let tool_choice = req.resolved_tool_choice();
             let mut raw_history: Vec<Json> = Vec::new();
             let mut chat_history: Vec<Message> = Vec::new();
             let mut oreq = types::CompletionRequest {
@@ -793,19 +1069,18 @@ impl CompletionFeaturesDyn for CompletionModelV2 {
                 oreq.tools = req
                     .tools
                     .into_iter()
-                    .map(|v| types::ToolDefinition {
-                        r#type: "function".to_string(),
-                        name: v.name,
-                        description: v.description,
-                        parameters: v.parameters,
-                        strict: v.strict.unwrap_or_default(),
+                    .map(|v| {
+                        let v = v.examples_in_description();
+                        types::ToolDefinition {
+                            r#type: "function".to_string(),
+                            name: v.name,
+                            description: v.description,
+                            parameters: v.parameters,
+                            strict: v.strict.unwrap_or_default(),
+                        }
                     })
                     .collect::<Vec<_>>();
-                oreq.tool_choice = Some(if req.tool_choice_required {
-                    "required".to_string()
-                } else {
-                    "auto".to_string()
-                });
+                oreq.tool_choice = Some(types::responses_tool_choice(&tool_choice));
             };
 
             if log_enabled!(Debug)
@@ -849,3 +1124,126 @@ impl CompletionFeaturesDyn for CompletionModelV2 {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    /// Builds a response whose body arrives in the given byte chunks.
+    fn sse_response(chunks: &[&str]) -> reqwest::Response {
+        let chunks: Vec<Result<String, std::io::Error>> =
+            chunks.iter().map(|chunk| Ok(chunk.to_string())).collect();
+        let body = reqwest::Body::wrap_stream(futures::stream::iter(chunks));
+        http::Response::new(body).into()
+    }
+
+    async fn read_stream(chunks: &[&str]) -> (Result<AgentOutput, BoxError>, Vec<String>) {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let res = read_completion_stream(sse_response(chunks), &tx)
+            .await
+            .and_then(|res| res.try_into(Vec::new(), Vec::new()));
+        drop(tx);
+        let mut deltas = Vec::new();
+        while let Some(delta) = rx.recv().await {
+            deltas.push(delta);
+        }
+        (res, deltas)
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_read_completion_stream_content() {
+        let (res, deltas) = read_stream(&[
+            "data: {\"id\":\"chatcmpl-1\",\"created\":1,\"model\":\"gpt-4o\",\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n",
+            "data: {\"id\":\"chatcmpl-1\",\"choices\":[{\"delta\":{\"content\":\"Hello\"}}]}\n\ndata: {\"id\":\"chatcmpl-1\",\"choi",
+            "ces\":[{\"delta\":{\"content\":\", world\"}}]}\n\n",
+            ": keep-alive\n\n",
+            "data: {\"id\":\"chatcmpl-1\",\"choices\":[{\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+            "data: {\"id\":\"chatcmpl-1\",\"choices\":[],\"usage\":{\"prompt_tokens\":9,\"completion_tokens\":3,\"total_tokens\":12}}\n\n",
+            "data: [DONE]\n\n",
+        ])
+        .await;
+        let output = res.unwrap();
+        assert_eq!(deltas, vec!["Hello".to_string(), ", world".to_string()]);
+        assert_eq!(output.content, "Hello, world");
+        assert!(output.failed_reason.is_none());
+        assert!(output.tool_calls.is_empty());
+        // the usage-only final chunk
+        assert_eq!(output.usage.input_tokens, 9);
+        assert_eq!(output.usage.output_tokens, 3);
+        assert_eq!(output.usage.requests, 1);
+        assert_eq!(output.chat_history.len(), 1);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_read_completion_stream_tool_calls() {
+        let (res, deltas) = read_stream(&[
+            "data: {\"id\":\"chatcmpl-2\",\"choices\":[{\"delta\":{\"role\":\"assistant\",\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"get_weather\",\"arguments\":\"\"}}]}}]}\n\n",
+            "data: {\"id\":\"chatcmpl-2\",\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"{\\\"city\\\":\"}}]}}]}\n\n",
+            "data: {\"id\":\"chatcmpl-2\",\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":1,\"id\":\"call_2\",\"function\":{\"name\":\"get_time\",\"arguments\":\"{}\"}}]}}]}\n\n",
+            "data: {\"id\":\"chatcmpl-2\",\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"Paris\\\"}\"}}]}}]}\n\n",
+            "data: {\"id\":\"chatcmpl-2\",\"choices\":[{\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: [DONE]\n\n",
+        ])
+        .await;
+        let output = res.unwrap();
+        assert!(deltas.is_empty());
+        assert!(output.content.is_empty());
+        assert!(output.failed_reason.is_none());
+        assert_eq!(output.tool_calls.len(), 2);
+        assert_eq!(output.tool_calls[0].name, "get_weather");
+        assert_eq!(output.tool_calls[0].call_id.as_deref(), Some("call_1"));
+        assert_eq!(output.tool_calls[0].args, json!({"city": "Paris"}));
+        assert_eq!(output.tool_calls[1].name, "get_time");
+        assert_eq!(output.tool_calls[1].call_id.as_deref(), Some("call_2"));
+        assert_eq!(output.tool_calls[1].args, json!({}));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_read_completion_stream_length() {
+        let (res, deltas) = read_stream(&[
+            "data: {\"id\":\"chatcmpl-3\",\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"The answer is\"}}]}\n\n",
+            "data: {\"id\":\"chatcmpl-3\",\"choices\":[{\"delta\":{},\"finish_reason\":\"length\"}]}\n\n",
+            "data: [DONE]\n\n",
+        ])
+        .await;
+        let output = res.unwrap();
+        assert_eq!(deltas, vec!["The answer is".to_string()]);
+        assert_eq!(output.content, "The answer is");
+        assert_eq!(output.failed_reason.as_deref(), Some(FINISH_REASON_LENGTH));
+        assert!(output.usage.input_tokens == 0 && output.usage.output_tokens == 0);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_read_completion_stream_without_done() {
+        // the last line has no trailing newline
+        let (res, deltas) = read_stream(&[
+            "data: {\"id\":\"chatcmpl-4\",\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+            "data: {\"id\":\"chatcmpl-4\",\"choices\":[{\"delta\":{},\"finish_reason\":\"stop\"}]}",
+        ])
+        .await;
+        let output = res.unwrap();
+        assert_eq!(deltas, vec!["Hi".to_string()]);
+        assert_eq!(output.content, "Hi");
+        assert!(output.failed_reason.is_none());
+
+        // a stream cut off before the finish reason is an error
+        let (res, deltas) = read_stream(&[
+            "data: {\"id\":\"chatcmpl-5\",\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+        ])
+        .await;
+        assert!(
+            res.unwrap_err()
+                .to_string()
+                .contains("ended without finish reason")
+        );
+        assert_eq!(deltas, vec!["Hi".to_string()]);
+
+        let (res, _) = read_stream(&["data: {not json}\n\n"]).await;
+        assert!(
+            res.unwrap_err()
+                .to_string()
+                .contains("OpenAI completions stream error")
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use anda_core::{AgentOutput, BoxError, ContentPart, Json, Message, Usage as ModelUsage};
+use anda_core::{
+    AgentOutput, BoxError, ContentPart, FINISH_REASON_LENGTH, Json, Message, ToolChoice,
+    Usage as ModelUsage,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, json};
 
@@ -23,8 +26,9 @@ pub struct CompletionRequest {
     /// The temperature. Set higher (up to a max of 1.0) for more creative responses.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f64>,
-    /// Controls which (if any) tool is called by the model. "none", "auto", "required"
-    pub tool_choice: Option<String>,
+    /// Controls which (if any) tool is called by the model. "none", "auto", "required",
+    /// or `{"type": "function", "name": ...}` to force a specific function.
+    pub tool_choice: Option<Json>,
     /// The tools you want to use. Currently this is limited to functions, but will be expanded on in future.
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub tools: Vec<ToolDefinition>,
@@ -118,6 +122,7 @@ impl CompletionResponse {
                 input_tokens: self.usage.input_tokens,
                 output_tokens: self.usage.output_tokens,
                 requests: 1,
+                ..Default::default()
             },
             ..Default::default()
         };
@@ -137,6 +142,14 @@ impl CompletionResponse {
                 output.chat_history.push(msg);
             }
             output.failed_reason = failed_reason;
+            if output.failed_reason.is_none()
+                && self
+                    .incomplete_details
+                    .is_some_and(|d| d.reason == "max_output_tokens")
+            {
+                // keep the partial content, so the caller can continue
+                output.failed_reason = Some(FINISH_REASON_LENGTH.to_string());
+            }
         }
 
         Ok(output)
@@ -194,6 +207,16 @@ pub enum MessageItem {
     },
 }
 
+/// Returns the `tool_choice` value of the Response API.
+pub fn responses_tool_choice(choice: &ToolChoice) -> Json {
+    match choice {
+        ToolChoice::Auto => Json::from("auto"),
+        ToolChoice::None => Json::from("none"),
+        ToolChoice::Required => Json::from("required"),
+        ToolChoice::Function(name) => json!({"type": "function", "name": name}),
+    }
+}
+
 pub fn message_into(msg: Message) -> Vec<MessageItem> {
     let mut rt: Vec<MessageItem> = Vec::new();
     let mut content: Vec<ContentItem> = Vec::new();
@@ -537,6 +560,18 @@ mod tests {
     use anda_core::{ContentPart, Message};
     use serde_json::json;
 
+    #[test]
+    fn test_responses_tool_choice() {
+        assert_eq!(
+            responses_tool_choice(&ToolChoice::Required),
+            json!("required")
+        );
+        assert_eq!(
+            responses_tool_choice(&ToolChoice::Function("sum".into())),
+            json!({"type": "function", "name": "sum"})
+        );
+    }
+
     #[test]
     fn test_message_into_mixed_parts() {
         let msg = Message {
@@ -6,8 +6,8 @@
 //! - Response parsing and conversion to Anda's internal formats
 
 use anda_core::{
-    AgentOutput, BoxError, BoxPinFut, CompletionRequest, ContentPart, FunctionDefinition, Json,
-    Message, Usage as ModelUsage,
+    AgentOutput, BoxError, BoxPinFut, CompletionRequest, ContentPart, FINISH_REASON_LENGTH,
+    FunctionDefinition, Json, Message, Usage as ModelUsage,
 };
 use log::{Level::Debug, log_enabled};
 use serde::{Deserialize, Serialize};
@@ -125,15 +125,23 @@ impl CompletionResponse {
                     input_tokens: u.prompt_tokens as u64,
                     output_tokens: u.completion_tokens as u64,
                     requests: 1,
+                    ..Default::default()
                 })
                 .unwrap_or_default(),
             ..Default::default()
         };
 
         let choice = self.choices.pop().ok_or("No completion choice")?;
-        if !matches!(choice.finish_reason.as_str(), "stop" | "tool_calls") {
+        if !matches!(
+            choice.finish_reason.as_str(),
+            "stop" | "tool_calls" | FINISH_REASON_LENGTH
+        ) {
             output.failed_reason = Some(choice.finish_reason);
         } else {
+            if choice.finish_reason == FINISH_REASON_LENGTH {
+                // keep the partial content, so the caller can continue
+                output.failed_reason = Some(choice.finish_reason.clone());
+            }
             if let Some(refusal) = &choice.message.refusal {
                 output.failed_reason = Some(refusal.clone());
             }
@@ -293,7 +301,7 @@ impl From<FunctionDefinition> for ToolDefinition {
         f.strict = None; // Grok does not support strict mode
         Self {
             r#type: "function".into(),
-            function: f,
+            function: f.examples_in_description(),
         }
     }
 }
@@ -334,6 +342,7 @@ impl CompletionFeaturesDyn for CompletionModel {
 
         Box::pin(async move {
             let timestamp = unix_ms();
+            let tool_choice = req.resolved_tool_choice();
             let mut raw_history: Vec<Json> = Vec::new();
             let mut chat_history: Vec<Message> = Vec::new();
 
@@ -423,11 +432,7 @@ impl CompletionFeaturesDyn for CompletionModel {
                 );
                 body.insert(
                     "tool_choice".to_string(),
-                    if req.tool_choice_required {
-                        Json::from("required")
-                    } else {
-                        Json::from("auto")
-                    },
+                    super::chat_tool_choice(&tool_choice),
                 );
             };
 
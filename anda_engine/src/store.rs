@@ -8,6 +8,7 @@
 //! - **Store**: Main storage interface that handles object storage operations
 //! - **VectorStore**: Wrapper for vector search functionality
 //! - **VectorSearchFeaturesDyn**: Trait defining vector search capabilities
+//! - **ArtifactStore**: Persists large artifacts of agent runs and tool calls and returns references instead
 //!
 //! ## Features
 //!
@@ -30,8 +31,14 @@
 //! let (content, meta) = store.store_get(&namespace, &path).await?;
 //! ```
 
-use anda_core::{BoxError, BoxPinFut, ObjectMeta, Path, PutMode, PutResult, path_lowercase};
+use anda_core::{
+    BoxError, BoxPinFut, ListResult, ObjectMeta, Path, PutMode, PutResult, Resource,
+    path_lowercase, validate_path_part,
+};
+use candid::Principal;
 use futures::TryStreamExt;
+use ic_auth_types::ByteArrayB64;
+use ic_cose_types::cose::sha3_256;
 use object_store::PutOptions;
 use std::sync::Arc;
 
@@ -212,6 +219,26 @@ impl Store {
         Ok(metas)
     }
 
+    /// Lists objects and common prefixes ("directories") directly under the prefix
+    ///
+    /// Unlike [`Store::store_list`], this does not recurse into sub-paths, which makes it
+    /// suitable for hierarchical (file-browser like) listing, e.g. of nested namespaces.
+    ///
+    /// # Arguments
+    /// * `prefix` - Optional path prefix to list under
+    pub async fn store_list_dirs(
+        &self,
+        namespace: &Path,
+        prefix: Option<&Path>,
+    ) -> Result<ListResult, BoxError> {
+        let prefix = match prefix {
+            Some(p) => path_lowercase(&namespace.child(p.as_ref())),
+            None => path_lowercase(namespace),
+        };
+        let res = self.store.list_with_delimiter(Some(&prefix)).await?;
+        Ok(res)
+    }
+
     /// Stores data at the specified path with a given write mode
     ///
     /// # Arguments
@@ -267,3 +294,159 @@ impl Store {
         Ok(())
     }
 }
+
+/// Persists the artifacts of agent runs and tool calls to the store, so large generated artifacts
+/// don't bloat the response and can be fetched later.
+///
+/// Each artifact blob larger than the threshold is written to
+/// `{namespace}/{caller}/{hash}`, where `hash` is the hex encoded SHA3-256 hash of
+/// the blob, and replaced in the output by a reference: the blob is removed,
+/// `uri` is set to `store://{namespace}/{caller}/{hash}`, and `size` and `hash` are filled.
+/// The object can be read back with [`ArtifactStore::get`], or the `store_read` tool
+/// using the same namespace.
+#[derive(Clone)]
+pub struct ArtifactStore {
+    store: Store,
+    namespace: Path,
+    threshold: usize,
+}
+
+impl ArtifactStore {
+    /// Creates a new ArtifactStore
+    ///
+    /// # Arguments
+    /// * `store` - The store to write artifacts to
+    /// * `namespace` - The namespace under which each caller has its own namespace
+    /// * `threshold` - Artifacts with a blob larger than this many bytes are persisted
+    pub fn new(store: Store, namespace: Path, threshold: usize) -> Self {
+        Self {
+            store,
+            namespace,
+            threshold,
+        }
+    }
+
+    /// Persists the artifacts above the threshold and replaces them with references.
+    pub async fn persist(
+        &self,
+        caller: &Principal,
+        artifacts: &mut [Resource],
+    ) -> Result<(), BoxError> {
+        let namespace = self.namespace.child(caller.to_text());
+        for artifact in artifacts.iter_mut() {
+            let Some(blob) = artifact.blob.take_if(|blob| blob.len() > self.threshold) else {
+                continue;
+            };
+
+            let hash = sha3_256(&blob);
+            let name: String = hash.iter().map(|b| format!("{b:02x}")).collect();
+            let size = blob.len();
+            self.store
+                .store_put(
+                    &namespace,
+                    &Path::from(name.as_str()),
+                    PutMode::Overwrite,
+                    blob.0.into(),
+                )
+                .await?;
+            artifact.uri = Some(format!("store://{namespace}/{name}"));
+            artifact.size = Some(size);
+            artifact.hash = Some(ByteArrayB64(hash));
+        }
+        Ok(())
+    }
+
+    /// Returns the blob of a persisted artifact by its name, i.e. the hex encoded hash.
+    pub async fn get(&self, caller: &Principal, name: &str) -> Result<Vec<u8>, BoxError> {
+        validate_path_part(name)?;
+        let namespace = self.namespace.child(caller.to_text());
+        let (data, _) = self.store.store_get(&namespace, &Path::from(name)).await?;
+        Ok(data.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_auth_types::ByteBufB64;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_artifact_store() {
+        let store = Store::new(Arc::new(InMemory::new()));
+        let artifacts = ArtifactStore::new(store.clone(), Path::from("artifacts"), 4);
+        let caller = Principal::anonymous();
+        let mut resources = vec![
+            Resource {
+                name: "small.txt".to_string(),
+                blob: Some(ByteBufB64(b"tiny".to_vec())),
+                ..Default::default()
+            },
+            Resource {
+                name: "large.txt".to_string(),
+                blob: Some(ByteBufB64(b"hello world".to_vec())),
+                ..Default::default()
+            },
+        ];
+        artifacts.persist(&caller, &mut resources).await.unwrap();
+
+        assert_eq!(resources[0].blob.as_ref().unwrap().0, b"tiny");
+        assert!(resources[0].uri.is_none());
+
+        assert!(resources[1].blob.is_none());
+        assert_eq!(resources[1].size, Some(11));
+        let hash = resources[1].hash.as_ref().unwrap();
+        let name: String = hash.0.iter().map(|b| format!("{b:02x}")).collect();
+        let namespace = Path::from("artifacts").child(caller.to_text());
+        assert_eq!(
+            resources[1].uri.as_deref().unwrap(),
+            format!("store://{namespace}/{name}")
+        );
+        let (data, _) = store
+            .store_get(&namespace, &Path::from(name.as_str()))
+            .await
+            .unwrap();
+        assert_eq!(data.as_ref(), b"hello world");
+
+        let blob = artifacts.get(&caller, &name).await.unwrap();
+        assert_eq!(blob, b"hello world");
+        assert!(artifacts.get(&caller, "../other").await.is_err());
+        assert!(
+            artifacts
+                .get(&Principal::management_canister(), &name)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_store_list_dirs() {
+        let store = Store::new(Arc::new(InMemory::new()));
+        let ns = Path::from("engine/tool");
+        let sub = Path::from("engine/tool/sub");
+        let data = bytes::Bytes::from("x");
+        store
+            .store_put(&ns, &Path::from("a.txt"), PutMode::Create, data.clone())
+            .await
+            .unwrap();
+        store
+            .store_put(&sub, &Path::from("b.txt"), PutMode::Create, data)
+            .await
+            .unwrap();
+
+        let res = store.store_list_dirs(&ns, None).await.unwrap();
+        assert_eq!(res.common_prefixes, vec![sub.clone()]);
+        assert_eq!(res.objects.len(), 1);
+        assert_eq!(res.objects[0].location, Path::from("engine/tool/a.txt"));
+
+        let res = store
+            .store_list_dirs(&Path::from("engine"), None)
+            .await
+            .unwrap();
+        assert_eq!(res.common_prefixes, vec![ns]);
+        assert!(res.objects.is_empty());
+
+        let res = store.store_list_dirs(&sub, None).await.unwrap();
+        assert!(res.common_prefixes.is_empty());
+        assert_eq!(res.objects[0].location, Path::from("engine/tool/sub/b.txt"));
+    }
+}
@@ -0,0 +1,49 @@
+use std::{path::Path, process::Command, time::SystemTime};
+
+fn git(args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| s.trim().to_string())
+}
+
+fn main() {
+    let git_commit = git(&["rev-parse", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    // the git directory is resolved by git, so it works for any workspace layout and worktrees
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        // a missing path would rerun the script on every build
+        for name in ["HEAD", "refs", "packed-refs"] {
+            let path = Path::new(&git_dir).join(name);
+            if path.exists() {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+    }
+
+    // SOURCE_DATE_EPOCH (in seconds) overrides the build time for reproducible builds
+    let now_ms = || {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default()
+    };
+    let build_time_ms = match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => match epoch.trim().parse::<u128>() {
+            Ok(secs) => secs * 1000,
+            Err(_) => {
+                println!(
+                    "cargo:warning=SOURCE_DATE_EPOCH {epoch:?} is not a unix timestamp in seconds, using the current time"
+                );
+                now_ms()
+            }
+        },
+        Err(_) => now_ms(),
+    };
+
+    println!("cargo:rustc-env=ANDA_GIT_COMMIT={git_commit}");
+    println!("cargo:rustc-env=ANDA_BUILD_TIME_MS={build_time_ms}");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
@@ -1,5 +1,5 @@
-use anda_core::{AgentInput, Json, ToolInput};
-use anda_engine::engine::Engine;
+use anda_core::{AgentInput, BoxError, ByteBufB64, Json, NotFoundError, ToolInput, ToolOutput};
+use anda_engine::{engine::Engine, unix_ms};
 use axum::{
     extract::{Path, State},
     http::StatusCode,
@@ -16,16 +16,144 @@ use ic_tee_agent::{
     RPCRequest, RPCResponse,
     http::{Content, ContentWithSHA3},
 };
-use std::collections::BTreeMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
 
 use crate::types::*;
 
+/// Header with a client-chosen ID for an `agent_run` request, which can be
+/// passed to the `cancel` RPC to abort the run.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// In-flight agent runs by caller and request ID.
+pub(crate) type RunRegistry = Mutex<BTreeMap<(Principal, String), CancellationToken>>;
+
 #[derive(Clone)]
 pub struct AppState {
     pub(crate) engines: Arc<BTreeMap<Principal, Engine>>,
     pub(crate) default_engine: Principal,
     pub(crate) start_time_ms: u64,
+    pub(crate) app_name: String,
+    pub(crate) app_version: String,
+    pub(crate) git_commit: &'static str,
+    pub(crate) build_time_ms: u64,
+    pub(crate) runs: Arc<RunRegistry>,
+    pub(crate) database_status: Option<Arc<DatabaseStatusFn>>,
+    pub(crate) request_stats: Arc<RequestStats>,
+}
+
+/// Reports the status of the databases used by the engines.
+pub type DatabaseStatusFn = dyn Fn() -> Vec<DatabaseStatus> + Send + Sync;
+
+/// Counts of RPC requests and errors in per-second buckets over a sliding window.
+pub(crate) struct RequestStats {
+    window_secs: u64,
+    /// (unix seconds, requests, errors), oldest first
+    buckets: Mutex<VecDeque<(u64, u64, u64)>>,
+}
+
+impl RequestStats {
+    pub(crate) fn new(window_secs: u64) -> Self {
+        Self {
+            window_secs: window_secs.max(1),
+            buckets: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub(crate) fn record(&self, now_ms: u64, is_error: bool) {
+        let now = now_ms / 1000;
+        let mut buckets = self.buckets.lock().unwrap();
+        match buckets.back_mut() {
+            Some((sec, requests, errors)) if *sec == now => {
+                *requests += 1;
+                *errors += is_error as u64;
+            }
+            _ => buckets.push_back((now, 1, is_error as u64)),
+        }
+        Self::evict(&mut buckets, now, self.window_secs);
+    }
+
+    /// Returns the number of requests and errors in the window.
+    fn snapshot(&self, now_ms: u64) -> (u64, u64) {
+        let mut buckets = self.buckets.lock().unwrap();
+        Self::evict(&mut buckets, now_ms / 1000, self.window_secs);
+        buckets
+            .iter()
+            .fold((0, 0), |(r, e), (_, requests, errors)| {
+                (r + requests, e + errors)
+            })
+    }
+
+    fn evict(buckets: &mut VecDeque<(u64, u64, u64)>, now: u64, window_secs: u64) {
+        while let Some((sec, _, _)) = buckets.front() {
+            if *sec + window_secs > now {
+                break;
+            }
+            buckets.pop_front();
+        }
+    }
+}
+
+impl AppState {
+    /// Parses the engine ID of a request path, "default" is the default engine.
+    /// Returns a 400 Bad Request error if the ID is invalid.
+    pub(crate) fn engine_id(&self, id: &str) -> Result<Principal, (StatusCode, String)> {
+        if id == "default" {
+            return Ok(self.default_engine);
+        }
+        Principal::from_text(id).map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("invalid engine id: {id:?}"),
+            )
+        })
+    }
+}
+
+/// Returns the sender of the signed envelope in the request headers if it verifies against
+/// the engine ID and the request digest, otherwise the anonymous principal.
+pub(crate) fn verify_caller(
+    headers: &http::HeaderMap,
+    id: Option<Principal>,
+    digest: Option<&[u8]>,
+) -> Principal {
+    match SignedEnvelope::from_authorization(headers)
+        .or_else(|| SignedEnvelope::from_headers(headers))
+    {
+        Some(se) => match se.verify(unix_timestamp().as_millis() as u64, id, digest) {
+            Ok(_) => se.sender(),
+            Err(_) => ANONYMOUS_PRINCIPAL,
+        },
+        None => ANONYMOUS_PRINCIPAL,
+    }
+}
+
+/// Registers a cancellable run and removes it when dropped.
+struct RunGuard<'a> {
+    runs: &'a RunRegistry,
+    key: (Principal, String),
+}
+
+impl<'a> RunGuard<'a> {
+    fn register(
+        runs: &'a RunRegistry,
+        key: (Principal, String),
+        token: CancellationToken,
+    ) -> Result<Self, String> {
+        let mut map = runs.lock().unwrap();
+        if map.contains_key(&key) {
+            return Err(format!("request id {:?} is already in use", key.1));
+        }
+        map.insert(key.clone(), token);
+        Ok(Self { runs, key })
+    }
+}
+
+impl Drop for RunGuard<'_> {
+    fn drop(&mut self) {
+        self.runs.lock().unwrap().remove(&self.key);
+    }
 }
 
 /// GET /.well-known/information
@@ -33,21 +161,21 @@ pub async fn get_information(
     State(app): State<AppState>,
     headers: http::HeaderMap,
 ) -> impl IntoResponse {
-    let caller = if let Some(se) = SignedEnvelope::from_authorization(&headers)
-        .or_else(|| SignedEnvelope::from_headers(&headers))
-    {
-        match se.verify(unix_timestamp().as_millis() as u64, None, None) {
-            Ok(_) => se.sender(),
-            Err(_) => ANONYMOUS_PRINCIPAL,
-        }
-    } else {
-        ANONYMOUS_PRINCIPAL
-    };
+    let caller = verify_caller(&headers, None, None);
 
     let info = AppInformation {
         engines: app.engines.values().map(|e| e.info().clone()).collect(),
         default_engine: app.default_engine,
         start_time_ms: app.start_time_ms,
+        app_name: app.app_name.clone(),
+        app_version: app.app_version.clone(),
+        git_commit: app.git_commit.to_string(),
+        build_time_ms: app.build_time_ms,
+        agent_counts: app
+            .engines
+            .iter()
+            .map(|(id, e)| (*id, e.agent_count() as u64))
+            .collect(),
         caller,
     };
 
@@ -63,16 +191,9 @@ pub async fn get_engine_information(
     headers: http::HeaderMap,
     Path(id): Path<String>,
 ) -> impl IntoResponse {
-    let id = if &id == "default" {
-        app.default_engine
-    } else if let Ok(id) = Principal::from_text(&id) {
-        id
-    } else {
-        return (
-            StatusCode::BAD_REQUEST,
-            format!("invalid engine id: {id:?}"),
-        )
-            .into_response();
+    let id = match app.engine_id(&id) {
+        Ok(id) => id,
+        Err(err) => return err.into_response(),
     };
 
     match app.engines.get(&id) {
@@ -98,16 +219,9 @@ pub async fn anda_engine(
     Path(id): Path<String>,
     ct: ContentWithSHA3<RPCRequest>,
 ) -> impl IntoResponse {
-    let id = if &id == "default" {
-        app.default_engine
-    } else if let Ok(id) = Principal::from_text(&id) {
-        id
-    } else {
-        return (
-            StatusCode::BAD_REQUEST,
-            format!("invalid engine id: {id:?}"),
-        )
-            .into_response();
+    let id = match app.engine_id(&id) {
+        Ok(id) => id,
+        Err(err) => return err.into_response(),
     };
 
     let (req, hash) = match &ct {
@@ -115,20 +229,7 @@ pub async fn anda_engine(
         ContentWithSHA3::JSON(req, hash) => (req, hash),
     };
 
-    let caller = if let Some(se) = SignedEnvelope::from_authorization(&headers)
-        .or_else(|| SignedEnvelope::from_headers(&headers))
-    {
-        match se.verify(
-            unix_timestamp().as_millis() as u64,
-            Some(id),
-            Some(hash.as_slice()),
-        ) {
-            Ok(_) => se.sender(),
-            Err(_) => ANONYMOUS_PRINCIPAL,
-        }
-    } else {
-        ANONYMOUS_PRINCIPAL
-    };
+    let caller = verify_caller(&headers, Some(id), Some(hash.as_slice()));
 
     log::info!(
         method = req.method.as_str(),
@@ -136,10 +237,128 @@ pub async fn anda_engine(
         caller = caller.to_text();
         "anda_engine",
     );
-    let res = engine_run(req, &app, caller, id).await;
-    match &ct {
-        ContentWithSHA3::CBOR(_, _) => Content::CBOR(res, None).into_response(),
-        ContentWithSHA3::JSON(_, _) => Content::JSON(res, None).into_response(),
+    let request_id = headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string());
+    let (status, res) = match engine_run(req, &app, caller, id, request_id).await {
+        Ok(data) => (StatusCode::OK, Ok(data)),
+        Err(err) => (err.status, Err(err.message)),
+    };
+    app.request_stats.record(unix_ms(), res.is_err());
+    let res = match (Content::from(&headers), &ct) {
+        // the CBOR-encoded result is transcoded so that it can be parsed as plain JSON
+        (Content::JSON(_, _), _) => Content::JSON(json_response(res), None).into_response(),
+        (Content::CBOR(_, _), _) | (_, ContentWithSHA3::CBOR(_, _)) => {
+            Content::CBOR(res, None).into_response()
+        }
+        (_, ContentWithSHA3::JSON(_, _)) => Content::JSON(res, None).into_response(),
+    };
+    (status, res).into_response()
+}
+
+/// The error of an RPC request, responded with the HTTP status.
+/// Calls that ran and failed are responded with 200 OK and the error in the RPC result,
+/// calls of a tool or agent that does not exist with 404 Not Found.
+/// The body is the RPC result in both cases, clients decode it with [`anda_core::rpc_error_message`].
+struct RunError {
+    status: StatusCode,
+    message: String,
+}
+
+impl From<String> for RunError {
+    fn from(message: String) -> Self {
+        RunError {
+            status: StatusCode::OK,
+            message,
+        }
+    }
+}
+
+impl RunError {
+    fn from_call(prefix: &str, err: BoxError) -> Self {
+        match NotFoundError::from_error(&err) {
+            Some(err) => RunError {
+                status: StatusCode::NOT_FOUND,
+                message: format!("{prefix}: {err}"),
+            },
+            None => format!("{prefix}: {err:?}").into(),
+        }
+    }
+}
+
+/// Aggregates a health snapshot of the server from its engines, databases and request counts.
+fn server_status(app: &AppState) -> ServerStatus {
+    let now_ms = unix_ms();
+    let (requests, errors) = app.request_stats.snapshot(now_ms);
+    ServerStatus {
+        app_name: app.app_name.clone(),
+        app_version: app.app_version.clone(),
+        start_time_ms: app.start_time_ms,
+        uptime_ms: now_ms.saturating_sub(app.start_time_ms),
+        engines: app
+            .engines
+            .iter()
+            .map(|(id, e)| (*id, e.status()))
+            .collect(),
+        databases: app
+            .database_status
+            .as_ref()
+            .map(|f| f())
+            .unwrap_or_default(),
+        window_secs: app.request_stats.window_secs,
+        requests,
+        errors,
+        error_rate: if requests > 0 {
+            errors as f64 / requests as f64
+        } else {
+            0.0
+        },
+    }
+}
+
+/// Transcodes a CBOR-encoded RPC result into JSON, byte strings are base64-url encoded.
+/// Tags are dropped, and map keys that are not text are converted to their JSON text.
+fn json_response(res: RPCResponse) -> Result<Json, String> {
+    let data = res?;
+    let val: ciborium::Value =
+        from_reader(data.as_slice()).map_err(|err| format!("failed to decode result: {err:?}"))?;
+    Ok(cbor_to_json(val))
+}
+
+fn cbor_to_json(val: ciborium::Value) -> Json {
+    use ciborium::Value;
+
+    match val {
+        Value::Null => Json::Null,
+        Value::Bool(v) => Json::Bool(v),
+        Value::Integer(v) => {
+            let v = i128::from(v);
+            if let Ok(v) = i64::try_from(v) {
+                Json::from(v)
+            } else if let Ok(v) = u64::try_from(v) {
+                Json::from(v)
+            } else {
+                Json::String(v.to_string())
+            }
+        }
+        Value::Float(v) => Json::from(v),
+        Value::Text(v) => Json::String(v),
+        Value::Bytes(v) => Json::String(ByteBufB64(v).to_string()),
+        Value::Array(v) => Json::Array(v.into_iter().map(cbor_to_json).collect()),
+        Value::Map(v) => v
+            .into_iter()
+            .map(|(k, v)| {
+                let k = match cbor_to_json(k) {
+                    Json::String(k) => k,
+                    k => k.to_string(),
+                };
+                (k, cbor_to_json(v))
+            })
+            .collect(),
+        Value::Tag(_, v) => cbor_to_json(*v),
+        _ => Json::Null,
     }
 }
 
@@ -148,7 +367,8 @@ async fn engine_run(
     app: &AppState,
     caller: Principal,
     id: Principal,
-) -> RPCResponse {
+    request_id: Option<String>,
+) -> Result<ByteBufB64, RunError> {
     let engine = app
         .engines
         .get(&id)
@@ -158,10 +378,22 @@ async fn engine_run(
         "agent_run" => {
             let args: (AgentInput,) = from_reader(req.params.as_slice())
                 .map_err(|err| format!("failed to decode params: {err:?}"))?;
-            let res = engine
-                .agent_run(caller, args.0)
-                .await
-                .map_err(|err| format!("failed to run agent: {err:?}"))?;
+            let run = engine.agent_run(caller, args.0);
+            let res = match request_id {
+                // the anonymous caller is shared, so its runs can not be cancelled
+                Some(request_id) if caller != ANONYMOUS_PRINCIPAL => {
+                    let token = CancellationToken::new();
+                    let _guard =
+                        RunGuard::register(&app.runs, (caller, request_id), token.clone())?;
+                    tokio::select! {
+                        res = run => res,
+                        // dropping the run cancels it
+                        _ = token.cancelled() => Err("agent run cancelled".into()),
+                    }
+                }
+                _ => run.await,
+            }
+            .map_err(|err| RunError::from_call("failed to run agent", err))?;
             Ok(to_cbor_bytes(&res).into())
         }
         "tool_call" => {
@@ -170,16 +402,310 @@ async fn engine_run(
             let res = engine
                 .tool_call(caller, args.0)
                 .await
-                .map_err(|err| format!("failed to call tool: {err:?}"))?;
+                .map_err(|err| RunError::from_call("failed to call tool", err))?;
             Ok(to_cbor_bytes(&res).into())
         }
+        "tool_call_batch" => {
+            let args: (Vec<ToolInput<Json>>,) = from_reader(req.params.as_slice())
+                .map_err(|err| format!("failed to decode params: {err:?}"))?;
+            let res = engine
+                .tool_call_batch(caller, args.0)
+                .await
+                .map_err(|err| format!("failed to call tools: {err:?}"))?;
+            let res: Vec<Result<ToolOutput<Json>, String>> = res
+                .into_iter()
+                .map(|r| r.map_err(|err| format!("failed to call tool: {err:?}")))
+                .collect();
+            Ok(to_cbor_bytes(&res).into())
+        }
+        "get_artifact" => {
+            let args: (String,) = from_reader(req.params.as_slice())
+                .map_err(|err| format!("failed to decode params: {err:?}"))?;
+            let res = engine
+                .get_artifact(caller, &args.0)
+                .await
+                .map_err(|err| format!("failed to get artifact: {err:?}"))?;
+            Ok(to_cbor_bytes(&ByteBufB64(res)).into())
+        }
         "information" => {
             let res = engine.information();
             Ok(to_cbor_bytes(&res).into())
         }
-        method => Err(format!(
-            "{method} on engine {} not implemented",
-            id.to_text()
-        )),
+        "status" => {
+            if !engine.whoami(caller).is_manager {
+                return Err("caller is not a manager".to_string().into());
+            }
+            let res = server_status(app);
+            Ok(to_cbor_bytes(&res).into())
+        }
+        "identities" => {
+            let res = engine
+                .identities()
+                .await
+                .map_err(|err| format!("failed to get identities: {err:?}"))?;
+            Ok(to_cbor_bytes(&res).into())
+        }
+        "cancel" => {
+            let args: (String,) = from_reader(req.params.as_slice())
+                .map_err(|err| format!("failed to decode params: {err:?}"))?;
+            let token = app.runs.lock().unwrap().get(&(caller, args.0)).cloned();
+            let res = match token {
+                Some(token) => {
+                    token.cancel();
+                    true
+                }
+                None => false,
+            };
+            Ok(to_cbor_bytes(&res).into())
+        }
+        "whoami" => {
+            let res = engine.whoami(caller);
+            Ok(to_cbor_bytes(&res).into())
+        }
+        "set_visibility" => {
+            let args: (String,) = from_reader(req.params.as_slice())
+                .map_err(|err| format!("failed to decode params: {err:?}"))?;
+            engine
+                .set_visibility(&caller, &args.0)
+                .await
+                .map_err(|err| format!("failed to set visibility: {err:?}"))?;
+            Ok(to_cbor_bytes(&()).into())
+        }
+        "set_log_level" => {
+            let args: (String,) = from_reader(req.params.as_slice())
+                .map_err(|err| format!("failed to decode params: {err:?}"))?;
+            engine
+                .set_log_level(&caller, &args.0)
+                .map_err(|err| format!("failed to set log level: {err:?}"))?;
+            Ok(to_cbor_bytes(&()).into())
+        }
+        "set_default_agent" => {
+            let args: (String,) = from_reader(req.params.as_slice())
+                .map_err(|err| format!("failed to decode params: {err:?}"))?;
+            engine
+                .set_default_agent(&caller, &args.0)
+                .map_err(|err| format!("failed to set default agent: {err:?}"))?;
+            Ok(to_cbor_bytes(&()).into())
+        }
+        "describe" => {
+            let args: (String,) = from_reader(req.params.as_slice())
+                .map_err(|err| format!("failed to decode params: {err:?}"))?;
+            let res = engine.describe(&args.0).ok_or_else(|| RunError {
+                status: StatusCode::NOT_FOUND,
+                message: format!("agent or tool {} not found", args.0),
+            })?;
+            Ok(to_cbor_bytes(&res).into())
+        }
+        method => Err(format!("{method} on engine {} not implemented", id.to_text()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anda_engine::engine::{AgentInfo, EchoEngineInfo, EngineBuilder};
+
+    async fn app_state() -> AppState {
+        let agent = EchoEngineInfo::new(AgentInfo {
+            handle: "echo".to_string(),
+            name: "Echo".to_string(),
+            description: "Echoes the engine information".to_string(),
+            endpoint: "https://localhost:8443/default".to_string(),
+            handle_canister: None,
+            protocols: BTreeMap::new(),
+            payments: Default::default(),
+            provider: None,
+        });
+        let engine = EngineBuilder::new()
+            .register_agent(agent)
+            .unwrap()
+            .build("echo".to_string())
+            .await
+            .unwrap();
+        let id = engine.id();
+        AppState {
+            engines: Arc::new(BTreeMap::from([(id, engine)])),
+            default_engine: id,
+            start_time_ms: unix_ms(),
+            app_name: "test".to_string(),
+            app_version: "0.1.0".to_string(),
+            git_commit: "",
+            build_time_ms: 0,
+            runs: Arc::new(Default::default()),
+            database_status: None,
+            request_stats: Arc::new(RequestStats::new(60)),
+        }
+    }
+
+    async fn call(app: &AppState, method: &str, params: Vec<u8>) -> (StatusCode, RPCResponse) {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::ACCEPT,
+            http::HeaderValue::from_static("application/cbor"),
+        );
+        let req = RPCRequest {
+            method: method.to_string(),
+            params: params.into(),
+        };
+        let res = anda_engine(
+            State(app.clone()),
+            headers,
+            Path("default".to_string()),
+            ContentWithSHA3::CBOR(req, [0u8; 32]),
+        )
+        .await
+        .into_response();
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, from_reader(&body[..]).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_not_found_status() {
+        let app = app_state().await;
+
+        let input = ToolInput::new("missing".to_string(), Json::Null);
+        let (status, res) = call(&app, "tool_call", to_cbor_bytes(&(input,))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            res.unwrap_err(),
+            "failed to call tool: tool missing not found"
+        );
+
+        let (status, res) = call(&app, "describe", to_cbor_bytes(&("missing",))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(res.unwrap_err(), "agent or tool missing not found");
+
+        // requests that ran and failed are responded with 200 OK
+        let (status, res) = call(&app, "unknown", to_cbor_bytes(&())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(res.unwrap_err().contains("not implemented"));
+
+        let (status, res) = call(&app, "describe", to_cbor_bytes(&("echo",))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(res.is_ok());
+        assert_eq!(app.request_stats.snapshot(unix_ms()), (4, 3));
+    }
+
+    #[test]
+    fn test_request_stats() {
+        let stats = RequestStats::new(10);
+        assert_eq!(stats.snapshot(0), (0, 0));
+
+        stats.record(1_000, false);
+        stats.record(1_500, true);
+        stats.record(2_000, false);
+        stats.record(5_999, true);
+        assert_eq!(stats.buckets.lock().unwrap().len(), 3);
+        assert_eq!(stats.snapshot(6_000), (4, 2));
+
+        // the buckets of seconds 1 and 2 leave the window at 11s and 12s
+        assert_eq!(stats.snapshot(10_999), (4, 2));
+        assert_eq!(stats.snapshot(11_000), (2, 1));
+        assert_eq!(stats.snapshot(12_000), (1, 1));
+        assert_eq!(stats.snapshot(16_000), (0, 0));
+        assert!(stats.buckets.lock().unwrap().is_empty());
+
+        assert_eq!(RequestStats::new(0).window_secs, 1);
+    }
+
+    #[tokio::test]
+    async fn test_server_status() {
+        let mut app = app_state().await;
+        app.database_status = Some(Arc::new(|| {
+            vec![DatabaseStatus {
+                name: "db".to_string(),
+                collections: 3,
+            }]
+        }));
+
+        let (_, res) = call(&app, "describe", to_cbor_bytes(&("echo",))).await;
+        assert!(res.is_ok());
+        let (_, res) = call(&app, "describe", to_cbor_bytes(&("missing",))).await;
+        assert!(res.is_err());
+
+        // a stream counts as one request
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::ACCEPT,
+            http::HeaderValue::from_static("application/cbor"),
+        );
+        let input = ToolInput::new("missing".to_string(), Json::Null);
+        let req = RPCRequest {
+            method: "tool_call".to_string(),
+            params: to_cbor_bytes(&(input,)).into(),
+        };
+        let res = crate::stream::stream_engine(
+            State(app.clone()),
+            headers,
+            Path("default".to_string()),
+            ContentWithSHA3::CBOR(req, [0u8; 32]),
+        )
+        .await
+        .into_response();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).starts_with(r#"{"error":"#));
+
+        let status = server_status(&app);
+        assert_eq!(status.requests, 3);
+        assert_eq!(status.errors, 2);
+        assert!((status.error_rate - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(status.engines.len(), 1);
+        assert_eq!(status.databases.len(), 1);
+        assert_eq!(status.databases[0].name, "db");
+        assert_eq!(status.databases[0].collections, 3);
+    }
+
+    #[test]
+    fn test_cbor_to_json() {
+        use ciborium::Value;
+
+        let val = Value::Map(vec![
+            (Value::Text("bytes".into()), Value::Bytes(vec![1, 2, 3])),
+            // a bignum
+            (
+                Value::Text("tagged".into()),
+                Value::Tag(2, Box::new(Value::Bytes(vec![1, 0]))),
+            ),
+            (Value::Integer(1.into()), Value::Bool(true)),
+            (Value::Bytes(vec![1, 2, 3]), Value::Null),
+            (Value::Array(vec![Value::Integer(2.into())]), Value::Null),
+            (
+                Value::Text("ints".into()),
+                Value::Array(vec![
+                    Value::Integer((-1).into()),
+                    Value::Integer(u64::MAX.into()),
+                    Value::Integer(ciborium::value::Integer::try_from(-(1i128 << 64)).unwrap()),
+                ]),
+            ),
+            (
+                Value::Text("floats".into()),
+                Value::Array(vec![Value::Float(1.5), Value::Float(f64::NAN)]),
+            ),
+        ]);
+        assert_eq!(
+            cbor_to_json(val),
+            serde_json::json!({
+                "bytes": "AQID",
+                "tagged": "AQA=",
+                "1": true,
+                "AQID": null,
+                "[2]": null,
+                "ints": [-1, u64::MAX, "-18446744073709551616"],
+                "floats": [1.5, null],
+            })
+        );
+
+        let res = json_response(Ok(to_cbor_bytes(&ByteBufB64(vec![1, 2, 3])).into()));
+        assert_eq!(res, Ok(Json::String("AQID".to_string())));
+        assert_eq!(
+            json_response(Err("failed".to_string())),
+            Err("failed".to_string())
+        );
+        assert!(json_response(Ok(vec![0xff].into())).is_err());
     }
 }
@@ -1,19 +1,27 @@
 use anda_core::BoxError;
 use anda_engine::engine::Engine;
-use axum::{Router, routing};
+use axum::{Router, http::StatusCode, routing};
 use candid::Principal;
-use std::{collections::BTreeMap, future::Future, net::SocketAddr, sync::Arc};
+use std::{collections::BTreeMap, future::Future, net::SocketAddr, sync::Arc, time::Duration};
 use structured_logger::unix_ms;
 use tokio::signal;
 use tokio_util::sync::CancellationToken;
+use tower_http::timeout::TimeoutLayer;
 
 mod handler;
+pub mod stream;
 mod types;
+pub mod ws;
 
 use handler::*;
 
+pub use handler::{DatabaseStatusFn, REQUEST_ID_HEADER};
+pub use types::DatabaseStatus;
+
 const APP_NAME: &str = env!("CARGO_PKG_NAME");
 const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+const GIT_COMMIT: &str = env!("ANDA_GIT_COMMIT");
+const BUILD_TIME_MS: &str = env!("ANDA_BUILD_TIME_MS");
 
 pub struct ServerBuilder {
     app_name: String,
@@ -22,6 +30,10 @@ pub struct ServerBuilder {
     origin: String,
     engines: BTreeMap<Principal, Engine>,
     default_engine: Option<Principal>,
+    request_timeout: Option<Duration>,
+    base_path: String,
+    database_status: Option<Arc<DatabaseStatusFn>>,
+    status_window: Duration,
 }
 
 impl Default for ServerBuilder {
@@ -42,6 +54,10 @@ impl ServerBuilder {
             origin: "https://localhost:8443".to_string(),
             engines: BTreeMap::new(),
             default_engine: None,
+            request_timeout: None,
+            base_path: String::new(),
+            database_status: None,
+            status_window: Duration::from_secs(300),
         }
     }
 
@@ -65,15 +81,48 @@ impl ServerBuilder {
         self
     }
 
+    /// Sets the timeout for each request, a request that takes longer
+    /// is aborted with 504 Gateway Timeout. No timeout by default.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Mounts all routes under a path prefix, e.g. "/anda" serves the engines at
+    /// "/anda/{id}" behind a reverse proxy with path-based routing.
+    /// The engine endpoints include the prefix. Routes are mounted at "/" by default.
+    pub fn with_base_path(mut self, base_path: String) -> Self {
+        let path = base_path.trim_matches('/');
+        self.base_path = if path.is_empty() {
+            String::new()
+        } else {
+            format!("/{path}")
+        };
+        self
+    }
+
+    /// Sets the function that reports the status of the databases in the `status` RPC.
+    pub fn with_database_status(
+        mut self,
+        f: impl Fn() -> Vec<DatabaseStatus> + Send + Sync + 'static,
+    ) -> Self {
+        self.database_status = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets the window of the request and error counts reported by the `status` RPC,
+    /// 5 minutes by default.
+    pub fn with_status_window(mut self, window: Duration) -> Self {
+        self.status_window = window;
+        self
+    }
+
+    /// Registers the engines served, their endpoints are set to "{origin}{base_path}/{id}".
     pub fn with_engines(
         mut self,
-        mut engines: BTreeMap<Principal, Engine>,
+        engines: BTreeMap<Principal, Engine>,
         default_engine: Option<Principal>,
     ) -> Self {
-        for (id, engine) in engines.iter_mut() {
-            engine.info_mut().endpoint = format!("{}/{}", self.origin, id.to_text());
-        }
-
         self.engines = engines;
         self.default_engine = default_engine;
         self
@@ -86,28 +135,58 @@ impl ServerBuilder {
         if self.engines.is_empty() {
             return Err("no engines registered".into());
         }
+        if self.base_path.contains(['{', '}', '*']) {
+            return Err(format!("invalid base path: {:?}", self.base_path).into());
+        }
+
+        let mut engines = self.engines;
+        for (id, engine) in engines.iter_mut() {
+            engine.info_mut().endpoint =
+                format!("{}{}/{}", self.origin, self.base_path, id.to_text());
+        }
 
         let default_engine = self
             .default_engine
-            .unwrap_or_else(|| *self.engines.keys().next().unwrap());
-        if !self.engines.contains_key(&default_engine) {
+            .unwrap_or_else(|| *engines.keys().next().unwrap());
+        if !engines.contains_key(&default_engine) {
             return Err("default engine not found".into());
         }
 
         let state = AppState {
-            engines: Arc::new(self.engines),
+            engines: Arc::new(engines),
             default_engine,
             start_time_ms: unix_ms(),
+            app_name: self.app_name.clone(),
+            app_version: self.app_version.clone(),
+            git_commit: GIT_COMMIT,
+            build_time_ms: BUILD_TIME_MS.parse().unwrap_or_default(),
+            runs: Arc::new(Default::default()),
+            database_status: self.database_status,
+            request_stats: Arc::new(RequestStats::new(self.status_window.as_secs())),
         };
-        let app = Router::new()
+        let routes = Router::new()
             .route("/", routing::get(get_information))
+            .route("/.well-known/information", routing::get(get_information))
             .route("/.well-known/agents", routing::get(get_information))
             .route(
                 "/.well-known/agents/{id}",
                 routing::get(get_engine_information),
             )
+            .route("/ws/{id}", routing::get(ws::ws_engine))
+            .route("/stream/{id}", routing::post(stream::stream_engine))
             .route("/{*id}", routing::post(anda_engine))
             .with_state(state);
+        let mut app = if self.base_path.is_empty() {
+            routes
+        } else {
+            Router::new().nest(&self.base_path, routes)
+        };
+        if let Some(timeout) = self.request_timeout {
+            app = app.layer(TimeoutLayer::with_status_code(
+                StatusCode::GATEWAY_TIMEOUT,
+                timeout,
+            ));
+        }
 
         let addr: SocketAddr = self.addr.parse()?;
         let listener = create_reuse_port_listener(addr).await?;
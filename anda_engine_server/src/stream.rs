@@ -0,0 +1,349 @@
+//! NDJSON streaming of paginated tool results.
+//!
+//! A client posts a signed `tool_call` RPC request to `POST /stream/{engine_id}`, the same
+//! request it would post to the engine endpoint. The server calls the tool page by page,
+//! feeding the `next_cursor` of each page back as the `cursor` argument of the next call,
+//! and writes every item of each page's `result` as one JSON line (a non-array result is
+//! written as a single line). For example, streaming the messages of a thread:
+//!
+//! ```json
+//! {"name": "message_api", "args": {"type": "list", "thread_id": 1, "cursor": null, "limit": 100}}
+//! ```
+//!
+//! The stream ends when a page has no `next_cursor`. If a call fails, the last line is
+//! `{"error": "..."}`. Dropping the connection stops the paging. A finished stream counts as
+//! one request in the `status` RPC.
+
+use anda_core::{Json, ToolInput};
+use anda_engine::{engine::Engine, unix_ms};
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use candid::Principal;
+use ciborium::from_reader;
+use futures_util::stream;
+use ic_tee_agent::{RPCRequest, http::ContentWithSHA3};
+use std::convert::Infallible;
+
+use crate::handler::{AppState, verify_caller};
+
+/// POST /stream/{id}
+pub async fn stream_engine(
+    State(app): State<AppState>,
+    headers: http::HeaderMap,
+    Path(id): Path<String>,
+    ct: ContentWithSHA3<RPCRequest>,
+) -> impl IntoResponse {
+    let id = match app.engine_id(&id) {
+        Ok(id) => id,
+        Err(err) => return err.into_response(),
+    };
+
+    let engine = match app.engines.get(&id) {
+        Some(engine) => engine.clone(),
+        None => {
+            app.request_stats.record(unix_ms(), true);
+            return (
+                StatusCode::NOT_FOUND,
+                format!("engine {} not found", id.to_text()),
+            )
+                .into_response();
+        }
+    };
+
+    let (req, hash) = match &ct {
+        ContentWithSHA3::CBOR(req, hash) => (req, hash),
+        ContentWithSHA3::JSON(req, hash) => (req, hash),
+    };
+
+    let caller = verify_caller(&headers, Some(id), Some(hash.as_slice()));
+
+    if req.method != "tool_call" {
+        app.request_stats.record(unix_ms(), true);
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("method {:?} can not be streamed", req.method),
+        )
+            .into_response();
+    }
+    let args: (ToolInput<Json>,) = match from_reader(req.params.as_slice()) {
+        Ok(args) => args,
+        Err(err) => {
+            app.request_stats.record(unix_ms(), true);
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("failed to decode params: {err:?}"),
+            )
+                .into_response();
+        }
+    };
+
+    log::info!(
+        method = "stream_tool_call",
+        agent = id.to_text(),
+        caller = caller.to_text();
+        "anda_engine",
+    );
+    // a stream is counted as one request when it ends, failed if it ends with an error line
+    let stats = app.request_stats.clone();
+    let body = Body::from_stream(stream::unfold(Some(args.0), move |input| {
+        let engine = engine.clone();
+        let stats = stats.clone();
+        async move {
+            let input = input?;
+            let (lines, next) = match next_page(&engine, caller, input).await {
+                Ok(v) => v,
+                Err(err) => {
+                    stats.record(unix_ms(), true);
+                    return Some((
+                        Ok::<_, Infallible>(format!("{}\n", serde_json::json!({ "error": err }))),
+                        None,
+                    ));
+                }
+            };
+            if next.is_none() {
+                stats.record(unix_ms(), false);
+            }
+            Some((Ok(lines), next))
+        }
+    }));
+
+    ([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response()
+}
+
+/// Calls the tool for one page, returns the NDJSON lines of the page and the input for the
+/// next page if there is one.
+async fn next_page(
+    engine: &Engine,
+    caller: Principal,
+    input: ToolInput<Json>,
+) -> Result<(String, Option<ToolInput<Json>>), String> {
+    let res = engine
+        .tool_call(caller, input.clone())
+        .await
+        .map_err(|err| format!("failed to call tool: {err:?}"))?;
+    let mut output = res.output;
+    if let Some(error) = output.get("error") {
+        return Err(format!("tool returned an error: {error}"));
+    }
+
+    let mut lines = String::new();
+    match output.get_mut("result").map(Json::take) {
+        Some(Json::Array(items)) => {
+            for item in items {
+                lines.push_str(&item.to_string());
+                lines.push('\n');
+            }
+        }
+        Some(result) => {
+            lines.push_str(&result.to_string());
+            lines.push('\n');
+        }
+        None => return Err("tool output has no result".to_string()),
+    }
+
+    let next = match output.get("next_cursor").and_then(Json::as_str) {
+        // a tool that returns the cursor it was given would never end the stream
+        Some(cursor) if input.args.get("cursor").and_then(Json::as_str) == Some(cursor) => None,
+        Some(cursor) => {
+            let mut next = input;
+            match next.args.as_object_mut() {
+                Some(args) => {
+                    args.insert("cursor".to_string(), cursor.into());
+                }
+                None => return Err("tool args must be an object to be paged".to_string()),
+            }
+            Some(next)
+        }
+        None => None,
+    };
+    Ok((lines, next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::RequestStats;
+    use anda_core::{BoxError, FunctionDefinition, Resource, Tool, ToolOutput};
+    use anda_engine::{
+        context::BaseCtx,
+        engine::{EngineBuilder, PingAgent},
+        unix_ms,
+    };
+    use ic_cose_types::to_cbor_bytes;
+    use serde_json::json;
+    use std::{collections::BTreeMap, sync::Arc};
+
+    /// Pages over 1..=5, a "stuck" cursor returns itself and other cursors fail.
+    struct PageTool;
+
+    impl Tool<BaseCtx> for PageTool {
+        type Args = Json;
+        type Output = Json;
+
+        fn name(&self) -> String {
+            "pages".to_string()
+        }
+
+        fn description(&self) -> String {
+            "Returns pages of numbers".to_string()
+        }
+
+        fn definition(&self) -> FunctionDefinition {
+            FunctionDefinition {
+                name: self.name(),
+                description: self.description(),
+                parameters: json!({"type": "object"}),
+                strict: None,
+                examples: Vec::new(),
+            }
+        }
+
+        fn allow_anonymous(&self) -> bool {
+            true
+        }
+
+        async fn call(
+            &self,
+            _ctx: BaseCtx,
+            args: Json,
+            _resources: Vec<Resource>,
+        ) -> Result<ToolOutput<Json>, BoxError> {
+            let output = match args.get("cursor").and_then(Json::as_str) {
+                None => json!({"result": [1, 2], "next_cursor": "2"}),
+                Some("2") => json!({"result": [3, 4], "next_cursor": "4"}),
+                Some("4") => json!({"result": 5}),
+                Some("stuck") => json!({"result": [0], "next_cursor": "stuck"}),
+                Some(cursor) => return Err(format!("invalid cursor {cursor:?}").into()),
+            };
+            Ok(ToolOutput::new(output))
+        }
+    }
+
+    async fn engine() -> Engine {
+        EngineBuilder::for_test()
+            .register_tool(PageTool)
+            .unwrap()
+            .export_tools(vec!["pages".to_string()])
+            .register_agent(PingAgent)
+            .unwrap()
+            .build(PingAgent::NAME.to_string())
+            .await
+            .unwrap()
+    }
+
+    async fn stream_lines(app: &AppState, args: Json) -> Vec<Json> {
+        let input = ToolInput::new("pages".to_string(), args);
+        let req = RPCRequest {
+            method: "tool_call".to_string(),
+            params: to_cbor_bytes(&(input,)).into(),
+        };
+        let res = stream_engine(
+            State(app.clone()),
+            http::HeaderMap::new(),
+            Path("default".to_string()),
+            ContentWithSHA3::CBOR(req, [0u8; 32]),
+        )
+        .await
+        .into_response();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_next_page() {
+        let engine = engine().await;
+        let caller = Principal::anonymous();
+
+        let (lines, next) = next_page(
+            &engine,
+            caller,
+            ToolInput::new("pages".to_string(), json!({"cursor": null})),
+        )
+        .await
+        .unwrap();
+        assert_eq!(lines, "1\n2\n");
+        assert_eq!(next.unwrap().args, json!({"cursor": "2"}));
+
+        // a non-array result is one line and ends the paging
+        let (lines, next) = next_page(
+            &engine,
+            caller,
+            ToolInput::new("pages".to_string(), json!({"cursor": "4"})),
+        )
+        .await
+        .unwrap();
+        assert_eq!(lines, "5\n");
+        assert!(next.is_none());
+
+        // the same cursor ends the paging
+        let (lines, next) = next_page(
+            &engine,
+            caller,
+            ToolInput::new("pages".to_string(), json!({"cursor": "stuck"})),
+        )
+        .await
+        .unwrap();
+        assert_eq!(lines, "0\n");
+        assert!(next.is_none());
+
+        let err = next_page(
+            &engine,
+            caller,
+            ToolInput::new("pages".to_string(), json!({"cursor": "bad"})),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.contains("invalid cursor"), "{err}");
+
+        let id = engine.id();
+        let app = AppState {
+            engines: Arc::new(BTreeMap::from([(id, engine)])),
+            default_engine: id,
+            start_time_ms: unix_ms(),
+            app_name: "test".to_string(),
+            app_version: "0.1.0".to_string(),
+            git_commit: "",
+            build_time_ms: 0,
+            runs: Arc::new(Default::default()),
+            database_status: None,
+            request_stats: Arc::new(RequestStats::new(60)),
+        };
+        assert_eq!(
+            stream_lines(&app, json!({})).await,
+            vec![json!(1), json!(2), json!(3), json!(4), json!(5)]
+        );
+        assert_eq!(
+            stream_lines(&app, json!({"cursor": "stuck"})).await,
+            vec![json!(0)]
+        );
+        let lines = stream_lines(&app, json!({"cursor": "bad"})).await;
+        assert_eq!(lines.len(), 1);
+        assert!(
+            lines[0]["error"]
+                .as_str()
+                .unwrap()
+                .contains("invalid cursor")
+        );
+
+        // args that are not an object can not be paged, the error is the last line
+        let lines = stream_lines(&app, json!(null)).await;
+        assert_eq!(lines.len(), 1);
+        assert!(
+            lines[0]["error"]
+                .as_str()
+                .unwrap()
+                .contains("must be an object")
+        );
+    }
+}
@@ -1,6 +1,7 @@
-use anda_engine::engine::AgentInfo;
+use anda_engine::engine::{AgentInfo, EngineStatus};
 use candid::Principal;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AppInformation {
@@ -8,4 +9,83 @@ pub struct AppInformation {
     pub default_engine: Principal,
     pub caller: Principal,
     pub start_time_ms: u64,
+    #[serde(default)]
+    pub app_name: String,
+    #[serde(default)]
+    pub app_version: String,
+    /// The git commit hash the server was built from.
+    #[serde(default)]
+    pub git_commit: String,
+    /// The unix timestamp in milliseconds when the server was built.
+    #[serde(default)]
+    pub build_time_ms: u64,
+    /// The number of agents registered in each engine.
+    #[serde(default)]
+    pub agent_counts: BTreeMap<Principal, u64>,
+}
+
+/// A health snapshot of the server, returned by the `status` RPC.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ServerStatus {
+    pub app_name: String,
+    pub app_version: String,
+    pub start_time_ms: u64,
+    pub uptime_ms: u64,
+    /// The status of each engine.
+    pub engines: BTreeMap<Principal, EngineStatus>,
+    /// The databases registered with the server.
+    pub databases: Vec<DatabaseStatus>,
+    /// The length in seconds of the window the request counts cover.
+    pub window_secs: u64,
+    /// The number of RPC requests in the window.
+    pub requests: u64,
+    /// The number of failed RPC requests in the window.
+    pub errors: u64,
+    /// The ratio of failed requests in the window, 0 if there are no requests.
+    pub error_rate: f64,
+}
+
+/// The status of a database, reported by the `status` RPC.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DatabaseStatus {
+    pub name: String,
+    /// The number of collections in the database.
+    pub collections: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_information_without_build_metadata() {
+        // the payload of servers without build metadata
+        let info: AppInformation = serde_json::from_value(serde_json::json!({
+            "engines": [],
+            "default_engine": "2vxsx-fae",
+            "caller": "2vxsx-fae",
+            "start_time_ms": 1,
+            "app_name": "anda",
+            "app_version": "0.1.0",
+        }))
+        .unwrap();
+        assert_eq!(info.git_commit, "");
+        assert_eq!(info.build_time_ms, 0);
+        assert!(info.agent_counts.is_empty());
+    }
+
+    #[test]
+    fn test_app_information_without_app_name() {
+        // the payload of servers before the app name and version were added
+        let info: AppInformation = serde_json::from_value(serde_json::json!({
+            "engines": [],
+            "default_engine": "2vxsx-fae",
+            "caller": "2vxsx-fae",
+            "start_time_ms": 1,
+        }))
+        .unwrap();
+        assert_eq!(info.app_name, "");
+        assert_eq!(info.app_version, "");
+        assert_eq!(info.start_time_ms, 1);
+    }
 }
@@ -0,0 +1,483 @@
+//! WebSocket transport for bidirectional agent sessions.
+//!
+//! A client connects to `GET /ws/{engine_id}` and exchanges binary CBOR frames with the engine.
+//! Each client frame is a [`WsFrame`] carrying a CBOR-encoded [`WsRequest`] payload, signed by an
+//! optional [`SignedEnvelope`] over the SHA3-256 digest of the payload. Frames without a valid
+//! envelope are processed as the anonymous caller.
+//!
+//! The session runs one agent at a time. While an agent is running, the server streams its
+//! progress as [`WsResponse::Delta`] and [`WsResponse::Step`] frames, and the client can send
+//! [`WsRequest::Cancel`] to interrupt it. Follow-up messages can be sent once the run has
+//! finished. The server replies with CBOR-encoded [`WsResponse`] frames.
+
+use anda_core::{AgentInput, AgentOutput};
+use anda_engine::{context::AgentEvent, engine::Engine, unix_ms};
+use axum::{
+    extract::{
+        Path, State,
+        ws::{Message, WebSocketUpgrade},
+    },
+    http::StatusCode,
+    response::IntoResponse,
+};
+use candid::Principal;
+use ciborium::from_reader;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use ic_auth_verifier::{
+    envelope::{ANONYMOUS_PRINCIPAL, SignedEnvelope},
+    sha3_256, unix_timestamp,
+};
+use ic_cose_types::to_cbor_bytes;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::mpsc::unbounded_channel;
+
+use crate::handler::{AppState, RequestStats};
+
+/// A signed client frame.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WsFrame {
+    /// CBOR-encoded [`WsRequest`].
+    #[serde(with = "serde_bytes")]
+    pub payload: Vec<u8>,
+    /// Signature over the SHA3-256 digest of the payload.
+    pub envelope: Option<SignedEnvelope>,
+}
+
+/// A request from the client.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum WsRequest {
+    /// Runs an agent, fails if another run is in progress.
+    AgentRun(AgentInput),
+    /// Cancels the agent run in progress.
+    Cancel,
+}
+
+/// A response from the server.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum WsResponse {
+    /// Text content generated by the model in the agent run in progress.
+    Delta(String),
+    /// The output of an intermediate step of the agent run in progress, sent after its deltas.
+    Step(AgentOutput),
+    /// The output of a finished agent run.
+    Output(AgentOutput),
+    /// The agent run was cancelled by the client.
+    Cancelled,
+    /// An error occurred while processing a request.
+    Error(String),
+}
+
+/// GET /ws/{id}
+pub async fn ws_engine(
+    State(app): State<AppState>,
+    Path(id): Path<String>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let id = match app.engine_id(&id) {
+        Ok(id) => id,
+        Err(err) => return err.into_response(),
+    };
+
+    match app.engines.get(&id) {
+        Some(engine) => {
+            let engine = engine.clone();
+            let stats = app.request_stats.clone();
+            ws.on_upgrade(move |socket| {
+                let (tx, rx) = socket.split();
+                ws_session(tx, rx, engine, id, stats)
+            })
+        }
+        None => (
+            StatusCode::NOT_FOUND,
+            format!("engine {} not found", id.to_text()),
+        )
+            .into_response(),
+    }
+}
+
+/// Runs a session over the sending and receiving halves of a socket. Every response except
+/// the streamed deltas and steps counts as one request in the `status` RPC.
+async fn ws_session<T, R, E>(
+    mut tx: T,
+    mut rx: R,
+    engine: Engine,
+    id: Principal,
+    stats: Arc<RequestStats>,
+) where
+    T: Sink<Message> + Unpin,
+    R: Stream<Item = Result<Message, E>> + Unpin,
+{
+    let tx = &mut tx;
+    let respond = async |tx: &mut T, res: &WsResponse| {
+        stats.record(unix_ms(), matches!(res, WsResponse::Error(_)));
+        send(tx, res).await
+    };
+    while let Some(Ok(msg)) = rx.next().await {
+        let (caller, req) = match decode_frame(msg, id) {
+            Some(Ok(v)) => v,
+            Some(Err(err)) => {
+                if respond(tx, &WsResponse::Error(err)).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+            None => continue,
+        };
+
+        let input = match req {
+            WsRequest::AgentRun(input) => input,
+            WsRequest::Cancel => {
+                let res = WsResponse::Error("no agent run in progress".to_string());
+                if respond(tx, &res).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+
+        log::info!(
+            method = "ws_agent_run",
+            agent = id.to_text(),
+            caller = caller.to_text();
+            "anda_engine",
+        );
+        let (events_tx, mut events) = unbounded_channel();
+        let run = engine.agent_run_streaming(caller, input, events_tx);
+        tokio::pin!(run);
+        let res = loop {
+            tokio::select! {
+                // events are sent before the run's result
+                biased;
+                Some(event) = events.recv() => {
+                    if send(tx, &event.into()).await.is_err() {
+                        return;
+                    }
+                }
+                res = &mut run => {
+                    break match res {
+                        Ok(output) => WsResponse::Output(output),
+                        Err(err) => WsResponse::Error(format!("failed to run agent: {err:?}")),
+                    };
+                }
+                msg = rx.next() => {
+                    let msg = match msg {
+                        Some(Ok(msg)) => msg,
+                        // the client is gone, dropping the run cancels it
+                        _ => return,
+                    };
+                    let res = match decode_frame(msg, id) {
+                        Some(Ok((c, WsRequest::Cancel))) if c == caller => {
+                            break WsResponse::Cancelled;
+                        }
+                        Some(Ok((_, WsRequest::Cancel))) => {
+                            WsResponse::Error("caller does not own the agent run".to_string())
+                        }
+                        Some(Ok((_, WsRequest::AgentRun(_)))) => {
+                            WsResponse::Error("an agent run is in progress".to_string())
+                        }
+                        Some(Err(err)) => WsResponse::Error(err),
+                        None => continue,
+                    };
+                    if respond(tx, &res).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        };
+
+        // the last events may be sent when the run finishes
+        if !matches!(res, WsResponse::Cancelled) {
+            while let Ok(event) = events.try_recv() {
+                if send(tx, &event.into()).await.is_err() {
+                    return;
+                }
+            }
+        }
+        if respond(tx, &res).await.is_err() {
+            return;
+        }
+    }
+}
+
+impl From<AgentEvent> for WsResponse {
+    fn from(event: AgentEvent) -> Self {
+        match event {
+            AgentEvent::Delta(delta) => WsResponse::Delta(delta),
+            AgentEvent::Step(output) => WsResponse::Step(output),
+        }
+    }
+}
+
+/// Decodes and verifies a client frame, returns `None` for non-binary messages.
+fn decode_frame(msg: Message, id: Principal) -> Option<Result<(Principal, WsRequest), String>> {
+    match msg {
+        Message::Binary(data) => Some(verify_frame(&data, id)),
+        _ => None,
+    }
+}
+
+fn verify_frame(data: &[u8], id: Principal) -> Result<(Principal, WsRequest), String> {
+    let frame: WsFrame =
+        from_reader(data).map_err(|err| format!("failed to decode frame: {err:?}"))?;
+    let caller = match frame.envelope {
+        Some(se) => {
+            let digest = sha3_256(&frame.payload);
+            match se.verify(
+                unix_timestamp().as_millis() as u64,
+                Some(id),
+                Some(digest.as_slice()),
+            ) {
+                Ok(_) => se.sender(),
+                Err(_) => ANONYMOUS_PRINCIPAL,
+            }
+        }
+        None => ANONYMOUS_PRINCIPAL,
+    };
+    let req: WsRequest = from_reader(frame.payload.as_slice())
+        .map_err(|err| format!("failed to decode request: {err:?}"))?;
+    Ok((caller, req))
+}
+
+async fn send<T: Sink<Message> + Unpin>(tx: &mut T, res: &WsResponse) -> Result<(), T::Error> {
+    tx.send(Message::Binary(to_cbor_bytes(res).into())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anda_core::{
+        Agent, BoxError, BoxPinFut, CompletionFeatures, CompletionRequest, Resource, ToolCall,
+    };
+    use anda_engine::{
+        context::AgentCtx,
+        engine::EngineBuilder,
+        model::{CompletionFeaturesDyn, Model},
+    };
+    use futures::channel::mpsc;
+    use ic_agent::{Identity, identity::BasicIdentity};
+    use std::{convert::Infallible, time::Duration};
+
+    /// Calls a tool in the first step and answers in the second one.
+    struct StepCompleter;
+
+    impl CompletionFeaturesDyn for StepCompleter {
+        fn completion(&self, req: CompletionRequest) -> BoxPinFut<Result<AgentOutput, BoxError>> {
+            let output = if req.prompt.is_empty() {
+                AgentOutput {
+                    content: "done".to_string(),
+                    ..Default::default()
+                }
+            } else {
+                AgentOutput {
+                    content: "thinking".to_string(),
+                    tool_calls: vec![ToolCall {
+                        name: "lookup".to_string(),
+                        args: serde_json::json!({}),
+                        call_id: None,
+                        result: None,
+                        remote_id: None,
+                    }],
+                    ..Default::default()
+                }
+            };
+            Box::pin(futures::future::ready(Ok(output)))
+        }
+    }
+
+    struct StepAgent;
+
+    impl Agent<AgentCtx> for StepAgent {
+        fn name(&self) -> String {
+            "steps".to_string()
+        }
+
+        fn description(&self) -> String {
+            "Runs a completion with a tool call".to_string()
+        }
+
+        async fn run(
+            &self,
+            ctx: AgentCtx,
+            prompt: String,
+            resources: Vec<Resource>,
+        ) -> Result<AgentOutput, BoxError> {
+            let req = CompletionRequest {
+                prompt,
+                ..Default::default()
+            };
+            ctx.completion(req, resources).await
+        }
+    }
+
+    struct SlowAgent;
+
+    impl Agent<AgentCtx> for SlowAgent {
+        fn name(&self) -> String {
+            "slow".to_string()
+        }
+
+        fn description(&self) -> String {
+            "Never finishes in time".to_string()
+        }
+
+        async fn run(
+            &self,
+            _ctx: AgentCtx,
+            _prompt: String,
+            _resources: Vec<Resource>,
+        ) -> Result<AgentOutput, BoxError> {
+            tokio::time::sleep(Duration::from_secs(600)).await;
+            Ok(AgentOutput::default())
+        }
+    }
+
+    struct Client {
+        tx: mpsc::UnboundedSender<Result<Message, Infallible>>,
+        rx: mpsc::UnboundedReceiver<Message>,
+    }
+
+    impl Client {
+        fn send(&self, req: &WsRequest, identity: Option<&BasicIdentity>) {
+            let payload = to_cbor_bytes(req);
+            let envelope = identity
+                .map(|id| SignedEnvelope::sign_digest(id, sha3_256(&payload).to_vec()).unwrap());
+            let frame = to_cbor_bytes(&WsFrame { payload, envelope });
+            self.tx
+                .unbounded_send(Ok(Message::Binary(frame.into())))
+                .unwrap();
+        }
+
+        async fn recv(&mut self) -> WsResponse {
+            match self.rx.next().await.unwrap() {
+                Message::Binary(data) => from_reader(data.as_ref()).unwrap(),
+                msg => panic!("unexpected message {msg:?}"),
+            }
+        }
+    }
+
+    async fn client() -> (Client, Arc<RequestStats>) {
+        let engine = EngineBuilder::for_test()
+            .with_model(Model::with_completer(Arc::new(StepCompleter)))
+            .register_agent(StepAgent)
+            .unwrap()
+            .register_agent(SlowAgent)
+            .unwrap()
+            .export_agents(vec!["steps".to_string(), "slow".to_string()])
+            .build("steps".to_string())
+            .await
+            .unwrap();
+        let (tx, server_rx) = mpsc::unbounded();
+        let (server_tx, rx) = mpsc::unbounded();
+        let stats = Arc::new(RequestStats::new(60));
+        tokio::spawn(ws_session(
+            server_tx,
+            server_rx,
+            engine.clone(),
+            engine.id(),
+            stats.clone(),
+        ));
+        (Client { tx, rx }, stats)
+    }
+
+    #[tokio::test]
+    async fn test_ws_agent_run() {
+        let (mut client, _) = client().await;
+        client.send(&WsRequest::Cancel, None);
+        assert!(
+            matches!(client.recv().await, WsResponse::Error(err) if err == "no agent run in progress")
+        );
+        client
+            .tx
+            .unbounded_send(Ok(Message::Binary(vec![0xff].into())))
+            .unwrap();
+        assert!(
+            matches!(client.recv().await, WsResponse::Error(err) if err.contains("failed to decode frame"))
+        );
+
+        // the deltas and steps are streamed before the output
+        client.send(
+            &WsRequest::AgentRun(AgentInput::new("steps".to_string(), "hi".to_string())),
+            None,
+        );
+        assert!(matches!(client.recv().await, WsResponse::Delta(delta) if delta == "thinking"));
+        match client.recv().await {
+            WsResponse::Step(output) => {
+                assert_eq!(output.content, "thinking");
+                assert!(output.failed_reason.is_none());
+            }
+            res => panic!("unexpected response {res:?}"),
+        }
+        assert!(matches!(client.recv().await, WsResponse::Delta(delta) if delta == "done"));
+        match client.recv().await {
+            WsResponse::Output(output) => {
+                assert_eq!(output.content, "done");
+                assert!(output.failed_reason.is_none());
+                assert_eq!(output.tool_calls[0].name, "lookup");
+            }
+            res => panic!("unexpected response {res:?}"),
+        }
+
+        // the next run can start once the previous one has finished
+        client.send(
+            &WsRequest::AgentRun(AgentInput::new("steps".to_string(), "again".to_string())),
+            None,
+        );
+        let mut frames = 0;
+        loop {
+            frames += 1;
+            if let WsResponse::Output(output) = client.recv().await {
+                assert_eq!(output.content, "done");
+                break;
+            }
+        }
+        assert_eq!(frames, 4);
+    }
+
+    #[tokio::test]
+    async fn test_ws_cancel() {
+        let (mut client, _) = client().await;
+        let owner = BasicIdentity::from_raw_key(&[1u8; 32]);
+        assert_ne!(owner.sender().unwrap(), ANONYMOUS_PRINCIPAL);
+        client.send(
+            &WsRequest::AgentRun(AgentInput::new("slow".to_string(), "hi".to_string())),
+            Some(&owner),
+        );
+
+        client.send(
+            &WsRequest::AgentRun(AgentInput::new("steps".to_string(), "hi".to_string())),
+            Some(&owner),
+        );
+        assert!(
+            matches!(client.recv().await, WsResponse::Error(err) if err == "an agent run is in progress")
+        );
+
+        // only the caller that started the run can cancel it
+        client.send(&WsRequest::Cancel, None);
+        assert!(
+            matches!(client.recv().await, WsResponse::Error(err) if err == "caller does not own the agent run")
+        );
+        let other = BasicIdentity::from_raw_key(&[2u8; 32]);
+        client.send(&WsRequest::Cancel, Some(&other));
+        assert!(
+            matches!(client.recv().await, WsResponse::Error(err) if err == "caller does not own the agent run")
+        );
+        client.send(&WsRequest::Cancel, Some(&owner));
+        assert!(matches!(client.recv().await, WsResponse::Cancelled));
+
+        client.send(&WsRequest::Cancel, Some(&owner));
+        assert!(
+            matches!(client.recv().await, WsResponse::Error(err) if err == "no agent run in progress")
+        );
+        client.send(
+            &WsRequest::AgentRun(AgentInput::new("steps".to_string(), "hi".to_string())),
+            Some(&owner),
+        );
+        loop {
+            if let WsResponse::Output(output) = client.recv().await {
+                assert_eq!(output.content, "done");
+                break;
+            }
+        }
+    }
+}
@@ -19,7 +19,11 @@ use ic_cose_types::{
 };
 use ic_tee_gateway_sdk::crypto;
 use serde::{Serialize, de::DeserializeOwned};
-use std::{sync::Arc, time::Duration};
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 pub use ic_agent::{Agent, Identity};
 
@@ -34,6 +38,7 @@ use anda_engine::APP_USER_AGENT;
 pub struct Client {
     outer_http: reqwest::Client,
     root_secret: [u8; 48],
+    previous_root_secret: Option<PreviousRootSecret>,
     identity: Arc<dyn Identity>,
     agent: Agent,
     cose_canister: Principal,
@@ -45,11 +50,72 @@ pub struct Client {
 pub struct ClientBuilder {
     ic_host: String,
     root_secret: [u8; 48],
+    previous_root_secret: Option<([u8; 48], Duration)>,
     identity: Option<Arc<dyn Identity>>,
     agent: Option<Agent>,
     cose_canister: Principal,
     outer_http: Option<reqwest::Client>,
     allow_http: bool,
+    agent_transport: AgentTransport,
+}
+
+/// Transport settings of the agent built by [`ClientBuilder`] for canister communication.
+/// They are ignored when an agent is set with [`ClientBuilder::with_agent`].
+#[derive(Clone, Debug)]
+pub struct AgentTransport {
+    /// Maximum number of concurrent requests, default is 50, the limit the replica permits per client
+    pub max_concurrent_requests: usize,
+    /// Maximum number of idle connections kept per host, default is unlimited
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle connection is kept in the pool, default is 90 seconds
+    pub pool_idle_timeout: Option<Duration>,
+    /// Interval of HTTP/2 keepalive pings, default is 25 seconds, `None` disables keepalive
+    pub http2_keep_alive_interval: Option<Duration>,
+    /// Timeout of a HTTP/2 keepalive ping, default is 15 seconds
+    pub http2_keep_alive_timeout: Duration,
+    /// Timeout of establishing a connection, default is 10 seconds
+    pub connect_timeout: Duration,
+    /// Timeout of a request, default is 360 seconds
+    pub request_timeout: Duration,
+}
+
+impl Default for AgentTransport {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests: 50,
+            pool_max_idle_per_host: usize::MAX,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            http2_keep_alive_interval: Some(Duration::from_secs(25)),
+            http2_keep_alive_timeout: Duration::from_secs(15),
+            connect_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(360),
+        }
+    }
+}
+
+impl AgentTransport {
+    fn http_client(&self) -> Result<reqwest::Client, BoxError> {
+        let client = reqwest::Client::builder()
+            .use_rustls_tls()
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .http2_keep_alive_interval(self.http2_keep_alive_interval)
+            .http2_keep_alive_timeout(self.http2_keep_alive_timeout)
+            .http2_keep_alive_while_idle(self.http2_keep_alive_interval.is_some())
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.request_timeout)
+            .user_agent(APP_USER_AGENT)
+            .build()?;
+        Ok(client)
+    }
+}
+
+/// A rotated-out root secret that is still accepted for signature verification
+/// until `expires_at`.
+#[derive(Clone)]
+struct PreviousRootSecret {
+    root_secret: [u8; 48],
+    expires_at: SystemTime,
 }
 
 /// Returns a new Ed25519 identity from a 32-byte secret
@@ -69,8 +135,48 @@ pub fn identity_from_pem(path: &str) -> Result<Box<dyn Identity>, BoxError> {
     }
 }
 
+/// The signature scheme of an identity derived from a raw 32-byte secret.
+/// Signed requests from both schemes are accepted by the engine server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SignatureScheme {
+    /// Ed25519, the default scheme of ICP identities.
+    #[default]
+    Ed25519,
+    /// ECDSA over secp256k1, e.g. for Ethereum-style private keys.
+    Secp256k1,
+}
+
+impl FromStr for SignatureScheme {
+    type Err = BoxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ed25519" => Ok(SignatureScheme::Ed25519),
+            "secp256k1" => Ok(SignatureScheme::Secp256k1),
+            _ => Err(format!("invalid signature scheme: {s:?}").into()),
+        }
+    }
+}
+
+/// Returns a new secp256k1 identity from a 32-byte secret
+pub fn identity_from_secp256k1_secret(id_secret: [u8; 32]) -> Result<Box<dyn Identity>, BoxError> {
+    let key = k256::SecretKey::from_slice(&id_secret)
+        .map_err(|err| format!("invalid secp256k1 secret: {err}"))?;
+    Ok(Box::new(Secp256k1Identity::from_private_key(key)))
+}
+
 /// Loads an identity from a 32-byte hex-encoded secret or PEM file
 pub fn load_identity(id_secret_or_path: &str) -> Result<Box<dyn Identity>, BoxError> {
+    load_identity_with(id_secret_or_path, SignatureScheme::Ed25519)
+}
+
+/// Loads an identity from a 32-byte hex-encoded secret (with an optional `0x` prefix)
+/// or PEM file. The scheme only applies to a raw secret, the scheme of a PEM file is
+/// detected from its content.
+pub fn load_identity_with(
+    id_secret_or_path: &str,
+    scheme: SignatureScheme,
+) -> Result<Box<dyn Identity>, BoxError> {
     if id_secret_or_path == "Anonymous" {
         return Ok(Box::new(AnonymousIdentity));
     }
@@ -78,11 +184,17 @@ pub fn load_identity(id_secret_or_path: &str) -> Result<Box<dyn Identity>, BoxEr
     match identity_from_pem(id_secret_or_path) {
         Ok(identity) => Ok(identity),
         Err(_) => {
-            let id_secret = hex::decode(id_secret_or_path)?;
+            let hex_secret = id_secret_or_path
+                .strip_prefix("0x")
+                .unwrap_or(id_secret_or_path);
+            let id_secret = hex::decode(hex_secret)?;
             let id_secret: [u8; 32] = id_secret
                 .try_into()
                 .map_err(|_| format!("invalid id_secret: {id_secret_or_path:?}"))?;
-            Ok(identity_from_secret(id_secret))
+            match scheme {
+                SignatureScheme::Ed25519 => Ok(identity_from_secret(id_secret)),
+                SignatureScheme::Secp256k1 => identity_from_secp256k1_secret(id_secret),
+            }
         }
     }
 }
@@ -92,11 +204,13 @@ impl Default for ClientBuilder {
         Self {
             ic_host: "https://icp-api.io".to_string(),
             root_secret: [0; 48],
+            previous_root_secret: None,
             identity: None,
             agent: None,
             cose_canister: Principal::anonymous(),
             outer_http: None,
             allow_http: false,
+            agent_transport: AgentTransport::default(),
         }
     }
 }
@@ -114,6 +228,16 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets the previous 48-byte root secret after a key rotation.
+    ///
+    /// Signatures made with keys derived from the previous root secret are still
+    /// verified for the `grace` period after the client is built, while all signing
+    /// uses the current root secret.
+    pub fn with_previous_root_secret(mut self, root_secret: [u8; 48], grace: Duration) -> Self {
+        self.previous_root_secret = Some((root_secret, grace));
+        self
+    }
+
     /// Sets the principal of the COSE canister, default is anonymous, which disables COSE operations
     pub fn with_cose_canister(mut self, cose_canister: Principal) -> Self {
         self.cose_canister = cose_canister;
@@ -144,6 +268,12 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets the transport settings of the agent for canister communication
+    pub fn with_agent_transport(mut self, transport: AgentTransport) -> Self {
+        self.agent_transport = transport;
+        self
+    }
+
     pub async fn build(self) -> Result<Client, BoxError> {
         let identity = match self.identity {
             Some(identity) => identity,
@@ -156,7 +286,11 @@ impl ClientBuilder {
                 let agent = Agent::builder()
                     .with_url(self.ic_host.clone())
                     .with_arc_identity(identity.clone())
-                    .with_verify_query_signatures(false);
+                    .with_verify_query_signatures(false)
+                    .with_max_concurrent_requests(
+                        self.agent_transport.max_concurrent_requests.max(1),
+                    )
+                    .with_http_client(self.agent_transport.http_client()?);
 
                 let agent = if self.ic_host.starts_with("https://") {
                     agent.with_background_dynamic_routing().build()?
@@ -191,6 +325,12 @@ impl ClientBuilder {
         Ok(Client {
             outer_http,
             root_secret: self.root_secret,
+            previous_root_secret: self.previous_root_secret.map(|(root_secret, grace)| {
+                PreviousRootSecret {
+                    root_secret,
+                    expires_at: SystemTime::now() + grace,
+                }
+            }),
             identity,
             agent,
             cose_canister: self.cose_canister,
@@ -204,6 +344,18 @@ impl Client {
         ClientBuilder::default()
     }
 
+    /// Returns the root secrets accepted for signature verification:
+    /// the current one, and the previous one if its grace period has not expired.
+    fn verifying_root_secrets(&self) -> Vec<&[u8; 48]> {
+        let mut secrets = vec![&self.root_secret];
+        if let Some(prev) = &self.previous_root_secret
+            && SystemTime::now() < prev.expires_at
+        {
+            secrets.push(&prev.root_secret);
+        }
+        secrets
+    }
+
     pub fn get_principal(&self) -> Principal {
         self.identity
             .sender()
@@ -281,10 +433,15 @@ impl Web3ClientFeatures for Client {
         message: &[u8],
         signature: &[u8],
     ) -> BoxPinFut<Result<(), BoxError>> {
-        let res = crypto::ed25519_public_key(&self.root_secret, derivation_path);
-        Box::pin(futures::future::ready(
-            ed25519_verify(&res.0, message, signature).map_err(|e| e.into()),
-        ))
+        let mut res = Err("no root secret to verify with".into());
+        for root_secret in self.verifying_root_secrets() {
+            let pk = crypto::ed25519_public_key(root_secret, derivation_path.clone());
+            res = ed25519_verify(&pk.0, message, signature).map_err(|e| e.into());
+            if res.is_ok() {
+                break;
+            }
+        }
+        Box::pin(futures::future::ready(res))
     }
 
     /// Gets the public key for Ed25519
@@ -335,10 +492,16 @@ impl Web3ClientFeatures for Client {
         message: &[u8],
         signature: &[u8],
     ) -> BoxPinFut<Result<(), BoxError>> {
-        let res = crypto::secp256k1_public_key(&self.root_secret, derivation_path);
-        Box::pin(futures::future::ready(
-            secp256k1_verify_bip340(res.0.as_slice(), message, signature).map_err(|e| e.into()),
-        ))
+        let mut res = Err("no root secret to verify with".into());
+        for root_secret in self.verifying_root_secrets() {
+            let pk = crypto::secp256k1_public_key(root_secret, derivation_path.clone());
+            res =
+                secp256k1_verify_bip340(pk.0.as_slice(), message, signature).map_err(|e| e.into());
+            if res.is_ok() {
+                break;
+            }
+        }
+        Box::pin(futures::future::ready(res))
     }
 
     /// Signs a message using Secp256k1 ECDSA signature
@@ -383,10 +546,15 @@ impl Web3ClientFeatures for Client {
         message: &[u8],
         signature: &[u8],
     ) -> BoxPinFut<Result<(), BoxError>> {
-        let res = crypto::secp256k1_public_key(&self.root_secret, derivation_path);
-        Box::pin(futures::future::ready(
-            secp256k1_verify_ecdsa(res.0.as_slice(), message, signature).map_err(|e| e.into()),
-        ))
+        let mut res = Err("no root secret to verify with".into());
+        for root_secret in self.verifying_root_secrets() {
+            let pk = crypto::secp256k1_public_key(root_secret, derivation_path.clone());
+            res = secp256k1_verify_ecdsa(pk.0.as_slice(), message, signature).map_err(|e| e.into());
+            if res.is_ok() {
+                break;
+            }
+        }
+        Box::pin(futures::future::ready(res))
     }
 
     /// Gets the compressed SEC1-encoded public key for Secp256k1
@@ -692,3 +860,75 @@ impl CanisterCaller for Client {
         Ok(output)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn client(root_secret: [u8; 48], previous: Option<([u8; 48], Duration)>) -> Client {
+        let agent = Agent::builder()
+            .with_url("http://127.0.0.1:4943")
+            .build()
+            .unwrap();
+        let mut builder = Client::builder()
+            .with_agent(agent)
+            .with_root_secret(root_secret);
+        if let Some((secret, grace)) = previous {
+            builder = builder.with_previous_root_secret(secret, grace);
+        }
+        builder.build().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_previous_root_secret() {
+        let old = client([1; 48], None).await;
+        let path = vec![b"test".to_vec()];
+        let msg = b"hello";
+        let ed25519 = old.ed25519_sign_message(path.clone(), msg).await.unwrap();
+        let bip340 = old
+            .secp256k1_sign_message_bip340(path.clone(), msg)
+            .await
+            .unwrap();
+        // ECDSA verifies a message hash
+        let digest = sha3_256(msg);
+        let ecdsa = old
+            .secp256k1_sign_digest_ecdsa(path.clone(), &digest)
+            .await
+            .unwrap();
+
+        let verify = async |cli: &Client| {
+            [
+                cli.ed25519_verify(path.clone(), msg, &ed25519)
+                    .await
+                    .is_ok(),
+                cli.secp256k1_verify_bip340(path.clone(), msg, &bip340)
+                    .await
+                    .is_ok(),
+                cli.secp256k1_verify_ecdsa(path.clone(), &digest, &ecdsa)
+                    .await
+                    .is_ok(),
+            ]
+        };
+        assert_eq!(verify(&old).await, [true; 3]);
+        assert_eq!(verify(&client([2; 48], None).await).await, [false; 3]);
+        let rotated = client([2; 48], Some(([1; 48], Duration::from_secs(60)))).await;
+        assert_eq!(verify(&rotated).await, [true; 3]);
+        let expired = client([2; 48], Some(([1; 48], Duration::ZERO))).await;
+        assert_eq!(verify(&expired).await, [false; 3]);
+
+        // signing always uses the current root secret
+        let sig = rotated
+            .ed25519_sign_message(path.clone(), msg)
+            .await
+            .unwrap();
+        assert!(old.ed25519_verify(path.clone(), msg, &sig).await.is_err());
+        assert_eq!(
+            rotated.ed25519_public_key(path.clone()).await.unwrap(),
+            client([2; 48], None)
+                .await
+                .ed25519_public_key(path)
+                .await
+                .unwrap()
+        );
+    }
+}
@@ -1,3 +1,5 @@
 pub mod client;
+pub mod rpc;
 
 pub use client::*;
+pub use rpc::*;
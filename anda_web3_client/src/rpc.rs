@@ -0,0 +1,94 @@
+//! Typed wrapper over the signed RPC methods of a remote Anda engine.
+//!
+//! # Usage
+//! ```rust,ignore
+//! let web3 = Client::builder().with_identity(identity).build().await?;
+//! let engine = EngineClient::new(web3, "https://example.com/engine_id".to_string());
+//! let card = engine.information().await?;
+//! let output = engine
+//!     .agent_run(&AgentInput::new("".to_string(), "Hello".to_string()))
+//!     .await?;
+//! ```
+
+use anda_core::{
+    AgentInput, AgentOutput, BoxError, ByteBufB64, Function, HttpFeatures, Json, ToolInput,
+    ToolOutput,
+};
+use anda_engine::context::{CallerIdentity, EngineCard};
+
+/// A client for the RPC methods of a remote engine, signing each request with
+/// the identity of the underlying [`HttpFeatures`] implementation.
+#[derive(Clone)]
+pub struct EngineClient<H> {
+    http: H,
+    endpoint: String,
+}
+
+impl<H: HttpFeatures> EngineClient<H> {
+    /// Creates a new EngineClient
+    ///
+    /// # Arguments
+    /// * `http` - The transport used to send signed RPC requests
+    /// * `endpoint` - The engine endpoint, e.g. `https://example.com/{engine_id}`
+    pub fn new(http: H, endpoint: String) -> Self {
+        Self { http, endpoint }
+    }
+
+    /// Returns the engine endpoint
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Runs an agent on the engine, the default agent is run if `input.name` is empty
+    pub async fn agent_run(&self, input: &AgentInput) -> Result<AgentOutput, BoxError> {
+        self.http
+            .https_signed_rpc(&self.endpoint, "agent_run", &(input,))
+            .await
+    }
+
+    /// Calls a tool on the engine
+    pub async fn tool_call(&self, input: &ToolInput<Json>) -> Result<ToolOutput<Json>, BoxError> {
+        self.http
+            .https_signed_rpc(&self.endpoint, "tool_call", &(input,))
+            .await
+    }
+
+    /// Calls several tools on the engine, each call succeeds or fails on its own
+    pub async fn tool_call_batch(
+        &self,
+        inputs: &[ToolInput<Json>],
+    ) -> Result<Vec<Result<ToolOutput<Json>, String>>, BoxError> {
+        self.http
+            .https_signed_rpc(&self.endpoint, "tool_call_batch", &(inputs,))
+            .await
+    }
+
+    /// Returns the blob of an artifact persisted by the engine for the caller,
+    /// `name` is the last segment of the artifact's `store://` URI
+    pub async fn get_artifact(&self, name: &str) -> Result<ByteBufB64, BoxError> {
+        self.http
+            .https_signed_rpc(&self.endpoint, "get_artifact", &(name,))
+            .await
+    }
+
+    /// Returns the engine information, including the exported agent and tool definitions
+    pub async fn information(&self) -> Result<EngineCard, BoxError> {
+        self.http
+            .https_signed_rpc(&self.endpoint, "information", &(true,))
+            .await
+    }
+
+    /// Returns the caller identity as resolved by the engine from the signed request
+    pub async fn whoami(&self) -> Result<CallerIdentity, BoxError> {
+        self.http
+            .https_signed_rpc(&self.endpoint, "whoami", &())
+            .await
+    }
+
+    /// Returns the function definition of an exported agent or tool by name
+    pub async fn describe(&self, name: &str) -> Result<Function, BoxError> {
+        self.http
+            .https_signed_rpc(&self.endpoint, "describe", &(name,))
+            .await
+    }
+}
@@ -6,7 +6,7 @@ use anda_engine::{
     ANONYMOUS,
     context::{AgentCtx, BaseCtx},
 };
-use anda_icp::ledger::{BalanceOfTool, ICPLedgers, TransferTool};
+use anda_icp::ledger::{BalanceOfTool, ICPLedgers, PortfolioTool, TransferTool};
 use candid::Principal;
 use std::{collections::BTreeSet, sync::Arc};
 
@@ -17,6 +17,7 @@ use std::{collections::BTreeSet, sync::Arc};
 pub struct ICPLedgerAgent {
     ledgers: Arc<ICPLedgers>,
     tools: Vec<&'static str>,
+    dry_run: bool,
 }
 
 impl ICPLedgerAgent {
@@ -41,19 +42,27 @@ impl ICPLedgerAgent {
         let ledgers = Arc::new(ledgers);
         Ok(Self {
             ledgers,
-            tools: vec![BalanceOfTool::NAME, TransferTool::NAME],
+            tools: vec![BalanceOfTool::NAME, PortfolioTool::NAME, TransferTool::NAME],
+            dry_run: false,
         })
     }
 
+    /// Forces the transfer tool into dry-run mode, no transfer will be submitted.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
     /// Returns the set of tools available through this agent.
     ///
     /// # Returns
-    /// A ToolSet containing the balance check and transfer tools
+    /// A ToolSet containing the balance check, portfolio and transfer tools
     /// or an error if tool initialization fails.
     pub fn tools(&self) -> Result<ToolSet<BaseCtx>, BoxError> {
         let mut tools = ToolSet::new();
         tools.add(BalanceOfTool::new(self.ledgers.clone()))?;
-        tools.add(TransferTool::new(self.ledgers.clone()))?;
+        tools.add(PortfolioTool::new(self.ledgers.clone()))?;
+        tools.add(TransferTool::new(self.ledgers.clone()).with_dry_run(self.dry_run))?;
         Ok(tools)
     }
 }
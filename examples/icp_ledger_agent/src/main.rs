@@ -3,16 +3,17 @@ use anda_engine::{
     context::Web3SDK,
     engine::{AgentInfo, EngineBuilder},
     management::{BaseManagement, Visibility},
-    model::{Model, deepseek, openai, xai},
+    model::{Model, ModelConfig, ProviderConfig},
     store::{InMemory, Store},
 };
 use anda_engine_server::{ServerBuilder, shutdown_signal};
 use anda_icp::ledger::BalanceOfTool;
-use anda_web3_client::client::{Client as Web3Client, load_identity};
+use anda_web3_client::client::{AgentTransport, Client as Web3Client, load_identity};
 use clap::Parser;
 use std::{
     collections::{BTreeMap, BTreeSet},
     sync::Arc,
+    time::Duration,
 };
 use structured_logger::{Builder, async_json::new_writer, get_env_level};
 use tokio_util::sync::CancellationToken;
@@ -62,6 +63,18 @@ struct Cli {
     /// AI model name, empty for default to auto-detect
     #[arg(long, env = "MODEL_NAME", default_value = "")]
     model_name: String,
+
+    /// Maximum number of concurrent requests to the ICP network
+    #[arg(long, env = "IC_MAX_CONCURRENT_REQUESTS", default_value = "50")]
+    ic_max_concurrent_requests: usize,
+
+    /// Timeout in seconds of a request to the ICP network
+    #[arg(long, env = "IC_REQUEST_TIMEOUT_SECS", default_value = "360")]
+    ic_request_timeout_secs: u64,
+
+    /// Validate transfers without submitting them to the ledgers
+    #[arg(long, env = "DRY_RUN")]
+    dry_run: bool,
 }
 
 /// Main entry point for the ICP Ledger Agent service.
@@ -76,7 +89,8 @@ struct Cli {
 /// - ICP API host: The ICP network endpoint (default: https://icp-api.io)
 /// - ID Secret: 32-byte hex-encoded secret for identity management
 /// - Root Secret: 48-byte hex-encoded root secret for cryptographic operations
-/// - AI Model: Supports both Deepseek and OpenAI models (Deepseek is default)
+/// - AI Model: Supports Deepseek, OpenAI and XAI models (Deepseek is default)
+/// - Dry run: Validate transfers without submitting them (default: false)
 ///
 /// # Features
 /// - Real-time interaction with ICP ledger
@@ -101,9 +115,11 @@ async fn main() -> Result<(), BoxError> {
     let cli = Cli::parse();
 
     // Initialize structured logging with JSON format
-    Builder::with_level(&get_env_level().to_string())
+    // Log at the most verbose level, the level from env can be changed at runtime
+    Builder::with_level("trace")
         .with_target_writer("*", new_writer(tokio::io::stdout()))
         .init();
+    log::set_max_level(get_env_level());
 
     // Create global cancellation token for graceful shutdown
     let global_cancel_token = CancellationToken::new();
@@ -120,6 +136,11 @@ async fn main() -> Result<(), BoxError> {
         .with_ic_host(&cli.ic_host)
         .with_identity(Arc::new(identity))
         .with_root_secret(root_secret)
+        .with_agent_transport(AgentTransport {
+            max_concurrent_requests: cli.ic_max_concurrent_requests,
+            request_timeout: Duration::from_secs(cli.ic_request_timeout_secs),
+            ..Default::default()
+        })
         .build()
         .await?;
 
@@ -129,25 +150,24 @@ async fn main() -> Result<(), BoxError> {
         my_principal.to_text()
     );
 
-    // Configure AI model
-    let model = Model::with_completer(if !cli.deepseek_api_key.is_empty() {
-        Arc::new(
-            deepseek::Client::new(&cli.deepseek_api_key, Some(cli.model_endpoint))
-                .completion_model(&cli.model_name),
-        )
-    } else if !cli.openai_api_key.is_empty() {
-        Arc::new(
-            openai::Client::new(&cli.openai_api_key, Some(cli.model_endpoint))
-                .completion_model(&cli.model_name),
-        )
-    } else if !cli.xai_api_key.is_empty() {
-        Arc::new(
-            xai::Client::new(&cli.xai_api_key, Some(cli.model_endpoint))
-                .completion_model(&cli.model_name),
-        )
-    } else {
-        return Err("missing AI model API key".into());
-    });
+    // Configure AI model with the first provider that has an API key
+    let (provider, api_key) = [
+        ("deepseek", &cli.deepseek_api_key),
+        ("openai", &cli.openai_api_key),
+        ("xai", &cli.xai_api_key),
+    ]
+    .into_iter()
+    .find(|(_, key)| !key.is_empty())
+    .ok_or("missing AI model API key")?;
+    let model = Model::from_config(&ModelConfig {
+        completion: Some(ProviderConfig {
+            provider: provider.to_string(),
+            api_key: api_key.clone(),
+            endpoint: cli.model_endpoint.clone(),
+            model: cli.model_name.clone(),
+        }),
+        ..Default::default()
+    })?;
 
     // Initialize in-memory object store.
     // For production use, consider using a local file system store or ic_obejct_store_canister:
@@ -157,7 +177,9 @@ async fn main() -> Result<(), BoxError> {
     // Configure supported token ledgers (ICP and PANDA)
     let token_ledgers: Vec<&str> =
         vec!["ryjl3-tyaaa-aaaaa-aaaba-cai", "druyg-tyaaa-aaaaq-aactq-cai"];
-    let agent = ICPLedgerAgent::load(&web3, &token_ledgers).await?;
+    let agent = ICPLedgerAgent::load(&web3, &token_ledgers)
+        .await?
+        .with_dry_run(cli.dry_run);
 
     // Build agent engine with all configured components
     let engine = EngineBuilder::new()
@@ -94,6 +94,7 @@ pub async fn test_bnb_ledger_transfer() {
             account: to_addr.to_string(),
             symbol: symbol.clone(),
             amount: transfer_amount,
+            chain_id: None,
         };
 
         // Call tool to transfer tokens
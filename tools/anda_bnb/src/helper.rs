@@ -1,5 +1,10 @@
-use alloy::primitives::{U256, utils::format_units};
+use alloy::{
+    network::{Network, ReceiptResponse},
+    primitives::{TxHash, U256, utils::format_units},
+    providers::Provider,
+};
 use anda_core::BoxError;
+use std::time::Duration;
 
 /// Helper function to parse the balance from U256 to f64
 pub(crate) fn get_balance(balance: U256) -> Result<f64, BoxError> {
@@ -7,3 +12,91 @@ pub(crate) fn get_balance(balance: U256) -> Result<f64, BoxError> {
     let balance = balance.parse::<f64>()?;
     Ok(balance)
 }
+
+/// Error returned when a submitted transaction is not mined in time.
+///
+/// The transaction may still be mined later, callers can check it by `tx_hash`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("transaction {tx_hash} was submitted but not mined in time")]
+pub struct TxTimeoutError {
+    pub tx_hash: TxHash,
+}
+
+/// Polls for the receipt of a pending transaction with exponential backoff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxPoller {
+    /// Delay before the first poll, doubled after each attempt
+    pub interval: Duration,
+    /// Upper bound of the delay between two polls
+    pub max_interval: Duration,
+    /// Maximum number of polls
+    pub max_attempts: u32,
+    /// Overall time to wait for the receipt
+    pub timeout: Duration,
+}
+
+impl Default for TxPoller {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(10),
+            max_attempts: 20,
+            timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+impl TxPoller {
+    /// Creates a poller with the given initial interval, max attempts and overall timeout
+    pub fn new(interval: Duration, max_attempts: u32, timeout: Duration) -> Self {
+        Self {
+            interval,
+            max_interval: interval.max(TxPoller::default().max_interval),
+            max_attempts,
+            timeout,
+        }
+    }
+
+    /// Sets the upper bound of the delay between two polls
+    pub fn with_max_interval(mut self, max_interval: Duration) -> Self {
+        self.max_interval = max_interval;
+        self
+    }
+
+    /// Waits until the transaction is mined and returns its receipt.
+    ///
+    /// Returns a [`TxTimeoutError`] if the receipt is not available after
+    /// `max_attempts` polls or `timeout`, whichever comes first.
+    pub async fn wait_for_receipt<N, P>(
+        &self,
+        provider: &P,
+        tx_hash: TxHash,
+    ) -> Result<N::ReceiptResponse, BoxError>
+    where
+        N: Network,
+        P: Provider<N>,
+    {
+        let poll = async {
+            let mut interval = self.interval;
+            for _ in 0..self.max_attempts {
+                tokio::time::sleep(interval).await;
+                if let Some(receipt) = provider.get_transaction_receipt(tx_hash).await? {
+                    return Ok(Some(receipt));
+                }
+                interval = (interval * 2).min(self.max_interval);
+            }
+            Ok::<_, BoxError>(None)
+        };
+
+        match tokio::time::timeout(self.timeout, poll).await {
+            Ok(Ok(Some(receipt))) => {
+                if !receipt.status() {
+                    return Err(format!("transaction {tx_hash} reverted").into());
+                }
+                Ok(receipt)
+            }
+            Ok(Err(err)) => Err(err),
+            Ok(Ok(None)) | Err(_) => Err(TxTimeoutError { tx_hash }.into()),
+        }
+    }
+}
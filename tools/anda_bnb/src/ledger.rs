@@ -63,6 +63,7 @@ pub struct BNBLedgers {
     derivation_path: Vec<Vec<u8>>,
     /// Map of token symbols to their corresponding canister ID and decimals places
     pub ledgers: BTreeMap<String, (Address, u8)>,
+    tx_poller: TxPoller,
 }
 
 impl BNBLedgers {
@@ -101,11 +102,18 @@ impl BNBLedgers {
             chain_id,
             derivation_path,
             ledgers,
+            tx_poller: TxPoller::default(),
         };
 
         Ok(ledgers)
     }
 
+    /// Sets the poller used to wait for transfer transactions to be mined
+    pub fn with_tx_poller(mut self, tx_poller: TxPoller) -> Self {
+        self.tx_poller = tx_poller;
+        self
+    }
+
     /// Performs the token transfer operation
     ///
     /// # Arguments
@@ -119,7 +127,8 @@ impl BNBLedgers {
         ctx: BaseCtx,
         args: transfer::TransferToArgs,
     ) -> Result<(Address, FixedBytes<32>), BoxError> {
-        use std::str::FromStr;
+        // Validate the arguments before any network work
+        let to_addr = args.validate(self.chain_id)?;
 
         // Create an anda signer
         let signer =
@@ -139,7 +148,6 @@ impl BNBLedgers {
             .connect_http(self.provider_url.clone());
 
         // Get receiver address, transfer amount, and token address to transfer
-        let to_addr = Address::from_str(&args.account)?;
         let to_amount = &args.amount;
         let (token_addr, decimals) = self
             .ledgers
@@ -175,9 +183,13 @@ impl BNBLedgers {
         let pending_tx = contract.transfer(to_addr, to_amount).send().await?;
         log::debug!("BNB transfer pending tx: {:?}", pending_tx);
 
-        let res = pending_tx.watch().await?;
+        // Returns a `TxTimeoutError` with the tx hash if it isn't mined in time
+        let tx_hash = *pending_tx.tx_hash();
+        self.tx_poller
+            .wait_for_receipt(pending_tx.provider(), tx_hash)
+            .await?;
 
-        Ok((to_addr, res))
+        Ok((to_addr, tx_hash))
     }
 
     /// Retrieves the balance of a specific account for a given token
@@ -69,9 +69,15 @@ impl Tool<BaseCtx> for BalanceOfTool {
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: Vec::new(),
         }
     }
 
+    /// Balances are public ledger data, so anonymous callers can query them.
+    fn allow_anonymous(&self) -> bool {
+        true
+    }
+
     async fn call(
         &self,
         ctx: BaseCtx,
@@ -7,6 +7,7 @@
 //! - Atomic transfers with proper error handling
 
 use super::BNBLedgers;
+use alloy::primitives::Address;
 use anda_core::{BoxError, FunctionDefinition, Resource, Tool, ToolOutput, gen_schema_for};
 use anda_engine::context::BaseCtx;
 use schemars::JsonSchema;
@@ -23,6 +24,43 @@ pub struct TransferToArgs {
     pub symbol: String,
     /// Token amount, e.g. 1.1 BNB
     pub amount: f64,
+    /// Optional chain ID, the transfer is rejected if it does not match the ledger's chain
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+}
+
+impl TransferToArgs {
+    /// Validates the arguments before any network work.
+    /// A mixed-case account address must have a valid EIP-55 checksum.
+    ///
+    /// # Returns
+    /// The recipient address or an error
+    pub fn validate(&self, chain_id: u64) -> Result<Address, BoxError> {
+        if let Some(id) = self.chain_id
+            && id != chain_id
+        {
+            return Err(format!("Chain ID mismatch, expected {}, got {}", chain_id, id).into());
+        }
+
+        if !self.amount.is_finite() || self.amount <= 0.0 {
+            return Err(format!("Invalid amount: {}", self.amount).into());
+        }
+
+        let hex = self.account.strip_prefix("0x").ok_or_else(|| {
+            format!(
+                "Invalid account address {:?}: missing 0x prefix",
+                self.account
+            )
+        })?;
+        let mixed_case = hex.chars().any(|c| c.is_ascii_uppercase())
+            && hex.chars().any(|c| c.is_ascii_lowercase());
+        let addr = if mixed_case {
+            Address::parse_checksummed(&self.account, None)
+        } else {
+            hex.parse::<Address>().map_err(Into::into)
+        };
+        addr.map_err(|err| format!("Invalid account address {:?}: {}", self.account, err).into())
+    }
 }
 
 /// Implementation of the BNB Chain Ledger Transfer tool
@@ -78,6 +116,7 @@ impl Tool<BaseCtx> for TransferTool {
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: Vec::new(),
         }
     }
 
@@ -94,3 +133,42 @@ impl Tool<BaseCtx> for TransferTool {
         )))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_args_validate() {
+        let mut args = TransferToArgs {
+            account: "0xA8c4AAE4ce759072D933bD4a51172257622eF128".to_string(),
+            symbol: "BNB".to_string(),
+            amount: 1.1,
+            chain_id: None,
+        };
+        assert!(args.validate(97).is_ok());
+
+        args.chain_id = Some(56);
+        let err = args.validate(97).unwrap_err().to_string();
+        assert!(err.contains("Chain ID mismatch"));
+
+        args.chain_id = Some(97);
+        assert!(args.validate(97).is_ok());
+
+        args.account = "0xa8c4aae4ce759072d933bd4a51172257622ef128".to_string();
+        assert!(args.validate(97).is_ok());
+
+        args.account = "0xA8c4AAE4ce759072D933bD4a51172257622eF127".to_string();
+        assert!(args.validate(97).is_err());
+
+        args.account = "A8c4AAE4ce759072D933bD4a51172257622eF128".to_string();
+        assert!(args.validate(97).is_err());
+
+        args.account = "0x1234".to_string();
+        assert!(args.validate(97).is_err());
+
+        args.account = "0xa8c4aae4ce759072d933bd4a51172257622ef128".to_string();
+        args.amount = 0.0;
+        assert!(args.validate(97).is_err());
+    }
+}
@@ -4,7 +4,7 @@ use alloy::primitives::{Address, B256, ChainId, U256};
 use alloy::signers::{
     self as alloy_signer, Error, Result, Signature, Signer, sign_transaction_with_chain_id,
 };
-use anda_core::KeysFeatures;
+use anda_core::{BoxError, KeysFeatures};
 use anda_engine::context::BaseCtx;
 use async_trait::async_trait;
 use std::fmt;
@@ -170,6 +170,12 @@ pub fn derive_address_from_pubkey(pubkey: &[u8]) -> Result<Address, String> {
     Ok(alloy::signers::utils::public_key_to_address(&key))
 }
 
+/// Derives the checksummed Ethereum address string from a public key,
+/// usable as an [`anda_engine::engine::AddressFn`] for `EngineBuilder::with_identity`.
+pub fn evm_address(pubkey: &[u8]) -> Result<String, BoxError> {
+    Ok(derive_address_from_pubkey(pubkey)?.to_string())
+}
+
 /// Computes the parity bit allowing to recover the public key from the signature.
 ///
 /// # Arguments
@@ -286,4 +292,30 @@ mod tests {
             signer.address()
         );
     }
+
+    #[tokio::test]
+    async fn test_mock_signer() {
+        let signer = async || {
+            let ctx = EngineBuilder::new()
+                .with_web3_client(Arc::new(Web3SDK::mock([7u8; 48])))
+                .mock_ctx()
+                .base;
+            AndaEvmSigner::new(
+                ctx,
+                DRVT_PATH.iter().map(|&s| s.to_vec()).collect(),
+                Some(CHAIN_ID),
+            )
+            .await
+            .unwrap()
+        };
+
+        let a = signer().await;
+        let b = signer().await;
+        assert_eq!(a.address(), b.address());
+
+        let message = vec![0, 1, 2, 3];
+        let sig = a.sign_message(&message).await.unwrap();
+        assert_eq!(sig, b.sign_message(&message).await.unwrap());
+        assert_eq!(sig.recover_address_from_msg(message).unwrap(), a.address());
+    }
 }
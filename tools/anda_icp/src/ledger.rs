@@ -4,11 +4,15 @@
 //! - Loading and managing multiple ICP ledger canisters
 //! - Transferring tokens between accounts
 //! - Querying account balances
+//! - Querying account balances across tokens
+//! - Querying transaction status
+//! - Encoding and decoding transfer memos
 //!
 //! The implementation supports:
 //! - Multiple token symbols (though primarily designed for ICP)
 //! - Configurable subaccount usage for transfers
 //! - ICRC-1 standard compliant operations
+//! - Transfers to legacy ICP AccountIdentifiers (64-char hex) through the ICP ledger `transfer` method
 //!
 //! # Examples
 //! ```rust,ignore
@@ -24,23 +28,188 @@
 //! ```
 
 use anda_core::{BoxError, CanisterCaller};
-use candid::{Nat, Principal};
+use anda_engine::{context::UpdateRetry, unix_ms};
+use candid::{CandidType, Nat, Principal};
 use icrc_ledger_types::{
     icrc::generic_metadata_value::MetadataValue,
     icrc1::{
         account::{Account, principal_to_subaccount},
         transfer::{TransferArg, TransferError},
     },
+    icrc3::{
+        archive::QueryArchiveFn,
+        transactions::{GetTransactionsRequest, GetTransactionsResponse, TransactionRange},
+    },
 };
 use num_traits::cast::ToPrimitive;
+use serde::Deserialize;
 use std::collections::{BTreeMap, BTreeSet};
 
 pub mod balance;
+pub mod memo;
+pub mod portfolio;
+pub mod transaction;
 pub mod transfer;
 
 pub use balance::*;
+pub use memo::*;
+pub use portfolio::*;
+pub use transaction::*;
 pub use transfer::*;
 
+/// The ICP ledger canister, the only ledger that supports legacy AccountIdentifiers
+pub static ICP_LEDGER_CANISTER: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+
+/// The destination of a transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferTarget {
+    /// An ICRC-1 account owned by the principal, with the default subaccount
+    Principal(Principal),
+    /// A legacy ICP AccountIdentifier, e.g. an exchange deposit address
+    AccountId([u8; 32]),
+}
+
+impl TransferTarget {
+    /// Parses a principal text or a 64-char hex AccountIdentifier with a valid CRC32 checksum
+    pub fn parse(account: &str) -> Result<Self, BoxError> {
+        if account.len() == 64 && account.bytes().all(|b| b.is_ascii_hexdigit()) {
+            let mut id = [0u8; 32];
+            hex::decode_to_slice(account, &mut id)?;
+            let checksum = crc32fast::hash(&id[4..]).to_be_bytes();
+            if checksum != id[..4] {
+                return Err(format!("invalid account identifier checksum: {account}").into());
+            }
+            return Ok(Self::AccountId(id));
+        }
+
+        Ok(Self::Principal(Principal::from_text(account)?))
+    }
+}
+
+/// Tokens amount of the ICP ledger legacy interface
+#[derive(CandidType, Deserialize, Debug, Clone, Copy)]
+struct Tokens {
+    e8s: u64,
+}
+
+/// Arguments of the ICP ledger legacy `transfer` method
+#[derive(CandidType, Deserialize, Debug, Clone)]
+struct LegacyTransferArgs {
+    memo: u64,
+    amount: Tokens,
+    fee: Tokens,
+    from_subaccount: Option<Vec<u8>>,
+    to: Vec<u8>,
+    created_at_time: Option<TimeStamp>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Copy)]
+struct TimeStamp {
+    timestamp_nanos: u64,
+}
+
+/// Errors of the ICP ledger legacy `transfer` method
+#[derive(CandidType, Deserialize, Debug, Clone)]
+enum LegacyTransferError {
+    BadFee { expected_fee: Tokens },
+    InsufficientFunds { balance: Tokens },
+    TxTooOld { allowed_window_nanos: u64 },
+    TxCreatedInFuture,
+    TxDuplicate { duplicate_of: u64 },
+}
+
+/// Arguments of the ICP ledger `query_blocks` method and of its archive callbacks
+#[derive(CandidType, Deserialize, Debug, Clone, Copy)]
+struct GetBlocksArgs {
+    start: u64,
+    length: u64,
+}
+
+/// Response of the ICP ledger `query_blocks` method
+#[derive(CandidType, Deserialize, Debug, Clone)]
+struct QueryBlocksResponse {
+    first_block_index: u64,
+    blocks: Vec<Block>,
+    archived_blocks: Vec<ArchivedBlocksRange>,
+}
+
+/// A range of blocks served by an archive canister of the ICP ledger
+#[derive(CandidType, Deserialize, Debug, Clone)]
+struct ArchivedBlocksRange {
+    start: u64,
+    length: u64,
+    callback: QueryArchiveFn<GetBlocksArgs, QueryArchiveResult>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+enum QueryArchiveResult {
+    Ok(BlockRange),
+    Err(QueryArchiveError),
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+struct BlockRange {
+    blocks: Vec<Block>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+enum QueryArchiveError {
+    BadFirstBlockIndex {
+        requested_index: u64,
+        first_valid_index: u64,
+    },
+    Other {
+        error_code: u64,
+        error_message: String,
+    },
+}
+
+/// A block of the ICP ledger
+#[derive(CandidType, Deserialize, Debug, Clone)]
+struct Block {
+    transaction: LegacyTransaction,
+    timestamp: TimeStamp,
+}
+
+/// The transaction of an ICP ledger block
+#[derive(CandidType, Deserialize, Debug, Clone)]
+struct LegacyTransaction {
+    memo: u64,
+    icrc1_memo: Option<Vec<u8>>,
+    operation: Option<Operation>,
+}
+
+/// Operations of the ICP ledger, accounts are AccountIdentifiers
+#[derive(CandidType, Deserialize, Debug, Clone)]
+enum Operation {
+    Mint {
+        to: Vec<u8>,
+        amount: Tokens,
+    },
+    Burn {
+        from: Vec<u8>,
+        amount: Tokens,
+    },
+    Transfer {
+        from: Vec<u8>,
+        to: Vec<u8>,
+        amount: Tokens,
+        fee: Tokens,
+    },
+    Approve {
+        from: Vec<u8>,
+        spender: Vec<u8>,
+        allowance: Option<Tokens>,
+        fee: Tokens,
+    },
+    TransferFrom {
+        from: Vec<u8>,
+        to: Vec<u8>,
+        amount: Tokens,
+        fee: Tokens,
+    },
+}
+
 /// ICP Ledger Transfer tool implementation
 #[derive(Debug, Clone)]
 pub struct ICPLedgers {
@@ -101,31 +270,40 @@ impl ICPLedgers {
         })
     }
 
-    /// Performs the token transfer operation
+    /// Performs the token transfer operation, retrying the transfer call with the given policy.
     ///
-    /// # Arguments
-    /// * `ctx` - Canister caller context
-    /// * `args` - Transfer arguments containing destination account, amount, and memo
-    ///
-    /// # Returns
-    /// Result containing the ledger ID and transaction ID (Nat) or an error
-    async fn transfer(
+    /// A retried transfer sets `created_at_time`, so that the ledger deduplicates it. A transfer
+    /// that already succeeded in a previous attempt returns the ID of its transaction.
+    async fn transfer_with_retry(
         &self,
         ctx: &impl CanisterCaller,
+        retry: Option<&UpdateRetry>,
         me: Principal,
         args: transfer::TransferToArgs,
     ) -> Result<(Principal, Nat), BoxError> {
-        let owner = Principal::from_text(&args.account)?;
+        let (canister, decimals) = self
+            .ledgers
+            .get(&args.symbol)
+            .ok_or_else(|| format!("Token {} is not supported", args.symbol))?;
+        let owner = match TransferTarget::parse(&args.account)? {
+            TransferTarget::Principal(owner) => owner,
+            TransferTarget::AccountId(to) => {
+                return self
+                    .transfer_to_account_id(ctx, retry, me, *canister, *decimals, to, args)
+                    .await;
+            }
+        };
         let from_subaccount = if self.from_user_subaccount {
             Some(principal_to_subaccount(owner))
         } else {
             None
         };
-        let (canister, decimals) = self
-            .ledgers
-            .get(&args.symbol)
-            .ok_or_else(|| format!("Token {} is not supported", args.symbol))?;
 
+        let memo = args
+            .memo
+            .as_deref()
+            .map(|memo| encode_memo(memo, args.memo_format.unwrap_or_default()))
+            .transpose()?;
         let amount = (args.amount * 10u64.pow(*decimals as u32) as f64) as u64;
         let balance: Nat = ctx
             .canister_query(
@@ -142,31 +320,125 @@ impl ICPLedgers {
             return Err("insufficient balance".into());
         }
 
-        let res: Result<Nat, TransferError> = ctx
-            .canister_update(
-                canister,
-                "icrc1_transfer",
-                (TransferArg {
-                    from_subaccount,
-                    to: Account {
-                        owner,
-                        subaccount: None,
-                    },
-                    amount: amount.into(),
-                    memo: None,
-                    fee: None,
-                    created_at_time: None,
+        let arg = TransferArg {
+            from_subaccount,
+            to: Account {
+                owner,
+                subaccount: None,
+            },
+            amount: amount.into(),
+            memo,
+            fee: None,
+            created_at_time: retry.map(|_| unix_ms() * 1_000_000),
+        };
+        let res: Result<Nat, TransferError> = match retry {
+            Some(retry) => {
+                retry
+                    .canister_update(ctx, canister, "icrc1_transfer", (arg,), true)
+                    .await?
+            }
+            None => {
+                ctx.canister_update(canister, "icrc1_transfer", (arg,))
+                    .await?
+            }
+        };
+        let res = match res {
+            Err(TransferError::Duplicate { duplicate_of }) if retry.is_some() => Ok(duplicate_of),
+            res => res,
+        };
+        log::info!(
+            account = args.account,
+            symbol = args.symbol,
+            amount = args.amount,
+            result = res.is_ok();
+            "icrc1_transfer",
+        );
+        res.map(|v| (*canister, v))
+            .map_err(|err| format!("failed to transfer tokens, error: {:?}", err).into())
+    }
+
+    /// Transfers ICP to a legacy AccountIdentifier through the ICP ledger `transfer` method,
+    /// always from the Agent's main account.
+    #[allow(clippy::too_many_arguments)]
+    async fn transfer_to_account_id(
+        &self,
+        ctx: &impl CanisterCaller,
+        retry: Option<&UpdateRetry>,
+        me: Principal,
+        canister: Principal,
+        decimals: u8,
+        to: [u8; 32],
+        args: transfer::TransferToArgs,
+    ) -> Result<(Principal, Nat), BoxError> {
+        if canister.to_text() != ICP_LEDGER_CANISTER {
+            return Err(format!(
+                "Token {} does not support account identifiers, use a principal instead",
+                args.symbol
+            )
+            .into());
+        }
+        if self.from_user_subaccount {
+            return Err(
+                "account identifiers are not supported with user subaccounts, use a principal instead"
+                    .into(),
+            );
+        }
+
+        let memo = args
+            .memo
+            .as_deref()
+            .map(|memo| encode_legacy_memo(memo, args.memo_format.unwrap_or_default()))
+            .transpose()?
+            .unwrap_or_default();
+        let amount = (args.amount * 10u64.pow(decimals as u32) as f64) as u64;
+        let balance: Nat = ctx
+            .canister_query(
+                &canister,
+                "icrc1_balance_of",
+                (Account {
+                    owner: me,
+                    subaccount: None,
                 },),
             )
             .await?;
+        if balance < amount {
+            return Err("insufficient balance".into());
+        }
+
+        let fee: Nat = ctx.canister_query(&canister, "icrc1_fee", ()).await?;
+        let fee = fee.0.to_u64().ok_or("invalid ledger fee")?;
+        let arg = LegacyTransferArgs {
+            memo,
+            amount: Tokens { e8s: amount },
+            fee: Tokens { e8s: fee },
+            from_subaccount: None,
+            to: to.to_vec(),
+            created_at_time: retry.map(|_| TimeStamp {
+                timestamp_nanos: unix_ms() * 1_000_000,
+            }),
+        };
+        let res: Result<u64, LegacyTransferError> = match retry {
+            Some(retry) => {
+                retry
+                    .canister_update(ctx, &canister, "transfer", (arg,), true)
+                    .await?
+            }
+            None => ctx.canister_update(&canister, "transfer", (arg,)).await?,
+        };
+        let res = match res {
+            Err(LegacyTransferError::TxDuplicate { duplicate_of }) if retry.is_some() => {
+                Ok(duplicate_of)
+            }
+            res => res,
+        };
         log::info!(
             account = args.account,
             symbol = args.symbol,
             amount = args.amount,
             result = res.is_ok();
-            "icrc1_transfer",
+            "transfer",
         );
-        res.map(|v| (*canister, v))
+        res.map(|v| (canister, Nat::from(v)))
             .map_err(|err| format!("failed to transfer tokens, error: {:?}", err).into())
     }
 
@@ -207,4 +479,122 @@ impl ICPLedgers {
         );
         Ok((*canister, amount))
     }
+
+    /// Retrieves the status of a transaction by its block index
+    ///
+    /// # Arguments
+    /// * `ctx` - Canister caller context
+    /// * `args` - Query arguments containing token symbol and block index
+    ///
+    /// # Returns
+    /// Result containing the transaction status or an error
+    async fn transaction_status(
+        &self,
+        ctx: &impl CanisterCaller,
+        args: transaction::TransactionStatusArgs,
+    ) -> Result<transaction::TransactionStatus, BoxError> {
+        let (canister, decimals) = self
+            .ledgers
+            .get(&args.symbol)
+            .ok_or_else(|| format!("Token {} is not supported", args.symbol))?;
+
+        if canister.to_text() == ICP_LEDGER_CANISTER {
+            let block = Self::icp_block(ctx, canister, args.block_index).await?;
+            let status = transaction::TransactionStatus::from_icp_block(
+                args.block_index,
+                block,
+                *decimals,
+                args.memo_format.unwrap_or_default(),
+            );
+            log::info!(
+                symbol = args.symbol,
+                block_index = args.block_index,
+                status = status.status;
+                "transaction_status",
+            );
+            return Ok(status);
+        }
+
+        let index = Nat::from(args.block_index);
+        let req = GetTransactionsRequest {
+            start: index.clone(),
+            length: Nat::from(1u64),
+        };
+        let res: GetTransactionsResponse = ctx
+            .canister_query(canister, "get_transactions", (req.clone(),))
+            .await?;
+
+        let tx = if res.first_index == index && !res.transactions.is_empty() {
+            res.transactions.into_iter().next()
+        } else if let Some(archived) = res
+            .archived_transactions
+            .into_iter()
+            .find(|a| a.start <= index && index < a.start.clone() + a.length.clone())
+        {
+            let range: TransactionRange = ctx
+                .canister_query(
+                    &archived.callback.canister_id,
+                    &archived.callback.method,
+                    (req,),
+                )
+                .await?;
+            range.transactions.into_iter().next()
+        } else {
+            None
+        };
+
+        let status = transaction::TransactionStatus::new(
+            args.block_index,
+            tx,
+            *decimals,
+            args.memo_format.unwrap_or_default(),
+        );
+        log::info!(
+            symbol = args.symbol,
+            block_index = args.block_index,
+            status = status.status;
+            "transaction_status",
+        );
+        Ok(status)
+    }
+
+    /// Retrieves a block of the ICP ledger, which does not serve `get_transactions`,
+    /// through `query_blocks`, following the archive canister if the block has been archived.
+    async fn icp_block(
+        ctx: &impl CanisterCaller,
+        canister: &Principal,
+        index: u64,
+    ) -> Result<Option<Block>, BoxError> {
+        let req = GetBlocksArgs {
+            start: index,
+            length: 1,
+        };
+        let res: QueryBlocksResponse = ctx.canister_query(canister, "query_blocks", (req,)).await?;
+        if res.first_block_index == index && !res.blocks.is_empty() {
+            return Ok(res.blocks.into_iter().next());
+        }
+
+        match res
+            .archived_blocks
+            .into_iter()
+            .find(|a| a.start <= index && index < a.start + a.length)
+        {
+            Some(archived) => {
+                let rt: QueryArchiveResult = ctx
+                    .canister_query(
+                        &archived.callback.canister_id,
+                        &archived.callback.method,
+                        (req,),
+                    )
+                    .await?;
+                match rt {
+                    QueryArchiveResult::Ok(range) => Ok(range.blocks.into_iter().next()),
+                    QueryArchiveResult::Err(err) => {
+                        Err(format!("failed to query archived block {index}: {err:?}").into())
+                    }
+                }
+            }
+            None => Ok(None),
+        }
+    }
 }
@@ -0,0 +1,187 @@
+//! Encoding and decoding of ICRC transfer memos
+//!
+//! An ICRC memo is an arbitrary blob of up to 32 bytes with no meaning to the ledger.
+//! Exchanges and payment flows attach memos in a few common formats:
+//! - `u64`: an 8-byte big-endian number, the same as the legacy ICP ledger memo
+//! - `text`: UTF-8 text, e.g. an order reference
+//! - `json`: compact JSON, e.g. `{"order":42}`
+//! - `hex`: raw bytes as a hex string
+//!
+//! [`MemoFormat::Auto`] guesses the format, it can be overridden when the counterparty
+//! is known to use a specific one.
+
+use anda_core::BoxError;
+use icrc_ledger_types::icrc1::transfer::Memo;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The max length of an ICRC memo in bytes
+pub const MAX_MEMO_LEN: usize = 32;
+
+/// The interpretation of a memo
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoFormat {
+    /// Encodes a number as u64 and anything else as text. Decodes JSON objects or arrays
+    /// as json, printable UTF-8 as text, up to 8 bytes as u64 and anything else as hex.
+    #[default]
+    Auto,
+    /// An 8-byte big-endian number
+    U64,
+    /// UTF-8 text
+    Text,
+    /// Compact JSON
+    Json,
+    /// Raw bytes as a hex string
+    Hex,
+}
+
+/// Encodes a memo in the given format
+pub fn encode_memo(memo: &str, format: MemoFormat) -> Result<Memo, BoxError> {
+    let data = match format {
+        MemoFormat::Auto => match memo.parse::<u64>() {
+            Ok(n) => n.to_be_bytes().to_vec(),
+            Err(_) => memo.as_bytes().to_vec(),
+        },
+        MemoFormat::U64 => memo
+            .parse::<u64>()
+            .map_err(|err| format!("invalid u64 memo {memo:?}: {err}"))?
+            .to_be_bytes()
+            .to_vec(),
+        MemoFormat::Text => memo.as_bytes().to_vec(),
+        MemoFormat::Json => {
+            let value: Value = serde_json::from_str(memo)
+                .map_err(|err| format!("invalid json memo {memo:?}: {err}"))?;
+            serde_json::to_vec(&value)?
+        }
+        MemoFormat::Hex => {
+            hex::decode(memo).map_err(|err| format!("invalid hex memo {memo:?}: {err}"))?
+        }
+    };
+
+    if data.len() > MAX_MEMO_LEN {
+        return Err(format!(
+            "memo is too long, expected at most {} bytes, got {}",
+            MAX_MEMO_LEN,
+            data.len()
+        )
+        .into());
+    }
+    Ok(Memo::from(data))
+}
+
+/// Encodes a memo for the ICP ledger legacy `transfer` method, which only supports u64 memos
+pub fn encode_legacy_memo(memo: &str, format: MemoFormat) -> Result<u64, BoxError> {
+    match format {
+        MemoFormat::Auto | MemoFormat::U64 => memo
+            .parse::<u64>()
+            .map_err(|err| format!("invalid u64 memo {memo:?}: {err}").into()),
+        _ => Err(format!(
+            "account identifiers only support u64 memos, got {:?} format",
+            format
+        )
+        .into()),
+    }
+}
+
+/// Decodes a memo, returns the format it was decoded with and the value.
+/// A memo that does not match the given format is decoded as hex.
+pub fn decode_memo(memo: &[u8], format: MemoFormat) -> (MemoFormat, String) {
+    let decoded = match format {
+        MemoFormat::Auto => decode_json(memo)
+            .map(|v| (MemoFormat::Json, v))
+            .or_else(|| decode_text(memo).map(|v| (MemoFormat::Text, v)))
+            .or_else(|| decode_u64(memo).map(|v| (MemoFormat::U64, v))),
+        MemoFormat::U64 => decode_u64(memo).map(|v| (format, v)),
+        MemoFormat::Text => std::str::from_utf8(memo)
+            .ok()
+            .map(|v| (format, v.to_string())),
+        MemoFormat::Json => serde_json::from_slice::<Value>(memo)
+            .ok()
+            .map(|v| (format, v.to_string())),
+        MemoFormat::Hex => None,
+    };
+    decoded.unwrap_or_else(|| (MemoFormat::Hex, hex::encode(memo)))
+}
+
+fn decode_u64(memo: &[u8]) -> Option<String> {
+    if memo.is_empty() || memo.len() > 8 {
+        return None;
+    }
+    let mut data = [0u8; 8];
+    data[8 - memo.len()..].copy_from_slice(memo);
+    Some(u64::from_be_bytes(data).to_string())
+}
+
+fn decode_text(memo: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(memo).ok()?;
+    if text.is_empty() || text.chars().any(|c| c.is_control()) {
+        return None;
+    }
+    Some(text.to_string())
+}
+
+fn decode_json(memo: &[u8]) -> Option<String> {
+    match serde_json::from_slice::<Value>(memo).ok()? {
+        v @ (Value::Object(_) | Value::Array(_)) => Some(v.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memo() {
+        let memo = encode_memo("42", MemoFormat::Auto).unwrap();
+        assert_eq!(memo, Memo::from(42u64));
+        assert_eq!(
+            decode_memo(&memo.0, MemoFormat::Auto),
+            (MemoFormat::U64, "42".to_string())
+        );
+
+        let memo = encode_memo("42", MemoFormat::Text).unwrap();
+        assert_eq!(memo.0.as_slice(), b"42");
+        assert_eq!(
+            decode_memo(&memo.0, MemoFormat::Auto),
+            (MemoFormat::Text, "42".to_string())
+        );
+        assert_eq!(
+            decode_memo(&memo.0, MemoFormat::U64),
+            (MemoFormat::U64, "13362".to_string())
+        );
+
+        let memo = encode_memo(r#"{ "order": 42 }"#, MemoFormat::Json).unwrap();
+        assert_eq!(memo.0.as_slice(), br#"{"order":42}"#);
+        assert_eq!(
+            decode_memo(&memo.0, MemoFormat::Auto),
+            (MemoFormat::Json, r#"{"order":42}"#.to_string())
+        );
+        assert_eq!(
+            decode_memo(&memo.0, MemoFormat::Hex),
+            (MemoFormat::Hex, hex::encode(br#"{"order":42}"#))
+        );
+
+        let memo = encode_memo("ff00ff", MemoFormat::Hex).unwrap();
+        assert_eq!(memo.0.as_slice(), &[0xff, 0x00, 0xff]);
+        assert_eq!(
+            decode_memo(&[0xff; 12], MemoFormat::Auto),
+            (MemoFormat::Hex, "ff".repeat(12))
+        );
+        assert_eq!(
+            decode_memo(&[0xff; 12], MemoFormat::Text),
+            (MemoFormat::Hex, "ff".repeat(12))
+        );
+
+        assert!(encode_memo("abc", MemoFormat::U64).is_err());
+        assert!(encode_memo("{", MemoFormat::Json).is_err());
+        assert!(encode_memo("xyz", MemoFormat::Hex).is_err());
+        assert!(encode_memo(&"a".repeat(33), MemoFormat::Text).is_err());
+
+        assert_eq!(encode_legacy_memo("42", MemoFormat::Auto).unwrap(), 42);
+        assert!(encode_legacy_memo("order-42", MemoFormat::Auto).is_err());
+        assert!(encode_legacy_memo("42", MemoFormat::Text).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Enables AI Agent to query the balances of an account across the loaded ICP tokens
+//!
+//! This module provides a portfolio query over the ledgers of [`ICPLedgers`]. The balances
+//! are queried with bounded concurrency and a timeout per ledger call, and the result can be
+//! restricted to a subset of symbols, paged by symbol and filtered to non-zero holdings.
+
+use anda_core::{BoxError, FunctionDefinition, Resource, Tool, ToolOutput, gen_schema_for};
+use anda_engine::context::BaseCtx;
+use futures::{StreamExt, stream};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{sync::Arc, time::Duration};
+
+use super::{BalanceOfArgs, ICPLedgers};
+
+/// Arguments for the balances of an account across tokens
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct PortfolioArgs {
+    /// ICP account address (principal) to query, e.g. "77ibd-jp5kr-moeco-kgoar-rro5v-5tng4-krif5-5h2i6-osf2f-2sjtv-kqe"
+    pub account: String,
+    /// Token symbols to query, e.g. ["ICP"], all supported tokens if empty
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    /// Includes tokens with a zero balance if true, default to false
+    #[serde(default)]
+    pub include_zero: bool,
+    /// The max number of tokens to query, default to 20
+    pub limit: Option<usize>,
+    /// The `next_cursor` of the previous result to query the following tokens
+    pub cursor: Option<String>,
+}
+
+/// The balance of a token
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenBalance {
+    pub symbol: String,
+    pub balance: f64,
+}
+
+/// A token whose balance could not be queried
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenError {
+    pub symbol: String,
+    pub error: String,
+}
+
+/// The balances of an account
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Portfolio {
+    pub balances: Vec<TokenBalance>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<TokenError>,
+    /// Present if there are more tokens to query
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// ICP Ledger Portfolio tool implementation
+#[derive(Debug, Clone)]
+pub struct PortfolioTool {
+    ledgers: Arc<ICPLedgers>,
+    schema: Value,
+    concurrency: usize,
+    call_timeout: Duration,
+}
+
+impl PortfolioTool {
+    pub const NAME: &'static str = "icp_ledger_portfolio";
+    /// The default number of tokens queried per call
+    pub const DEFAULT_LIMIT: usize = 20;
+
+    /// Creates a new PortfolioTool instance, querying up to 8 ledgers concurrently
+    /// with a timeout of 10 seconds per call
+    pub fn new(ledgers: Arc<ICPLedgers>) -> Self {
+        let schema = gen_schema_for::<PortfolioArgs>();
+
+        PortfolioTool {
+            ledgers,
+            schema: json!(schema),
+            concurrency: 8,
+            call_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets the maximum number of ledgers queried concurrently
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets the timeout of each ledger balance query
+    pub fn with_call_timeout(mut self, timeout: Duration) -> Self {
+        self.call_timeout = timeout;
+        self
+    }
+
+    /// Selects the supported symbols of a page in symbol order, returns them with the
+    /// cursor of the next page if there are more.
+    fn select_symbols(
+        &self,
+        args: &PortfolioArgs,
+    ) -> Result<(Vec<String>, Option<String>), BoxError> {
+        for symbol in &args.symbols {
+            if !self.ledgers.ledgers.contains_key(symbol) {
+                return Err(format!("Token {} is not supported", symbol).into());
+            }
+        }
+
+        let limit = args.limit.unwrap_or(Self::DEFAULT_LIMIT).max(1);
+        let mut symbols = self
+            .ledgers
+            .ledgers
+            .keys()
+            .filter(|s| args.symbols.is_empty() || args.symbols.contains(s))
+            .filter(|s| args.cursor.as_ref().is_none_or(|c| *s > c))
+            .cloned();
+        let page: Vec<String> = symbols.by_ref().take(limit).collect();
+        let next_cursor = match symbols.next() {
+            Some(_) => page.last().cloned(),
+            None => None,
+        };
+        Ok((page, next_cursor))
+    }
+
+    /// Queries the balances of an account for a page of tokens
+    ///
+    /// # Arguments
+    /// * `ctx` - Base context to call the ledgers
+    /// * `args` - Portfolio query arguments
+    pub async fn portfolio(
+        &self,
+        ctx: &BaseCtx,
+        args: PortfolioArgs,
+    ) -> Result<Portfolio, BoxError> {
+        let (symbols, next_cursor) = self.select_symbols(&args)?;
+        let results: Vec<(String, Result<f64, BoxError>)> = stream::iter(symbols)
+            .map(|symbol| {
+                let args = BalanceOfArgs {
+                    account: args.account.clone(),
+                    symbol: symbol.clone(),
+                };
+                async move {
+                    let res = match tokio::time::timeout(
+                        self.call_timeout,
+                        self.ledgers.balance_of(ctx, args),
+                    )
+                    .await
+                    {
+                        Ok(res) => res.map(|(_, amount)| amount),
+                        Err(_) => {
+                            Err(format!("query timed out after {:?}", self.call_timeout).into())
+                        }
+                    };
+                    (symbol, res)
+                }
+            })
+            .buffered(self.concurrency)
+            .collect()
+            .await;
+
+        let mut portfolio = Portfolio {
+            next_cursor,
+            ..Default::default()
+        };
+        for (symbol, res) in results {
+            match res {
+                Ok(balance) if balance > 0.0 || args.include_zero => {
+                    portfolio.balances.push(TokenBalance { symbol, balance });
+                }
+                Ok(_) => {}
+                Err(err) => portfolio.errors.push(TokenError {
+                    symbol,
+                    error: err.to_string(),
+                }),
+            }
+        }
+        Ok(portfolio)
+    }
+}
+
+/// Implementation of the [`Tool`]` trait for PortfolioTool
+/// Enables AI Agent to query the balances of an account across ICP tokens
+impl Tool<BaseCtx> for PortfolioTool {
+    type Args = PortfolioArgs;
+    type Output = Portfolio;
+
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        let tokens = self
+            .ledgers
+            .ledgers
+            .keys()
+            .map(|k| k.as_str())
+            .collect::<Vec<_>>();
+        format!(
+            "Query the non-zero balances of the specified account on ICP blockchain across the following tokens: {}",
+            tokens.join(", ")
+        )
+    }
+
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: self.name(),
+            description: self.description(),
+            parameters: self.schema.clone(),
+            strict: Some(true),
+            examples: Vec::new(),
+        }
+    }
+
+    async fn call(
+        &self,
+        ctx: BaseCtx,
+        data: Self::Args,
+        _resources: Vec<Resource>,
+    ) -> Result<ToolOutput<Self::Output>, BoxError> {
+        let portfolio = self.portfolio(&ctx, data).await?;
+        Ok(ToolOutput::new(portfolio))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_select_symbols() {
+        let ledger = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+        let ledgers = ICPLedgers {
+            ledgers: BTreeMap::from([
+                (String::from("CKBTC"), (ledger, 8)),
+                (String::from("ICP"), (ledger, 8)),
+                (String::from("PANDA"), (ledger, 8)),
+            ]),
+            from_user_subaccount: false,
+        };
+        let tool = PortfolioTool::new(Arc::new(ledgers));
+        assert_eq!(tool.definition().name, "icp_ledger_portfolio");
+
+        let mut args = PortfolioArgs {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let (page, cursor) = tool.select_symbols(&args).unwrap();
+        assert_eq!(page, vec!["CKBTC", "ICP"]);
+        assert_eq!(cursor.as_deref(), Some("ICP"));
+
+        args.cursor = cursor;
+        let (page, cursor) = tool.select_symbols(&args).unwrap();
+        assert_eq!(page, vec!["PANDA"]);
+        assert_eq!(cursor, None);
+
+        let args = PortfolioArgs {
+            symbols: vec!["PANDA".to_string(), "CKBTC".to_string()],
+            ..Default::default()
+        };
+        let (page, cursor) = tool.select_symbols(&args).unwrap();
+        assert_eq!(page, vec!["CKBTC", "PANDA"]);
+        assert_eq!(cursor, None);
+
+        let args = PortfolioArgs {
+            symbols: vec!["DOGE".to_string()],
+            ..Default::default()
+        };
+        assert!(tool.select_symbols(&args).is_err());
+    }
+}
@@ -0,0 +1,486 @@
+//! Enables AI Agent to look up the status of a transaction on ICP ledgers
+//!
+//! This module queries the ledger's `get_transactions` endpoint for a block index, following
+//! the archive canister if the transaction has been archived. It works with ICRC ledgers that
+//! implement `get_transactions`, e.g. SNS ledgers and ckBTC. The ICP ledger does not serve
+//! `get_transactions`, so its blocks are queried through `query_blocks` and its accounts are
+//! AccountIdentifiers in hex.
+
+use anda_core::{BoxError, FunctionDefinition, Resource, Tool, ToolOutput, gen_schema_for};
+use anda_engine::context::BaseCtx;
+use candid::Nat;
+use icrc_ledger_types::icrc3::transactions::Transaction;
+use num_traits::cast::ToPrimitive;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::{ICPLedgers, MemoFormat, decode_memo};
+
+/// Arguments for querying the status of a transaction
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct TransactionStatusArgs {
+    /// Token symbol, e.g. "ICP"
+    pub symbol: String,
+    /// Block index (transaction ID) returned by the transfer, e.g. 123456
+    pub block_index: u64,
+    /// The format to decode the memo with, default to "auto" which guesses the format
+    pub memo_format: Option<MemoFormat>,
+}
+
+/// The status of a transaction on the ledger
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct TransactionStatus {
+    /// Block index of the transaction
+    pub block_index: u64,
+    /// "settled" if the transaction is in the ledger, "not_found" otherwise
+    pub status: String,
+    /// Transaction kind, e.g. "transfer", "mint", "burn", "approve"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Token amount
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<f64>,
+    /// Token fee
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee: Option<f64>,
+    /// Sender account
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// Receiver account
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// Timestamp in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_ms: Option<u64>,
+    /// Decoded memo of the transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    /// The format the memo was decoded with, "hex" if it does not match the requested format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo_format: Option<MemoFormat>,
+}
+
+impl TransactionStatus {
+    /// Creates the status from a ledger transaction, `None` means the transaction was not found.
+    /// The memo is decoded with `memo_format`.
+    pub fn new(
+        block_index: u64,
+        tx: Option<Transaction>,
+        decimals: u8,
+        memo_format: MemoFormat,
+    ) -> Self {
+        let tx = match tx {
+            Some(tx) => tx,
+            None => {
+                return Self {
+                    block_index,
+                    status: "not_found".to_string(),
+                    ..Default::default()
+                };
+            }
+        };
+
+        let to_amount =
+            |n: Nat| n.0.to_f64().unwrap_or_default() / 10u64.pow(decimals as u32) as f64;
+        let mut status = Self {
+            block_index,
+            status: "settled".to_string(),
+            kind: Some(tx.kind),
+            timestamp_ms: Some(tx.timestamp / 1_000_000),
+            ..Default::default()
+        };
+        let memo = if let Some(v) = tx.transfer {
+            status.amount = Some(to_amount(v.amount));
+            status.fee = v.fee.map(to_amount);
+            status.from = Some(v.from.to_string());
+            status.to = Some(v.to.to_string());
+            v.memo
+        } else if let Some(v) = tx.mint {
+            status.amount = Some(to_amount(v.amount));
+            status.fee = v.fee.map(to_amount);
+            status.to = Some(v.to.to_string());
+            v.memo
+        } else if let Some(v) = tx.burn {
+            status.amount = Some(to_amount(v.amount));
+            status.fee = v.fee.map(to_amount);
+            status.from = Some(v.from.to_string());
+            v.memo
+        } else if let Some(v) = tx.approve {
+            status.amount = Some(to_amount(v.amount));
+            status.fee = v.fee.map(to_amount);
+            status.from = Some(v.from.to_string());
+            status.to = Some(v.spender.to_string());
+            v.memo
+        } else {
+            None
+        };
+        if let Some(memo) = memo {
+            let (format, memo) = decode_memo(&memo.0, memo_format);
+            status.memo = Some(memo);
+            status.memo_format = Some(format);
+        }
+        status
+    }
+}
+
+impl TransactionStatus {
+    /// Creates the status from a block of the ICP ledger, `None` means the block was not found.
+    /// The memo is the ICRC-1 memo if set, otherwise the legacy u64 memo if not zero.
+    pub(super) fn from_icp_block(
+        block_index: u64,
+        block: Option<super::Block>,
+        decimals: u8,
+        memo_format: MemoFormat,
+    ) -> Self {
+        use super::Operation;
+
+        let block = match block {
+            Some(block) => block,
+            None => {
+                return Self {
+                    block_index,
+                    status: "not_found".to_string(),
+                    ..Default::default()
+                };
+            }
+        };
+
+        let to_amount = |t: super::Tokens| t.e8s as f64 / 10u64.pow(decimals as u32) as f64;
+        let mut status = Self {
+            block_index,
+            status: "settled".to_string(),
+            timestamp_ms: Some(block.timestamp.timestamp_nanos / 1_000_000),
+            ..Default::default()
+        };
+        let tx = block.transaction;
+        let (kind, amount, fee, from, to) = match tx.operation {
+            Some(Operation::Transfer {
+                from,
+                to,
+                amount,
+                fee,
+            })
+            | Some(Operation::TransferFrom {
+                from,
+                to,
+                amount,
+                fee,
+            }) => ("transfer", Some(amount), Some(fee), Some(from), Some(to)),
+            Some(Operation::Mint { to, amount }) => ("mint", Some(amount), None, None, Some(to)),
+            Some(Operation::Burn { from, amount }) => {
+                ("burn", Some(amount), None, Some(from), None)
+            }
+            Some(Operation::Approve {
+                from,
+                spender,
+                allowance,
+                fee,
+            }) => ("approve", allowance, Some(fee), Some(from), Some(spender)),
+            None => ("unknown", None, None, None, None),
+        };
+        status.kind = Some(kind.to_string());
+        status.amount = amount.map(to_amount);
+        status.fee = fee.map(to_amount);
+        status.from = from.map(hex::encode);
+        status.to = to.map(hex::encode);
+
+        let memo = match tx.icrc1_memo {
+            Some(memo) => Some(memo),
+            None if tx.memo != 0 => Some(tx.memo.to_be_bytes().to_vec()),
+            None => None,
+        };
+        if let Some(memo) = memo {
+            let (format, memo) = decode_memo(&memo, memo_format);
+            status.memo = Some(memo);
+            status.memo_format = Some(format);
+        }
+        status
+    }
+}
+
+/// ICP Ledger TransactionStatus tool implementation
+#[derive(Debug, Clone)]
+pub struct TransactionStatusTool {
+    ledgers: Arc<ICPLedgers>,
+    schema: Value,
+    memo_format: MemoFormat,
+}
+
+impl TransactionStatusTool {
+    pub const NAME: &'static str = "icp_ledger_transaction_status";
+
+    /// Creates a new TransactionStatusTool instance
+    pub fn new(ledgers: Arc<ICPLedgers>) -> Self {
+        let schema = gen_schema_for::<TransactionStatusArgs>();
+
+        TransactionStatusTool {
+            ledgers,
+            schema,
+            memo_format: MemoFormat::Auto,
+        }
+    }
+
+    /// Sets the memo format used when the arguments do not specify one,
+    /// e.g. [`MemoFormat::U64`] for exchanges that attach numeric deposit tags
+    pub fn with_memo_format(mut self, format: MemoFormat) -> Self {
+        self.memo_format = format;
+        self
+    }
+}
+
+/// Implementation of the [`Tool`] trait for TransactionStatusTool
+/// Enables AI Agent to confirm that a transfer has settled
+impl Tool<BaseCtx> for TransactionStatusTool {
+    type Args = TransactionStatusArgs;
+    type Output = TransactionStatus;
+
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        let tokens = self
+            .ledgers
+            .ledgers
+            .keys()
+            .map(|k| k.as_str())
+            .collect::<Vec<_>>();
+        format!(
+            "Query the status, amount and timestamp of a transaction by block index on ICP blockchain for the following tokens: {}",
+            tokens.join(", ")
+        )
+    }
+
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: self.name(),
+            description: self.description(),
+            parameters: self.schema.clone(),
+            strict: Some(true),
+            examples: Vec::new(),
+        }
+    }
+
+    async fn call(
+        &self,
+        ctx: BaseCtx,
+        mut data: Self::Args,
+        _resources: Vec<Resource>,
+    ) -> Result<ToolOutput<Self::Output>, BoxError> {
+        data.memo_format.get_or_insert(self.memo_format);
+        let status = self.ledgers.transaction_status(&ctx, data).await?;
+        Ok(ToolOutput::new(status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anda_engine::context::mock;
+    use candid::{Principal, decode_args, encode_args};
+    use icrc_ledger_types::{
+        icrc1::{account::Account, transfer::Memo},
+        icrc3::transactions::{GetTransactionsRequest, GetTransactionsResponse, Transfer},
+    };
+    use std::collections::BTreeMap;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_icp_ledger_transaction_status() {
+        let panda_ledger = Principal::from_text("druyg-tyaaa-aaaaq-aactq-cai").unwrap();
+        let ledgers = ICPLedgers {
+            ledgers: BTreeMap::from([(String::from("PANDA"), (panda_ledger, 8))]),
+            from_user_subaccount: false,
+        };
+        let account = Account {
+            owner: Principal::anonymous(),
+            subaccount: None,
+        };
+        let mocker = mock::MockCanisterCaller::new(move |canister, method, args| {
+            assert_eq!(canister, &panda_ledger);
+            assert_eq!(method, "get_transactions");
+            let (req,): (GetTransactionsRequest,) = decode_args(&args).unwrap();
+            let transactions = if req.start == 321u64 {
+                vec![Transaction::transfer(
+                    Transfer {
+                        amount: Nat::from(110_000_000u64),
+                        from: account,
+                        to: account,
+                        spender: None,
+                        memo: Some(Memo::from(b"order-42".to_vec())),
+                        fee: Some(Nat::from(10_000u64)),
+                        created_at_time: None,
+                    },
+                    1_700_000_000_000_000_000,
+                )]
+            } else {
+                vec![]
+            };
+            let res = GetTransactionsResponse {
+                log_length: Nat::from(322u64),
+                first_index: req.start,
+                transactions,
+                archived_transactions: vec![],
+            };
+            encode_args((res,)).unwrap()
+        });
+
+        let res = ledgers
+            .transaction_status(
+                &mocker,
+                TransactionStatusArgs {
+                    symbol: "PANDA".to_string(),
+                    block_index: 321,
+                    memo_format: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status, "settled");
+        assert_eq!(res.kind.as_deref(), Some("transfer"));
+        assert_eq!(res.amount, Some(1.1));
+        assert_eq!(res.fee, Some(0.0001));
+        assert_eq!(res.to, Some(account.to_string()));
+        assert_eq!(res.timestamp_ms, Some(1_700_000_000_000));
+        assert_eq!(res.memo.as_deref(), Some("order-42"));
+        assert_eq!(res.memo_format, Some(MemoFormat::Text));
+
+        let res = ledgers
+            .transaction_status(
+                &mocker,
+                TransactionStatusArgs {
+                    symbol: "PANDA".to_string(),
+                    block_index: 321,
+                    memo_format: Some(MemoFormat::Hex),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.memo, Some(hex::encode(b"order-42")));
+        assert_eq!(res.memo_format, Some(MemoFormat::Hex));
+
+        let res = ledgers
+            .transaction_status(
+                &mocker,
+                TransactionStatusArgs {
+                    symbol: "PANDA".to_string(),
+                    block_index: 999,
+                    memo_format: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status, "not_found");
+
+        let res = ledgers
+            .transaction_status(
+                &mocker,
+                TransactionStatusArgs {
+                    symbol: "ICP".to_string(),
+                    block_index: 321,
+                    memo_format: None,
+                },
+            )
+            .await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_icp_ledger_block_status() {
+        use crate::ledger::{
+            ArchivedBlocksRange, Block, BlockRange, GetBlocksArgs, LegacyTransaction, Operation,
+            QueryArchiveResult, QueryBlocksResponse, TimeStamp, Tokens,
+        };
+        use icrc_ledger_types::icrc3::archive::QueryArchiveFn;
+
+        let icp_ledger = Principal::from_text(crate::ledger::ICP_LEDGER_CANISTER).unwrap();
+        let archive = Principal::from_text("qjdve-lqaaa-aaaaa-aaaeq-cai").unwrap();
+        let ledgers = ICPLedgers {
+            ledgers: BTreeMap::from([(String::from("ICP"), (icp_ledger, 8))]),
+            from_user_subaccount: false,
+        };
+        let block = |index: u64, operation| Block {
+            transaction: LegacyTransaction {
+                memo: index,
+                icrc1_memo: None,
+                operation: Some(operation),
+            },
+            timestamp: TimeStamp {
+                timestamp_nanos: 1_700_000_000_000_000_000,
+            },
+        };
+        let mocker = mock::MockCanisterCaller::new(move |canister, method, args| {
+            let (req,): (GetBlocksArgs,) = decode_args(&args).unwrap();
+            if canister == &archive {
+                assert_eq!(method, "get_blocks");
+                let blocks = vec![block(
+                    req.start,
+                    Operation::Mint {
+                        to: vec![2; 32],
+                        amount: Tokens { e8s: 500_000_000 },
+                    },
+                )];
+                return encode_args((QueryArchiveResult::Ok(BlockRange { blocks }),)).unwrap();
+            }
+
+            assert_eq!(canister, &icp_ledger);
+            assert_eq!(method, "query_blocks");
+            let blocks = if req.start == 1000 {
+                vec![block(
+                    req.start,
+                    Operation::Transfer {
+                        from: vec![1; 32],
+                        to: vec![2; 32],
+                        amount: Tokens { e8s: 110_000_000 },
+                        fee: Tokens { e8s: 10_000 },
+                    },
+                )]
+            } else {
+                vec![]
+            };
+            let res = QueryBlocksResponse {
+                first_block_index: req.start,
+                blocks,
+                archived_blocks: vec![ArchivedBlocksRange {
+                    start: 0,
+                    length: 100,
+                    callback: QueryArchiveFn::new(archive, "get_blocks"),
+                }],
+            };
+            encode_args((res,)).unwrap()
+        });
+        let status = async |block_index| {
+            ledgers
+                .transaction_status(
+                    &mocker,
+                    TransactionStatusArgs {
+                        symbol: "ICP".to_string(),
+                        block_index,
+                        memo_format: None,
+                    },
+                )
+                .await
+                .unwrap()
+        };
+
+        let res = status(1000).await;
+        assert_eq!(res.status, "settled");
+        assert_eq!(res.kind.as_deref(), Some("transfer"));
+        assert_eq!(res.amount, Some(1.1));
+        assert_eq!(res.fee, Some(0.0001));
+        assert_eq!(res.from, Some(hex::encode([1u8; 32])));
+        assert_eq!(res.to, Some(hex::encode([2u8; 32])));
+        assert_eq!(res.timestamp_ms, Some(1_700_000_000_000));
+        assert_eq!(res.memo.as_deref(), Some("1000"));
+        assert_eq!(res.memo_format, Some(MemoFormat::U64));
+
+        let res = status(42).await;
+        assert_eq!(res.status, "settled");
+        assert_eq!(res.kind.as_deref(), Some("mint"));
+        assert_eq!(res.amount, Some(5.0));
+        assert_eq!(res.from, None);
+
+        assert_eq!(status(2000).await.status, "not_found");
+    }
+}
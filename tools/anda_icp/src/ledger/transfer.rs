@@ -17,17 +17,23 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
 
-use super::ICPLedgers;
+use super::{ICPLedgers, MemoFormat, TransferTarget, encode_legacy_memo, encode_memo};
 
 /// Arguments for transferring tokens to an account
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct TransferToArgs {
-    /// ICP account address (principal) to receive token, e.g. "77ibd-jp5kr-moeco-kgoar-rro5v-5tng4-krif5-5h2i6-osf2f-2sjtv-kqe"
+    /// ICP account address (principal) to receive token, e.g. "77ibd-jp5kr-moeco-kgoar-rro5v-5tng4-krif5-5h2i6-osf2f-2sjtv-kqe",
+    /// or a 64-char hex account identifier for ICP, e.g. an exchange deposit address
     pub account: String,
     /// Token symbol, e.g. "ICP"
     pub symbol: String,
     /// Token amount, e.g. 1.1 ICP
     pub amount: f64,
+    /// Optional memo attached to the transfer, e.g. "123456" or an exchange deposit tag
+    pub memo: Option<String>,
+    /// The format of the memo, default to "auto" which encodes a number as u64 and
+    /// anything else as text. Account identifiers only support u64 memos.
+    pub memo_format: Option<MemoFormat>,
 }
 
 /// Implementation of the ICP Ledger Transfer tool
@@ -35,6 +41,8 @@ pub struct TransferToArgs {
 pub struct TransferTool {
     ledgers: Arc<ICPLedgers>,
     schema: Value,
+    dry_run: bool,
+    memo_format: MemoFormat,
 }
 
 impl TransferTool {
@@ -43,7 +51,25 @@ impl TransferTool {
     pub fn new(ledgers: Arc<ICPLedgers>) -> Self {
         let schema = gen_schema_for::<TransferToArgs>();
 
-        TransferTool { ledgers, schema }
+        TransferTool {
+            ledgers,
+            schema,
+            dry_run: false,
+            memo_format: MemoFormat::Auto,
+        }
+    }
+
+    /// Enables or disables dry-run mode. In dry-run mode the arguments are validated
+    /// but no transfer is submitted to the ledger.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Sets the memo format used when the arguments do not specify one
+    pub fn with_memo_format(mut self, format: MemoFormat) -> Self {
+        self.memo_format = format;
+        self
     }
 }
 
@@ -83,18 +109,42 @@ impl Tool<BaseCtx> for TransferTool {
             description: self.description(),
             parameters: self.schema.clone(),
             strict: Some(true),
+            examples: Vec::new(),
         }
     }
 
     async fn call(
         &self,
         ctx: BaseCtx,
-        data: Self::Args,
+        mut data: Self::Args,
         _resources: Vec<Resource>,
     ) -> Result<ToolOutput<Self::Output>, BoxError> {
+        data.memo_format.get_or_insert(self.memo_format);
+        if self.dry_run {
+            let target = TransferTarget::parse(&data.account)?;
+            if !self.ledgers.ledgers.contains_key(&data.symbol) {
+                return Err(format!("Token {} is not supported", data.symbol).into());
+            }
+            if let Some(memo) = &data.memo {
+                let format = data.memo_format.unwrap_or_default();
+                match target {
+                    TransferTarget::Principal(_) => {
+                        encode_memo(memo, format)?;
+                    }
+                    TransferTarget::AccountId(_) => {
+                        encode_legacy_memo(memo, format)?;
+                    }
+                }
+            }
+            return Ok(ToolOutput::new(format!(
+                "Dry run, no transfer submitted: {} {} to {}",
+                data.amount, data.symbol, data.account
+            )));
+        }
+
         let (ledger, tx) = self
             .ledgers
-            .transfer(&ctx, ctx.engine_id().to_owned(), data)
+            .transfer_with_retry(&ctx, ctx.update_retry(), ctx.engine_id().to_owned(), data)
             .await?;
         Ok(ToolOutput::new(format!(
             "Successful, transaction ID: {}, detail: https://www.icexplorer.io/token/details/{}",
@@ -107,7 +157,11 @@ impl Tool<BaseCtx> for TransferTool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use anda_engine::context::mock;
+    use crate::ledger::{ICP_LEDGER_CANISTER, LegacyTransferArgs, LegacyTransferError};
+    use anda_engine::{
+        context::{UpdateRetry, mock},
+        engine::EngineBuilder,
+    };
     use candid::{Nat, Principal, decode_args, encode_args};
     use icrc_ledger_types::icrc1::{
         account::principal_to_subaccount,
@@ -173,6 +227,8 @@ mod tests {
             account: Principal::anonymous().to_string(),
             symbol: "PANDA".to_string(),
             amount: 9999.000012345678,
+            memo: Some("order-42".to_string()),
+            memo_format: None,
         };
         let mocker = mock::MockCanisterCaller::new(|canister, method, args| {
             if method == "icrc1_balance_of" {
@@ -189,15 +245,173 @@ mod tests {
             );
             assert_eq!(args.to.owner, Principal::anonymous());
             assert_eq!(args.amount, Nat::from(999900001234u64));
+            assert_eq!(args.memo.unwrap().0.as_slice(), b"order-42");
 
             let res: Result<Nat, TransferError> = Ok(Nat::from(321u64));
             encode_args((res,)).unwrap()
         });
 
         let (_, res) = ledgers
-            .transfer(&mocker, Principal::anonymous(), args)
+            .transfer_with_retry(&mocker, None, Principal::anonymous(), args)
             .await
             .unwrap();
         assert_eq!(res, Nat::from(321u64));
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_icp_ledger_transfer_to_account_id() {
+        let icp_ledger = Principal::from_text(ICP_LEDGER_CANISTER).unwrap();
+        let mut to = [7u8; 32];
+        let checksum = crc32fast::hash(&to[4..]).to_be_bytes();
+        to[..4].copy_from_slice(&checksum);
+        let account = hex::encode(to);
+        assert_eq!(
+            TransferTarget::parse(&account).unwrap(),
+            TransferTarget::AccountId(to)
+        );
+        assert!(TransferTarget::parse(&hex::encode([7u8; 32])).is_err());
+        assert_eq!(
+            TransferTarget::parse(&Principal::anonymous().to_text()).unwrap(),
+            TransferTarget::Principal(Principal::anonymous())
+        );
+
+        let ledgers = ICPLedgers {
+            ledgers: BTreeMap::from([(String::from("ICP"), (icp_ledger, 8))]),
+            from_user_subaccount: false,
+        };
+        let mocker = mock::MockCanisterCaller::new(move |canister, method, args| {
+            assert_eq!(canister, &icp_ledger);
+            match method {
+                "icrc1_balance_of" => encode_args((Nat::from(200_000_000u64),)).unwrap(),
+                "icrc1_fee" => encode_args((Nat::from(10_000u64),)).unwrap(),
+                "transfer" => {
+                    let (args,): (LegacyTransferArgs,) = decode_args(&args).unwrap();
+                    assert_eq!(args.to, to.to_vec());
+                    assert_eq!(args.amount.e8s, 150_000_000);
+                    assert_eq!(args.fee.e8s, 10_000);
+                    assert_eq!(args.memo, 42);
+                    assert!(args.from_subaccount.is_none());
+                    let res: Result<u64, LegacyTransferError> = Ok(123);
+                    encode_args((res,)).unwrap()
+                }
+                _ => panic!("unexpected method {method}"),
+            }
+        });
+        let args = TransferToArgs {
+            account: account.clone(),
+            symbol: "ICP".to_string(),
+            amount: 1.5,
+            memo: Some("42".to_string()),
+            memo_format: None,
+        };
+        let (ledger, res) = ledgers
+            .transfer_with_retry(&mocker, None, Principal::anonymous(), args)
+            .await
+            .unwrap();
+        assert_eq!(ledger, icp_ledger);
+        assert_eq!(res, Nat::from(123u64));
+
+        let args = TransferToArgs {
+            account,
+            symbol: "ICP".to_string(),
+            amount: 1.5,
+            memo: Some("order-42".to_string()),
+            memo_format: None,
+        };
+        assert!(
+            ledgers
+                .transfer_with_retry(&mocker, None, Principal::anonymous(), args)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_icp_ledger_transfer_with_retry() {
+        let ledger = Principal::from_text(ICP_LEDGER_CANISTER).unwrap();
+        let ledgers = ICPLedgers {
+            ledgers: BTreeMap::from([(String::from("ICP"), (ledger, 8))]),
+            from_user_subaccount: false,
+        };
+        // the transfer succeeded in an attempt whose response was lost
+        let mocker = mock::MockCanisterCaller::new(|_, method, args| match method {
+            "icrc1_balance_of" => encode_args((Nat::from(200_000_000u64),)).unwrap(),
+            "icrc1_transfer" => {
+                let (args,): (TransferArg,) = decode_args(&args).unwrap();
+                assert!(args.created_at_time.is_some());
+                let res: Result<Nat, TransferError> = Err(TransferError::Duplicate {
+                    duplicate_of: Nat::from(42u64),
+                });
+                encode_args((res,)).unwrap()
+            }
+            _ => panic!("unexpected method {method}"),
+        });
+        let args = || TransferToArgs {
+            account: Principal::anonymous().to_text(),
+            symbol: "ICP".to_string(),
+            amount: 1.0,
+            memo: None,
+            memo_format: None,
+        };
+        let retry = UpdateRetry::default();
+        let (_, res) = ledgers
+            .transfer_with_retry(&mocker, Some(&retry), Principal::anonymous(), args())
+            .await
+            .unwrap();
+        assert_eq!(res, Nat::from(42u64));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_icp_ledger_transfer_dry_run() {
+        let ledgers = ICPLedgers {
+            ledgers: BTreeMap::from([(
+                String::from("ICP"),
+                (
+                    Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap(),
+                    8,
+                ),
+            )]),
+            from_user_subaccount: true,
+        };
+        let tool = TransferTool::new(Arc::new(ledgers)).with_dry_run(true);
+        let ctx = EngineBuilder::new().mock_ctx();
+
+        let args = TransferToArgs {
+            account: Principal::anonymous().to_string(),
+            symbol: "ICP".to_string(),
+            amount: 1.1,
+            memo: None,
+            memo_format: None,
+        };
+        let res = tool.call(ctx.base.clone(), args, Vec::new()).await.unwrap();
+        assert!(res.output.starts_with("Dry run"));
+
+        let args = TransferToArgs {
+            account: Principal::anonymous().to_string(),
+            symbol: "PANDA".to_string(),
+            amount: 1.1,
+            memo: None,
+            memo_format: None,
+        };
+        assert!(tool.call(ctx.base.clone(), args, Vec::new()).await.is_err());
+
+        let args = TransferToArgs {
+            account: "invalid".to_string(),
+            symbol: "ICP".to_string(),
+            amount: 1.1,
+            memo: None,
+            memo_format: None,
+        };
+        assert!(tool.call(ctx.base.clone(), args, Vec::new()).await.is_err());
+
+        let tool = tool.with_memo_format(MemoFormat::Hex);
+        let args = TransferToArgs {
+            account: Principal::anonymous().to_string(),
+            symbol: "ICP".to_string(),
+            amount: 1.1,
+            memo: Some("order-42".to_string()),
+            memo_format: None,
+        };
+        assert!(tool.call(ctx.base, args, Vec::new()).await.is_err());
+    }
 }